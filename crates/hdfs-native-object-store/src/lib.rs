@@ -14,28 +14,31 @@
 //! # }
 //! ```
 use std::{
+    collections::BTreeMap,
     fmt::{Display, Formatter},
     future,
+    ops::Range,
     path::PathBuf,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+    sync::Arc,
 };
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use futures::stream::{BoxStream, StreamExt};
 use hdfs_native::{client::FileStatus, file::FileWriter, Client, HdfsError, WriteOptions};
 use object_store::{
+    coalesce_ranges,
     multipart::{PartId, PutPart, WriteMultiPart},
     path::Path,
     GetOptions, GetResult, GetResultPayload, ListResult, MultipartId, ObjectMeta, ObjectStore,
-    PutMode, PutOptions, PutResult, Result,
+    PutMode, PutOptions, PutResult, Result, OBJECT_STORE_COALESCE_DEFAULT,
 };
 use tokio::io::AsyncWrite;
 
+/// Maximum number of deletes running at once in [HdfsObjectStore::delete_stream]
+const DELETE_CONCURRENCY: usize = 10;
+
 #[derive(Debug)]
 pub struct HdfsObjectStore {
     client: Arc<Client>,
@@ -48,6 +51,8 @@ impl HdfsObjectStore {
         }
     }
 
+    /// HDFS has no native copy, so the source file is streamed into a new file. The replication
+    /// factor and block size of the source file are preserved.
     async fn internal_copy(&self, from: &Path, to: &Path, overwrite: bool) -> Result<()> {
         let overwrite = match self.client.get_file_info(&make_absolute_file(to)).await {
             Ok(_) if overwrite => true,
@@ -56,8 +61,16 @@ impl HdfsObjectStore {
             Err(e) => Err(e).to_object_store_err()?,
         };
 
+        let status = self
+            .client
+            .get_file_info(&make_absolute_file(from))
+            .await
+            .to_object_store_err()?;
+
         let write_options = WriteOptions {
             overwrite,
+            block_size: Some(status.block_size).filter(|size| *size > 0),
+            replication: Some(status.replication as u32).filter(|replication| *replication > 0),
             ..Default::default()
         };
 
@@ -155,8 +168,11 @@ impl ObjectStore for HdfsObjectStore {
         })
     }
 
-    /// Uses the [PutPart] trait to implement an asynchronous writer. We can't actually upload
-    /// multiple parts at once, so we simply set a limit of one part at a time.
+    /// Uses the [PutPart] trait to implement an asynchronous writer. HDFS has no native
+    /// multipart upload and files can only be appended to, so parts are written sequentially
+    /// to a single temporary file that is renamed into place on completion. Any parts that
+    /// are uploaded concurrently are coalesced into sequential writes in part order, so we
+    /// simply set a limit of one part at a time.
     async fn put_multipart(
         &self,
         location: &Path,
@@ -212,7 +228,7 @@ impl ObjectStore for HdfsObjectStore {
         ))
     }
 
-    /// Attempts to delete the temporary file used for multipart uploads.
+    /// Deletes the partially written temporary file used for a multipart upload.
     async fn abort_multipart(&self, _location: &Path, multipart_id: &MultipartId) -> Result<()> {
         // The multipart_id is the resolved temporary file name, so we can just delete it
         self.client
@@ -234,6 +250,15 @@ impl ObjectStore for HdfsObjectStore {
         let meta = self.head(location).await?;
 
         let range = options.range.unwrap_or(0..meta.size);
+        check_range(&range, meta.size)?;
+
+        if options.head {
+            return Ok(GetResult {
+                payload: GetResultPayload::Stream(futures::stream::empty().boxed()),
+                meta,
+                range,
+            });
+        }
 
         let reader = self
             .client
@@ -254,19 +279,50 @@ impl ObjectStore for HdfsObjectStore {
         })
     }
 
-    /// Return the metadata for the specified location
+    /// Reads the ranges from a single opened file, merging nearby ranges and fetching them
+    /// concurrently. The results are in the same order as `ranges`.
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        let reader = self
+            .client
+            .read(&make_absolute_file(location))
+            .await
+            .to_object_store_err()?;
+
+        for range in ranges {
+            check_range(range, reader.file_length())?;
+        }
+
+        let reader = &reader;
+        coalesce_ranges(
+            ranges,
+            |range| async move {
+                reader
+                    .read_range(range.start, range.end - range.start)
+                    .await
+                    .to_object_store_err()
+            },
+            OBJECT_STORE_COALESCE_DEFAULT,
+        )
+        .await
+    }
+
+    /// Return the metadata for the specified location. Directories aren't objects, so they are
+    /// reported as not found.
     async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        let path = make_absolute_file(location);
         let status = self
             .client
-            .get_file_info(&make_absolute_file(location))
+            .get_file_info(&path)
             .await
             .to_object_store_err()?;
 
+        if status.isdir {
+            return Err(HdfsError::FileNotFound(path)).to_object_store_err();
+        }
+
         Ok(ObjectMeta {
             location: location.clone(),
-            last_modified: Utc.from_utc_datetime(
-                &NaiveDateTime::from_timestamp_opt(status.modification_time as i64, 0).unwrap(),
-            ),
+            last_modified: get_last_modified(&status),
             size: status.length,
             e_tag: None,
             version: None,
@@ -291,17 +347,35 @@ impl ObjectStore for HdfsObjectStore {
         Ok(())
     }
 
+    /// Deletes the objects in `locations`, running up to 10 deletes at once. The deleted paths
+    /// are returned in the same order as `locations`, and an object that fails to be deleted
+    /// returns an error for its path without stopping the others.
+    fn delete_stream<'a>(
+        &'a self,
+        locations: BoxStream<'a, Result<Path>>,
+    ) -> BoxStream<'a, Result<Path>> {
+        locations
+            .map(move |location| async move {
+                let location = location?;
+                self.delete(&location).await?;
+                Ok(location)
+            })
+            .buffered(DELETE_CONCURRENCY)
+            .boxed()
+    }
+
     /// List all the objects with the given prefix.
     ///
     /// Prefixes are evaluated on a path segment basis, i.e. `foo/bar/` is a prefix of `foo/bar/x` but not of
     /// `foo/bar_baz/x`.
     ///
-    /// Note: the order of returned [`ObjectMeta`] is not guaranteed
+    /// Note: the order of returned [`ObjectMeta`] is not guaranteed. Directories are not included
+    /// since they aren't objects, but the files inside them are.
     fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
         let status_stream = self
             .client
             .list_status_iter(
-                &prefix.map(make_absolute_dir).unwrap_or("".to_string()),
+                &prefix.map(make_absolute_dir).unwrap_or("/".to_string()),
                 true,
             )
             .into_stream()
@@ -325,11 +399,14 @@ impl ObjectStore for HdfsObjectStore {
     ///
     /// Prefixes are evaluated on a path segment basis, i.e. `foo/bar/` is a prefix of `foo/bar/x` but not of
     /// `foo/bar_baz/x`.
+    ///
+    /// The delimiter is always `/`, so this is a single non-recursive listing of the prefix
+    /// directory.
     async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
         let mut status_stream = self
             .client
             .list_status_iter(
-                &prefix.map(make_absolute_dir).unwrap_or("".to_string()),
+                &prefix.map(make_absolute_dir).unwrap_or("/".to_string()),
                 false,
             )
             .into_stream()
@@ -363,20 +440,35 @@ impl ObjectStore for HdfsObjectStore {
         })
     }
 
+    /// Move an object from one path to another in the same object store.
+    ///
+    /// If there exists an object at the destination, it will be overwritten. Missing parent
+    /// directories of the destination are created.
     async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
         Ok(self
             .client
-            .rename(&make_absolute_file(from), &make_absolute_file(to), true)
+            .rename_with_mkdirs(&make_absolute_file(from), &make_absolute_file(to), true)
             .await
             .to_object_store_err()?)
     }
 
+    /// Move an object from one path to another in the same object store.
+    ///
+    /// Will return an [object_store::Error::AlreadyExists] error if the destination already
+    /// has an object. Missing parent directories of the destination are created.
     async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
-        Ok(self
+        match self
             .client
-            .rename(&make_absolute_file(from), &make_absolute_file(to), false)
+            .rename_with_mkdirs(&make_absolute_file(from), &make_absolute_file(to), false)
             .await
-            .to_object_store_err()?)
+        {
+            // The server's message describes the whole rename, so report the destination instead
+            Err(err @ HdfsError::AlreadyExists(_)) => Err(object_store::Error::AlreadyExists {
+                path: to.to_string(),
+                source: Box::new(err),
+            }),
+            result => result.to_object_store_err(),
+        }
     }
 
     /// Copy an object from one path to another in the same object store.
@@ -415,6 +507,10 @@ impl<T> HdfsErrorConvert<T> for hdfs_native::Result<T> {
                 path: path.clone(),
                 source: Box::new(HdfsError::FileNotFound(path)),
             },
+            HdfsError::ParentNotFound(path) => object_store::Error::NotFound {
+                path: path.clone(),
+                source: Box::new(HdfsError::ParentNotFound(path)),
+            },
             HdfsError::AlreadyExists(path) => object_store::Error::AlreadyExists {
                 path: path.clone(),
                 source: Box::new(HdfsError::AlreadyExists(path)),
@@ -434,10 +530,36 @@ struct HdfsMultipartWriter {
     // FileWriter is stateful, but put_part doesn't allow a mutable borrow so we
     // have to wrap in an async mutex
     client: Arc<Client>,
-    inner: Arc<tokio::sync::Mutex<FileWriter>>,
+    inner: Arc<tokio::sync::Mutex<PartWriter>>,
     tmp_filename: String,
     final_filename: String,
-    next_part: AtomicUsize,
+}
+
+/// Writes parts to the underlying file in order, buffering any parts that arrive
+/// before the parts preceding them.
+struct PartWriter {
+    writer: FileWriter,
+    next_part: usize,
+    pending: BTreeMap<usize, Vec<u8>>,
+}
+
+impl PartWriter {
+    async fn write_part(&mut self, buf: Vec<u8>, part_idx: usize) -> Result<()> {
+        if part_idx < self.next_part || self.pending.contains_key(&part_idx) {
+            return Err(HdfsError::InvalidArgument(format!(
+                "Part {} was already uploaded",
+                part_idx
+            )))
+            .to_object_store_err();
+        }
+        self.pending.insert(part_idx, buf);
+
+        while let Some(buf) = self.pending.remove(&self.next_part) {
+            self.writer.write(buf.into()).await.to_object_store_err()?;
+            self.next_part += 1;
+        }
+        Ok(())
+    }
 }
 
 impl HdfsMultipartWriter {
@@ -449,10 +571,13 @@ impl HdfsMultipartWriter {
     ) -> Self {
         Self {
             client,
-            inner: Arc::new(tokio::sync::Mutex::new(inner)),
+            inner: Arc::new(tokio::sync::Mutex::new(PartWriter {
+                writer: inner,
+                next_part: 0,
+                pending: BTreeMap::new(),
+            })),
             tmp_filename: tmp_filename.to_string(),
             final_filename: final_filename.to_string(),
-            next_part: AtomicUsize::new(0),
         }
     }
 }
@@ -460,21 +585,10 @@ impl HdfsMultipartWriter {
 #[async_trait]
 impl PutPart for HdfsMultipartWriter {
     /// Upload a single part
+    ///
+    /// Parts received out of order are buffered until all previous parts have been written
     async fn put_part(&self, buf: Vec<u8>, part_idx: usize) -> Result<PartId> {
-        if part_idx != self.next_part.load(Ordering::SeqCst) {
-            return Err(object_store::Error::NotSupported {
-                source: "Part received out of order".to_string().into(),
-            });
-        }
-
-        self.inner
-            .lock()
-            .await
-            .write(buf.into())
-            .await
-            .to_object_store_err()?;
-
-        self.next_part.fetch_add(1, Ordering::SeqCst);
+        self.inner.lock().await.write_part(buf, part_idx).await?;
 
         Ok(PartId {
             content_id: part_idx.to_string(),
@@ -484,13 +598,17 @@ impl PutPart for HdfsMultipartWriter {
     /// Complete the upload with the provided parts
     ///
     /// `completed_parts` is in order of part number
-    async fn complete(&self, _completed_parts: Vec<PartId>) -> Result<()> {
-        self.inner
-            .lock()
-            .await
-            .close()
-            .await
-            .to_object_store_err()?;
+    async fn complete(&self, completed_parts: Vec<PartId>) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        if !inner.pending.is_empty() || inner.next_part != completed_parts.len() {
+            return Err(HdfsError::InvalidArgument(format!(
+                "Multipart upload is missing parts, only {} of {} parts were written",
+                inner.next_part,
+                completed_parts.len()
+            )))
+            .to_object_store_err();
+        }
+        inner.writer.close().await.to_object_store_err()?;
         self.client
             .rename(&self.tmp_filename, &self.final_filename, true)
             .await
@@ -512,12 +630,28 @@ fn make_absolute_dir(path: &Path) -> String {
     }
 }
 
+/// Validates a requested range, since reading past the end of a file panics
+fn check_range(range: &Range<usize>, file_length: usize) -> Result<()> {
+    if range.start > range.end || range.end > file_length {
+        return Err(HdfsError::InvalidArgument(format!(
+            "Range {:?} is invalid for an object of size {}",
+            range, file_length
+        )))
+        .to_object_store_err();
+    }
+    Ok(())
+}
+
+/// HDFS modification times are in milliseconds since the epoch
+fn get_last_modified(status: &FileStatus) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(status.modification_time as i64)
+        .unwrap()
+}
+
 fn get_object_meta(status: &FileStatus) -> Result<ObjectMeta> {
     Ok(ObjectMeta {
         location: Path::parse(&status.path)?,
-        last_modified: Utc.from_utc_datetime(
-            &NaiveDateTime::from_timestamp_opt(status.modification_time as i64, 0).unwrap(),
-        ),
+        last_modified: get_last_modified(status),
         size: status.length,
         e_tag: None,
         version: None,
@@ -4,9 +4,9 @@ mod common;
 #[cfg(feature = "integration-test")]
 mod test {
     use bytes::{Buf, BufMut, Bytes, BytesMut};
-    use hdfs_native::{minidfs::DfsFeatures, Client};
+    use hdfs_native::{minidfs::DfsFeatures, Client, WriteOptions};
     use hdfs_native_object_store::{HdfsErrorConvert, HdfsObjectStore};
-    use object_store::{PutMode, PutOptions};
+    use object_store::{GetOptions, PutMode, PutOptions};
     use serial_test::serial;
     use std::collections::HashSet;
 
@@ -25,7 +25,8 @@ mod test {
         test_object_store_read(&store).await?;
         test_object_store_write(&store).await?;
         test_object_store_write_multipart(&store).await?;
-        test_object_store_copy(&store).await?;
+        test_object_store_copy(&store, &Client::new(&dfs.url).to_object_store_err()?).await?;
+        test_object_store_delete_stream(&store).await?;
 
         Ok(())
     }
@@ -36,8 +37,19 @@ mod test {
         let head = store.head(&Path::from("/testfile")).await?;
         assert_eq!(head.location, Path::from("/testfile"));
         assert_eq!(head.size, TEST_FILE_INTS * 4);
+        // The file was just created, which catches mixing up seconds and milliseconds
+        assert!((chrono::Utc::now() - head.last_modified).num_days() < 1);
 
-        assert!(store.head(&Path::from("/testfile2")).await.is_err());
+        assert!(matches!(
+            store.head(&Path::from("/testfile2")).await,
+            Err(object_store::Error::NotFound { .. })
+        ));
+
+        // Directories aren't objects
+        assert!(matches!(
+            store.head(&Path::from("")).await,
+            Err(object_store::Error::NotFound { .. })
+        ));
 
         Ok(())
     }
@@ -65,6 +77,76 @@ mod test {
         assert_eq!(list.common_prefixes.len(), 0);
         assert_eq!(list.objects.len(), 0);
 
+        // Prefixes match whole path segments, and nested files are included
+        for path in [
+            "data/foo.parquet",
+            "data/nested/bar.parquet",
+            "database/baz",
+        ] {
+            store.put(&Path::from(path), Bytes::from("data")).await?;
+        }
+
+        let mut locations: Vec<Path> = store
+            .list(Some(&Path::from("data/")))
+            .map(|meta| meta.map(|meta| meta.location))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<object_store::Result<_>>()?;
+        locations.sort();
+        assert_eq!(
+            locations,
+            vec![
+                Path::from("data/foo.parquet"),
+                Path::from("data/nested/bar.parquet")
+            ]
+        );
+
+        let list: Vec<ObjectMeta> = store
+            .list(None)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<object_store::Result<_>>()?;
+        assert_eq!(list.len(), 4);
+        assert!(list
+            .iter()
+            .all(|meta| meta.size == 4 || meta.size == TEST_FILE_INTS * 4));
+
+        // Listing with a delimiter only returns the direct children of the prefix, with
+        // directories as common prefixes
+        let mut list = store.list_with_delimiter(Some(&Path::from("data"))).await?;
+        list.objects.sort_by(|a, b| a.location.cmp(&b.location));
+        assert_eq!(list.common_prefixes, vec![Path::from("data/nested")]);
+        assert_eq!(
+            list.objects
+                .iter()
+                .map(|meta| meta.location.clone())
+                .collect::<Vec<_>>(),
+            vec![Path::from("data/foo.parquet")]
+        );
+        assert_eq!(list.objects[0].size, 4);
+
+        let list = store.list_with_delimiter(None).await?;
+        assert!(list.common_prefixes.contains(&Path::from("data")));
+        assert!(list.common_prefixes.contains(&Path::from("database")));
+        assert!(!list.common_prefixes.contains(&Path::from("data/nested")));
+        assert_eq!(
+            list.objects
+                .iter()
+                .map(|meta| meta.location.clone())
+                .collect::<Vec<_>>(),
+            vec![Path::from("testfile")]
+        );
+
+        for path in [
+            "data/foo.parquet",
+            "data/nested/bar.parquet",
+            "database/baz",
+        ] {
+            store.delete(&Path::from(path)).await?;
+        }
+
         Ok(())
     }
 
@@ -85,6 +167,69 @@ mod test {
         assert!(store.head(&Path::from("/testfile")).await.is_ok());
         assert!(store.head(&Path::from("/testfile2")).await.is_err());
 
+        // Renaming over an existing object replaces it
+        store
+            .put(&Path::from("/renamefile"), Bytes::from_static(b"data"))
+            .await?;
+        store
+            .put(
+                &Path::from("/renamefile2"),
+                Bytes::from_static(b"other data"),
+            )
+            .await?;
+        store
+            .rename(&Path::from("/renamefile"), &Path::from("/renamefile2"))
+            .await?;
+        assert!(store.head(&Path::from("/renamefile")).await.is_err());
+        assert_eq!(
+            store
+                .get(&Path::from("/renamefile2"))
+                .await?
+                .bytes()
+                .await?,
+            Bytes::from_static(b"data")
+        );
+
+        // Unless the destination must not exist
+        store
+            .put(&Path::from("/renamefile"), Bytes::from_static(b"new data"))
+            .await?;
+        let result = store
+            .rename_if_not_exists(&Path::from("/renamefile"), &Path::from("/renamefile2"))
+            .await;
+        assert!(
+            matches!(result, Err(object_store::Error::AlreadyExists { ref path, .. }) if path == "renamefile2"),
+            "{:?}",
+            result
+        );
+        assert!(store.head(&Path::from("/renamefile")).await.is_ok());
+
+        store
+            .rename_if_not_exists(&Path::from("/renamefile"), &Path::from("/renamefile3"))
+            .await?;
+        assert!(store.head(&Path::from("/renamefile")).await.is_err());
+        assert!(store.head(&Path::from("/renamefile3")).await.is_ok());
+
+        // Parent directories of the destination are created
+        store
+            .rename(
+                &Path::from("/renamefile3"),
+                &Path::from("/renamedir/nested/renamefile3"),
+            )
+            .await?;
+        assert!(store
+            .head(&Path::from("/renamedir/nested/renamefile3"))
+            .await
+            .is_ok());
+
+        store.delete(&Path::from("/renamefile2")).await?;
+        store
+            .delete(&Path::from("/renamedir/nested/renamefile3"))
+            .await?;
+        // The directories are empty now
+        store.delete(&Path::from("/renamedir/nested")).await?;
+        store.delete(&Path::from("/renamedir")).await?;
+
         Ok(())
     }
 
@@ -102,6 +247,47 @@ mod test {
         let offset = TEST_FILE_INTS / 2 * 4;
         let mut buf = store.get_range(&location, offset..(offset + 4)).await?;
         assert_eq!(buf.get_i32(), TEST_FILE_INTS as i32 / 2);
+
+        // Ranges are returned in request order, including overlapping and empty ranges
+        let ranges = [
+            offset..(offset + 8),
+            0..4,
+            (offset + 4)..(offset + 12),
+            8..8,
+            (TEST_FILE_INTS * 4 - 4)..(TEST_FILE_INTS * 4),
+        ];
+        let results = store.get_ranges(&location, &ranges).await?;
+        assert_eq!(results.len(), ranges.len());
+        for (range, mut buf) in ranges.into_iter().zip(results) {
+            assert_eq!(buf.len(), range.len());
+            for i in (range.start / 4)..(range.end / 4) {
+                assert_eq!(buf.get_i32(), i as i32);
+            }
+        }
+
+        // Reading past the end is an error rather than a panic
+        let len = TEST_FILE_INTS * 4;
+        assert!(store
+            .get_range(&location, (len - 4)..(len + 4))
+            .await
+            .is_err());
+        assert!(store
+            .get_ranges(&location, &[0..4, len..(len + 1)])
+            .await
+            .is_err());
+
+        let result = store
+            .get_opts(
+                &location,
+                GetOptions {
+                    head: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert_eq!(result.meta.size, len);
+        assert!(result.bytes().await?.is_empty());
+
         Ok(())
     }
 
@@ -231,17 +417,31 @@ mod test {
         store.delete(&Path::from("/newfile")).await?;
 
         // Test aborting
-        let (multipart_id, _) = store.put_multipart(&"/newfile".into()).await?;
+        let (multipart_id, mut writer) = store.put_multipart(&"/newfile".into()).await?;
         assert!(store.head(&"/.newfile.tmp".into()).await.is_ok());
+        writer
+            .write_all(&[0u8; 1024])
+            .await
+            .map_err(HdfsError::from)
+            .to_object_store_err()?;
+        writer
+            .flush()
+            .await
+            .map_err(HdfsError::from)
+            .to_object_store_err()?;
         store
             .abort_multipart(&"/newfile".into(), &multipart_id)
             .await?;
         assert!(store.head(&"/.newfile.tmp".into()).await.is_err());
+        assert!(store.head(&"/newfile".into()).await.is_err());
 
         Ok(())
     }
 
-    async fn test_object_store_copy(store: &HdfsObjectStore) -> object_store::Result<()> {
+    async fn test_object_store_copy(
+        store: &HdfsObjectStore,
+        client: &Client,
+    ) -> object_store::Result<()> {
         use object_store::{path::Path, ObjectStore};
 
         store.put(&Path::from("/newfile"), Bytes::new()).await?;
@@ -262,6 +462,33 @@ mod test {
         let read_data = store.get(&Path::from("/newfile2")).await?.bytes().await?;
         assert_eq!(buf.len(), read_data.len());
 
+        // Copies of a file with a non-default block size and replication keep them
+        let mut writer = client
+            .create(
+                "/customfile",
+                WriteOptions {
+                    block_size: Some(64 * 1024 * 1024),
+                    replication: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .to_object_store_err()?;
+        writer.write(buf.clone()).await.to_object_store_err()?;
+        writer.close().await.to_object_store_err()?;
+        store
+            .copy(&Path::from("/customfile"), &Path::from("/customfile2"))
+            .await?;
+        let status = client
+            .get_file_info("/customfile2")
+            .await
+            .to_object_store_err()?;
+        assert_eq!(status.block_size, 64 * 1024 * 1024);
+        assert_eq!(status.replication, 1);
+        assert_eq!(status.length, buf.len());
+        store.delete(&Path::from("/customfile")).await?;
+        store.delete(&Path::from("/customfile2")).await?;
+
         for pos in 0..buf.len() {
             assert_eq!(
                 buf[pos], read_data[pos],
@@ -280,4 +507,35 @@ mod test {
 
         Ok(())
     }
+
+    async fn test_object_store_delete_stream(store: &HdfsObjectStore) -> object_store::Result<()> {
+        use futures::{stream, StreamExt};
+        use object_store::{path::Path, ObjectStore};
+
+        let mut paths: Vec<Path> = (0..25)
+            .map(|i| Path::from(format!("/deletes/part-{:05}", i)))
+            .collect();
+        for path in paths.iter() {
+            store.put(path, Bytes::from("data")).await?;
+        }
+
+        // A missing object fails on its own without stopping the other deletes
+        paths.insert(10, Path::from("/deletes/missing"));
+        let results: Vec<object_store::Result<Path>> = store
+            .delete_stream(stream::iter(paths.clone()).map(Ok).boxed())
+            .collect()
+            .await;
+        assert_eq!(results.len(), paths.len());
+        for (result, path) in results.iter().zip(paths.iter()) {
+            if path.as_ref() == "deletes/missing" {
+                assert!(result.is_err());
+            } else {
+                assert_eq!(result.as_ref().unwrap(), path);
+            }
+        }
+
+        assert_eq!(store.list(Some(&Path::from("/deletes"))).count().await, 0);
+
+        Ok(())
+    }
 }
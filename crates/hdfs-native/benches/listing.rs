@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+use criterion::*;
+use futures::{stream, StreamExt, TryStreamExt};
+use hdfs_native::{minidfs::MiniDfs, Client, WriteOptions};
+
+// Number of files in the listed directory
+const NUM_FILES: usize = 20_000;
+
+fn bench(c: &mut Criterion) {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let _dfs = MiniDfs::with_features(&HashSet::new());
+    let client = Client::default();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    rt.block_on(async {
+        client.mkdirs("/bench", 0o755, true).await.unwrap();
+        stream::iter(0..NUM_FILES)
+            .map(|i| {
+                let client = &client;
+                async move {
+                    client
+                        .create(&format!("/bench/file-{}", i), WriteOptions::default())
+                        .await?
+                        .close()
+                        .await
+                }
+            })
+            .buffer_unordered(64)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+    });
+
+    let mut group = c.benchmark_group("listing");
+    group.throughput(Throughput::Elements(NUM_FILES as u64));
+    group.sample_size(10);
+    group.bench_function("list-status", |b| {
+        b.iter(|| rt.block_on(client.list_status("/bench", false)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+
+use bytes::{BufMut, BytesMut};
+use criterion::*;
+use futures::{stream, StreamExt, TryStreamExt};
+use hdfs_native::{minidfs::MiniDfs, Client, ClientBuilder, WriteOptions};
+
+// Number of ranges read at the same time
+const NUM_READERS: usize = 32;
+const RANGE_SIZE: usize = 8 * 1024 * 1024;
+
+async fn read_concurrently(client: &Client) {
+    let reader = client.read("/bench").await.unwrap();
+    stream::iter(0..NUM_READERS)
+        .map(|i| reader.read_range(i * RANGE_SIZE, RANGE_SIZE))
+        .buffer_unordered(NUM_READERS)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+}
+
+fn bench(c: &mut Criterion) {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let _dfs = MiniDfs::with_features(&HashSet::new());
+    let client = Client::default();
+    let pooled_client = ClientBuilder::default()
+        .with_read_buffer_pool_size(64 * 1024 * 1024)
+        .build()
+        .unwrap();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    rt.block_on(async {
+        let mut data = BytesMut::with_capacity(NUM_READERS * RANGE_SIZE);
+        for i in 0..(NUM_READERS * RANGE_SIZE / 4) {
+            data.put_u32(i as u32);
+        }
+        let mut writer = client
+            .create("/bench", WriteOptions::default())
+            .await
+            .unwrap();
+        writer.write(data.freeze()).await.unwrap();
+        writer.close().await.unwrap();
+    });
+
+    let mut group = c.benchmark_group("read");
+    group.throughput(Throughput::Bytes((NUM_READERS * RANGE_SIZE) as u64));
+    group.sample_size(10);
+    group.bench_function("concurrent-ranges", |b| {
+        b.iter(|| rt.block_on(read_concurrently(&client)))
+    });
+    group.bench_function("concurrent-ranges-buffer-pool", |b| {
+        b.iter(|| rt.block_on(read_concurrently(&pooled_client)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -13,6 +13,7 @@ fn main() -> Result<()> {
         prost_build::compile_protos(
             &[
                 "src/proto/hdfs/ClientNamenodeProtocol.proto",
+                "src/proto/hdfs/ClientDatanodeProtocol.proto",
                 "src/proto/hdfs/datatransfer.proto",
                 "src/proto/common/RpcHeader.proto",
                 "src/proto/common/IpcConnectionContext.proto",
@@ -4,18 +4,25 @@ import java.io.BufferedReader;
 import java.io.DataOutputStream;
 import java.io.File;
 import java.io.FileOutputStream;
+import java.io.FileWriter;
 import java.io.InputStreamReader;
+import java.io.Writer;
 import java.util.HashSet;
 import java.util.Set;
 
 import org.apache.hadoop.conf.Configuration;
+import org.apache.hadoop.crypto.key.kms.server.KMSConfiguration;
+import org.apache.hadoop.crypto.key.kms.server.MiniKMS;
+import org.apache.hadoop.fs.FileSystem;
 import org.apache.hadoop.fs.Path;
 import org.apache.hadoop.fs.permission.FsPermission;
+import org.apache.hadoop.hdfs.DFSTestUtil;
 import org.apache.hadoop.hdfs.DistributedFileSystem;
 import org.apache.hadoop.hdfs.HdfsConfiguration;
 import org.apache.hadoop.hdfs.MiniDFSCluster;
 import org.apache.hadoop.hdfs.MiniDFSNNTopology;
 import org.apache.hadoop.hdfs.client.HdfsClientConfigKeys;
+import org.apache.hadoop.hdfs.protocol.LocatedBlock;
 import org.apache.hadoop.hdfs.security.token.delegation.DelegationTokenIdentifier;
 import org.apache.hadoop.hdfs.server.federation.RouterConfigBuilder;
 import org.apache.hadoop.hdfs.server.federation.StateStoreDFSCluster;
@@ -23,6 +30,7 @@ import org.apache.hadoop.hdfs.server.federation.MiniRouterDFSCluster.RouterConte
 import org.apache.hadoop.hdfs.server.federation.store.StateStoreService;
 import org.apache.hadoop.io.Text;
 import org.apache.hadoop.minikdc.MiniKdc;
+import org.apache.hadoop.net.unix.DomainSocket;
 import org.apache.hadoop.security.Credentials;
 import org.apache.hadoop.security.Credentials.SerializedFormat;
 import org.apache.hadoop.security.token.Token;
@@ -41,12 +49,17 @@ public class Main {
         }
         MiniKdc kdc = null;
 
+        // Symlinks are disabled outside of tests
+        FileSystem.enableSymlinks();
+
         Configuration conf = new Configuration();
         if (flags.contains("security")) {
             kdc = new MiniKdc(MiniKdc.createConf(), new File("target/test/kdc"));
             kdc.setTransport("UDP");
             kdc.start();
             kdc.createPrincipal(new File("target/test/hdfs.keytab"), "hdfs/localhost");
+            // A regular user, for clients that log in as someone other than the superuser
+            kdc.createPrincipal(new File("target/test/alice.keytab"), "alice");
 
             conf.set(HADOOP_SECURITY_AUTHENTICATION, "kerberos");
             conf.set(HADOOP_SECURITY_AUTHORIZATION, "true");
@@ -60,12 +73,78 @@ public class Main {
             conf.set(DFSConfigKeys.DFS_DATANODE_KEYTAB_FILE_KEY, "target/test/hdfs.keytab");
             conf.set(DFS_DATANODE_KERBEROS_PRINCIPAL_KEY, "hdfs/localhost@" + kdc.getRealm());
             conf.set(DFSConfigKeys.DFS_BLOCK_ACCESS_TOKEN_ENABLE_KEY, "true");
-            // conf.set(DFS_DATA_TRANSFER_PROTECTION_KEY, "authentication");
+            if (flags.contains("data_transfer_security")) {
+                if (flags.contains("privacy")) {
+                    conf.set(DFS_DATA_TRANSFER_PROTECTION_KEY, "privacy");
+                } else {
+                    conf.set(DFS_DATA_TRANSFER_PROTECTION_KEY, "integrity");
+                }
+            }
             conf.set(DFSConfigKeys.IGNORE_SECURE_PORTS_FOR_TESTING_KEY, "true");
         }
 
+        if (flags.contains("short_circuit")) {
+            // The DataNode can only listen on a domain socket with the native Hadoop library
+            if (DomainSocket.getLoadingFailureReason() == null) {
+                DomainSocket.disableBindPathValidation();
+                conf.setBoolean(Read.ShortCircuit.KEY, true);
+                conf.set(DFS_DOMAIN_SOCKET_PATH_KEY, new File("target/test/dn_socket._PORT").getAbsolutePath());
+            } else {
+                System.err.println("Short-circuit reads are disabled: " + DomainSocket.getLoadingFailureReason());
+            }
+        }
+
+        if (flags.contains("data_transfer_encryption")) {
+            // Encryption keys are derived from the block keys, so block tokens are needed too
+            conf.set(DFSConfigKeys.DFS_BLOCK_ACCESS_TOKEN_ENABLE_KEY, "true");
+            conf.set(DFSConfigKeys.DFS_ENCRYPT_DATA_TRANSFER_KEY, "true");
+        }
+
+        if (flags.contains("block_token_expiry")) {
+            conf.set(DFSConfigKeys.DFS_BLOCK_ACCESS_TOKEN_ENABLE_KEY, "true");
+            conf.set(DFSConfigKeys.DFS_BLOCK_ACCESS_TOKEN_LIFETIME_KEY, "1");
+        }
+
+        MiniKMS kms = null;
+        if (flags.contains("kms")) {
+            // Keys come from a fresh keystore, since they're created again for each cluster
+            File kmsDir = new File("target/test/kms").getAbsoluteFile();
+            kmsDir.mkdirs();
+            new File(kmsDir, "kms.keystore").delete();
+            new File(kmsDir, ".kms.keystore.crc").delete();
+
+            Configuration kmsConf = new Configuration(false);
+            kmsConf.set(KMSConfiguration.KEY_PROVIDER_URI,
+                "jceks://file@" + new Path(kmsDir.getPath(), "kms.keystore").toUri());
+            kmsConf.set("hadoop.kms.authentication.type", "simple");
+            try (Writer writer = new FileWriter(new File(kmsDir, "kms-site.xml"))) {
+                kmsConf.writeXml(writer);
+            }
+
+            kms = new MiniKMS.Builder().setKmsConfDir(kmsDir).build();
+            kms.start();
+            conf.set(HADOOP_SECURITY_KEY_PROVIDER_PATH, "kms://http@localhost:" + kms.getKMSUrl().getPort() + "/kms");
+        }
+
+        if (flags.contains("token_expiry")) {
+            // Delegation tokens expire after a few seconds unless they're renewed
+            conf.set(DFSConfigKeys.DFS_NAMENODE_DELEGATION_TOKEN_RENEW_INTERVAL_KEY, "5000");
+        }
+
         HdfsConfiguration hdfsConf = new HdfsConfiguration(conf);
 
+        // A second cluster can run next to the main one on its own ports and storage
+        int nameNodePort = 9000;
+        int nameNodeHttpPort = 9870;
+        String confDir = "target/test";
+        if (flags.contains("second_cluster")) {
+            nameNodePort = 9010;
+            nameNodeHttpPort = 9880;
+            confDir = "target/test/second_cluster";
+            new File(confDir).mkdirs();
+            hdfsConf.set(MiniDFSCluster.HDFS_MINIDFS_BASEDIR, new File(confDir, "data").getAbsolutePath());
+        }
+
         MiniDFSCluster dfs = null;
         StateStoreDFSCluster routerDfs = null;
         if (flags.contains("rbf")) {
@@ -99,8 +178,8 @@ public class Main {
             }
 
             dfs = new MiniDFSCluster.Builder(hdfsConf)
-                .nameNodePort(9000)
-                .nameNodeHttpPort(9870)
+                .nameNodePort(nameNodePort)
+                .nameNodeHttpPort(nameNodeHttpPort)
                 .nnTopology(nnTopology)
                 .numDataNodes(numDataNodes)
                 .build();
@@ -110,11 +189,16 @@ public class Main {
             } else if (flags.contains("ha")) {
                 hdfsConf.set(FS_DEFAULT_NAME_KEY, "hdfs://minidfs-ns");
             } else {
-                hdfsConf.set(FS_DEFAULT_NAME_KEY, "hdfs://127.0.0.1:9000");
+                hdfsConf.set(FS_DEFAULT_NAME_KEY, "hdfs://127.0.0.1:" + nameNodePort);
             }
 
             dfs.waitActive();
 
+            if (flags.contains("block_token_expiry")) {
+                // The configured lifetime is in minutes, so shorten it to a few seconds directly
+                dfs.getNamesystem().getBlockManager().getBlockTokenSecretManager().setTokenLifetime(3000);
+            }
+
             int activeNamenode = 0;
             if (flags.contains("viewfs")) {
                 // Each name services has two namenodes
@@ -122,7 +206,7 @@ public class Main {
                 dfs.transitionToActive(2);
             } else if (flags.contains("ha")) {
                 activeNamenode = 2;
-                // dfs.transitionToObserver(1);
+                dfs.transitionToObserver(1);
                 dfs.transitionToActive(activeNamenode);
             }
 
@@ -138,16 +222,26 @@ public class Main {
                 fs.setErasureCodingPolicy(new Path("/ec-10-4"), "RS-10-4-1024k");
             }
 
+            if (flags.contains("kms")) {
+                // With ViewFS the zone is in the first name service, at /mount1/ez
+                Path ezPath = new Path(flags.contains("viewfs") ? "/nested/ez" : "/ez");
+                DFSTestUtil.createKey("key", dfs, activeNamenode, hdfsConf);
+                DistributedFileSystem fs = dfs.getFileSystem(activeNamenode);
+                fs.mkdirs(ezPath);
+                fs.setPermission(ezPath, new FsPermission("777"));
+                fs.createEncryptionZone(ezPath, "key");
+            }
+
             if (flags.contains("token")) {
                 Credentials creds = new Credentials();
                 if (flags.contains("ha")) {
                     System.err.println("Getting token from namenode! " + dfs.getNameNode(2).getTokenServiceName());
-                    Token<DelegationTokenIdentifier> token = dfs.getNameNodeRpc(2).getDelegationToken(null);
+                    Token<DelegationTokenIdentifier> token = dfs.getNameNodeRpc(2).getDelegationToken(new Text("hdfs"));
                     token.setService(new Text("ha-hdfs:minidfs-ns"));
                     creds.addToken(new Text("ha-hdfs:minidfs-ns"), token);
                 } else {
                     System.err.println("Getting token from namenode! " + dfs.getNameNode().getTokenServiceName());
-                    Token<DelegationTokenIdentifier> token = dfs.getNameNodeRpc().getDelegationToken(null);
+                    Token<DelegationTokenIdentifier> token = dfs.getNameNodeRpc().getDelegationToken(new Text("hdfs"));
                     token.setService(new Text(dfs.getNameNode().getTokenServiceName()));
                     creds.addToken(new Text(dfs.getNameNode().getTokenServiceName()), token);
                 }
@@ -155,20 +249,37 @@ public class Main {
                 DataOutputStream os = new DataOutputStream(new FileOutputStream("target/test/delegation_token"));
                 creds.writeTokenStorageToStream(os, SerializedFormat.WRITABLE);
                 os.close();
-            } else {
+            } else if (!flags.contains("second_cluster")) {
                 new File("target/test/delegation_token").delete();
             }
         }
 
-        hdfsConf.writeXml(new FileOutputStream("target/test/core-site.xml"));
+        hdfsConf.writeXml(new FileOutputStream(new File(confDir, "core-site.xml")));
 
         System.out.println("Ready!");
         if (flags.contains("security")) {
             System.out.println(kdc.getKrb5conf().toPath().toString());
         }
 
+        // Run commands from the tests until an empty line
         BufferedReader reader = new BufferedReader(new InputStreamReader(System.in));
-        reader.readLine();
+        String line;
+        while ((line = reader.readLine()) != null && !line.isEmpty()) {
+            String[] command = line.split(" ");
+            if (command[0].equals("stop_datanode") && dfs != null) {
+                // Stops the first DataNode in the pipeline of the last block of a file being written
+                LocatedBlock block = dfs.getFileSystem().getClient()
+                    .getLocatedBlocks(command[1], 0).getLastLocatedBlock();
+                String name = block.getLocations()[0].getXferAddr();
+                if (dfs.stopDataNode(name) != null) {
+                    System.out.println("Stopped " + name);
+                } else {
+                    System.out.println("Unknown DataNode " + name);
+                }
+            } else {
+                System.out.println("Unknown command " + line);
+            }
+        }
 
         if (dfs != null) {
             dfs.close();
@@ -176,6 +287,9 @@ public class Main {
         if (routerDfs != null) {
             routerDfs.shutdown();
         }
+        if (kms != null) {
+            kms.stop();
+        }
 
         if (flags.contains("security")) {
             kdc.stop();
@@ -195,7 +309,7 @@ public class Main {
             conf.set(CONFIG_VIEWFS_PREFIX + ".minidfs-viewfs.linkFallback", "hdfs://ns1/nested");
         } else if (flags.contains("ha")) {
             nnTopology = MiniDFSNNTopology.simpleHATopology(3);
-            conf.set(HdfsClientConfigKeys.Failover.PROXY_PROVIDER_KEY_PREFIX + ".minidfs-ns", "org.apache.hadoop.hdfs.server.namenode.ha.ConfiguredFailoverProxyProvider");
+            conf.set(HdfsClientConfigKeys.Failover.PROXY_PROVIDER_KEY_PREFIX + ".minidfs-ns", "org.apache.hadoop.hdfs.server.namenode.ha.ObserverReadProxyProvider");
             conf.set(DFSConfigKeys.DFS_NAMENODE_STATE_CONTEXT_ENABLED_KEY, "true");
             conf.set(DFSConfigKeys.DFS_HA_TAILEDITS_INPROGRESS_KEY, "true");
             conf.set(DFSConfigKeys.DFS_HA_TAILEDITS_PERIOD_KEY, "0ms");
@@ -0,0 +1,291 @@
+//! Command line tool for basic file system operations, built with the `cli` feature. The
+//! Hadoop configs are loaded from `HADOOP_CONF_DIR` like the Java tools, and paths are either
+//! full `hdfs://` or `viewfs://` URLs or paths on `fs.defaultFS`.
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use bytes::Bytes;
+use clap::{Parser, Subcommand};
+use futures::StreamExt;
+use hdfs_native::client::FileStatus;
+use hdfs_native::{Client, ClientBuilder, HdfsError, Result, WriteOptions};
+use url::Url;
+
+// How much of a local file is read at a time by `put`
+const PUT_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Parser)]
+#[command(name = "hdfs-native", version, about = "Basic HDFS operations")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the contents of a directory, or the status of a file
+    Ls {
+        /// List sub-directories recursively
+        #[arg(short = 'R', long)]
+        recursive: bool,
+        path: String,
+    },
+    /// Write the contents of files to stdout
+    Cat {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Copy a local file to HDFS
+    Put {
+        /// Overwrite the destination if it exists
+        #[arg(short, long)]
+        force: bool,
+        local: String,
+        path: String,
+    },
+    /// Copy a file from HDFS to a local file
+    Get { path: String, local: String },
+    /// Delete files or directories
+    Rm {
+        /// Delete directories and their contents
+        #[arg(short, long)]
+        recursive: bool,
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Create directories
+    Mkdir {
+        /// Create any missing parent directories
+        #[arg(short, long)]
+        parents: bool,
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Print the checksums of files, the same as `hadoop fs -checksum`
+    Checksum {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("hdfs-native: {}", error_message(&e));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Ls { recursive, path } => {
+            let (client, path) = client_for(&path)?;
+            for status in client.list_status(&path, recursive).await? {
+                println!("{}", format_status(&status));
+            }
+        }
+        Command::Cat { paths } => {
+            let mut stdout = std::io::stdout().lock();
+            for path in paths {
+                let (client, path) = client_for(&path)?;
+                let reader = client.read(&path).await?;
+                let mut stream = reader.read_range_stream(0, reader.file_length());
+                while let Some(data) = stream.next().await {
+                    stdout.write_all(&data?)?;
+                }
+            }
+            stdout.flush()?;
+        }
+        Command::Put { force, local, path } => {
+            let (client, path) = client_for(&path)?;
+            let mut file = File::open(&local)?;
+            let mut writer = client
+                .create(&path, WriteOptions::default().overwrite(force))
+                .await?;
+            let mut buf = vec![0u8; PUT_BUFFER_SIZE];
+            loop {
+                let len = file.read(&mut buf)?;
+                if len == 0 {
+                    break;
+                }
+                writer.write(Bytes::copy_from_slice(&buf[..len])).await?;
+            }
+            writer.close().await?;
+        }
+        Command::Get { path, local } => {
+            let (client, path) = client_for(&path)?;
+            let reader = client.read(&path).await?;
+            let mut file = File::create(&local)?;
+            let mut stream = reader.read_range_stream(0, reader.file_length());
+            while let Some(data) = stream.next().await {
+                file.write_all(&data?)?;
+            }
+            file.flush()?;
+        }
+        Command::Rm { recursive, paths } => {
+            for path in paths {
+                let (client, path) = client_for(&path)?;
+                if !client.delete(&path, recursive).await? {
+                    return Err(HdfsError::FileNotFound(path));
+                }
+            }
+        }
+        Command::Mkdir { parents, paths } => {
+            for path in paths {
+                let (client, path) = client_for(&path)?;
+                client.mkdirs(&path, 0o777, parents).await?;
+            }
+        }
+        Command::Checksum { paths } => {
+            for path in paths {
+                let (client, resolved_path) = client_for(&path)?;
+                let checksum = client.get_file_checksum(&resolved_path).await?;
+                let hex: String = checksum
+                    .bytes
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                println!("{}\t{}\t{}", path, checksum.algorithm, hex);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The error with its sources, or its details if it has none, since the messages of most
+/// errors don't include what they apply to
+fn error_message(error: &HdfsError) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    if source.is_none() {
+        return format!("{} ({:?})", message, error);
+    }
+    while let Some(e) = source {
+        message.push_str(&format!(": {}", e));
+        source = e.source();
+    }
+    message
+}
+
+/// Creates a client for the file system of `path`, and returns the path within it
+fn client_for(path: &str) -> Result<(Client, String)> {
+    match Url::parse(path) {
+        Ok(url) if url.has_host() => {
+            let client = Client::new(&format!("{}://{}", url.scheme(), url.authority()))?;
+            Ok((client, url.path().to_string()))
+        }
+        _ => Ok((ClientBuilder::default().build()?, path.to_string())),
+    }
+}
+
+/// Formats a status like `hadoop fs -ls`
+fn format_status(status: &FileStatus) -> String {
+    let replication = if status.isdir {
+        "-".to_string()
+    } else {
+        status.replication.to_string()
+    };
+    format!(
+        "{} {:>3} {} {} {:>10} {} {}",
+        format_permission(status),
+        replication,
+        status.owner,
+        status.group,
+        status.length,
+        format_time(status.modification_time),
+        status.path
+    )
+}
+
+/// Formats the type and permission of a file like `drwxr-xr-x`
+fn format_permission(status: &FileStatus) -> String {
+    let file_type = if status.symlink.is_some() {
+        'l'
+    } else if status.isdir {
+        'd'
+    } else {
+        '-'
+    };
+    let mut formatted = String::from(file_type);
+    for shift in [6, 3, 0] {
+        let bits = (status.permission >> shift) & 0o7;
+        formatted.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        formatted.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        formatted.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    formatted
+}
+
+/// Formats milliseconds since the epoch as a `yyyy-MM-dd HH:mm` UTC time
+fn format_time(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let days = (seconds / 86400) as i64;
+    let minutes_of_day = (seconds % 86400) / 60;
+
+    // Converts days since the epoch to a civil date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes_of_day / 60,
+        minutes_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use hdfs_native::client::FileStatus;
+
+    use super::{format_permission, format_time};
+
+    #[test]
+    fn test_format_permission() {
+        let mut status = FileStatus {
+            path: "/dir".to_string(),
+            length: 0,
+            isdir: true,
+            permission: 0o755,
+            owner: "user".into(),
+            group: "supergroup".into(),
+            modification_time: 0,
+            access_time: 0,
+            replication: 0,
+            block_size: 0,
+            symlink: None,
+        };
+        assert_eq!(format_permission(&status), "drwxr-xr-x");
+
+        status.isdir = false;
+        status.permission = 0o640;
+        assert_eq!(format_permission(&status), "-rw-r-----");
+
+        status.symlink = Some("/target".to_string());
+        status.permission = 0o777;
+        assert_eq!(format_permission(&status), "lrwxrwxrwx");
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951_827_696_000), "2000-02-29 12:34");
+        assert_eq!(format_time(1_704_067_199_999), "2023-12-31 23:59");
+    }
+}
@@ -1,36 +1,83 @@
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use futures::stream::BoxStream;
 use futures::{stream, StreamExt};
+use md5::{Digest, Md5};
+use tokio::sync::Semaphore;
 use url::Url;
 
+use crate::common::buffer::BufferPool;
 use crate::common::config::{self, Configuration};
+use crate::common::glob::{self, GlobComponent};
+use crate::common::intern::StringInterner;
 use crate::ec::resolve_ec_policy;
 use crate::error::{HdfsError, Result};
 use crate::file::{FileReader, FileWriter};
+use crate::hdfs::datanode::{get_block_checksum, DatanodeConfig};
 use crate::hdfs::protocol::NamenodeProtocol;
 use crate::hdfs::proxy::NameServiceProxy;
+use crate::metrics::{Metrics, MetricsRecorder};
 use crate::proto::hdfs::hdfs_file_status_proto::FileType;
+#[cfg(feature = "kerberos")]
+use crate::security::kerberos::KerberosRenewer;
+#[cfg(feature = "kerberos")]
+use log::warn;
 
-use crate::proto::hdfs::HdfsFileStatusProto;
+use crate::proto::hdfs::acl_entry_proto::{AclEntryScopeProto, AclEntryTypeProto, FsActionProto};
+use crate::proto::hdfs::x_attr_proto::XAttrNamespaceProto;
+use crate::proto::hdfs::ErasureCodingPolicyState as ErasureCodingPolicyStateProto;
+use crate::proto::hdfs::{
+    AclEntryProto, AclStatusProto, BlockStoragePolicyProto, CacheDirectiveEntryProto,
+    CacheDirectiveInfoProto, CachePoolEntryProto, CachePoolInfoProto, ChecksumTypeProto,
+    ContentSummaryProto, EncryptionZoneProto, ErasureCodingPolicyProto, FileEncryptionInfoProto,
+    FsServerDefaultsProto, GetFsStatsResponseProto, HdfsFileStatusProto, LocatedBlockProto,
+    SafeModeActionProto, StorageTypeProto, StorageTypesProto, XAttrProto, XAttrSetFlagProto,
+};
+use crate::security::encryption::FileCipher;
+#[cfg(feature = "encryption")]
+use crate::security::encryption::KmsClient;
+use crate::security::sasl::AuthProvider;
+use crate::security::user::{Token, User};
 
 #[derive(Clone)]
 pub struct WriteOptions {
-    /// Block size. Default is retrieved from the server.
+    /// Block size. Default is `dfs.blocksize` from the client config, or retrieved from the
+    /// server if it isn't set.
     pub block_size: Option<u64>,
-    /// Replication factor. Default is retrieved from the server.
+    /// Replication factor. Default is `dfs.replication` from the client config, or retrieved
+    /// from the server if it isn't set.
     pub replication: Option<u32>,
     /// Unix file permission, defaults to 0o755. This is the raw octal
-    /// value represented in base 10.
+    /// value represented in base 10. The client umask is applied before
+    /// the file is created, see [Client::mkdirs].
     pub permission: u32,
-    /// Whether to overwrite the file, defaults to false. If true and the
-    /// file does not exist, it will result in an error.
+    /// Whether to overwrite the file, defaults to false. If false and the
+    /// file already exists, an [HdfsError::AlreadyExists] error is returned.
     pub overwrite: bool,
     /// Whether to create any missing parent directories, defaults to true. If false
     /// and the parent directory does not exist, an error will be returned.
     pub create_parent: bool,
+    /// Maximum size of the packets sent to the DataNodes. Default is
+    /// `dfs.client-write-packet-size` from the client config, or retrieved from the server if
+    /// it isn't set, which is 64 KiB unless it has been changed.
+    pub packet_size: Option<u32>,
+    /// Maximum number of packets sent to the DataNodes of a block that haven't been
+    /// acknowledged yet. Writes wait for acknowledgements once it's reached. A larger window
+    /// improves throughput when the DataNodes are far away, but buffers more packets in memory
+    /// to send again if the pipeline fails. Default is `dfs.client.write.max-packets-in-flight`
+    /// from the client config, or 80 if it isn't set.
+    pub max_packets_in_flight: Option<usize>,
+    /// Checksum computed for the data of the file. Default is `dfs.checksum.type` from the
+    /// client config, or retrieved from the server if it isn't set.
+    pub checksum_type: Option<ChecksumType>,
+    /// Number of bytes covered by each checksum. Default is `dfs.bytes-per-checksum` from the
+    /// client config, or retrieved from the server if it isn't set.
+    pub bytes_per_checksum: Option<u32>,
 }
 
 impl Default for WriteOptions {
@@ -41,6 +88,10 @@ impl Default for WriteOptions {
             permission: 0o755,
             overwrite: false,
             create_parent: true,
+            packet_size: None,
+            max_packets_in_flight: None,
+            checksum_type: None,
+            bytes_per_checksum: None,
         }
     }
 }
@@ -81,6 +132,179 @@ impl WriteOptions {
         self.create_parent = create_parent;
         self
     }
+
+    /// Set the size of the packets sent to the DataNodes
+    pub fn packet_size(mut self, packet_size: u32) -> Self {
+        self.packet_size = Some(packet_size);
+        self
+    }
+
+    /// Set the number of packets that can wait to be acknowledged by the DataNodes
+    pub fn max_packets_in_flight(mut self, max_packets_in_flight: usize) -> Self {
+        self.max_packets_in_flight = Some(max_packets_in_flight);
+        self
+    }
+
+    /// Set the checksum computed for the data of the new file
+    pub fn checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = Some(checksum_type);
+        self
+    }
+
+    /// Set the number of bytes covered by each checksum of the new file
+    pub fn bytes_per_checksum(mut self, bytes_per_checksum: u32) -> Self {
+        self.bytes_per_checksum = Some(bytes_per_checksum);
+        self
+    }
+}
+
+/// Checksum algorithm used for the data of a file, the same as Hadoop's `DataChecksum.Type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumType {
+    /// No checksums are computed or verified
+    Null,
+    Crc32,
+    Crc32c,
+}
+
+impl FromStr for ChecksumType {
+    type Err = HdfsError;
+
+    /// Parses the names used by `dfs.checksum.type`: `NULL`, `CRC32` or `CRC32C`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "NULL" => Ok(Self::Null),
+            "CRC32" => Ok(Self::Crc32),
+            "CRC32C" => Ok(Self::Crc32c),
+            _ => Err(HdfsError::InvalidArgument(format!(
+                "Unknown checksum type {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ChecksumType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Null => "NULL",
+            Self::Crc32 => "CRC32",
+            Self::Crc32c => "CRC32C",
+        };
+        f.write_str(name)
+    }
+}
+
+impl From<ChecksumType> for ChecksumTypeProto {
+    fn from(value: ChecksumType) -> Self {
+        match value {
+            ChecksumType::Null => ChecksumTypeProto::ChecksumNull,
+            ChecksumType::Crc32 => ChecksumTypeProto::ChecksumCrc32,
+            ChecksumType::Crc32c => ChecksumTypeProto::ChecksumCrc32c,
+        }
+    }
+}
+
+impl From<ChecksumTypeProto> for ChecksumType {
+    fn from(value: ChecksumTypeProto) -> Self {
+        match value {
+            ChecksumTypeProto::ChecksumNull => ChecksumType::Null,
+            ChecksumTypeProto::ChecksumCrc32 => ChecksumType::Crc32,
+            ChecksumTypeProto::ChecksumCrc32c => ChecksumType::Crc32c,
+        }
+    }
+}
+
+/// Action of [Client::set_safe_mode], the same as `hdfs dfsadmin -safemode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafeModeAction {
+    /// Enters safe mode, which only allows reads until it's left
+    Enter,
+    /// Leaves safe mode, which fails while the NameNode is still starting up without enough
+    /// block reports
+    Leave,
+    /// Only gets whether safe mode is on
+    Get,
+    /// Leaves safe mode even if the NameNode hasn't received enough block reports yet
+    ForceExit,
+}
+
+impl From<SafeModeAction> for SafeModeActionProto {
+    fn from(value: SafeModeAction) -> Self {
+        match value {
+            SafeModeAction::Enter => SafeModeActionProto::SafemodeEnter,
+            SafeModeAction::Leave => SafeModeActionProto::SafemodeLeave,
+            SafeModeAction::Get => SafeModeActionProto::SafemodeGet,
+            SafeModeAction::ForceExit => SafeModeActionProto::SafemodeForceExit,
+        }
+    }
+}
+
+/// How NameNode calls that fail with a transient error, like a `RetriableException` or a lost
+/// connection, are retried. Calls that modify the namespace are only retried if they failed
+/// before reaching the NameNode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of times a call is retried after the first attempt
+    pub max_attempts: usize,
+    /// Backoff before the first retry, which doubles for each following retry
+    pub backoff_base: Duration,
+    /// Maximum backoff between retries
+    pub backoff_max: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: usize, backoff_base: Duration, backoff_max: Duration) -> Self {
+        Self {
+            max_attempts,
+            backoff_base,
+            backoff_max,
+        }
+    }
+}
+
+/// How a client authenticates with NameNodes, see [ClientBuilder::with_authentication]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Authentication {
+    /// Send only the username from `$HADOOP_USER_NAME` or the current OS user, for clusters
+    /// without security. A delegation token is still used if one is available.
+    Simple,
+    /// Authenticate with Kerberos through SASL, which requires the `kerberos` feature
+    Kerberos,
+}
+
+impl Authentication {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Simple => "simple",
+            Self::Kerberos => "kerberos",
+        }
+    }
+}
+
+/// The timeouts and retry policy a client uses for NameNode calls, see [Client::rpc_settings]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcSettings {
+    /// How long to wait for the response to a call, or `None` to wait indefinitely
+    pub rpc_timeout: Option<Duration>,
+    /// How long to wait for a connection to a NameNode to be established
+    pub connect_timeout: Duration,
+    pub retry_policy: RetryPolicy,
+}
+
+impl RpcSettings {
+    fn new(config: &Configuration) -> Self {
+        let (backoff_base, backoff_max) = config.get_retry_sleep();
+        Self {
+            rpc_timeout: config.get_rpc_timeout(),
+            connect_timeout: config.get_connect_timeout(),
+            retry_policy: RetryPolicy::new(
+                config.get_retry_max_attempts(),
+                backoff_base,
+                backoff_max,
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -103,6 +327,16 @@ impl MountLink {
             protocol,
         }
     }
+    /// Convert a name service path back into a viewfs path if it's under the target of this link
+    fn unresolve(&self, path: &Path) -> Option<PathBuf> {
+        let relative_path = path.strip_prefix(&self.hdfs_path).ok()?;
+        if relative_path.components().count() == 0 {
+            Some(self.viewfs_path.clone())
+        } else {
+            Some(self.viewfs_path.join(relative_path))
+        }
+    }
+
     /// Convert a viewfs path into a name service path if it matches this link
     fn resolve(&self, path: &Path) -> Option<PathBuf> {
         if let Ok(relative_path) = path.strip_prefix(&self.viewfs_path) {
@@ -117,155 +351,989 @@ impl MountLink {
     }
 }
 
+// Same limit as the Java client
+const MAX_SYMLINK_DEPTH: usize = 32;
+// Most file info RPCs sent at once by `get_file_infos`
+const FILE_INFOS_CONCURRENCY: usize = 32;
+
+/// Resolves the `target` of the symlink at `link` to an absolute path, or returns `None` if the
+/// target is a URL that can't be followed by this client
+fn symlink_target(link: &str, target: &str) -> Option<String> {
+    if Url::parse(target).is_ok() {
+        return None;
+    }
+    let parent = link
+        .rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("");
+    Some(absolute_path(target, parent))
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.ends_with('/') {
+        format!("{}{}", parent, name)
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+/// Makes `path` absolute the way the Java client does. Absolute paths are returned unchanged.
+/// Otherwise `~` is expanded to `home_directory`, other relative paths are resolved against
+/// `home_directory`, and `.` and `..` segments are collapsed.
+pub(crate) fn absolute_path(path: &str, home_directory: &str) -> String {
+    if path.starts_with('/') {
+        return path.to_string();
+    }
+    let relative = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => path,
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in home_directory.split('/').chain(relative.split('/')) {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
 #[derive(Debug)]
 struct MountTable {
+    /// Sorted from the longest viewfs path to the shortest
     mounts: Vec<MountLink>,
-    fallback: MountLink,
+    fallback: Option<MountLink>,
 }
 
 impl MountTable {
-    fn resolve(&self, src: &str) -> (&MountLink, String) {
+    /// Resolves `src` to the link it is mounted on and the path on that name service. Fails
+    /// with [HdfsError::NotInMountpoint] if `src` isn't under a mount point and there's no
+    /// fallback link.
+    fn resolve(&self, src: &str) -> Result<(&MountLink, String)> {
         let path = Path::new(src);
-        for link in self.mounts.iter() {
-            if let Some(resolved) = link.resolve(path) {
-                return (link, resolved.to_string_lossy().into());
-            }
-        }
-        (
-            &self.fallback,
-            self.fallback
-                .resolve(path)
-                .unwrap()
-                .to_string_lossy()
-                .into(),
-        )
+        self.mounts
+            .iter()
+            .chain(self.fallback.iter())
+            .find_map(|link| {
+                link.resolve(path)
+                    .map(|resolved| (link, resolved.to_string_lossy().into()))
+            })
+            .ok_or_else(|| HdfsError::NotInMountpoint(src.to_string()))
+    }
+
+    /// The link for operations on a whole name service rather than a path, which is the
+    /// fallback, or the shortest mount point if there's no fallback
+    fn default_link(&self) -> &MountLink {
+        self.fallback
+            .as_ref()
+            .or(self.mounts.last())
+            .expect("mount table has no links")
     }
 }
 
 #[derive(Debug)]
 pub struct Client {
     mount_table: Arc<MountTable>,
+    config: Arc<Configuration>,
+    user: Arc<User>,
+    username: String,
+    home_directory: String,
+    buffer_pool: BufferPool,
+    metrics: Metrics,
+    #[cfg(feature = "kerberos")]
+    _kerberos_renewer: Option<Arc<KerberosRenewer>>,
+    // A client for each key provider URI, kept so their HTTP connections are reused
+    #[cfg(feature = "encryption")]
+    kms_clients: Mutex<HashMap<String, Arc<KmsClient>>>,
+}
+
+/// Builds a [Client] with settings applied on top of the Hadoop configs. Settings from the
+/// builder take precedence over the Hadoop config files, which take precedence over the
+/// defaults.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use hdfs_native::ClientBuilder;
+/// # use hdfs_native::Result;
+/// # fn main() -> Result<()> {
+/// let client = ClientBuilder::new("hdfs://localhost:9000")
+///     .with_rpc_timeout(Duration::from_secs(30))
+///     .with_read_ahead_blocks(2)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ClientBuilder {
+    url: Option<String>,
+    config: HashMap<String, String>,
+    config_dir: Option<PathBuf>,
+    user: Option<String>,
+    proxy_user: Option<String>,
+    tokens: Vec<Token>,
+    auth: Option<Arc<dyn AuthProvider>>,
+    metrics: Metrics,
+}
+
+impl ClientBuilder {
+    /// Creates a builder for a client to `url`, see [Client::new]. Use [ClientBuilder::default]
+    /// to connect to `fs.defaultFS` instead.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: Some(url.to_string()),
+            config: HashMap::new(),
+            config_dir: None,
+            user: None,
+            proxy_user: None,
+            tokens: Vec::new(),
+            auth: None,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Sets config values, which take precedence over values from the Hadoop config files
+    pub fn with_config(mut self, config: HashMap<String, String>) -> Self {
+        self.config.extend(config);
+        self
+    }
+
+    /// Loads `core-site.xml` and `hdfs-site.xml` from `dir` instead of `$HADOOP_CONF_DIR`, so
+    /// clients for different clusters can use different config files
+    pub fn with_config_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.config_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Acts as `user` with simple authentication, instead of `$HADOOP_USER_NAME` or the current
+    /// OS user
+    pub fn with_user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Impersonates `user` after authenticating as the real user, instead of
+    /// `$HADOOP_PROXY_USER`, like `UserGroupInformation.createProxyUser`. Every call is then
+    /// checked against the permissions of `user`. The NameNode must allow the real user to
+    /// impersonate it with the `hadoop.proxyuser.*` settings, or calls fail with
+    /// [HdfsError::ProxyUserNotAllowed].
+    pub fn with_proxy_user(mut self, user: &str) -> Self {
+        self.proxy_user = Some(user.to_string());
+        self
+    }
+
+    /// Authenticates with `token` when connecting to the NameNode it was issued for, which takes
+    /// precedence over the tokens loaded from `$HADOOP_TOKEN_FILE_LOCATION`
+    pub fn with_delegation_token(mut self, token: Token) -> Self {
+        self.tokens.push(token);
+        self
+    }
+
+    /// Chooses how connections to NameNodes authenticate with `provider`, for SASL mechanisms
+    /// the client doesn't support itself. The SASL handshake is then used even if
+    /// `hadoop.security.authentication` is `simple`. Without a provider, the client uses
+    /// [DefaultAuth](crate::DefaultAuth).
+    pub fn with_auth(mut self, provider: Arc<dyn AuthProvider>) -> Self {
+        self.auth = Some(provider);
+        self
+    }
+
+    /// Sets how many blocks readers fetch in the background ahead of the block being consumed,
+    /// see [FileReader::set_read_ahead_blocks]. Defaults to 1, and 0 disables read-ahead for
+    /// random access workloads.
+    pub fn with_read_ahead_blocks(mut self, blocks: usize) -> Self {
+        self.config
+            .insert(config::READ_AHEAD_BLOCKS.to_string(), blocks.to_string());
+        self
+    }
+
+    /// Sets how many blocks are fetched at the same time when a range that spans several blocks
+    /// is read into a single buffer, see [FileReader::set_read_parallelism]. Defaults to 1,
+    /// which reads the blocks in order.
+    pub fn with_read_parallelism(mut self, blocks: usize) -> Self {
+        self.config
+            .insert(config::READ_PARALLELISM.to_string(), blocks.to_string());
+        self
+    }
+
+    /// Sets whether checksums of the data that's read are sent by the DataNodes and verified.
+    /// Defaults to true. Disabling it saves the bandwidth and CPU time spent on checksums where
+    /// the network and disks are trusted, but corrupt data is then returned without an error.
+    pub fn with_verify_checksums(mut self, verify: bool) -> Self {
+        self.config
+            .insert(config::VERIFY_CHECKSUM.to_string(), verify.to_string());
+        self
+    }
+
+    /// Sets whether DataNodes are connected to by the hostnames they registered with instead
+    /// of the IP addresses the NameNode returns, overriding `dfs.client.use.datanode.hostname`.
+    /// Defaults to false. This is needed when the DataNodes' IP addresses aren't reachable from
+    /// the client, such as for clusters in containers or behind NAT.
+    pub fn with_use_datanode_hostname(mut self, use_hostname: bool) -> Self {
+        self.config.insert(
+            config::USE_DATANODE_HOSTNAME.to_string(),
+            use_hostname.to_string(),
+        );
+        self
+    }
+
+    /// Sets whether a [FileWriter](crate::file::FileWriter) that is dropped without being closed
+    /// recovers the lease on its file in the background, so the file is closed right away instead
+    /// of once the lease's hard limit expires. Data written after the last flush is lost either
+    /// way. Defaults to false.
+    pub fn with_recover_lease_on_drop(mut self, recover: bool) -> Self {
+        self.config.insert(
+            config::RECOVER_LEASE_ON_DROP.to_string(),
+            recover.to_string(),
+        );
+        self
+    }
+
+    /// Keeps up to `bytes` of the buffers that data read from DataNodes is received into, and
+    /// reuses them for later reads once the [Bytes] read into them are dropped. This saves
+    /// allocating a new buffer for every packet when many files are read at once. Defaults to
+    /// 0, which disables the pool.
+    pub fn with_read_buffer_pool_size(mut self, bytes: usize) -> Self {
+        self.config
+            .insert(config::READ_BUFFER_POOL_SIZE.to_string(), bytes.to_string());
+        self
+    }
+
+    /// Authenticates as `principal` using the keys in `keytab`, instead of relying on an
+    /// existing ticket in the Kerberos ticket cache. The login is done with `kinit` when the
+    /// client is built, and repeated in the background before the ticket expires for as long
+    /// as the client is alive.
+    ///
+    /// The ticket is stored in a ticket cache of the client's own, which is removed once the
+    /// client is dropped, so clients in the same process can log in as different principals.
+    #[cfg(feature = "kerberos")]
+    pub fn with_kerberos_keytab(mut self, keytab: impl AsRef<Path>, principal: &str) -> Self {
+        self.config.insert(
+            config::KERBEROS_KEYTAB.to_string(),
+            keytab.as_ref().to_string_lossy().into(),
+        );
+        self.config.insert(
+            config::KERBEROS_PRINCIPAL.to_string(),
+            principal.to_string(),
+        );
+        self
+    }
+
+    /// Sets how the client authenticates, instead of `hadoop.security.authentication`. If
+    /// neither is set, the method is negotiated with the NameNode through SASL.
+    pub fn with_authentication(mut self, authentication: Authentication) -> Self {
+        self.config.insert(
+            config::SECURITY_AUTHENTICATION.to_string(),
+            authentication.as_str().to_string(),
+        );
+        self
+    }
+
+    /// Sets how long to wait for the response to a NameNode call before failing with a
+    /// timeout. Defaults to 2 minutes, and a zero duration waits indefinitely.
+    pub fn with_rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.config.insert(
+            config::RPC_TIMEOUT.to_string(),
+            timeout.as_millis().to_string(),
+        );
+        self
+    }
+
+    /// Sets how long to wait for a connection to a NameNode to be established. Defaults to 20
+    /// seconds.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.insert(
+            config::CONNECT_TIMEOUT.to_string(),
+            timeout.as_millis().to_string(),
+        );
+        self
+    }
+
+    /// Sets how long a NameNode connection can go without sending anything before a ping is
+    /// sent to keep it open through firewalls and load balancers that drop idle connections.
+    /// Defaults to 1 minute, and a zero duration disables pings.
+    pub fn with_ping_interval(mut self, interval: Duration) -> Self {
+        self.config.insert(
+            config::PING_INTERVAL.to_string(),
+            interval.as_millis().to_string(),
+        );
+        self
+    }
+
+    /// Sets how long a NameNode connection is idle before the OS starts sending TCP keepalive
+    /// probes, which detect a connection that was dropped without being closed. Defaults to the
+    /// OS setting, which is usually 2 hours.
+    pub fn with_tcp_keepalive(mut self, time: Duration) -> Self {
+        self.config.insert(
+            config::TCP_KEEPALIVE_TIME.to_string(),
+            time.as_millis().to_string(),
+        );
+        self
+    }
+
+    /// Sets how long calls rejected because the NameNode is in safe mode are retried before
+    /// failing with [HdfsError::SafeMode]. Defaults to zero, which fails immediately.
+    pub fn with_safe_mode_wait(mut self, wait: Duration) -> Self {
+        self.config.insert(
+            config::SAFE_MODE_WAIT.to_string(),
+            wait.as_millis().to_string(),
+        );
+        self
+    }
+
+    /// Sets how NameNode calls are retried after transient failures. Defaults to 10 retries
+    /// with a backoff from 100 milliseconds up to 5 seconds.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.config.insert(
+            config::RETRY_MAX_ATTEMPTS.to_string(),
+            retry_policy.max_attempts.to_string(),
+        );
+        self.config.insert(
+            config::RETRY_SLEEP_BASE.to_string(),
+            retry_policy.backoff_base.as_millis().to_string(),
+        );
+        self.config.insert(
+            config::RETRY_SLEEP_MAX.to_string(),
+            retry_policy.backoff_max.as_millis().to_string(),
+        );
+        self
+    }
+
+    /// Sends metrics about NameNode calls, DataNode transfers and failures to `recorder`. No
+    /// metrics are recorded by default.
+    pub fn with_metrics(mut self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        self.metrics = Metrics::new(recorder);
+        self
+    }
+
+    /// Loads the Hadoop configs and creates the client. Fails if the configs can't be loaded,
+    /// the URL is invalid, or no URL was given and `fs.defaultFS` isn't set.
+    pub fn build(self) -> Result<Client> {
+        let (url, config, user, metrics) = self.load()?;
+        Client::with_config(&url, config, metrics, user)
+    }
+
+    /// Loads the Hadoop configs like [ClientBuilder::build] and creates a
+    /// [WebHdfsClient](crate::webhdfs::WebHdfsClient) for a `webhdfs://` or `swebhdfs://` URL.
+    /// Metrics aren't recorded for WebHDFS requests.
+    #[cfg(feature = "webhdfs")]
+    pub fn build_webhdfs(self) -> Result<crate::webhdfs::WebHdfsClient> {
+        let (url, config, user, _) = self.load()?;
+        crate::webhdfs::WebHdfsClient::with_config(&url, config, user)
+    }
+
+    fn load(self) -> Result<(String, Configuration, User, Metrics)> {
+        let mut config = match self.config_dir {
+            Some(dir) => Configuration::from_dir(&dir)?,
+            None => Configuration::new()?,
+        };
+        config.set_all(self.config);
+        let url = match self.url {
+            Some(url) => url,
+            None => config
+                .get(config::DEFAULT_FS)
+                .ok_or(HdfsError::InvalidArgument(format!(
+                    "No {} setting found",
+                    config::DEFAULT_FS
+                )))?,
+        };
+        let user = User::new(self.user, self.proxy_user, self.tokens).with_auth(self.auth);
+        Ok((url, config, user, self.metrics))
+    }
 }
 
 impl Client {
     /// Creates a new HDFS Client. The URL must include the protocol and host, and optionally a port.
     /// If a port is included, the host is treated as a single NameNode. If no port is included, the
-    /// host is treated as a name service that will be resolved using the HDFS config.
+    /// host is treated as a name service that will be resolved using the HDFS config. A URL with
+    /// any other scheme, or without a host, fails with [HdfsError::InvalidUrl]. `webhdfs://` URLs
+    /// need a `WebHdfsClient` instead, built with `ClientBuilder::build_webhdfs` when the
+    /// `webhdfs` feature is enabled.
+    ///
+    /// This is the same as `ClientBuilder::new(url).build()`, see [ClientBuilder] for other
+    /// settings.
     pub fn new(url: &str) -> Result<Self> {
-        let parsed_url = Url::parse(url)?;
-        Self::with_config(&parsed_url, Configuration::new()?)
+        ClientBuilder::new(url).build()
     }
 
     pub fn new_with_config(url: &str, config: HashMap<String, String>) -> Result<Self> {
-        let parsed_url = Url::parse(url)?;
-        Self::with_config(&parsed_url, Configuration::from(config))
+        Self::with_config(
+            url,
+            Configuration::from(config),
+            Metrics::default(),
+            User::new(None, None, Vec::new()),
+        )
     }
 
-    fn with_config(url: &Url, config: Configuration) -> Result<Self> {
-        if !url.has_host() {
-            return Err(HdfsError::InvalidArgument(
-                "URL must contain a host".to_string(),
+    fn with_config(url: &str, config: Configuration, metrics: Metrics, user: User) -> Result<Self> {
+        let url = parse_url(url, &config)?;
+
+        #[cfg(feature = "kerberos")]
+        let kerberos_renewer = KerberosRenewer::start(&config)?;
+        #[cfg(feature = "kerberos")]
+        let user = user.with_kerberos_ccache(
+            kerberos_renewer
+                .as_ref()
+                .and_then(|renewer| renewer.ccache())
+                .map(String::from),
+        );
+        let user = Arc::new(user);
+
+        let mount_table = if url.scheme() == "viewfs" {
+            Self::build_mount_table(url.host_str().unwrap(), &config, &metrics, &user)?
+        } else {
+            let proxy = NameServiceProxy::new(&url, &config, metrics.clone(), Arc::clone(&user));
+            let protocol = Arc::new(NamenodeProtocol::new(
+                proxy,
+                config.get_lease_renewal_interval(),
             ));
+
+            MountTable {
+                mounts: Vec::new(),
+                fallback: Some(MountLink::new("/", "/", protocol)),
+            }
+        };
+
+        let username = Self::get_username(&config, &user)?;
+        Ok(Self {
+            mount_table: Arc::new(mount_table),
+            home_directory: format!("/user/{}", username),
+            username,
+            user,
+            buffer_pool: BufferPool::new(config.get_read_buffer_pool_size()),
+            metrics,
+            #[cfg(feature = "kerberos")]
+            _kerberos_renewer: kerberos_renewer,
+            #[cfg(feature = "encryption")]
+            kms_clients: Mutex::new(HashMap::new()),
+            config: Arc::new(config),
+        })
+    }
+
+    /// The short name of the user the client acts as. With Kerberos this is the proxy user from
+    /// `$HADOOP_PROXY_USER` or the short name of the logged in principal, and otherwise it is
+    /// `$HADOOP_USER_NAME` or the current OS user.
+    #[cfg_attr(not(feature = "kerberos"), allow(unused_variables))]
+    pub(crate) fn get_username(config: &Configuration, user: &User) -> Result<String> {
+        #[cfg(feature = "kerberos")]
+        if config.get_kerberos_enabled() {
+            match crate::security::gssapi::get_default_principal(user.kerberos_ccache()) {
+                Ok(principal) => {
+                    let user_info =
+                        user.get_proxied_user_info(User::get_user_from_principal(&principal));
+                    if let Some(user) = user_info.effective_user.or(user_info.real_user) {
+                        return Ok(user);
+                    }
+                }
+                Err(e) => warn!("Failed to get the Kerberos principal: {:?}", e),
+            }
         }
+        Ok(user.get_simpler_user()?.effective_user.unwrap())
+    }
 
-        match url.scheme() {
-            "hdfs" => {
-                let proxy = NameServiceProxy::new(url, &config);
-                let protocol = Arc::new(NamenodeProtocol::new(proxy));
+    /// The home directory of the user, `/user/<username>`, which relative paths are resolved
+    /// against
+    pub fn home_directory(&self) -> &str {
+        &self.home_directory
+    }
+
+    /// The effective timeouts and retry policy for NameNode calls, from the builder settings
+    /// and the Hadoop configs
+    pub fn rpc_settings(&self) -> RpcSettings {
+        RpcSettings::new(&self.config)
+    }
+
+    fn datanode_config(&self) -> DatanodeConfig {
+        DatanodeConfig::new(
+            &self.config,
+            Arc::clone(&self.user),
+            self.buffer_pool.clone(),
+            self.metrics.clone(),
+        )
+    }
+
+    /// The config of DataNode connections for a file on the NameNode of `protocol`, with the
+    /// key they're encrypted with if the NameNode requires it
+    async fn file_datanode_config(&self, protocol: &NamenodeProtocol) -> Result<DatanodeConfig> {
+        let mut datanode_config = self.datanode_config();
+        datanode_config.encryption_key = protocol.get_data_encryption_key().await?;
+        Ok(datanode_config)
+    }
 
-                let mount_table = Arc::new(MountTable {
-                    mounts: Vec::new(),
-                    fallback: MountLink::new("/", "/", protocol),
-                });
-                Ok(Self { mount_table })
+    /// Applies the packet settings of `write_options`, or the client config when they aren't
+    /// set, to the config of a writer's DataNode connections and the server defaults it uses
+    async fn writer_config(
+        &self,
+        protocol: &NamenodeProtocol,
+        write_options: &WriteOptions,
+        server_defaults: &mut FsServerDefaultsProto,
+    ) -> Result<DatanodeConfig> {
+        if let Some(packet_size) = write_options
+            .packet_size
+            .or(self.config.get_write_packet_size())
+        {
+            server_defaults.write_packet_size = packet_size;
+        }
+        let mut datanode_config = self.file_datanode_config(protocol).await?;
+        if let Some(max_packets_in_flight) = write_options.max_packets_in_flight {
+            if max_packets_in_flight == 0 {
+                return Err(HdfsError::InvalidArgument(
+                    "Max packets in flight must be greater than 0".to_string(),
+                ));
             }
-            "viewfs" => Ok(Self {
-                mount_table: Arc::new(Self::build_mount_table(url.host_str().unwrap(), &config)?),
-            }),
-            _ => Err(HdfsError::InvalidArgument(
-                "Only `hdfs` and `viewfs` schemes are supported".to_string(),
-            )),
+            datanode_config.write_max_packets = max_packets_in_flight;
+        }
+        Ok(datanode_config)
+    }
+
+    fn absolute_path(&self, path: &str) -> String {
+        absolute_path(path, &self.home_directory)
+    }
+
+    /// Decrypts the key of a file in an encryption zone with the KMS, for encrypting and
+    /// decrypting its data. The KMS is the one from the client config, or otherwise the one
+    /// the NameNode uses.
+    #[cfg(feature = "encryption")]
+    async fn file_cipher(
+        &self,
+        protocol: &NamenodeProtocol,
+        encryption_info: &FileEncryptionInfoProto,
+    ) -> Result<FileCipher> {
+        let key_provider_uri = match self.config.get_key_provider_uri() {
+            Some(uri) => uri,
+            None => protocol
+                .get_server_defaults()
+                .await?
+                .key_provider_uri
+                .filter(|uri| !uri.is_empty())
+                .ok_or(HdfsError::KmsError(
+                    "No key provider is configured".to_string(),
+                ))?,
+        };
+        let kms = self.kms_client(&key_provider_uri)?;
+        FileCipher::new(encryption_info, &kms).await
+    }
+
+    /// The KMS client for `key_provider_uri`, which is created the first time it's needed
+    #[cfg(feature = "encryption")]
+    fn kms_client(&self, key_provider_uri: &str) -> Result<Arc<KmsClient>> {
+        let mut kms_clients = self.kms_clients.lock().unwrap();
+        if let Some(kms) = kms_clients.get(key_provider_uri) {
+            return Ok(Arc::clone(kms));
         }
+        let kms = Arc::new(KmsClient::new(
+            key_provider_uri,
+            &self.config,
+            Arc::clone(&self.user),
+        )?);
+        kms_clients.insert(key_provider_uri.to_string(), Arc::clone(&kms));
+        Ok(kms)
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    async fn file_cipher(
+        &self,
+        _protocol: &NamenodeProtocol,
+        _encryption_info: &FileEncryptionInfoProto,
+    ) -> Result<FileCipher> {
+        Err(HdfsError::UnsupportedFeature(
+            "Encryption zones require the encryption feature".to_string(),
+        ))
     }
 
-    fn build_mount_table(host: &str, config: &Configuration) -> Result<MountTable> {
+    fn build_mount_table(
+        host: &str,
+        config: &Configuration,
+        metrics: &Metrics,
+        user: &Arc<User>,
+    ) -> Result<MountTable> {
         let mut mounts: Vec<MountLink> = Vec::new();
         let mut fallback: Option<MountLink> = None;
 
         for (viewfs_path, hdfs_url) in config.get_mount_table(host).iter() {
-            let url = Url::parse(hdfs_url)?;
+            let url = Url::parse(hdfs_url).map_err(|e| {
+                HdfsError::InvalidMountTable(format!("Invalid link URL {}: {}", hdfs_url, e))
+            })?;
             if !url.has_host() {
-                return Err(HdfsError::InvalidArgument(
-                    "URL must contain a host".to_string(),
-                ));
+                return Err(HdfsError::InvalidMountTable(format!(
+                    "Link URL {} must contain a host",
+                    hdfs_url
+                )));
             }
             if url.scheme() != "hdfs" {
-                return Err(HdfsError::InvalidArgument(
-                    "Only hdfs mounts are supported for viewfs".to_string(),
-                ));
+                return Err(HdfsError::InvalidMountTable(format!(
+                    "Only hdfs links are supported for viewfs, found {}",
+                    hdfs_url
+                )));
             }
-            let proxy = NameServiceProxy::new(&url, config);
-            let protocol = Arc::new(NamenodeProtocol::new(proxy));
+            let proxy = NameServiceProxy::new(&url, config, metrics.clone(), Arc::clone(user));
+            let protocol = Arc::new(NamenodeProtocol::new(
+                proxy,
+                config.get_lease_renewal_interval(),
+            ));
 
             if let Some(prefix) = viewfs_path {
                 mounts.push(MountLink::new(prefix, url.path(), protocol));
             } else {
                 if fallback.is_some() {
-                    return Err(HdfsError::InvalidArgument(
-                        "Multiple viewfs fallback links found".to_string(),
-                    ));
+                    return Err(HdfsError::InvalidMountTable(format!(
+                        "Multiple fallback links found for {}",
+                        host
+                    )));
                 }
                 fallback = Some(MountLink::new("/", url.path(), protocol));
             }
         }
 
-        if let Some(fallback) = fallback {
-            // Sort the mount table from longest viewfs path to shortest. This makes sure more specific paths are considered first.
-            mounts.sort_by_key(|m| m.viewfs_path.components().count());
-            mounts.reverse();
-
-            Ok(MountTable { mounts, fallback })
-        } else {
-            Err(HdfsError::InvalidArgument(
-                "No viewfs fallback mount found".to_string(),
-            ))
+        if mounts.is_empty() && fallback.is_none() {
+            return Err(HdfsError::InvalidMountTable(format!(
+                "No links found for {}",
+                host
+            )));
         }
+
+        // Sort the mount table from longest viewfs path to shortest. This makes sure more specific paths are considered first.
+        mounts.sort_by_key(|m| m.viewfs_path.components().count());
+        mounts.reverse();
+
+        Ok(MountTable { mounts, fallback })
     }
 
-    /// Retrieve the file status for the file at `path`.
+    /// Retrieve the file status for the file or directory at `path` with a single `getFileInfo`
+    /// call to the NameNode. Returns [HdfsError::FileNotFound] if `path` doesn't exist.
+    ///
+    /// Symlinks anywhere in `path` are followed, like `FileSystem.resolvePath` in the Java
+    /// client, and the status of the final target is returned with `path` set to the target's
+    /// path. A symlink whose target is a URL, such as one pointing to the local file system,
+    /// can't be followed. If it's the last component of `path`, the status of the symlink
+    /// itself is returned instead, otherwise [HdfsError::UnresolvedLink] is returned. Use
+    /// [Client::get_file_link_info] to not follow a symlink at the end of `path`.
     pub async fn get_file_info(&self, path: &str) -> Result<FileStatus> {
-        let (link, resolved_path) = self.mount_table.resolve(path);
-        match link.protocol.get_file_info(&resolved_path).await?.fs {
-            Some(status) => Ok(FileStatus::from(status, path)),
-            None => Err(HdfsError::FileNotFound(path.to_string())),
+        let mut path = self.absolute_path(path);
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            let (link, resolved_path) = self.mount_table.resolve(&path)?;
+            match link.protocol.get_file_info(&resolved_path).await {
+                Ok(response) => {
+                    return match response.fs {
+                        Some(status) => Ok(FileStatus::from(status, &path)),
+                        None => Err(HdfsError::FileNotFound(path)),
+                    }
+                }
+                Err(HdfsError::UnresolvedLink(msg)) => {
+                    // The NameNode doesn't say which component is the symlink, so find the
+                    // first one and replace it with its target
+                    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+                    let mut prefix = "/".to_string();
+                    let mut next = None;
+                    for (i, component) in components.iter().enumerate() {
+                        prefix = join_path(&prefix, component);
+                        let status = self.get_file_link_info(&prefix).await?;
+                        let Some(target) = status.symlink.as_deref() else {
+                            continue;
+                        };
+                        let rest = &components[i + 1..];
+                        match symlink_target(&prefix, target) {
+                            Some(target) => {
+                                next = Some(rest.iter().fold(target, |p, c| join_path(&p, c)))
+                            }
+                            None if rest.is_empty() => return Ok(status),
+                            None => return Err(HdfsError::UnresolvedLink(msg)),
+                        }
+                        break;
+                    }
+                    match next {
+                        Some(next) => path = next,
+                        None => return Err(HdfsError::UnresolvedLink(msg)),
+                    }
+                }
+                Err(e) => return Err(e),
+            }
         }
+        Err(HdfsError::InvalidPath(format!(
+            "Too many levels of symlinks resolving {}",
+            path
+        )))
     }
 
-    /// Retrives a list of all files in directories located at `path`. Wrapper around `list_status_iter` that
-    /// returns Err if any part of the stream fails, or Ok if all file statuses were found successfully.
-    pub async fn list_status(&self, path: &str, recursive: bool) -> Result<Vec<FileStatus>> {
-        let iter = self.list_status_iter(path, recursive);
-        let statuses = iter
-            .into_stream()
-            .collect::<Vec<Result<FileStatus>>>()
-            .await;
+    /// Retrieve the file statuses of `paths` the same way as [Client::get_file_info], with up
+    /// to 32 calls to the NameNode in flight at once. The results are in the same order as
+    /// `paths`, with `None` for paths that don't exist. An error for one path doesn't affect the
+    /// others.
+    pub async fn get_file_infos(&self, paths: &[&str]) -> Vec<Result<Option<FileStatus>>> {
+        stream::iter(paths.iter().map(|path| async move {
+            match self.get_file_info(path).await {
+                Ok(status) => Ok(Some(status)),
+                Err(HdfsError::FileNotFound(_)) => Ok(None),
+                Err(e) => Err(e),
+            }
+        }))
+        .buffered(FILE_INFOS_CONCURRENCY)
+        .collect()
+        .await
+    }
 
-        let mut resolved_statues = Vec::<FileStatus>::with_capacity(statuses.len());
-        for status in statuses.into_iter() {
-            resolved_statues.push(status?);
+    /// Retrieve the file status for the file, directory or symlink at `path` without following
+    /// a symlink at the end of `path`. For a symlink, the returned status has
+    /// [FileStatus::symlink] set to its target.
+    pub async fn get_file_link_info(&self, path: &str) -> Result<FileStatus> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        match link.protocol.get_file_link_info(&resolved_path).await?.fs {
+            Some(status) => Ok(FileStatus::from(status, path)),
+            None => Err(HdfsError::FileNotFound(path.to_string())),
         }
-
-        Ok(resolved_statues)
     }
 
-    /// Retrives an iterator of all files in directories located at `path`.
-    pub fn list_status_iter(&self, path: &str, recursive: bool) -> ListStatusIterator {
-        ListStatusIterator::new(path.to_string(), Arc::clone(&self.mount_table), recursive)
+    /// Creates a symlink at `link` pointing to `target`. The target is stored as given, so it
+    /// can be a relative path, which is resolved against the symlink's parent directory, or a
+    /// URL outside of HDFS, and it doesn't need to exist. Missing parent directories of `link`
+    /// are created if `create_parent` is true.
+    pub async fn create_symlink(
+        &self,
+        link: &str,
+        target: &str,
+        create_parent: bool,
+    ) -> Result<()> {
+        let link_path = &self.absolute_path(link);
+        let (mount, resolved_path) = self.mount_table.resolve(link_path)?;
+        mount
+            .protocol
+            .create_symlink(
+                target,
+                &resolved_path,
+                0o777 & !self.config.get_umask(),
+                create_parent,
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Returns the target of the symlink at `path`, exactly as it was given when the symlink
+    /// was created.
+    pub async fn get_link_target(&self, path: &str) -> Result<String> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .get_link_target(&resolved_path)
+            .await?
+            .target_path
+            .ok_or(HdfsError::FileNotFound(path.to_string()))
+    }
+
+    /// Returns whether a file or directory exists at `path`, using a single `getFileInfo` call.
+    /// Errors are only returned for failures other than `path` not existing.
+    pub async fn exists(&self, path: &str) -> Result<bool> {
+        match self.get_file_info(path).await {
+            Ok(_) => Ok(true),
+            Err(HdfsError::FileNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the locations of the blocks of the file at `path` that overlap the byte range
+    /// `[offset, offset + length)`, in order of their offset in the file.
+    ///
+    /// Like opening a file in the Java client, this `getBlockLocations` call updates the access
+    /// time of the file if it's older than the NameNode's `dfs.namenode.accesstime.precision`,
+    /// which is a write to the edit log.
+    pub async fn get_block_locations(
+        &self,
+        path: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<Vec<BlockLocation>> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        let locations = link
+            .protocol
+            .get_block_locations(&resolved_path, offset, length)
+            .await?
+            .locations
+            .ok_or(HdfsError::FileNotFound(path.to_string()))?;
+
+        let end = offset.saturating_add(length);
+        Ok(locations
+            .blocks
+            .into_iter()
+            .map(BlockLocation::from)
+            .filter(|block| block.offset < end && block.offset + block.length > offset)
+            .collect())
+    }
+
+    /// Retrieve the content summary for the file or directory at `path`. This is computed by the
+    /// namenode in a single RPC, so it is much faster than recursively listing a directory.
+    pub async fn get_content_summary(&self, path: &str) -> Result<ContentSummary> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        Ok(link
+            .protocol
+            .get_content_summary(&resolved_path)
+            .await?
+            .summary
+            .into())
+    }
+
+    /// Retrives a list of all files in directories located at `path`. Wrapper around `list_status_iter` that
+    /// returns Err if any part of the stream fails, or Ok if all file statuses were found successfully.
+    pub async fn list_status(&self, path: &str, recursive: bool) -> Result<Vec<FileStatus>> {
+        let iter = self.list_status_iter(path, recursive);
+        let statuses = iter
+            .into_stream()
+            .collect::<Vec<Result<FileStatus>>>()
+            .await;
+
+        let mut resolved_statues = Vec::<FileStatus>::with_capacity(statuses.len());
+        for status in statuses.into_iter() {
+            resolved_statues.push(status?);
+        }
+
+        Ok(resolved_statues)
+    }
+
+    /// Lists the directory at `path` along with the locations of the blocks of every file, like
+    /// `listLocatedStatus` in Hadoop's `FileSystem`. The locations are returned by the same
+    /// paginated listing RPCs, so planning reads of many files doesn't need a call to
+    /// [Client::get_block_locations] for each of them. The NameNode returns fewer entries per
+    /// page when it includes locations.
+    pub async fn list_status_with_locations(&self, path: &str) -> Result<Vec<LocatedFileStatus>> {
+        let path = self.absolute_path(path);
+        let mut iter = DirListingIterator::new(path, &self.mount_table, false).with_locations();
+        let mut statuses = Vec::new();
+        while let Some(status) = iter.next_located().await {
+            statuses.push(status?);
+        }
+        Ok(statuses)
+    }
+
+    /// Retrives an iterator of all files in directories located at `path`. Listings are fetched
+    /// lazily one page at a time, with the page size controlled by the namenode's `dfs.ls.limit`
+    /// setting (1000 by default), so arbitrarily large directories can be listed without loading
+    /// everything into memory. Sub-directories that are removed during a recursive listing are
+    /// skipped.
+    pub fn list_status_iter(&self, path: &str, recursive: bool) -> ListStatusIterator {
+        let path = &self.absolute_path(path);
+        ListStatusIterator::new(path.to_string(), Arc::clone(&self.mount_table), recursive)
+    }
+
+    /// Recursively lists all files and directories under `path` in depth-first order, like
+    /// `hadoop fs -ls -R`. Each directory is listed with the same paginated listing as
+    /// [Client::list_status_iter], and every [FileStatus] contains the full path of the entry.
+    ///
+    /// The first page of up to `concurrency` sub-directories is fetched ahead of time while
+    /// walking the tree, and no more than `concurrency` listing RPCs are in flight at once. A
+    /// `concurrency` of 1 lists one directory at a time.
+    pub fn list_status_recursive(
+        &self,
+        path: &str,
+        concurrency: usize,
+    ) -> BoxStream<'static, Result<FileStatus>> {
+        let path = &self.absolute_path(path);
+        let concurrency = usize::max(concurrency, 1);
+        let iter = DirListingIterator::new(path.to_string(), &self.mount_table, false);
+        walk_dir(
+            iter,
+            Arc::clone(&self.mount_table),
+            Arc::new(Semaphore::new(concurrency)),
+            concurrency,
+        )
+    }
+
+    /// Returns the files and directories matching the glob `pattern`, sorted by path, or an empty
+    /// list if nothing matches. The syntax is the same as Hadoop's `globStatus`: `*` matches any
+    /// characters within a path component, `?` matches one character, `[abc]`, `[a-z]` and
+    /// `[!a]` match one of or none of a set of characters, `{a,b}` matches any of the
+    /// alternatives, which can contain `/`, and `\` escapes the next character. Like Hadoop,
+    /// wildcards match hidden files starting with `.` or `_` too.
+    ///
+    /// The pattern is matched one path component at a time, so only directories that match
+    /// the pattern so far are listed, and components without wildcards aren't listed at all.
+    /// Symlinks to directories aren't descended into.
+    pub async fn glob(&self, pattern: &str) -> Result<Vec<FileStatus>> {
+        let pattern = self.absolute_path(pattern);
+        let mut statuses = Vec::new();
+        for expanded in glob::expand(&pattern)? {
+            let components = expanded
+                .split('/')
+                .filter(|c| !c.is_empty())
+                .map(GlobComponent::parse)
+                .collect::<Result<Vec<_>>>()?;
+            statuses.extend(self.glob_components(&components).await?);
+        }
+        statuses.sort_by(|a, b| a.path.cmp(&b.path));
+        statuses.dedup_by(|a, b| a.path == b.path);
+        Ok(statuses)
+    }
+
+    async fn glob_components(&self, components: &[GlobComponent]) -> Result<Vec<FileStatus>> {
+        // Paths matching the components so far, with their status if they came from a listing
+        let mut candidates: Vec<(String, Option<FileStatus>)> = vec![("/".to_string(), None)];
+        for (i, component) in components.iter().enumerate() {
+            let last = i == components.len() - 1;
+            let mut matches = Vec::new();
+            for (path, status) in candidates {
+                match component {
+                    GlobComponent::Literal(name) => {
+                        matches.push((join_path(&path, name), None));
+                    }
+                    GlobComponent::Pattern(_) => {
+                        if status.is_some_and(|s| !s.isdir) {
+                            continue;
+                        }
+                        let mut iter =
+                            DirListingIterator::new(path.clone(), &self.mount_table, false);
+                        while let Some(child) = iter.next().await {
+                            let child = match child {
+                                Ok(child) => child,
+                                Err(HdfsError::FileNotFound(_)) => break,
+                                Err(e) => return Err(e),
+                            };
+                            // Listing a file returns the file itself
+                            if child.path == path || (!last && !child.isdir) {
+                                continue;
+                            }
+                            let name = child.path.rsplit('/').next().unwrap_or_default();
+                            if component.matches(name) {
+                                matches.push((child.path.clone(), Some(child)));
+                            }
+                        }
+                    }
+                }
+            }
+            candidates = matches;
+        }
+
+        let mut statuses = Vec::with_capacity(candidates.len());
+        for (path, status) in candidates {
+            match status {
+                Some(status) => statuses.push(status),
+                None => match self.get_file_info(&path).await {
+                    Ok(mut status) => {
+                        status.path = path;
+                        statuses.push(status);
+                    }
+                    Err(HdfsError::FileNotFound(_)) => (),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        Ok(statuses)
     }
 
     /// Opens a file reader for the file at `path`. Path should not include a scheme.
+    ///
+    /// The block locations are fetched with `getLocatedFileInfo`, so unlike the Java client,
+    /// opening and reading a file doesn't update its access time. Use [Client::set_times] to
+    /// record an access explicitly.
+    ///
+    /// Files that are still being written can be read up to the data the DataNodes have
+    /// acknowledged so far, which can be more than the NameNode reports if the writer hasn't
+    /// flushed it.
     pub async fn read(&self, path: &str) -> Result<FileReader> {
-        let (link, resolved_path) = self.mount_table.resolve(path);
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
         let located_info = link.protocol.get_located_file_info(&resolved_path).await?;
         match located_info.fs {
             Some(mut status) => {
@@ -275,15 +1343,28 @@ impl Client {
                     None
                 };
 
-                if status.file_encryption_info.is_some() {
-                    return Err(HdfsError::UnsupportedFeature("File encryption".to_string()));
-                }
                 if status.file_type() == FileType::IsDir {
                     return Err(HdfsError::IsADirectoryError(path.to_string()));
                 }
+                let cipher = match status.file_encryption_info.as_ref() {
+                    Some(encryption_info) => {
+                        Some(self.file_cipher(&link.protocol, encryption_info).await?)
+                    }
+                    None => None,
+                };
 
                 if let Some(locations) = status.locations.take() {
-                    Ok(FileReader::new(status, locations, ec_schema))
+                    let mut reader = FileReader::new(
+                        Arc::clone(&link.protocol),
+                        resolved_path,
+                        status,
+                        locations,
+                        ec_schema,
+                        self.file_datanode_config(&link.protocol).await?,
+                        cipher,
+                    );
+                    reader.update_visible_length().await;
+                    Ok(reader)
                 } else {
                     Err(HdfsError::BlocksNotFound(path.to_string()))
                 }
@@ -292,6 +1373,88 @@ impl Client {
         }
     }
 
+    /// Retrieves the checksum of the file at `path`, computed the same way as
+    /// `DistributedFileSystem.getFileChecksum`: the MD5 of the per-block MD5 of CRC checksums
+    /// reported by the DataNodes. The result matches the output of `hadoop fs -checksum`, which
+    /// makes it useful for comparing files across clusters without reading their contents.
+    ///
+    /// Erasure coded files are not currently supported.
+    pub async fn get_file_checksum(&self, path: &str) -> Result<FileChecksum> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        let located_info = link.protocol.get_located_file_info(&resolved_path).await?;
+        let mut status = located_info
+            .fs
+            .ok_or(HdfsError::FileNotFound(path.to_string()))?;
+
+        if status.file_type() == FileType::IsDir {
+            return Err(HdfsError::IsADirectoryError(path.to_string()));
+        }
+        if status.ec_policy.is_some() {
+            return Err(HdfsError::UnsupportedFeature(
+                "Checksum of erasure coded files".to_string(),
+            ));
+        }
+
+        let blocks = status
+            .locations
+            .take()
+            .map(|l| l.blocks)
+            .unwrap_or_default();
+        if blocks.is_empty() {
+            return Ok(FileChecksum::empty());
+        }
+
+        let datanode_config = self.file_datanode_config(&link.protocol).await?;
+        let mut remaining = status.length;
+        let mut bytes_per_crc: Option<u32> = None;
+        let mut crc_per_block = 0;
+        let mut crc_type: Option<ChecksumTypeProto> = None;
+        let mut block_md5s: Vec<u8> = Vec::with_capacity(blocks.len() * 16);
+
+        for (i, block) in blocks.iter().enumerate() {
+            let len = u64::min(block.b.num_bytes(), remaining);
+            remaining -= len;
+
+            let checksum = get_block_checksum(block, len, &datanode_config).await?;
+
+            match bytes_per_crc {
+                None => bytes_per_crc = Some(checksum.bytes_per_crc),
+                Some(bpc) if bpc != checksum.bytes_per_crc => {
+                    return Err(HdfsError::OperationFailed(format!(
+                        "Byte-per-checksum not matched: bpc={} but bytesPerCRC={}",
+                        checksum.bytes_per_crc, bpc
+                    )))
+                }
+                _ => (),
+            }
+
+            // Like Hadoop, CRCs per block is only included if there is more than one block
+            if blocks.len() > 1 && i == 0 {
+                crc_per_block = checksum.crc_per_block;
+            }
+
+            let block_crc_type = checksum
+                .crc_type
+                .and_then(ChecksumTypeProto::from_i32)
+                .unwrap_or(ChecksumTypeProto::ChecksumCrc32);
+            crc_type = match crc_type {
+                // Mixed CRC types are reported as CRC32
+                Some(t) if t != block_crc_type => Some(ChecksumTypeProto::ChecksumCrc32),
+                _ => Some(block_crc_type),
+            };
+
+            block_md5s.extend_from_slice(&checksum.block_checksum);
+        }
+
+        Ok(FileChecksum::new(
+            bytes_per_crc.unwrap_or(0),
+            crc_per_block,
+            crc_type.unwrap_or(ChecksumTypeProto::ChecksumCrc32),
+            Md5::digest(&block_md5s).into(),
+        ))
+    }
+
     /// Opens a new file for writing. See [WriteOptions] for options and behavior for different
     /// scenarios.
     pub async fn create(
@@ -299,23 +1462,46 @@ impl Client {
         src: &str,
         write_options: impl AsRef<WriteOptions>,
     ) -> Result<FileWriter> {
+        let src = &self.absolute_path(src);
         let write_options = write_options.as_ref();
 
-        let (link, resolved_path) = self.mount_table.resolve(src);
-        let server_defaults = link.protocol.get_server_defaults().await?.server_defaults;
+        let (link, resolved_path) = self.mount_table.resolve(src)?;
+        let mut server_defaults = link.protocol.get_server_defaults().await?;
 
         let block_size = write_options
             .block_size
+            .or(self.config.get_block_size())
             .unwrap_or(server_defaults.block_size);
         let replication = write_options
             .replication
+            .or(self.config.get_replication())
             .unwrap_or(server_defaults.replication);
+        if let Some(checksum_type) = write_options
+            .checksum_type
+            .or(self.config.get_checksum_type())
+        {
+            server_defaults.checksum_type = Some(ChecksumTypeProto::from(checksum_type) as i32);
+        }
+        if let Some(bytes_per_checksum) = write_options
+            .bytes_per_checksum
+            .or(self.config.get_bytes_per_checksum())
+        {
+            if bytes_per_checksum == 0 {
+                return Err(HdfsError::InvalidArgument(
+                    "Bytes per checksum must be greater than 0".to_string(),
+                ));
+            }
+            server_defaults.bytes_per_checksum = bytes_per_checksum;
+        }
+        let datanode_config = self
+            .writer_config(&link.protocol, write_options, &mut server_defaults)
+            .await?;
 
         let create_response = link
             .protocol
             .create(
                 &resolved_path,
-                write_options.permission,
+                write_options.permission & !self.config.get_umask(),
                 write_options.overwrite,
                 write_options.create_parent,
                 replication,
@@ -325,10 +1511,18 @@ impl Client {
 
         match create_response.fs {
             Some(status) => {
-                if status.file_encryption_info.is_some() {
-                    let _ = self.delete(src, false).await;
-                    return Err(HdfsError::UnsupportedFeature("File encryption".to_string()));
-                }
+                let cipher = match status.file_encryption_info.as_ref() {
+                    Some(encryption_info) => {
+                        match self.file_cipher(&link.protocol, encryption_info).await {
+                            Ok(cipher) => Some(cipher),
+                            Err(e) => {
+                                let _ = self.delete(src, false).await;
+                                return Err(e);
+                            }
+                        }
+                    }
+                    None => None,
+                };
 
                 Ok(FileWriter::new(
                     Arc::clone(&link.protocol),
@@ -336,6 +1530,8 @@ impl Client {
                     status,
                     None,
                     server_defaults,
+                    datanode_config,
+                    cipher,
                 ))
             }
             None => Err(HdfsError::FileNotFound(src.to_string())),
@@ -350,8 +1546,23 @@ impl Client {
     /// file is replicated, the current block will be appended to until it is full. If the file is erasure
     /// coded, a new block will be created.
     pub async fn append(&self, src: &str) -> Result<FileWriter> {
-        let (link, resolved_path) = self.mount_table.resolve(src);
-        let server_defaults = link.protocol.get_server_defaults().await?.server_defaults;
+        self.append_with_options(src, WriteOptions::default()).await
+    }
+
+    /// Opens an existing file for appending like [Client::append], sending packets with the
+    /// [WriteOptions::packet_size] and [WriteOptions::max_packets_in_flight] of `write_options`.
+    /// The other options only apply to new files, so they're ignored.
+    pub async fn append_with_options(
+        &self,
+        src: &str,
+        write_options: impl AsRef<WriteOptions>,
+    ) -> Result<FileWriter> {
+        let src = &self.absolute_path(src);
+        let (link, resolved_path) = self.mount_table.resolve(src)?;
+        let mut server_defaults = link.protocol.get_server_defaults().await?;
+        let datanode_config = self
+            .writer_config(&link.protocol, write_options.as_ref(), &mut server_defaults)
+            .await?;
 
         // Assume the file is replicated and try to append to the current block. If the file is
         // erasure coded, then try again by appending to a new block.
@@ -364,13 +1575,25 @@ impl Client {
 
         match append_response.stat {
             Some(status) => {
-                if status.file_encryption_info.is_some() {
-                    let _ = link
-                        .protocol
-                        .complete(src, append_response.block.map(|b| b.b), status.file_id)
-                        .await;
-                    return Err(HdfsError::UnsupportedFeature("File encryption".to_string()));
-                }
+                let cipher = match status.file_encryption_info.as_ref() {
+                    Some(encryption_info) => {
+                        match self.file_cipher(&link.protocol, encryption_info).await {
+                            Ok(cipher) => Some(cipher),
+                            Err(e) => {
+                                let _ = link
+                                    .protocol
+                                    .complete(
+                                        &resolved_path,
+                                        append_response.block.map(|b| b.b),
+                                        status.file_id,
+                                    )
+                                    .await;
+                                return Err(e);
+                            }
+                        }
+                    }
+                    None => None,
+                };
 
                 Ok(FileWriter::new(
                     Arc::clone(&link.protocol),
@@ -378,6 +1601,8 @@ impl Client {
                     status,
                     append_response.block,
                     server_defaults,
+                    datanode_config,
+                    cipher,
                 ))
             }
             None => Err(HdfsError::FileNotFound(src.to_string())),
@@ -389,232 +1614,1981 @@ impl Client {
     /// `permission` is the raw octal value representing the Unix style permission. For example, to
     /// set 755 (`rwxr-x-rx`) permissions, use 0o755.
     ///
+    /// Like Hadoop, the umask from `fs.permissions.umask-mode` (022 by default) is removed from
+    /// `permission` before the directory is created, so 0o777 results in `rwxr-xr-x` on disk
+    /// unless the umask is overridden.
+    ///
     /// If `create_parent` is true, any missing parent directories will be created as well,
-    /// otherwise an error will be returned if the parent directory doesn't already exist.
+    /// otherwise an [HdfsError::FileNotFound] error will be returned if the parent directory
+    /// doesn't already exist.
     pub async fn mkdirs(&self, path: &str, permission: u32, create_parent: bool) -> Result<()> {
-        let (link, resolved_path) = self.mount_table.resolve(path);
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
         link.protocol
-            .mkdirs(&resolved_path, permission, create_parent)
+            .mkdirs(
+                &resolved_path,
+                permission & !self.config.get_umask(),
+                create_parent,
+            )
             .await
             .map(|_| ())
     }
 
-    /// Renames `src` to `dst`. Returns Ok(()) on success, and Err otherwise.
+    /// Renames `src` to `dst`. Returns Ok(()) on success, and Err otherwise. Common failures
+    /// are reported as:
+    /// - [HdfsError::FileNotFound] if `src` doesn't exist
+    /// - [HdfsError::AlreadyExists] if `dst` exists and `overwrite` is false, or `src` and `dst`
+    ///   are the same
+    /// - [HdfsError::ParentNotFound] if the parent directory of `dst` doesn't exist
+    /// - [HdfsError::InvalidPath] if `dst` is inside `src`
+    /// - [HdfsError::DirectoryNotEmpty] if `dst` is a non-empty directory being overwritten
+    /// - [HdfsError::CrossMountpoint] if `src` and `dst` are under different ViewFS mount points
     pub async fn rename(&self, src: &str, dst: &str, overwrite: bool) -> Result<()> {
-        let (src_link, src_resolved_path) = self.mount_table.resolve(src);
-        let (dst_link, dst_resolved_path) = self.mount_table.resolve(dst);
+        let src = &self.absolute_path(src);
+        let dst = &self.absolute_path(dst);
+        let (src_link, src_resolved_path) = self.mount_table.resolve(src)?;
+        let (dst_link, dst_resolved_path) = self.mount_table.resolve(dst)?;
         if src_link.viewfs_path == dst_link.viewfs_path {
             src_link
                 .protocol
                 .rename(&src_resolved_path, &dst_resolved_path, overwrite)
                 .await
                 .map(|_| ())
+                .map_err(|e| match e {
+                    HdfsError::FileNotFound(msg)
+                        if msg.starts_with("rename destination parent") =>
+                    {
+                        HdfsError::ParentNotFound(msg)
+                    }
+                    HdfsError::RPCError(class, msg)
+                        if class == "java.io.IOException"
+                            && msg.contains("is a directory or file under source") =>
+                    {
+                        HdfsError::InvalidPath(msg)
+                    }
+                    e => e,
+                })
         } else {
-            Err(HdfsError::InvalidArgument(
-                "Cannot rename across different name services".to_string(),
-            ))
+            Err(HdfsError::CrossMountpoint(format!(
+                "Cannot rename {} to {} across mount points",
+                src, dst
+            )))
+        }
+    }
+
+    /// Renames `src` to `dst` like [Client::rename], but creates any missing parent directories
+    /// of `dst` first instead of failing with [HdfsError::ParentNotFound]. The directories get
+    /// the same permissions as [Client::mkdirs] with 0o777, so the umask applies. Nothing is
+    /// created if `src` doesn't exist.
+    pub async fn rename_with_mkdirs(&self, src: &str, dst: &str, overwrite: bool) -> Result<()> {
+        match self.rename(src, dst, overwrite).await {
+            Err(HdfsError::ParentNotFound(_)) => {
+                let dst = self.absolute_path(dst);
+                if let Some(parent) = Path::new(&dst).parent().and_then(|p| p.to_str()) {
+                    self.mkdirs(parent, 0o777, true).await?;
+                }
+                self.rename(src, &dst, overwrite).await
+            }
+            result => result,
+        }
+    }
+
+    /// Moves the blocks of `sources` onto the end of `target` without copying any data. The
+    /// source files are deleted by the namenode once their blocks have been moved.
+    ///
+    /// HDFS requires all files to share the same block size and replication factor, and all
+    /// files except the last source must end on a block boundary. These constraints are checked
+    /// before making the call, returning an [HdfsError::InvalidArgument] error describing the
+    /// file that violates them. With ViewFS, every file must be under the same mount point, or
+    /// [HdfsError::CrossMountpoint] is returned.
+    pub async fn concat(&self, target: &str, sources: &[&str]) -> Result<()> {
+        let target = &self.absolute_path(target);
+        let sources: Vec<String> = sources
+            .iter()
+            .map(|source| self.absolute_path(source))
+            .collect();
+        if sources.is_empty() {
+            return Err(HdfsError::InvalidArgument(
+                "At least one source file is required".to_string(),
+            ));
+        }
+        if sources.contains(target) {
+            return Err(HdfsError::InvalidArgument(format!(
+                "Target {} cannot also be a source",
+                target
+            )));
+        }
+
+        let (link, target_resolved_path) = self.mount_table.resolve(target)?;
+        let target_status = self.get_file_info(target).await?;
+        if target_status.isdir {
+            return Err(HdfsError::InvalidArgument(format!(
+                "Target {} is a directory",
+                target
+            )));
+        }
+
+        let mut resolved_sources = Vec::with_capacity(sources.len());
+        let mut previous = (target, target_status.length);
+        for source in sources.iter() {
+            let (source_link, source_resolved_path) = self.mount_table.resolve(source)?;
+            if source_link.viewfs_path != link.viewfs_path {
+                return Err(HdfsError::CrossMountpoint(format!(
+                    "Cannot concat {} onto {} across mount points",
+                    source, target
+                )));
+            }
+
+            let status = self.get_file_info(source).await?;
+            if status.isdir {
+                return Err(HdfsError::InvalidArgument(format!(
+                    "Source {} is a directory",
+                    source
+                )));
+            }
+            if status.block_size != target_status.block_size {
+                return Err(HdfsError::InvalidArgument(format!(
+                    "Source {} has block size {} but target {} has block size {}",
+                    source, status.block_size, target, target_status.block_size
+                )));
+            }
+            if status.replication != target_status.replication {
+                return Err(HdfsError::InvalidArgument(format!(
+                    "Source {} has replication {} but target {} has replication {}",
+                    source, status.replication, target, target_status.replication
+                )));
+            }
+            if previous.1 % target_status.block_size as usize != 0 {
+                return Err(HdfsError::InvalidArgument(format!(
+                    "{} does not end on a block boundary, only the last source can have a partial block",
+                    previous.0
+                )));
+            }
+
+            previous = (source, status.length);
+            resolved_sources.push(source_resolved_path);
         }
+
+        link.protocol
+            .concat(&target_resolved_path, resolved_sources)
+            .await
+            .map(|_| ())
     }
 
     /// Deletes the file or directory at `path`. If `recursive` is false and `path` is a non-empty
-    /// directory, this will fail. Returns `Ok(true)` if it was successfully deleted.
+    /// directory, an [HdfsError::DirectoryNotEmpty] error is returned. Returns `Ok(true)` if it was
+    /// successfully deleted, or `Ok(false)` if `path` didn't exist.
     pub async fn delete(&self, path: &str, recursive: bool) -> Result<bool> {
-        let (link, resolved_path) = self.mount_table.resolve(path);
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
         link.protocol
             .delete(&resolved_path, recursive)
             .await
             .map(|r| r.result)
     }
-}
 
-impl Default for Client {
-    /// Creates a new HDFS Client based on the fs.defaultFS setting. Panics if the config files fail to load,
-    /// no defaultFS is defined, or the defaultFS is invalid.
-    fn default() -> Self {
-        let config = Configuration::new().expect("Failed to load configuration");
-        let url = config
-            .get(config::DEFAULT_FS)
-            .ok_or(HdfsError::InvalidArgument(format!(
-                "No {} setting found",
-                config::DEFAULT_FS
+    /// Returns the encryption zone `path` is in, or `None` if it isn't encrypted. For ViewFS, the
+    /// path of the zone is translated back through the mount point of `path`, or is the mount
+    /// point itself if the root of the zone isn't visible through it.
+    pub async fn get_ez_for_path(&self, path: &str) -> Result<Option<EncryptionZone>> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        Ok(link
+            .protocol
+            .get_ez_for_path(&resolved_path)
+            .await?
+            .zone
+            .map(|zone| {
+                let zone_root = link
+                    .unresolve(Path::new(&zone.path))
+                    .unwrap_or_else(|| link.viewfs_path.clone());
+                EncryptionZone {
+                    path: zone_root.to_string_lossy().into_owned(),
+                    ..zone.into()
+                }
+            }))
+    }
+
+    /// The trash directory of the user for `path`, the same as
+    /// `DistributedFileSystem.getTrashRoot`. This is `<home directory>/.Trash`, unless `path`
+    /// is in an encryption zone, in which case it's `<zone>/.Trash/<username>` so files don't
+    /// have to be moved out of the zone. For ViewFS, a zone whose root isn't visible through the
+    /// mount point of `path` has its trash at `<mount point>/.Trash/<username>`.
+    pub async fn get_trash_root(&self, path: &str) -> Result<String> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        let ez_path = match link.protocol.get_ez_for_path(&resolved_path).await?.zone {
+            Some(zone) => zone.path,
+            None => return Ok(format!("{}/.Trash", self.home_directory)),
+        };
+        let zone_root = link
+            .unresolve(Path::new(&ez_path))
+            .unwrap_or_else(|| link.viewfs_path.clone());
+        Ok(join_path(
+            &join_path(&zone_root.to_string_lossy(), ".Trash"),
+            &self.username,
+        ))
+    }
+
+    /// Moves the file or directory at `path` into the trash instead of deleting it, like
+    /// `hadoop fs -rm`. It is moved to the same path under `Current` in the trash root from
+    /// [Client::get_trash_root], creating any missing parent directories, and the NameNode
+    /// deletes it once the trash interval has passed. If something is already at that path, the
+    /// current time in milliseconds is appended to the name. Returns the path in the trash.
+    ///
+    /// The trash is enabled when `fs.trash.interval` is set in the client config or on the
+    /// NameNode. If it isn't, `path` is deleted instead when `delete_if_disabled` is true,
+    /// returning `Ok(None)`, otherwise an [HdfsError::OperationFailed] error is returned. For
+    /// ViewFS outside of an encryption zone, the home directory must be on the same name service
+    /// as `path`.
+    pub async fn move_to_trash(
+        &self,
+        path: &str,
+        delete_if_disabled: bool,
+    ) -> Result<Option<String>> {
+        let path = self.absolute_path(path);
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return Err(HdfsError::InvalidArgument(
+                "Cannot move / to the trash, as it contains the trash".to_string(),
+            ));
+        }
+
+        let (link, _) = self.mount_table.resolve(path)?;
+        let server_interval = link
+            .protocol
+            .get_server_defaults()
+            .await?
+            .trash_interval
+            .unwrap_or_default();
+        if server_interval == 0 && self.config.get_trash_interval() <= 0.0 {
+            if !delete_if_disabled {
+                return Err(HdfsError::OperationFailed(format!(
+                    "Cannot move {} to the trash, the trash is disabled",
+                    path
+                )));
+            }
+            return if self.delete(path, true).await? {
+                Ok(None)
+            } else {
+                Err(HdfsError::FileNotFound(path.to_string()))
+            };
+        }
+
+        let trash_root = self.get_trash_root(path).await?;
+        if path == trash_root || path.starts_with(&format!("{}/", trash_root)) {
+            return Err(HdfsError::InvalidArgument(format!(
+                "{} is already in the trash",
+                path
+            )));
+        }
+        // Fail early for a missing path instead of creating its parents in the trash
+        self.get_file_info(path).await?;
+
+        let mut trash_path = format!("{}/Current{}", trash_root, path);
+        let (trash_parent, _) = trash_path.rsplit_once('/').unwrap();
+        self.mkdirs(trash_parent, 0o700, true).await?;
+        if self.exists(&trash_path).await? {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            trash_path = format!("{}{}", trash_path, now.as_millis());
+        }
+
+        self.rename(path, &trash_path, false).await?;
+        Ok(Some(trash_path))
+    }
+
+    /// Sets the permission of the file or directory at `path`. `permission` is the raw octal value,
+    /// such as 0o644. Unlike [Client::mkdirs], the umask is not applied.
+    pub async fn set_permission(&self, path: &str, permission: u32) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_permission(&resolved_path, permission)
+            .await
+            .map(|_| ())
+    }
+
+    /// Sets the owner and group of the file or directory at `path`. Passing `None` for either
+    /// leaves that value unchanged. Changing the owner generally requires superuser privileges.
+    pub async fn set_owner(
+        &self,
+        path: &str,
+        owner: Option<&str>,
+        group: Option<&str>,
+    ) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_owner(&resolved_path, owner, group)
+            .await
+            .map(|_| ())
+    }
+
+    /// Sets the replication factor of the file at `path`. Returns `Ok(true)` if the namenode
+    /// accepted the change, or `Ok(false)` if it didn't, such as when `path` is a directory.
+    /// `replication` must be at least 1.
+    pub async fn set_replication(&self, path: &str, replication: u16) -> Result<bool> {
+        let path = &self.absolute_path(path);
+        if replication < 1 {
+            return Err(HdfsError::InvalidArgument(
+                "Replication must be at least 1".to_string(),
+            ));
+        }
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_replication(&resolved_path, replication as u32)
+            .await
+            .map(|r| r.result)
+    }
+
+    /// Sets the namespace quota, the maximum number of files and directories, and the space
+    /// quota, the maximum number of bytes including replication, of the directory at `path`.
+    /// Pass [QUOTA_DONT_SET] to leave a quota unchanged or [QUOTA_RESET] to remove it. Other
+    /// quotas must be positive. Setting quotas requires superuser privileges.
+    pub async fn set_quota(
+        &self,
+        path: &str,
+        namespace_quota: i64,
+        space_quota: i64,
+    ) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_quota(
+                &resolved_path,
+                namespace_quota as u64,
+                space_quota as u64,
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Sets the space quota for a single storage type, such as `SSD` or `ARCHIVE`, of the
+    /// directory at `path`. Pass [QUOTA_DONT_SET] to leave the quota unchanged or [QUOTA_RESET]
+    /// to remove it.
+    pub async fn set_quota_by_storage_type(
+        &self,
+        path: &str,
+        storage_type: &str,
+        quota: i64,
+    ) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let storage_type = StorageTypeProto::from_str_name(&storage_type.to_uppercase())
+            .ok_or_else(|| {
+                HdfsError::InvalidArgument(format!("Unknown storage type {}", storage_type))
+            })?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_quota(
+                &resolved_path,
+                QUOTA_DONT_SET as u64,
+                quota as u64,
+                Some(storage_type),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Sets the modification and access times of the file or directory at `path`, in
+    /// milliseconds since the epoch. Passing `None` or a negative value leaves that time
+    /// unchanged.
+    pub async fn set_times(
+        &self,
+        path: &str,
+        mtime: Option<i64>,
+        atime: Option<i64>,
+    ) -> Result<()> {
+        let path = &self.absolute_path(path);
+        // The NameNode treats -1 as "don't change"
+        let to_proto = |time: Option<i64>| time.filter(|t| *t >= 0).unwrap_or(-1) as u64;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_times(&resolved_path, to_proto(mtime), to_proto(atime))
+            .await
+            .map(|_| ())
+    }
+
+    /// Returns all extended attributes of the file or directory at `path` that the caller has
+    /// access to, keyed by their full name including the namespace prefix.
+    pub async fn get_xattrs(&self, path: &str) -> Result<HashMap<String, Vec<u8>>> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        Ok(link
+            .protocol
+            .get_xattrs(&resolved_path, vec![])
+            .await?
+            .x_attrs
+            .into_iter()
+            .map(|xattr| (xattr_name(&xattr), xattr.value.unwrap_or_default()))
+            .collect())
+    }
+
+    /// Returns the value of the extended attribute `name` of the file or directory at `path`.
+    /// `name` must include a namespace prefix such as `user.`. The namenode returns an error if
+    /// the attribute doesn't exist.
+    pub async fn get_xattr(&self, path: &str, name: &str) -> Result<Vec<u8>> {
+        let path = &self.absolute_path(path);
+        let xattr = xattr_proto(name, None)?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .get_xattrs(&resolved_path, vec![xattr])
+            .await?
+            .x_attrs
+            .into_iter()
+            .next()
+            .map(|xattr| xattr.value.unwrap_or_default())
+            .ok_or(HdfsError::OperationFailed(format!(
+                "Extended attribute {} not returned for {}",
+                name, path
             )))
-            .expect("No fs.defaultFS config defined");
-        Self::with_config(
-            &Url::parse(&url).expect("Failed to parse fs.defaultFS"),
-            config,
-        )
-        .expect("Failed to create default client")
+    }
+
+    /// Sets the extended attribute `name` of the file or directory at `path` to `value`.
+    /// `name` must include a namespace prefix such as `user.`, and `flag` controls whether
+    /// an existing attribute can or must be replaced.
+    pub async fn set_xattr(
+        &self,
+        path: &str,
+        name: &str,
+        value: Vec<u8>,
+        flag: XAttrSetFlag,
+    ) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let xattr = xattr_proto(name, Some(value))?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_xattr(&resolved_path, xattr, flag.to_proto())
+            .await
+            .map(|_| ())
+    }
+
+    /// Removes the extended attribute `name` from the file or directory at `path`. `name`
+    /// must include a namespace prefix such as `user.`.
+    pub async fn remove_xattr(&self, path: &str, name: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let xattr = xattr_proto(name, None)?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .remove_xattr(&resolved_path, xattr)
+            .await
+            .map(|_| ())
+    }
+
+    /// Lists the full names of the extended attributes of the file or directory at `path` that
+    /// the caller has access to.
+    pub async fn list_xattrs(&self, path: &str) -> Result<Vec<String>> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        Ok(link
+            .protocol
+            .list_xattrs(&resolved_path)
+            .await?
+            .x_attrs
+            .iter()
+            .map(xattr_name)
+            .collect())
+    }
+
+    /// Checks whether the user can access the file or directory at `path` with `mode`, given as
+    /// permission bits from 0 to 7 such as 0o6 for read and write, without touching the file.
+    /// The NameNode checks the permission bits and ACLs the same way it would for the real
+    /// operation. This lets a job fail fast before it starts writing, for example by checking
+    /// for write and execute access to the output directory.
+    ///
+    /// Fails with [HdfsError::PermissionDenied] if access is denied, and with
+    /// [HdfsError::FileNotFound] if `path` doesn't exist. Superusers always have access.
+    pub async fn check_access(&self, path: &str, mode: u8) -> Result<()> {
+        let path = &self.absolute_path(path);
+        // FsActionProto values are the permission bits themselves
+        let mode = FsActionProto::from_i32(mode as i32)
+            .ok_or_else(|| HdfsError::InvalidArgument(format!("Invalid access mode {:o}", mode)))?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .check_access(&resolved_path, mode)
+            .await
+            .map(|_| ())
+    }
+
+    /// Returns the ACL of the file or directory at `path`.
+    pub async fn get_acl_status(&self, path: &str) -> Result<AclStatus> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        Ok(link
+            .protocol
+            .get_acl_status(&resolved_path)
+            .await?
+            .result
+            .into())
+    }
+
+    /// Adds `entries` to the ACL of the file or directory at `path`, replacing the permissions
+    /// of any existing entries with the same scope, type and name. The namenode recalculates
+    /// the mask to cover the new entries unless one is provided.
+    pub async fn modify_acl_entries(&self, path: &str, entries: Vec<AclEntry>) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let acl_spec = acl_spec(entries)?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .modify_acl_entries(&resolved_path, acl_spec)
+            .await
+            .map(|_| ())
+    }
+
+    /// Removes `entries` from the ACL of the file or directory at `path`. Only the scope, type
+    /// and name of each entry are used to match existing entries.
+    pub async fn remove_acl_entries(&self, path: &str, entries: Vec<AclEntry>) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let acl_spec = acl_spec(entries)?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .remove_acl_entries(&resolved_path, acl_spec)
+            .await
+            .map(|_| ())
+    }
+
+    /// Removes all default ACL entries from the directory at `path`.
+    pub async fn remove_default_acl(&self, path: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .remove_default_acl(&resolved_path)
+            .await
+            .map(|_| ())
+    }
+
+    /// Removes all ACL entries from the file or directory at `path`, leaving only the
+    /// permission bits.
+    pub async fn remove_acl(&self, path: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol.remove_acl(&resolved_path).await.map(|_| ())
+    }
+
+    /// Replaces the ACL of the file or directory at `path` with `entries`. The entries must
+    /// include the unnamed user, group and other entries, which replace the permission bits.
+    pub async fn set_acl(&self, path: &str, entries: Vec<AclEntry>) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let acl_spec = acl_spec(entries)?;
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_acl(&resolved_path, acl_spec)
+            .await
+            .map(|_| ())
+    }
+
+    /// Allows snapshots to be created of the directory at `path`. Requires superuser privileges.
+    pub async fn allow_snapshot(&self, path: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .allow_snapshot(&resolved_path)
+            .await
+            .map(|_| ())
+    }
+
+    /// Stops snapshots from being created of the directory at `path`. Any existing snapshots
+    /// must be deleted first. Requires superuser privileges.
+    pub async fn disallow_snapshot(&self, path: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .disallow_snapshot(&resolved_path)
+            .await
+            .map(|_| ())
+    }
+
+    /// Creates a snapshot of the snapshottable directory at `path`, named `name` or a timestamp
+    /// based name chosen by the namenode if `None`. Returns the path of the snapshot, such as
+    /// `{path}/.snapshot/{name}`, which can be read like any other directory. An
+    /// [HdfsError::SnapshotError] error is returned if `path` isn't snapshottable.
+    pub async fn create_snapshot(&self, path: &str, name: Option<&str>) -> Result<String> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        let snapshot_path = link
+            .protocol
+            .create_snapshot(&resolved_path, name)
+            .await?
+            .snapshot_path;
+
+        // The namenode returns the resolved path, so map it back to the client's view
+        let snapshot_name = snapshot_path
+            .rsplit_once('/')
+            .map(|(_, name)| name)
+            .unwrap_or(&snapshot_path);
+        Ok(format!(
+            "{}/.snapshot/{}",
+            path.trim_end_matches('/'),
+            snapshot_name
+        ))
+    }
+
+    /// Deletes the snapshot `name` of the directory at `path`.
+    pub async fn delete_snapshot(&self, path: &str, name: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .delete_snapshot(&resolved_path, name)
+            .await
+            .map(|_| ())
+    }
+
+    /// Renames the snapshot `old_name` of the directory at `path` to `new_name`.
+    pub async fn rename_snapshot(&self, path: &str, old_name: &str, new_name: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .rename_snapshot(&resolved_path, old_name, new_name)
+            .await
+            .map(|_| ())
+    }
+
+    /// Truncates the file at `path` to `new_length` bytes. Returns `Ok(true)` if the file was
+    /// truncated immediately, which happens when `new_length` is on a block boundary, or
+    /// `Ok(false)` if block recovery is in progress, in which case the file can't be written
+    /// to until recovery completes. HDFS can only shrink files, so an
+    /// [HdfsError::InvalidArgument] error is returned if `new_length` is larger than the file.
+    pub async fn truncate(&self, path: &str, new_length: u64) -> Result<bool> {
+        let path = &self.absolute_path(path);
+        let length = self.get_file_info(path).await?.length as u64;
+        if new_length > length {
+            return Err(HdfsError::InvalidArgument(format!(
+                "Cannot truncate a file of length {} to a larger length {}",
+                length, new_length
+            )));
+        }
+
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .truncate(&resolved_path, new_length)
+            .await
+            .map(|r| r.result)
+    }
+
+    /// Recovers the lease of a file that was left open, such as by a writer that crashed,
+    /// so the file can be appended to or read in full. Returns `Ok(true)` once the file is
+    /// closed, or `Ok(false)` if the NameNode started recovering the last block, in which case
+    /// the file is closed once the DataNodes agree on its length. Callers have to keep calling
+    /// this until it returns `Ok(true)`, waiting a few seconds in between, before reopening the
+    /// file.
+    ///
+    /// Recovery is also forced if this client holds the lease. Fails with
+    /// [HdfsError::FileNotFound] if `path` doesn't exist.
+    pub async fn recover_lease(&self, path: &str) -> Result<bool> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .recover_lease(&resolved_path)
+            .await
+            .map(|r| r.result)
+    }
+
+    /// Sets the storage policy of the file or directory at `path` to the policy named
+    /// `policy_name`, such as `HOT`, `WARM`, `COLD` or `ALL_SSD`. Files in a directory without
+    /// their own policy inherit the directory's policy. Only blocks written after the policy is
+    /// set are placed according to it, existing blocks are moved by running the HDFS mover.
+    ///
+    /// An [HdfsError::InvalidArgument] error listing the valid names is returned if there is no
+    /// policy named `policy_name`.
+    pub async fn set_storage_policy(&self, path: &str, policy_name: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        match link
+            .protocol
+            .set_storage_policy(&resolved_path, policy_name)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(HdfsError::InvalidArgument(msg)) => {
+                let names: Vec<String> = link
+                    .protocol
+                    .get_storage_policies()
+                    .await?
+                    .policies
+                    .into_iter()
+                    .map(|policy| policy.name)
+                    .collect();
+                Err(HdfsError::InvalidArgument(format!(
+                    "{}. Valid storage policies are: {}",
+                    msg,
+                    names.join(", ")
+                )))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Removes the storage policy set on `path`, so it inherits the policy of its parent
+    /// directory again. Like [Client::set_storage_policy], this only affects newly written
+    /// blocks.
+    pub async fn unset_storage_policy(&self, path: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .unset_storage_policy(&resolved_path)
+            .await
+            .map(|_| ())
+    }
+
+    /// Returns the storage policy that applies to `path`, which is inherited from the closest
+    /// parent with a policy if `path` doesn't have its own, or the default `HOT` policy if no
+    /// parent has one.
+    pub async fn get_storage_policy(&self, path: &str) -> Result<StoragePolicy> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .get_storage_policy(&resolved_path)
+            .await
+            .map(|r| r.storage_policy.into())
+    }
+
+    /// Lists all storage policies supported by the NameNode. For ViewFS, the policies are from
+    /// the NameNode of the root path.
+    pub async fn list_storage_policies(&self) -> Result<Vec<StoragePolicy>> {
+        let link = self.mount_table.default_link();
+        Ok(link
+            .protocol
+            .get_storage_policies()
+            .await?
+            .policies
+            .into_iter()
+            .map(StoragePolicy::from)
+            .collect())
+    }
+
+    /// Sets the erasure coding policy of the directory at `path` to the policy named
+    /// `policy_name`, such as `RS-6-3-1024k`, which must be enabled on the NameNode. Only files
+    /// created in the directory after the policy is set are erasure coded, existing files keep
+    /// their layout.
+    pub async fn set_erasure_coding_policy(&self, path: &str, policy_name: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .set_erasure_coding_policy(&resolved_path, Some(policy_name))
+            .await
+            .map(|_| ())
+    }
+
+    /// Removes the erasure coding policy set on the directory at `path`, so new files in it
+    /// inherit the policy of its parent directory again.
+    pub async fn unset_erasure_coding_policy(&self, path: &str) -> Result<()> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .unset_erasure_coding_policy(&resolved_path)
+            .await
+            .map(|_| ())
+    }
+
+    /// Returns the erasure coding policy of the file or directory at `path`, or `None` if it is
+    /// replicated.
+    pub async fn get_erasure_coding_policy(
+        &self,
+        path: &str,
+    ) -> Result<Option<ErasureCodingPolicy>> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        link.protocol
+            .get_erasure_coding_policy(&resolved_path)
+            .await?
+            .ec_policy
+            .map(ErasureCodingPolicy::try_from)
+            .transpose()
+    }
+
+    /// Lists every erasure coding policy known to the NameNode, including disabled ones. For
+    /// ViewFS, the policies are from the NameNode of the root path.
+    pub async fn get_erasure_coding_policies(&self) -> Result<Vec<ErasureCodingPolicy>> {
+        let link = self.mount_table.default_link();
+        link.protocol
+            .get_erasure_coding_policies()
+            .await?
+            .ec_policies
+            .into_iter()
+            .map(ErasureCodingPolicy::try_from)
+            .collect()
+    }
+
+    /// Adds a directive caching the blocks of the file at `path`, or of the files directly in
+    /// the directory at `path`, in the memory of `replication` DataNodes each. The directive is
+    /// added to the cache pool named `pool`, which limits how much can be cached. Returns the ID
+    /// of the directive, which is needed to remove it.
+    pub async fn add_cache_directive(
+        &self,
+        path: &str,
+        pool: &str,
+        replication: u16,
+    ) -> Result<i64> {
+        let path = &self.absolute_path(path);
+        let (link, resolved_path) = self.mount_table.resolve(path)?;
+        let info = CacheDirectiveInfoProto {
+            path: Some(resolved_path),
+            replication: Some(replication as u32),
+            pool: Some(pool.to_string()),
+            ..Default::default()
+        };
+        link.protocol.add_cache_directive(info).await.map(|r| r.id)
+    }
+
+    /// Removes the cache directive with ID `id`, so its blocks are uncached. For ViewFS, the
+    /// directive is removed from the NameNode of the root path.
+    pub async fn remove_cache_directive(&self, id: i64) -> Result<()> {
+        let link = self.mount_table.default_link();
+        link.protocol.remove_cache_directive(id).await.map(|_| ())
+    }
+
+    /// Lists the cache directives matching `filter`, fetched from the NameNode in batches the
+    /// same way as [Client::list_status]. For ViewFS, directives are listed from the NameNode the
+    /// path of the filter is mounted on, or the NameNode of the root path if the filter has no
+    /// path, and the paths of the directives are the paths on that NameNode.
+    pub async fn list_cache_directives(
+        &self,
+        filter: CacheDirectiveFilter,
+    ) -> Result<Vec<CacheDirective>> {
+        let (link, path) = match filter.path {
+            Some(path) => {
+                let path = self.absolute_path(&path);
+                let (link, resolved_path) = self.mount_table.resolve(&path)?;
+                (link, Some(resolved_path))
+            }
+            None => (self.mount_table.default_link(), None),
+        };
+        let filter = CacheDirectiveInfoProto {
+            id: filter.id,
+            path,
+            pool: filter.pool,
+            ..Default::default()
+        };
+
+        let mut directives = Vec::new();
+        let mut prev_id = 0;
+        loop {
+            let response = link
+                .protocol
+                .list_cache_directives(prev_id, filter.clone())
+                .await?;
+            directives.extend(response.elements.into_iter().map(CacheDirective::from));
+            match directives.last() {
+                Some(last) if response.has_more => prev_id = last.id,
+                _ => break,
+            }
+        }
+        Ok(directives)
+    }
+
+    /// Adds a cache pool for cache directives. Adding pools requires superuser privileges. For
+    /// ViewFS, the pool is added to the NameNode of the root path.
+    pub async fn add_cache_pool(&self, info: CachePoolInfo) -> Result<()> {
+        let link = self.mount_table.default_link();
+        link.protocol.add_cache_pool(info.into()).await.map(|_| ())
+    }
+
+    /// Removes the cache pool named `name`, along with all of its cache directives. For ViewFS,
+    /// the pool is removed from the NameNode of the root path.
+    pub async fn remove_cache_pool(&self, name: &str) -> Result<()> {
+        let link = self.mount_table.default_link();
+        link.protocol.remove_cache_pool(name).await.map(|_| ())
+    }
+
+    /// Lists all cache pools, fetched from the NameNode in batches. For ViewFS, the pools are
+    /// from the NameNode of the root path.
+    pub async fn list_cache_pools(&self) -> Result<Vec<CachePool>> {
+        let link = self.mount_table.default_link();
+        let mut pools = Vec::new();
+        let mut prev_pool_name = String::new();
+        loop {
+            let response = link.protocol.list_cache_pools(&prev_pool_name).await?;
+            pools.extend(response.entries.into_iter().map(CachePool::from));
+            match pools.last() {
+                Some(last) if response.has_more => prev_pool_name = last.info.name.clone(),
+                _ => break,
+            }
+        }
+        Ok(pools)
+    }
+
+    /// Returns whether the active NameNode is in safe mode, during which changes to the
+    /// namespace fail with [HdfsError::SafeMode]. With a ViewFS mount table this checks the
+    /// NameNode the root path is mounted on.
+    pub async fn is_in_safe_mode(&self) -> Result<bool> {
+        self.set_safe_mode(SafeModeAction::Get).await
+    }
+
+    /// Enters or leaves safe mode on the active NameNode, and returns whether safe mode is on
+    /// afterwards. Every action other than [SafeModeAction::Get] requires superuser privileges,
+    /// and fails with [HdfsError::PermissionDenied] otherwise. With a ViewFS mount table this
+    /// applies to the NameNode the root path is mounted on.
+    pub async fn set_safe_mode(&self, action: SafeModeAction) -> Result<bool> {
+        let link = self.mount_table.default_link();
+        Ok(link
+            .protocol
+            .set_safe_mode(action.into(), true)
+            .await?
+            .result)
+    }
+
+    /// Gets the capacity and usage of the whole file system, the same numbers reported by
+    /// `hdfs dfs -df` and the NameNode UI. With a ViewFS mount table this reports the NameNode
+    /// the root path is mounted on.
+    pub async fn get_fs_status(&self) -> Result<FsStatus> {
+        let link = self.mount_table.default_link();
+        Ok(link.protocol.get_fs_stats().await?.into())
+    }
+
+    /// Gets the NameNode's defaults for new files, which are used for any settings not given in
+    /// [WriteOptions] or the client config. The defaults are fetched once and cached by the
+    /// client.
+    pub async fn get_server_defaults(&self) -> Result<FsServerDefaults> {
+        let link = self.mount_table.default_link();
+        Ok(link.protocol.get_server_defaults().await?.into())
+    }
+
+    /// Gets a new delegation token from the NameNode that can be renewed by `renewer`. The
+    /// NameNode only issues tokens to clients authenticated with Kerberos. For ViewFS, the token
+    /// is for the NameNode of the root path.
+    pub async fn get_delegation_token(&self, renewer: &str) -> Result<Token> {
+        let link = self.mount_table.default_link();
+        let token = link
+            .protocol
+            .get_delegation_token(renewer)
+            .await?
+            .token
+            .ok_or(HdfsError::OperationFailed(
+                "NameNode did not issue a delegation token, Kerberos authentication is required"
+                    .to_string(),
+            ))?;
+
+        // Tokens are looked up by the service they are for
+        let mut token = Token::from(token);
+        token.service = link.protocol.token_service().to_string();
+        token.alias = token.service.clone();
+        Ok(token)
+    }
+
+    /// Renews a delegation token, returning its new expiration time in milliseconds since the
+    /// epoch. Only the renewer of the token can renew it.
+    ///
+    /// Tokens loaded from `$HADOOP_TOKEN_FILE_LOCATION` can instead be renewed automatically
+    /// by setting `dfs.client.delegation.token.auto-renew` to true.
+    pub async fn renew_delegation_token(&self, token: &Token) -> Result<u64> {
+        let link = self.mount_table.default_link();
+        link.protocol
+            .renew_delegation_token(token.into())
+            .await
+            .map(|r| r.new_expiry_time)
+    }
+
+    /// Cancels a delegation token so it can no longer be used
+    pub async fn cancel_delegation_token(&self, token: &Token) -> Result<()> {
+        let link = self.mount_table.default_link();
+        link.protocol
+            .cancel_delegation_token(token.into())
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Parses the URL of the file system a client is for, which must be an `hdfs://` URL of a
+/// NameNode or name service or a `viewfs://` URL of a mount table. A bare name service name from
+/// the config is accepted as `hdfs://<nameservice>`.
+fn parse_url(url: &str, config: &Configuration) -> Result<Url> {
+    let invalid = |reason: String| {
+        HdfsError::InvalidUrl(format!(
+            "`{}` {}. Expected hdfs://<host>:<port>, hdfs://<nameservice> or viewfs://<cluster>",
+            url, reason
+        ))
+    };
+
+    if !url.contains("://") {
+        if !url.is_empty() && !config.get_urls_for_nameservice(url).is_empty() {
+            return Ok(Url::parse(&format!("hdfs://{}", url))?);
+        }
+        return Err(invalid(
+            "is not a URL or a configured name service".to_string(),
+        ));
+    }
+
+    let parsed = Url::parse(url).map_err(|e| invalid(format!("could not be parsed: {}", e)))?;
+    if matches!(parsed.scheme(), "webhdfs" | "swebhdfs") {
+        return Err(HdfsError::InvalidUrl(format!(
+            "`{}` is a WebHDFS URL, which needs a WebHdfsClient from ClientBuilder::build_webhdfs",
+            url
+        )));
+    }
+    if !matches!(parsed.scheme(), "hdfs" | "viewfs") {
+        return Err(invalid(format!(
+            "has the unsupported scheme `{}`",
+            parsed.scheme()
+        )));
+    }
+    if !parsed.has_host() {
+        return Err(invalid("has no host".to_string()));
+    }
+    Ok(parsed)
+}
+
+impl Default for Client {
+    /// Creates a new HDFS Client based on the fs.defaultFS setting. Panics if the config files fail to load,
+    /// no defaultFS is defined, or the defaultFS is invalid.
+    fn default() -> Self {
+        ClientBuilder::default()
+            .build()
+            .expect("Failed to create default client")
+    }
+}
+
+pub(crate) struct DirListingIterator {
+    path: String,
+    resolved_path: String,
+    // None if `path` isn't in a mount point, which fails the first batch
+    link: Option<MountLink>,
+    files_only: bool,
+    // Whether the statuses of files include their block locations
+    need_location: bool,
+    partial_listing: VecDeque<HdfsFileStatusProto>,
+    remaining: u32,
+    last_seen: Vec<u8>,
+    // Owner and group names of the listed files, shared with the listings of sub-directories
+    names: Arc<Mutex<StringInterner>>,
+}
+
+impl DirListingIterator {
+    fn new(path: String, mount_table: &Arc<MountTable>, files_only: bool) -> Self {
+        let (link, resolved_path) = match mount_table.resolve(&path) {
+            Ok((link, resolved_path)) => (Some(link.clone()), resolved_path),
+            Err(_) => (None, String::new()),
+        };
+
+        DirListingIterator {
+            path,
+            resolved_path,
+            link,
+            files_only,
+            need_location: false,
+            partial_listing: VecDeque::new(),
+            remaining: 1,
+            last_seen: Vec::new(),
+            names: Default::default(),
+        }
+    }
+
+    fn with_locations(mut self) -> Self {
+        self.need_location = true;
+        self
+    }
+
+    fn with_names(mut self, names: Arc<Mutex<StringInterner>>) -> Self {
+        self.names = names;
+        self
+    }
+
+    fn to_status(&self, status: HdfsFileStatusProto) -> FileStatus {
+        FileStatus::interned(status, &self.path, &mut self.names.lock().unwrap())
+    }
+
+    async fn get_next_batch(&mut self) -> Result<bool> {
+        let link = self
+            .link
+            .as_ref()
+            .ok_or_else(|| HdfsError::NotInMountpoint(self.path.clone()))?;
+        let listing = link
+            .protocol
+            .get_listing(
+                &self.resolved_path,
+                self.last_seen.clone(),
+                self.need_location,
+            )
+            .await?;
+
+        if let Some(dir_list) = listing.dir_list {
+            self.last_seen = dir_list
+                .partial_listing
+                .last()
+                .map(|p| p.path.clone())
+                .unwrap_or(Vec::new());
+
+            self.remaining = dir_list.remaining_entries;
+
+            self.partial_listing = dir_list
+                .partial_listing
+                .into_iter()
+                .filter(|s| !self.files_only || s.file_type() != FileType::IsDir)
+                .collect();
+            Ok(!self.partial_listing.is_empty())
+        } else {
+            Err(HdfsError::FileNotFound(self.path.clone()))
+        }
+    }
+
+    async fn next_proto(&mut self) -> Option<Result<HdfsFileStatusProto>> {
+        if self.partial_listing.is_empty() && self.remaining > 0 {
+            if let Err(error) = self.get_next_batch().await {
+                self.remaining = 0;
+                return Some(Err(error));
+            }
+        }
+        self.partial_listing.pop_front().map(Ok)
+    }
+
+    pub async fn next(&mut self) -> Option<Result<FileStatus>> {
+        let next = self.next_proto().await?;
+        Some(next.map(|status| self.to_status(status)))
+    }
+
+    async fn next_located(&mut self) -> Option<Result<LocatedFileStatus>> {
+        let next = self.next_proto().await?;
+        Some(next.map(|mut status| {
+            let blocks = status
+                .locations
+                .take()
+                .map(|locations| {
+                    locations
+                        .blocks
+                        .into_iter()
+                        .map(BlockLocation::from)
+                        .collect()
+                })
+                .unwrap_or_default();
+            LocatedFileStatus {
+                status: self.to_status(status),
+                blocks,
+            }
+        }))
+    }
+}
+
+/// Depth-first walk of the directory listed by `iter`, used by [Client::list_status_recursive].
+/// Sub-directories have their first page prefetched in order using `buffered`, while `semaphore`
+/// bounds the number of listing RPCs across the entire walk.
+fn walk_dir(
+    iter: DirListingIterator,
+    mount_table: Arc<MountTable>,
+    semaphore: Arc<Semaphore>,
+    concurrency: usize,
+) -> BoxStream<'static, Result<FileStatus>> {
+    let names = Arc::clone(&iter.names);
+    let listing = stream::unfold(
+        (iter, Arc::clone(&semaphore)),
+        |(mut iter, semaphore)| async move {
+            let next = {
+                let _permit = semaphore.acquire().await.unwrap();
+                iter.next().await
+            };
+            next.map(|n| (n, (iter, semaphore)))
+        },
+    );
+
+    listing
+        .map(move |status| {
+            let mount_table = Arc::clone(&mount_table);
+            let semaphore = Arc::clone(&semaphore);
+            let names = Arc::clone(&names);
+            async move {
+                match status {
+                    Ok(status) if status.isdir => {
+                        let mut child =
+                            DirListingIterator::new(status.path.clone(), &mount_table, false)
+                                .with_names(names);
+                        let prefetched = {
+                            let _permit = semaphore.acquire().await.unwrap();
+                            child.get_next_batch().await
+                        };
+                        let children = match prefetched {
+                            Ok(_) => walk_dir(child, mount_table, semaphore, concurrency),
+                            // The directory was removed after it was listed
+                            Err(HdfsError::FileNotFound(_)) => stream::empty().boxed(),
+                            Err(e) => stream::once(async { Err(e) }).boxed(),
+                        };
+                        stream::once(async { Ok(status) }).chain(children).boxed()
+                    }
+                    other => stream::once(async { other }).boxed(),
+                }
+            }
+        })
+        .buffered(concurrency)
+        .flatten()
+        .boxed()
+}
+
+pub struct ListStatusIterator {
+    mount_table: Arc<MountTable>,
+    recursive: bool,
+    iters: Vec<DirListingIterator>,
+}
+
+impl ListStatusIterator {
+    fn new(path: String, mount_table: Arc<MountTable>, recursive: bool) -> Self {
+        let initial = DirListingIterator::new(path.clone(), &mount_table, false);
+
+        ListStatusIterator {
+            mount_table,
+            recursive,
+            iters: vec![initial],
+        }
+    }
+
+    pub async fn next(&mut self) -> Option<Result<FileStatus>> {
+        let mut next_file: Option<Result<FileStatus>> = None;
+        while next_file.is_none() {
+            if let Some(iter) = self.iters.last_mut() {
+                if let Some(file_result) = iter.next().await {
+                    if let Ok(file) = file_result {
+                        // Return the directory as the next result, but start traversing into that directory
+                        // next if we're doing a recursive listing
+                        if file.isdir && self.recursive {
+                            let names = Arc::clone(&iter.names);
+                            self.iters.push(
+                                DirListingIterator::new(
+                                    file.path.clone(),
+                                    &self.mount_table,
+                                    false,
+                                )
+                                .with_names(names),
+                            )
+                        }
+                        next_file = Some(Ok(file));
+                    } else if self.iters.len() > 1
+                        && matches!(file_result, Err(HdfsError::FileNotFound(_)))
+                    {
+                        // A sub-directory was removed after it was listed, so skip it instead of
+                        // failing the whole listing
+                        self.iters.pop();
+                    } else {
+                        // Error, return that as the next element
+                        next_file = Some(file_result)
+                    }
+                } else {
+                    // We've exhausted this directory
+                    self.iters.pop();
+                }
+            } else {
+                // There's nothing left, just return None
+                break;
+            }
+        }
+
+        next_file
+    }
+
+    pub fn into_stream(self) -> BoxStream<'static, Result<FileStatus>> {
+        let listing = stream::unfold(self, |mut state| async move {
+            let next = state.next().await;
+            next.map(|n| (n, state))
+        });
+        Box::pin(listing)
+    }
+}
+
+#[derive(Debug)]
+pub struct FileStatus {
+    /// Full path of the file, including its parent directories
+    pub path: String,
+    /// Length of the file in bytes, or 0 for directories
+    pub length: usize,
+    /// Whether this is a directory, see [FileStatus::is_directory]
+    pub isdir: bool,
+    /// Permission bits, such as `0o755`
+    pub permission: u16,
+    /// Name of the owner. Statuses from the same listing share their copies of the owner and
+    /// group names.
+    pub owner: Arc<str>,
+    pub group: Arc<str>,
+    /// Milliseconds since the epoch
+    pub modification_time: u64,
+    /// Milliseconds since the epoch. Only updated by the NameNode as often as
+    /// `dfs.namenode.accesstime.precision`, so it may lag behind the last read.
+    pub access_time: u64,
+    /// Replication factor of the file, or 0 for directories
+    pub replication: u16,
+    /// Block size of the file, or 0 for directories
+    pub block_size: u64,
+    /// Target of the symlink, or `None` if this isn't a symlink
+    pub symlink: Option<String>,
+}
+
+impl FileStatus {
+    /// Whether this is a directory
+    pub fn is_directory(&self) -> bool {
+        self.isdir
+    }
+
+    /// Whether this is a symlink, in which case [FileStatus::symlink] is its target
+    pub fn is_symlink(&self) -> bool {
+        self.symlink.is_some()
+    }
+
+    fn from(mut value: HdfsFileStatusProto, base_path: &str) -> Self {
+        let owner = std::mem::take(&mut value.owner).into();
+        let group = std::mem::take(&mut value.group).into();
+        Self::with_names(value, base_path, owner, group)
+    }
+
+    /// Converts a status from a listing, sharing the owner and group names with other statuses
+    /// converted with `names`
+    fn interned(
+        mut value: HdfsFileStatusProto,
+        base_path: &str,
+        names: &mut StringInterner,
+    ) -> Self {
+        let owner = names.intern(std::mem::take(&mut value.owner));
+        let group = names.intern(std::mem::take(&mut value.group));
+        Self::with_names(value, base_path, owner, group)
+    }
+
+    fn with_names(
+        value: HdfsFileStatusProto,
+        base_path: &str,
+        owner: Arc<str>,
+        group: Arc<str>,
+    ) -> Self {
+        let mut path = PathBuf::from(base_path);
+        if let Ok(relative_path) = std::str::from_utf8(&value.path) {
+            if !relative_path.is_empty() {
+                path.push(relative_path)
+            }
+        }
+
+        FileStatus {
+            isdir: value.file_type() == FileType::IsDir,
+            path: path
+                .to_str()
+                .map(|x| x.to_string())
+                .unwrap_or(String::new()),
+            length: value.length as usize,
+            replication: value.block_replication() as u16,
+            block_size: value.blocksize(),
+            permission: value.permission.perm as u16,
+            owner,
+            group,
+            modification_time: value.modification_time,
+            access_time: value.access_time,
+            symlink: value
+                .symlink
+                .map(|target| String::from_utf8_lossy(&target).into_owned()),
+        }
+    }
+}
+
+/// A [FileStatus] with the locations of the blocks of the file, returned by
+/// [Client::list_status_with_locations]
+#[derive(Debug)]
+pub struct LocatedFileStatus {
+    pub status: FileStatus,
+    /// Locations of every block of the file in order of their offset, or empty for directories
+    pub blocks: Vec<BlockLocation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockLocation {
+    /// Offset of the block in the file
+    pub offset: u64,
+    /// Number of bytes in the block
+    pub length: u64,
+    /// Hostnames of the datanodes holding replicas of the block
+    pub hosts: Vec<String>,
+    /// `ip:port` data transfer addresses of the datanodes holding replicas of the block
+    pub names: Vec<String>,
+    /// Whether all replicas of the block are corrupt
+    pub corrupt: bool,
+}
+
+impl From<LocatedBlockProto> for BlockLocation {
+    fn from(value: LocatedBlockProto) -> Self {
+        Self {
+            offset: value.offset,
+            length: value.b.num_bytes(),
+            hosts: value
+                .locs
+                .iter()
+                .map(|loc| loc.id.host_name.clone())
+                .collect(),
+            names: value
+                .locs
+                .iter()
+                .map(|loc| format!("{}:{}", loc.id.ip_addr, loc.id.xfer_port))
+                .collect(),
+            corrupt: value.corrupt,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ContentSummary {
+    /// Total number of bytes in all files, not including replication
+    pub length: u64,
+    pub file_count: u64,
+    pub directory_count: u64,
+    /// Namespace quota, or -1 if there is no quota
+    pub quota: i64,
+    /// Total number of bytes used, including replication
+    pub space_consumed: u64,
+    /// Space quota in bytes, or -1 if there is no quota
+    pub space_quota: i64,
+    /// Quotas and usage per storage type
+    pub type_quotas: Vec<StorageTypeQuota>,
+}
+
+impl From<ContentSummaryProto> for ContentSummary {
+    fn from(value: ContentSummaryProto) -> Self {
+        ContentSummary {
+            length: value.length,
+            file_count: value.file_count,
+            directory_count: value.directory_count,
+            quota: value.quota as i64,
+            space_consumed: value.space_consumed,
+            space_quota: value.space_quota as i64,
+            type_quotas: value
+                .type_quota_infos
+                .map(|infos| infos.type_quota_info)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|info| StorageTypeQuota {
+                    storage_type: info.r#type().as_str_name().to_string(),
+                    quota: info.quota as i64,
+                    consumed: info.consumed,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageTypeQuota {
+    /// The storage type, such as `DISK` or `SSD`
+    pub storage_type: String,
+    /// Space quota in bytes, or -1 if there is no quota
+    pub quota: i64,
+    /// Bytes used on this storage type, including replication
+    pub consumed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclEntryScope {
+    /// Applies to the file or directory itself
+    Access,
+    /// Inherited by new children of a directory
+    Default,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclEntryType {
+    User,
+    Group,
+    Mask,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclEntry {
+    pub scope: AclEntryScope,
+    pub r#type: AclEntryType,
+    /// User or group the entry applies to, or `None` for the file owner or group
+    pub name: Option<String>,
+    /// Permission bits from 0 to 7, such as 0o6 for read and write
+    pub permissions: u8,
+}
+
+impl AclEntry {
+    pub fn new(
+        scope: AclEntryScope,
+        r#type: AclEntryType,
+        name: Option<&str>,
+        permissions: u8,
+    ) -> Self {
+        Self {
+            scope,
+            r#type,
+            name: name.map(|n| n.to_string()),
+            permissions,
+        }
+    }
+}
+
+impl From<AclEntryProto> for AclEntry {
+    fn from(value: AclEntryProto) -> Self {
+        Self {
+            scope: match value.scope() {
+                AclEntryScopeProto::Access => AclEntryScope::Access,
+                AclEntryScopeProto::Default => AclEntryScope::Default,
+            },
+            r#type: match value.r#type() {
+                AclEntryTypeProto::User => AclEntryType::User,
+                AclEntryTypeProto::Group => AclEntryType::Group,
+                AclEntryTypeProto::Mask => AclEntryType::Mask,
+                AclEntryTypeProto::Other => AclEntryType::Other,
+            },
+            permissions: value.permissions() as u8,
+            name: value.name,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AclStatus {
+    pub owner: String,
+    pub group: String,
+    pub sticky: bool,
+    /// ACL entries beyond the owner, group and other permission bits
+    pub entries: Vec<AclEntry>,
+    /// Permission bits of the file or directory. When there is an access ACL, the group bits
+    /// are the ACL mask, which the namenode recalculates when entries are modified.
+    pub permission: u16,
+}
+
+impl From<AclStatusProto> for AclStatus {
+    fn from(value: AclStatusProto) -> Self {
+        Self {
+            owner: value.owner,
+            group: value.group,
+            sticky: value.sticky,
+            entries: value.entries.into_iter().map(AclEntry::from).collect(),
+            permission: value.permission.map(|p| p.perm as u16).unwrap_or_default(),
+        }
+    }
+}
+
+fn acl_spec(entries: Vec<AclEntry>) -> Result<Vec<AclEntryProto>> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            // FsActionProto values are the permission bits themselves
+            if entry.permissions > FsActionProto::PermAll as u8 {
+                return Err(HdfsError::InvalidArgument(format!(
+                    "Invalid ACL permissions {:o}",
+                    entry.permissions
+                )));
+            }
+
+            Ok(AclEntryProto {
+                r#type: match entry.r#type {
+                    AclEntryType::User => AclEntryTypeProto::User,
+                    AclEntryType::Group => AclEntryTypeProto::Group,
+                    AclEntryType::Mask => AclEntryTypeProto::Mask,
+                    AclEntryType::Other => AclEntryTypeProto::Other,
+                } as i32,
+                scope: match entry.scope {
+                    AclEntryScope::Access => AclEntryScopeProto::Access,
+                    AclEntryScope::Default => AclEntryScopeProto::Default,
+                } as i32,
+                permissions: entry.permissions as i32,
+                name: entry.name,
+            })
+        })
+        .collect()
+}
+
+/// How [Client::set_xattr] treats an existing extended attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XAttrSetFlag {
+    /// Fail if the attribute already exists
+    Create,
+    /// Fail if the attribute doesn't already exist
+    Replace,
+    /// Create the attribute or replace an existing value
+    CreateOrReplace,
+}
+
+impl XAttrSetFlag {
+    fn to_proto(self) -> u32 {
+        match self {
+            Self::Create => XAttrSetFlagProto::XattrCreate as u32,
+            Self::Replace => XAttrSetFlagProto::XattrReplace as u32,
+            Self::CreateOrReplace => {
+                XAttrSetFlagProto::XattrCreate as u32 | XAttrSetFlagProto::XattrReplace as u32
+            }
+        }
+    }
+}
+
+/// Converts a full extended attribute name like `user.attr` into its namespace and name
+fn xattr_proto(name: &str, value: Option<Vec<u8>>) -> Result<XAttrProto> {
+    let invalid =
+        || HdfsError::InvalidArgument(format!("Invalid extended attribute name {}", name));
+
+    let (prefix, attr_name) = name.split_once('.').ok_or_else(invalid)?;
+    if attr_name.is_empty() {
+        return Err(invalid());
+    }
+    let namespace = match prefix.to_lowercase().as_str() {
+        "user" => XAttrNamespaceProto::User,
+        "trusted" => XAttrNamespaceProto::Trusted,
+        "security" => XAttrNamespaceProto::Security,
+        "system" => XAttrNamespaceProto::System,
+        "raw" => XAttrNamespaceProto::Raw,
+        _ => return Err(invalid()),
+    };
+
+    Ok(XAttrProto {
+        namespace: namespace as i32,
+        name: attr_name.to_string(),
+        value,
+    })
+}
+
+fn xattr_name(xattr: &XAttrProto) -> String {
+    format!(
+        "{}.{}",
+        xattr.namespace().as_str_name().to_lowercase(),
+        xattr.name
+    )
+}
+
+/// A policy for which types of storage the replicas of blocks are placed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoragePolicy {
+    pub id: u8,
+    pub name: String,
+    /// Storage types of the replicas of new blocks, such as `DISK` or `ARCHIVE`. The last type
+    /// is used for any additional replicas.
+    pub storage_types: Vec<String>,
+    /// Storage types used instead when creating a block if the preferred ones are unavailable
+    pub creation_fallbacks: Vec<String>,
+    /// Storage types used instead when re-replicating a block if the preferred ones are
+    /// unavailable
+    pub replication_fallbacks: Vec<String>,
+}
+
+impl From<BlockStoragePolicyProto> for StoragePolicy {
+    fn from(value: BlockStoragePolicyProto) -> Self {
+        let names = |types: Option<StorageTypesProto>| -> Vec<String> {
+            types
+                .map(|t| t.storage_types)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(StorageTypeProto::from_i32)
+                .map(|t| t.as_str_name().to_string())
+                .collect()
+        };
+        Self {
+            id: value.policy_id as u8,
+            name: value.name,
+            storage_types: names(Some(value.creation_policy)),
+            creation_fallbacks: names(value.creation_fallback_policy),
+            replication_fallbacks: names(value.replication_fallback_policy),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErasureCodingPolicyState {
+    Disabled,
+    Enabled,
+    Removed,
+}
+
+/// An erasure coding policy, which stripes the data of files across `data_units` blocks in
+/// cells of `cell_size` bytes, with `parity_units` blocks of parity computed with `codec_name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErasureCodingPolicy {
+    pub id: u8,
+    pub name: String,
+    pub codec_name: String,
+    pub data_units: usize,
+    pub parity_units: usize,
+    pub cell_size: usize,
+    pub state: ErasureCodingPolicyState,
+}
+
+impl TryFrom<ErasureCodingPolicyProto> for ErasureCodingPolicy {
+    type Error = HdfsError;
+
+    fn try_from(value: ErasureCodingPolicyProto) -> Result<Self> {
+        // Built-in policies may only be identified by their ID
+        let schema = resolve_ec_policy(&value)?;
+        let name = value.name.clone().unwrap_or_else(|| {
+            format!(
+                "{}-{}-{}-{}k",
+                schema.codec_name.to_uppercase(),
+                schema.data_units,
+                schema.parity_units,
+                schema.cell_size / 1024
+            )
+        });
+        Ok(Self {
+            id: value.id as u8,
+            name,
+            codec_name: schema.codec_name,
+            data_units: schema.data_units,
+            parity_units: schema.parity_units,
+            cell_size: schema.cell_size,
+            state: match value.state() {
+                ErasureCodingPolicyStateProto::Disabled => ErasureCodingPolicyState::Disabled,
+                ErasureCodingPolicyStateProto::Enabled => ErasureCodingPolicyState::Enabled,
+                ErasureCodingPolicyStateProto::Removed => ErasureCodingPolicyState::Removed,
+            },
+        })
     }
 }
 
-pub(crate) struct DirListingIterator {
-    path: String,
-    resolved_path: String,
-    link: MountLink,
-    files_only: bool,
-    partial_listing: VecDeque<HdfsFileStatusProto>,
-    remaining: u32,
-    last_seen: Vec<u8>,
+/// Filter for [Client::list_cache_directives]. Fields left as `None` match any directive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheDirectiveFilter {
+    pub id: Option<i64>,
+    pub path: Option<String>,
+    pub pool: Option<String>,
 }
 
-impl DirListingIterator {
-    fn new(path: String, mount_table: &Arc<MountTable>, files_only: bool) -> Self {
-        let (link, resolved_path) = mount_table.resolve(&path);
+/// A directive caching the blocks of a file, or of the files directly in a directory, in the
+/// memory of DataNodes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheDirective {
+    pub id: i64,
+    pub path: String,
+    pub pool: String,
+    /// Number of DataNodes each block is cached on
+    pub replication: u16,
+    /// Time the directive expires in milliseconds since the epoch. Directives that never expire
+    /// have an expiration far in the future.
+    pub expiration: i64,
+    pub has_expired: bool,
+    pub bytes_needed: i64,
+    pub bytes_cached: i64,
+    pub files_needed: i64,
+    pub files_cached: i64,
+}
 
-        DirListingIterator {
-            path,
-            resolved_path,
-            link: link.clone(),
-            files_only,
-            partial_listing: VecDeque::new(),
-            remaining: 1,
-            last_seen: Vec::new(),
+impl From<CacheDirectiveEntryProto> for CacheDirective {
+    fn from(value: CacheDirectiveEntryProto) -> Self {
+        let info = value.info;
+        Self {
+            id: info.id.unwrap_or_default(),
+            path: info.path.unwrap_or_default(),
+            pool: info.pool.unwrap_or_default(),
+            replication: info.replication.unwrap_or(1) as u16,
+            expiration: info.expiration.map(|e| e.millis).unwrap_or(i64::MAX),
+            has_expired: value.stats.has_expired,
+            bytes_needed: value.stats.bytes_needed,
+            bytes_cached: value.stats.bytes_cached,
+            files_needed: value.stats.files_needed,
+            files_cached: value.stats.files_cached,
         }
     }
+}
 
-    async fn get_next_batch(&mut self) -> Result<bool> {
-        let listing = self
-            .link
-            .protocol
-            .get_listing(&self.resolved_path, self.last_seen.clone(), false)
-            .await?;
-
-        if let Some(dir_list) = listing.dir_list {
-            self.last_seen = dir_list
-                .partial_listing
-                .last()
-                .map(|p| p.path.clone())
-                .unwrap_or(Vec::new());
-
-            self.remaining = dir_list.remaining_entries;
+/// Settings of a cache pool. Settings left as `None` when adding a pool use the NameNode's
+/// defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CachePoolInfo {
+    pub name: String,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    /// Permission of the pool, such as `0o755`. Adding directives to a pool requires write
+    /// permission.
+    pub mode: Option<u32>,
+    /// Maximum number of bytes the directives in the pool can cache
+    pub limit: Option<i64>,
+    /// Maximum time in milliseconds directives in the pool can be set to expire after
+    pub max_relative_expiry: Option<i64>,
+    /// Replication of directives added to the pool without one
+    pub default_replication: Option<u16>,
+}
 
-            self.partial_listing = dir_list
-                .partial_listing
-                .into_iter()
-                .filter(|s| !self.files_only || s.file_type() != FileType::IsDir)
-                .collect();
-            Ok(!self.partial_listing.is_empty())
-        } else {
-            Err(HdfsError::FileNotFound(self.path.clone()))
+impl CachePoolInfo {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
         }
     }
+}
 
-    pub async fn next(&mut self) -> Option<Result<FileStatus>> {
-        if self.partial_listing.is_empty() && self.remaining > 0 {
-            if let Err(error) = self.get_next_batch().await {
-                self.remaining = 0;
-                return Some(Err(error));
-            }
-        }
-        if let Some(next) = self.partial_listing.pop_front() {
-            Some(Ok(FileStatus::from(next, &self.path)))
-        } else {
-            None
+impl From<CachePoolInfo> for CachePoolInfoProto {
+    fn from(value: CachePoolInfo) -> Self {
+        Self {
+            pool_name: Some(value.name),
+            owner_name: value.owner,
+            group_name: value.group,
+            mode: value.mode.map(|m| m as i32),
+            limit: value.limit,
+            max_relative_expiry: value.max_relative_expiry,
+            default_replication: value.default_replication.map(|r| r as u32),
         }
     }
 }
 
-pub struct ListStatusIterator {
-    mount_table: Arc<MountTable>,
-    recursive: bool,
-    iters: Vec<DirListingIterator>,
+/// A cache pool listed by [Client::list_cache_pools], with how much its directives cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachePool {
+    pub info: CachePoolInfo,
+    pub bytes_needed: i64,
+    pub bytes_cached: i64,
+    /// Bytes needed by the directives of the pool beyond its limit
+    pub bytes_overlimit: i64,
+    pub files_needed: i64,
+    pub files_cached: i64,
 }
 
-impl ListStatusIterator {
-    fn new(path: String, mount_table: Arc<MountTable>, recursive: bool) -> Self {
-        let initial = DirListingIterator::new(path.clone(), &mount_table, false);
-
-        ListStatusIterator {
-            mount_table,
-            recursive,
-            iters: vec![initial],
+impl From<CachePoolEntryProto> for CachePool {
+    fn from(value: CachePoolEntryProto) -> Self {
+        let info = value.info;
+        Self {
+            info: CachePoolInfo {
+                name: info.pool_name.unwrap_or_default(),
+                owner: info.owner_name,
+                group: info.group_name,
+                mode: info.mode.map(|m| m as u32),
+                limit: info.limit,
+                max_relative_expiry: info.max_relative_expiry,
+                default_replication: info.default_replication.map(|r| r as u16),
+            },
+            bytes_needed: value.stats.bytes_needed,
+            bytes_cached: value.stats.bytes_cached,
+            bytes_overlimit: value.stats.bytes_overlimit,
+            files_needed: value.stats.files_needed,
+            files_cached: value.stats.files_cached,
         }
     }
+}
 
-    pub async fn next(&mut self) -> Option<Result<FileStatus>> {
-        let mut next_file: Option<Result<FileStatus>> = None;
-        while next_file.is_none() {
-            if let Some(iter) = self.iters.last_mut() {
-                if let Some(file_result) = iter.next().await {
-                    if let Ok(file) = file_result {
-                        // Return the directory as the next result, but start traversing into that directory
-                        // next if we're doing a recursive listing
-                        if file.isdir && self.recursive {
-                            self.iters.push(DirListingIterator::new(
-                                file.path.clone(),
-                                &self.mount_table,
-                                false,
-                            ))
-                        }
-                        next_file = Some(Ok(file));
-                    } else {
-                        // Error, return that as the next element
-                        next_file = Some(file_result)
-                    }
-                } else {
-                    // We've exhausted this directory
-                    self.iters.pop();
-                }
-            } else {
-                // There's nothing left, just return None
-                break;
-            }
-        }
+/// A directory whose files are transparently encrypted with keys derived from a key in the KMS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptionZone {
+    pub id: i64,
+    /// Path of the root of the zone
+    pub path: String,
+    /// Name of the zone's key in the KMS
+    pub key_name: String,
+}
 
-        next_file
+impl From<EncryptionZoneProto> for EncryptionZone {
+    fn from(value: EncryptionZoneProto) -> Self {
+        Self {
+            id: value.id,
+            path: value.path,
+            key_name: value.key_name,
+        }
     }
+}
 
-    pub fn into_stream(self) -> BoxStream<'static, Result<FileStatus>> {
-        let listing = stream::unfold(self, |mut state| async move {
-            let next = state.next().await;
-            next.map(|n| (n, state))
-        });
-        Box::pin(listing)
+/// Capacity and block health of the file system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsStatus {
+    /// Total raw capacity of all DataNodes in bytes
+    pub capacity: u64,
+    /// Bytes used by HDFS blocks, including replication
+    pub used: u64,
+    /// Bytes still available for HDFS blocks
+    pub remaining: u64,
+    pub under_replicated_blocks: u64,
+    pub corrupt_blocks: u64,
+    pub missing_blocks: u64,
+}
+
+impl From<GetFsStatsResponseProto> for FsStatus {
+    fn from(value: GetFsStatsResponseProto) -> Self {
+        Self {
+            capacity: value.capacity,
+            used: value.used,
+            remaining: value.remaining,
+            under_replicated_blocks: value.under_replicated,
+            corrupt_blocks: value.corrupt_blocks,
+            missing_blocks: value.missing_blocks,
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct FileStatus {
-    pub path: String,
-    pub length: usize,
-    pub isdir: bool,
-    pub permission: u16,
-    pub owner: String,
-    pub group: String,
-    pub modification_time: u64,
-    pub access_time: u64,
+/// Quota value passed to [Client::set_quota] to leave a quota unchanged
+pub const QUOTA_DONT_SET: i64 = i64::MAX;
+/// Quota value passed to [Client::set_quota] to remove a quota
+pub const QUOTA_RESET: i64 = -1;
+
+/// The NameNode's defaults for new files. The access time precision isn't included, since the
+/// NameNode doesn't report it to clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsServerDefaults {
+    pub block_size: u64,
+    pub bytes_per_checksum: u32,
+    pub write_packet_size: u32,
+    pub replication: u32,
+    pub file_buffer_size: u32,
+    pub encrypt_data_transfer: bool,
+    /// Minutes deleted files are kept in the trash, or 0 if the trash is disabled
+    pub trash_interval: u64,
+    /// Checksum used for new blocks
+    pub checksum_type: ChecksumType,
 }
 
-impl FileStatus {
-    fn from(value: HdfsFileStatusProto, base_path: &str) -> Self {
-        let mut path = PathBuf::from(base_path);
-        if let Ok(relative_path) = std::str::from_utf8(&value.path) {
-            if !relative_path.is_empty() {
-                path.push(relative_path)
-            }
+impl From<FsServerDefaultsProto> for FsServerDefaults {
+    fn from(value: FsServerDefaultsProto) -> Self {
+        Self {
+            block_size: value.block_size,
+            bytes_per_checksum: value.bytes_per_checksum,
+            write_packet_size: value.write_packet_size,
+            replication: value.replication,
+            file_buffer_size: value.file_buffer_size,
+            encrypt_data_transfer: value.encrypt_data_transfer(),
+            trash_interval: value.trash_interval(),
+            checksum_type: value.checksum_type().into(),
         }
+    }
+}
 
-        FileStatus {
-            isdir: value.file_type() == FileType::IsDir,
-            path: path
-                .to_str()
-                .map(|x| x.to_string())
-                .unwrap_or(String::new()),
-            length: value.length as usize,
-            permission: value.permission.perm as u16,
-            owner: value.owner,
-            group: value.group,
-            modification_time: value.modification_time,
-            access_time: value.access_time,
+/// An MD5-of-MD5-of-CRC file checksum, equivalent to Hadoop's `MD5MD5CRC32FileChecksum`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChecksum {
+    /// Name of the checksum algorithm, such as `MD5-of-0MD5-of-512CRC32C`
+    pub algorithm: String,
+    /// Serialized checksum, the same bytes printed in hex by `hadoop fs -checksum`
+    pub bytes: Vec<u8>,
+}
+
+impl FileChecksum {
+    fn new(
+        bytes_per_crc: u32,
+        crc_per_block: u64,
+        crc_type: ChecksumTypeProto,
+        md5: [u8; 16],
+    ) -> Self {
+        let crc_name = match crc_type {
+            ChecksumTypeProto::ChecksumCrc32c => "CRC32C",
+            _ => "CRC32",
+        };
+
+        let mut bytes = Vec::with_capacity(28);
+        bytes.extend_from_slice(&bytes_per_crc.to_be_bytes());
+        bytes.extend_from_slice(&crc_per_block.to_be_bytes());
+        bytes.extend_from_slice(&md5);
+
+        Self {
+            algorithm: format!(
+                "MD5-of-{}MD5-of-{}{}",
+                crc_per_block, bytes_per_crc, crc_name
+            ),
+            bytes,
         }
     }
+
+    /// The checksum of an empty file. Hadoop uses the MD5 of 32 zero bytes for compatibility
+    /// with older versions.
+    fn empty() -> Self {
+        Self::new(
+            0,
+            0,
+            ChecksumTypeProto::ChecksumCrc32,
+            Md5::digest([0u8; 32]).into(),
+        )
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::{
+        collections::HashMap,
         path::{Path, PathBuf},
-        sync::Arc,
+        sync::{Arc, Mutex},
+        time::Duration,
     };
 
     use url::Url;
@@ -622,14 +3596,31 @@ mod test {
     use crate::{
         common::config::Configuration,
         hdfs::{protocol::NamenodeProtocol, proxy::NameServiceProxy},
+        metrics::{Metrics, MetricsRecorder},
+        proto::hdfs::{
+            hdfs_file_status_proto::FileType, BlockStoragePolicyProto, ChecksumTypeProto,
+            ErasureCodingPolicyProto, FsPermissionProto, FsServerDefaultsProto,
+            HdfsFileStatusProto, StorageTypeProto, StorageTypesProto,
+        },
+        security::user::User,
+        HdfsError,
     };
 
-    use super::{MountLink, MountTable};
+    use super::{
+        absolute_path, acl_spec, parse_url, symlink_target, xattr_name, xattr_proto, AclEntry,
+        AclEntryScope, AclEntryType, ChecksumType, ClientBuilder, ErasureCodingPolicy,
+        ErasureCodingPolicyState, FileChecksum, FileStatus, FsServerDefaults, MountLink,
+        MountTable, RetryPolicy, StoragePolicy, XAttrSetFlag,
+    };
 
     fn create_protocol(url: &str) -> Arc<NamenodeProtocol> {
-        let proxy =
-            NameServiceProxy::new(&Url::parse(url).unwrap(), &Configuration::new().unwrap());
-        Arc::new(NamenodeProtocol::new(proxy))
+        let proxy = NameServiceProxy::new(
+            &Url::parse(url).unwrap(),
+            &Configuration::new().unwrap(),
+            Metrics::default(),
+            Arc::new(User::new(None, None, Vec::new())),
+        );
+        Arc::new(NamenodeProtocol::new(proxy, Duration::from_secs(30)))
     }
 
     #[test]
@@ -646,6 +3637,72 @@ mod test {
             PathBuf::from("/hdfs")
         );
         assert!(link.resolve(Path::new("/hdfs/path")).is_none());
+
+        assert_eq!(
+            link.unresolve(Path::new("/hdfs/zone")).unwrap(),
+            PathBuf::from("/view/zone")
+        );
+        assert_eq!(
+            link.unresolve(Path::new("/hdfs")).unwrap(),
+            PathBuf::from("/view")
+        );
+        assert!(link.unresolve(Path::new("/")).is_none());
+        assert!(link.unresolve(Path::new("/hdfs2/zone")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lease_renewal() {
+        #[derive(Default)]
+        struct RenewalRecorder {
+            renewals: Mutex<usize>,
+        }
+
+        impl MetricsRecorder for RenewalRecorder {
+            fn record_rpc(&self, method: &str, _latency: Duration, _success: bool) {
+                if method == "renewLease" {
+                    *self.renewals.lock().unwrap() += 1;
+                }
+            }
+        }
+
+        // Nothing is listening, so each renewal fails right away, but is still recorded
+        let config = Configuration::from(
+            [("dfs.client.retry.max.attempts", "0")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+        );
+        let recorder = Arc::new(RenewalRecorder::default());
+        let proxy = NameServiceProxy::new(
+            &Url::parse("hdfs://127.0.0.1:1").unwrap(),
+            &config,
+            Metrics::new(recorder.clone()),
+            Arc::new(User::new(None, None, Vec::new())),
+        );
+        let protocol = Arc::new(NamenodeProtocol::new(proxy, Duration::from_millis(50)));
+        let renewals = || *recorder.renewals.lock().unwrap();
+
+        let first = protocol.begin_file_lease();
+        let second = protocol.begin_file_lease();
+        assert_eq!(protocol.open_files(), 2);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(renewals() >= 2);
+
+        drop(first);
+        assert_eq!(protocol.open_files(), 1);
+        drop(second);
+        assert_eq!(protocol.open_files(), 0);
+
+        // Renewal stops once no files are open
+        let stopped = renewals();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(renewals(), stopped);
+
+        // Renewal starts again for the next open file
+        let _guard = protocol.begin_file_lease();
+        assert_eq!(protocol.open_files(), 1);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(renewals() > stopped);
     }
 
     #[test]
@@ -688,30 +3745,359 @@ mod test {
 
         let mount_table = MountTable {
             mounts: vec![link1, link2, link3],
-            fallback,
+            fallback: Some(fallback),
         };
 
         // Exact mount path resolves to the exact HDFS path
-        let (link, resolved) = mount_table.resolve("/mount1");
+        let (link, resolved) = mount_table.resolve("/mount1").unwrap();
         assert_eq!(link.viewfs_path, Path::new("/mount1"));
         assert_eq!(resolved, "/path1/nested");
 
         // Trailing slash is treated the same
-        let (link, resolved) = mount_table.resolve("/mount1/");
+        let (link, resolved) = mount_table.resolve("/mount1/").unwrap();
         assert_eq!(link.viewfs_path, Path::new("/mount1"));
         assert_eq!(resolved, "/path1/nested");
 
         // Doesn't do partial matches on a directory name
-        let (link, resolved) = mount_table.resolve("/mount12");
+        let (link, resolved) = mount_table.resolve("/mount12").unwrap();
         assert_eq!(link.viewfs_path, Path::new("/"));
         assert_eq!(resolved, "/path4/mount12");
 
-        let (link, resolved) = mount_table.resolve("/mount3/file");
+        let (link, resolved) = mount_table.resolve("/mount3/file").unwrap();
         assert_eq!(link.viewfs_path, Path::new("/"));
         assert_eq!(resolved, "/path4/mount3/file");
 
-        let (link, resolved) = mount_table.resolve("/mount3/nested/file");
+        let (link, resolved) = mount_table.resolve("/mount3/nested/file").unwrap();
         assert_eq!(link.viewfs_path, Path::new("/mount3/nested"));
         assert_eq!(resolved, "/path3/file");
+        assert_eq!(mount_table.default_link().viewfs_path, Path::new("/"));
+
+        // Without a fallback, paths outside of a mount point can't be resolved
+        let mount_table = MountTable {
+            mounts: mount_table.mounts,
+            fallback: None,
+        };
+        let (link, resolved) = mount_table.resolve("/mount2/file").unwrap();
+        assert_eq!(link.viewfs_path, Path::new("/mount2"));
+        assert_eq!(resolved, "/path2/file");
+        assert!(matches!(
+            mount_table.resolve("/mount3/file"),
+            Err(HdfsError::NotInMountpoint(path)) if path == "/mount3/file"
+        ));
+        assert!(matches!(
+            mount_table.resolve("/"),
+            Err(HdfsError::NotInMountpoint(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_status() {
+        let proto = HdfsFileStatusProto {
+            file_type: FileType::IsFile as i32,
+            path: b"file".to_vec(),
+            length: 10,
+            permission: FsPermissionProto { perm: 0o644 },
+            owner: "user".to_string(),
+            group: "supergroup".to_string(),
+            modification_time: 2000,
+            access_time: 1000,
+            block_replication: Some(3),
+            blocksize: Some(128 * 1024 * 1024),
+            ..Default::default()
+        };
+        let status = FileStatus::from(proto, "/dir");
+        assert_eq!(status.path, "/dir/file");
+        assert_eq!(status.length, 10);
+        assert!(!status.is_directory());
+        assert!(!status.is_symlink());
+        assert_eq!(status.permission, 0o644);
+        assert_eq!(&*status.owner, "user");
+        assert_eq!(&*status.group, "supergroup");
+        assert_eq!(status.modification_time, 2000);
+        assert_eq!(status.access_time, 1000);
+        assert_eq!(status.replication, 3);
+        assert_eq!(status.block_size, 128 * 1024 * 1024);
+
+        let proto = HdfsFileStatusProto {
+            file_type: FileType::IsSymlink as i32,
+            path: b"link".to_vec(),
+            symlink: Some(b"/target".to_vec()),
+            ..Default::default()
+        };
+        let status = FileStatus::from(proto, "/dir");
+        assert!(status.is_symlink());
+        assert_eq!(status.symlink.as_deref(), Some("/target"));
+
+        // The status of a path itself has an empty relative path
+        let proto = HdfsFileStatusProto {
+            file_type: FileType::IsDir as i32,
+            ..Default::default()
+        };
+        let status = FileStatus::from(proto, "/dir");
+        assert_eq!(status.path, "/dir");
+        assert!(status.is_directory());
+        assert_eq!(status.replication, 0);
+    }
+
+    #[test]
+    fn test_empty_file_checksum() {
+        // Matches `hadoop fs -checksum` of an empty file
+        let checksum = FileChecksum::empty();
+        assert_eq!(checksum.algorithm, "MD5-of-0MD5-of-0CRC32");
+        assert_eq!(
+            checksum
+                .bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+            "00000000000000000000000070bc8f4b72a86921468bf8e8441dce51"
+        );
+    }
+
+    #[test]
+    fn test_acl_spec() {
+        let entries = vec![
+            AclEntry::new(AclEntryScope::Access, AclEntryType::User, Some("user"), 0o7),
+            AclEntry::new(AclEntryScope::Default, AclEntryType::Mask, None, 0o5),
+        ];
+        let protos = acl_spec(entries.clone()).unwrap();
+        assert_eq!(protos[0].permissions, 7);
+        assert_eq!(protos[1].scope, 1);
+        assert_eq!(
+            protos.into_iter().map(AclEntry::from).collect::<Vec<_>>(),
+            entries
+        );
+
+        assert!(acl_spec(vec![AclEntry::new(
+            AclEntryScope::Access,
+            AclEntryType::Other,
+            None,
+            0o10
+        )])
+        .is_err());
+    }
+
+    #[test]
+    fn test_xattr_names() {
+        for name in [
+            "user.attr",
+            "trusted.attr",
+            "security.attr",
+            "system.attr",
+            "raw.attr",
+        ] {
+            assert_eq!(xattr_name(&xattr_proto(name, None).unwrap()), name);
+        }
+
+        // Only the first dot separates the namespace and the prefix is case insensitive
+        let xattr = xattr_proto("USER.nested.attr", Some(vec![1])).unwrap();
+        assert_eq!(xattr.name, "nested.attr");
+        assert_eq!(xattr.value, Some(vec![1]));
+        assert_eq!(xattr_name(&xattr), "user.nested.attr");
+
+        for name in ["attr", "user.", "other.attr", ".attr"] {
+            assert!(xattr_proto(name, None).is_err(), "{}", name);
+        }
+
+        assert_eq!(XAttrSetFlag::Create.to_proto(), 1);
+        assert_eq!(XAttrSetFlag::Replace.to_proto(), 2);
+        assert_eq!(XAttrSetFlag::CreateOrReplace.to_proto(), 3);
+    }
+
+    #[test]
+    fn test_storage_policy() {
+        let policy = StoragePolicy::from(BlockStoragePolicyProto {
+            policy_id: 5,
+            name: "WARM".to_string(),
+            creation_policy: StorageTypesProto {
+                storage_types: vec![
+                    StorageTypeProto::Disk as i32,
+                    StorageTypeProto::Archive as i32,
+                ],
+            },
+            creation_fallback_policy: Some(StorageTypesProto {
+                storage_types: vec![
+                    StorageTypeProto::Disk as i32,
+                    StorageTypeProto::Archive as i32,
+                ],
+            }),
+            replication_fallback_policy: None,
+        });
+        assert_eq!(policy.id, 5);
+        assert_eq!(policy.name, "WARM");
+        assert_eq!(policy.storage_types, vec!["DISK", "ARCHIVE"]);
+        assert_eq!(policy.creation_fallbacks, vec!["DISK", "ARCHIVE"]);
+        assert!(policy.replication_fallbacks.is_empty());
+    }
+
+    #[test]
+    fn test_erasure_coding_policy() {
+        // Built-in policies can be sent with only their ID
+        let policy = ErasureCodingPolicy::try_from(ErasureCodingPolicyProto {
+            id: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(policy.name, "RS-3-2-1024k");
+        assert_eq!(policy.codec_name, "rs");
+        assert_eq!((policy.data_units, policy.parity_units), (3, 2));
+        assert_eq!(policy.cell_size, 1024 * 1024);
+        assert_eq!(policy.state, ErasureCodingPolicyState::Enabled);
+
+        assert!(ErasureCodingPolicy::try_from(ErasureCodingPolicyProto {
+            id: 100,
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_server_defaults() {
+        let defaults = FsServerDefaults::from(FsServerDefaultsProto {
+            block_size: 128 * 1024 * 1024,
+            bytes_per_checksum: 512,
+            write_packet_size: 64 * 1024,
+            replication: 3,
+            file_buffer_size: 4096,
+            checksum_type: Some(ChecksumTypeProto::ChecksumCrc32c as i32),
+            ..Default::default()
+        });
+        assert_eq!(
+            defaults,
+            FsServerDefaults {
+                block_size: 128 * 1024 * 1024,
+                bytes_per_checksum: 512,
+                write_packet_size: 64 * 1024,
+                replication: 3,
+                file_buffer_size: 4096,
+                encrypt_data_transfer: false,
+                trash_interval: 0,
+                checksum_type: ChecksumType::Crc32c,
+            }
+        );
+    }
+
+    #[test]
+    fn test_symlink_target() {
+        assert_eq!(
+            symlink_target("/dir/link", "/other/file").unwrap(),
+            "/other/file"
+        );
+        assert_eq!(symlink_target("/dir/link", "file").unwrap(), "/dir/file");
+        assert_eq!(symlink_target("/dir/link", "../file").unwrap(), "/file");
+        assert_eq!(symlink_target("/link", "file").unwrap(), "/file");
+        assert!(symlink_target("/dir/link", "file:///tmp/file").is_none());
+        assert!(symlink_target("/dir/link", "hdfs://other/file").is_none());
+    }
+
+    #[test]
+    fn test_absolute_path() {
+        let home = "/user/test";
+        assert_eq!(absolute_path("/testfile", home), "/testfile");
+        assert_eq!(absolute_path("/a/../b/./c/", home), "/a/../b/./c/");
+        assert_eq!(absolute_path("foo/bar", home), "/user/test/foo/bar");
+        assert_eq!(absolute_path("foo/./bar/", home), "/user/test/foo/bar");
+        assert_eq!(absolute_path("foo/../bar", home), "/user/test/bar");
+        assert_eq!(absolute_path("../other", home), "/user/other");
+        assert_eq!(absolute_path("../../../..", home), "/");
+        assert_eq!(absolute_path("", home), "/user/test");
+        assert_eq!(absolute_path(".", home), "/user/test");
+        assert_eq!(absolute_path("~", home), "/user/test");
+        assert_eq!(absolute_path("~/foo", home), "/user/test/foo");
+        assert_eq!(absolute_path("~foo", home), "/user/test/~foo");
+    }
+    #[test]
+    fn test_rpc_settings() {
+        let client = ClientBuilder::new("hdfs://127.0.0.1:9000")
+            .with_rpc_timeout(Duration::from_secs(5))
+            .with_connect_timeout(Duration::from_millis(1500))
+            .with_retry_policy(RetryPolicy::new(
+                3,
+                Duration::from_millis(10),
+                Duration::from_secs(1),
+            ))
+            .build()
+            .unwrap();
+
+        let settings = client.rpc_settings();
+        assert_eq!(settings.rpc_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(settings.connect_timeout, Duration::from_millis(1500));
+        assert_eq!(
+            settings.retry_policy,
+            RetryPolicy::new(3, Duration::from_millis(10), Duration::from_secs(1))
+        );
+
+        let client = ClientBuilder::new("hdfs://127.0.0.1:9000")
+            .with_rpc_timeout(Duration::ZERO)
+            .build()
+            .unwrap();
+        assert_eq!(client.rpc_settings().rpc_timeout, None);
+    }
+
+    #[test]
+    fn test_client_builder() {
+        // Builder settings are applied in order, so later ones take precedence
+        let client = ClientBuilder::default()
+            .with_config(
+                [
+                    ("fs.defaultFS", "hdfs://127.0.0.1:9000"),
+                    ("ipc.client.connect.timeout", "1000"),
+                ]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            )
+            .with_connect_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.rpc_settings().connect_timeout,
+            Duration::from_secs(5)
+        );
+
+        assert!(ClientBuilder::new("not a url").build().is_err());
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let config = Configuration::from(HashMap::from([
+            ("dfs.ha.namenodes.ns".to_string(), "nn1".to_string()),
+            (
+                "dfs.namenode.rpc-address.ns.nn1".to_string(),
+                "localhost:9000".to_string(),
+            ),
+        ]));
+
+        assert_eq!(
+            parse_url("hdfs://localhost:9000", &config)
+                .unwrap()
+                .as_str(),
+            "hdfs://localhost:9000"
+        );
+        assert_eq!(
+            parse_url("viewfs://cluster", &config).unwrap().host_str(),
+            Some("cluster")
+        );
+        // A configured name service doesn't need the scheme
+        assert_eq!(parse_url("ns", &config).unwrap().as_str(), "hdfs://ns");
+
+        for (url, reason) in [
+            ("http://localhost:9870", "unsupported scheme `http`"),
+            ("webhdfs://localhost:9870", "ClientBuilder::build_webhdfs"),
+            ("swebhdfs://localhost:9871", "ClientBuilder::build_webhdfs"),
+            ("localhost:9000", "not a URL or a configured name service"),
+            ("namenode", "not a URL or a configured name service"),
+            ("", "not a URL or a configured name service"),
+            ("hdfs://", "has no host"),
+            ("hdfs://[::1", "could not be parsed"),
+        ] {
+            match parse_url(url, &config) {
+                Err(HdfsError::InvalidUrl(message)) => {
+                    assert!(message.contains(&format!("`{}`", url)), "{}", message);
+                    assert!(message.contains(reason), "{}", message);
+                }
+                result => panic!("Expected InvalidUrl for {}, got {:?}", url, result),
+            }
+        }
     }
 }
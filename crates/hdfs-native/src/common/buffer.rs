@@ -0,0 +1,156 @@
+//! A pool of the buffers that packets read from DataNodes are split from, configured with
+//! [ClientBuilder::with_read_buffer_pool_size](crate::ClientBuilder::with_read_buffer_pool_size).
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use bytes::BytesMut;
+
+#[derive(Debug)]
+struct PoolState {
+    // Buffers with the capacity they had when they were taken, which counts against the size
+    // of the pool
+    buffers: VecDeque<(BytesMut, usize)>,
+    pooled_bytes: usize,
+    max_bytes: usize,
+}
+
+/// Recycles the allocations backing packet data. A buffer goes back to the pool as soon as a
+/// packet has been split from it, and its allocation is reused the next time it's taken if all
+/// the [Bytes](bytes::Bytes) split from it have been dropped by then. Buffers are taken in the
+/// order they were returned, which gives their data the most time to be dropped. A buffer still
+/// in use is replaced with a new allocation, and the old one is freed once its data is dropped.
+///
+/// The default pool is disabled and allocates a new buffer for every packet.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BufferPool(Option<Arc<Mutex<PoolState>>>);
+
+impl BufferPool {
+    /// Creates a pool that keeps up to `max_bytes` of buffers, or a disabled pool if it's 0
+    pub(crate) fn new(max_bytes: usize) -> Self {
+        if max_bytes == 0 {
+            return Self(None);
+        }
+        Self(Some(Arc::new(Mutex::new(PoolState {
+            buffers: VecDeque::new(),
+            pooled_bytes: 0,
+            max_bytes,
+        }))))
+    }
+
+    /// Gets an empty buffer with room for at least `len` bytes
+    pub(crate) fn get(&self, len: usize) -> PooledBuffer {
+        let pooled = self.0.as_ref().and_then(|state| {
+            let mut state = state.lock().unwrap();
+            let (buf, size) = state.buffers.pop_front()?;
+            state.pooled_bytes -= size;
+            Some(buf)
+        });
+        let buf = match pooled {
+            Some(mut buf) => {
+                // Reclaims the whole allocation if nothing split from it is still alive
+                buf.reserve(len);
+                buf
+            }
+            None => BytesMut::with_capacity(len),
+        };
+        PooledBuffer {
+            size: buf.capacity(),
+            buf,
+            pool: self.clone(),
+        }
+    }
+
+    fn put(&self, mut buf: BytesMut, size: usize) {
+        if let Some(state) = self.0.as_ref() {
+            buf.clear();
+            let mut state = state.lock().unwrap();
+            if state.pooled_bytes + size <= state.max_bytes {
+                state.pooled_bytes += size;
+                state.buffers.push_back((buf, size));
+            }
+        }
+    }
+}
+
+/// A buffer taken from a [BufferPool]. Once it's filled, [PooledBuffer::split] takes the data
+/// out of it and returns the rest of the buffer to the pool.
+#[derive(Debug)]
+pub(crate) struct PooledBuffer {
+    buf: BytesMut,
+    size: usize,
+    pool: BufferPool,
+}
+
+impl PooledBuffer {
+    pub(crate) fn split(mut self) -> BytesMut {
+        self.buf.split()
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buf), self.size);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BufMut;
+
+    use super::BufferPool;
+
+    fn pooled_buffers(pool: &BufferPool) -> usize {
+        pool.0.as_ref().unwrap().lock().unwrap().buffers.len()
+    }
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new(1024);
+
+        let mut buf = pool.get(512);
+        let ptr = buf.as_ptr();
+        buf.put_bytes(1, 512);
+        let data = buf.split().freeze();
+        assert_eq!(pooled_buffers(&pool), 1);
+
+        // The allocation is reused once the data split from it is dropped
+        drop(data);
+        let mut buf = pool.get(512);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 512);
+
+        // A buffer whose data is still alive isn't reused
+        buf.put_bytes(2, 512);
+        let data = buf.split().freeze();
+        let buf = pool.get(512);
+        assert_ne!(buf.as_ptr(), data.as_ptr());
+        assert!(data.iter().all(|b| *b == 2));
+
+        // Buffers that don't fit in the pool are dropped
+        let buffers: Vec<_> = (0..3).map(|_| pool.get(512)).collect();
+        drop(buf);
+        drop(buffers);
+        assert_eq!(pooled_buffers(&pool), 2);
+
+        // A disabled pool always allocates
+        let pool = BufferPool::new(0);
+        assert!(pool.0.is_none());
+        assert!(pool.get(512).capacity() >= 512);
+    }
+}
@@ -3,12 +3,122 @@ use std::env;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::client::ChecksumType;
 
 const HADOOP_CONF_DIR: &str = "HADOOP_CONF_DIR";
 const HADOOP_HOME: &str = "HADOOP_HOME";
 
 pub(crate) const DEFAULT_FS: &str = "fs.defaultFS";
 
+// Permission settings
+const UMASK: &str = "fs.permissions.umask-mode";
+const DEFAULT_UMASK: u32 = 0o022;
+
+// Write settings
+const REPLICATION: &str = "dfs.replication";
+const BLOCK_SIZE: &str = "dfs.blocksize";
+const WRITE_PACKET_SIZE: &str = "dfs.client-write-packet-size";
+const CHECKSUM_TYPE: &str = "dfs.checksum.type";
+const BYTES_PER_CHECKSUM: &str = "dfs.bytes-per-checksum";
+const WRITE_MAX_PACKETS: &str = "dfs.client.write.max-packets-in-flight";
+const DEFAULT_WRITE_MAX_PACKETS: usize = 80;
+// Not a Java client setting, since Java output streams aren't closed when they're collected
+pub(crate) const RECOVER_LEASE_ON_DROP: &str = "dfs.client.write.recover-lease-on-drop";
+
+// Trash settings
+const TRASH_INTERVAL: &str = "fs.trash.interval";
+
+// Encryption zone settings
+#[cfg(feature = "encryption")]
+const KEY_PROVIDER_PATH: &str = "hadoop.security.key.provider.path";
+#[cfg(feature = "encryption")]
+const KEY_PROVIDER_URI: &str = "dfs.encryption.key.provider.uri";
+#[cfg(feature = "encryption")]
+const KMS_CLIENT_TIMEOUT: &str = "hadoop.security.kms.client.timeout";
+#[cfg(feature = "encryption")]
+const DEFAULT_KMS_CLIENT_TIMEOUT_SECS: u64 = 60;
+
+// DataNode settings
+const SOCKET_TIMEOUT: &str = "dfs.client.socket-timeout";
+const DEFAULT_SOCKET_TIMEOUT_MS: u64 = 60000;
+const DATA_TRANSFER_PROTECTION: &str = "dfs.data.transfer.protection";
+pub(crate) const READ_AHEAD_BLOCKS: &str = "dfs.client.read-ahead.blocks";
+const DEFAULT_READ_AHEAD_BLOCKS: usize = 1;
+pub(crate) const READ_PARALLELISM: &str = "dfs.client.read.parallelism";
+const DEFAULT_READ_PARALLELISM: usize = 1;
+pub(crate) const VERIFY_CHECKSUM: &str = "dfs.client.read.verify.checksum";
+pub(crate) const READ_BUFFER_POOL_SIZE: &str = "dfs.client.read.buffer.pool.size";
+const SOCKET_CACHE_CAPACITY: &str = "dfs.client.socketcache.capacity";
+const DEFAULT_SOCKET_CACHE_CAPACITY: usize = 16;
+const SOCKET_CACHE_EXPIRY: &str = "dfs.client.socketcache.expiryMsec";
+const DEFAULT_SOCKET_CACHE_EXPIRY_MS: u64 = 3000;
+pub(crate) const USE_DATANODE_HOSTNAME: &str = "dfs.client.use.datanode.hostname";
+const SHORT_CIRCUIT_READS: &str = "dfs.client.read.shortcircuit";
+const DOMAIN_SOCKET_PATH: &str = "dfs.domain.socket.path";
+const REPLACE_DATANODE_ON_FAILURE: &str =
+    "dfs.client.block.write.replace-datanode-on-failure.enable";
+const REPLACE_DATANODE_POLICY: &str = "dfs.client.block.write.replace-datanode-on-failure.policy";
+const DEFAULT_REPLACE_DATANODE_POLICY: &str = "DEFAULT";
+const REPLACE_DATANODE_BEST_EFFORT: &str =
+    "dfs.client.block.write.replace-datanode-on-failure.best-effort";
+
+// NameNode RPC settings
+pub(crate) const RPC_TIMEOUT: &str = "ipc.client.rpc-timeout.ms";
+const DEFAULT_RPC_TIMEOUT_MS: u64 = 120000;
+pub(crate) const CONNECT_TIMEOUT: &str = "ipc.client.connect.timeout";
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 20000;
+pub(crate) const PING: &str = "ipc.client.ping";
+pub(crate) const PING_INTERVAL: &str = "ipc.ping.interval";
+const DEFAULT_PING_INTERVAL_MS: u64 = 60000;
+pub(crate) const TCP_KEEPALIVE_TIME: &str = "ipc.client.tcp.keepalive.time.ms";
+pub(crate) const RETRY_MAX_ATTEMPTS: &str = "dfs.client.retry.max.attempts";
+const DEFAULT_RETRY_MAX_ATTEMPTS: usize = 10;
+pub(crate) const RETRY_SLEEP_BASE: &str = "dfs.client.retry.sleep.base.millis";
+const DEFAULT_RETRY_SLEEP_BASE_MS: u64 = 100;
+pub(crate) const RETRY_SLEEP_MAX: &str = "dfs.client.retry.sleep.max.millis";
+const DEFAULT_RETRY_SLEEP_MAX_MS: u64 = 5000;
+pub(crate) const SAFE_MODE_WAIT: &str = "dfs.client.safemode.wait.millis";
+const DEFAULT_SAFE_MODE_WAIT_MS: u64 = 0;
+// Not a Java client setting, which always uses the NameNode's 60 second soft limit
+const LEASE_SOFT_LIMIT: &str = "dfs.client.lease.soft-limit.millis";
+const DEFAULT_LEASE_SOFT_LIMIT_MS: u64 = 60000;
+
+// Failover settings
+const FAILOVER_MAX_ATTEMPTS: &str = "dfs.client.failover.max.attempts";
+const DEFAULT_FAILOVER_MAX_ATTEMPTS: usize = 15;
+const FAILOVER_SLEEP_BASE: &str = "dfs.client.failover.sleep.base.millis";
+const DEFAULT_FAILOVER_SLEEP_BASE_MS: u64 = 500;
+const FAILOVER_SLEEP_MAX: &str = "dfs.client.failover.sleep.max.millis";
+const DEFAULT_FAILOVER_SLEEP_MAX_MS: u64 = 15000;
+
+const FAILOVER_PROXY_PROVIDER_PREFIX: &str = "dfs.client.failover.proxy.provider";
+const OBSERVER_READ_PROXY_PROVIDERS: [&str; 2] = [
+    "org.apache.hadoop.hdfs.server.namenode.ha.ObserverReadProxyProvider",
+    "org.apache.hadoop.hdfs.server.namenode.ha.ObserverReadProxyProviderWithIPFailover",
+];
+
+// Security settings
+pub(crate) const SECURITY_AUTHENTICATION: &str = "hadoop.security.authentication";
+
+// Kerberos settings
+#[cfg(feature = "kerberos")]
+const KINIT_COMMAND: &str = "hadoop.kerberos.kinit.command";
+#[cfg(feature = "kerberos")]
+const DEFAULT_KINIT_COMMAND: &str = "kinit";
+#[cfg(feature = "kerberos")]
+pub(crate) const KERBEROS_KEYTAB: &str = "hadoop.kerberos.keytab";
+#[cfg(feature = "kerberos")]
+pub(crate) const KERBEROS_PRINCIPAL: &str = "hadoop.kerberos.principal";
+
+// Delegation token settings
+const TOKEN_AUTO_RENEW: &str = "dfs.client.delegation.token.auto-renew";
+
+// Same limit as the Java client to prevent infinite loops
+const MAX_SUBSTITUTIONS: usize = 20;
+
 // Name Service settings
 const HA_NAMENODES_PREFIX: &str = "dfs.ha.namenodes";
 const HA_NAMENODE_RPC_ADDRESS_PREFIX: &str = "dfs.namenode.rpc-address";
@@ -22,16 +132,27 @@ pub struct Configuration {
 }
 
 impl From<HashMap<String, String>> for Configuration {
-    fn from(conf_map: HashMap<String, String>) -> Self {
+    fn from(mut conf_map: HashMap<String, String>) -> Self {
+        Self::substitute_vars(&mut conf_map);
         Self { map: conf_map }
     }
 }
 
 impl Configuration {
+    /// Loads the config files from `$HADOOP_CONF_DIR`, or `$HADOOP_HOME/etc/hadoop`
     pub fn new() -> io::Result<Self> {
+        Self::load(Self::get_conf_dir())
+    }
+
+    /// Loads the config files from `conf_dir`
+    pub fn from_dir(conf_dir: &Path) -> io::Result<Self> {
+        Self::load(Some(conf_dir.to_path_buf()))
+    }
+
+    fn load(conf_dir: Option<PathBuf>) -> io::Result<Self> {
         let mut map: HashMap<String, String> = HashMap::new();
 
-        if let Some(conf_dir) = Self::get_conf_dir() {
+        if let Some(conf_dir) = conf_dir {
             for file in ["core-site.xml", "hdfs-site.xml"] {
                 let config_path = conf_dir.join(file);
                 if config_path.as_path().exists() {
@@ -44,6 +165,8 @@ impl Configuration {
             }
         }
 
+        Self::substitute_vars(&mut map);
+
         Ok(Configuration { map })
     }
 
@@ -52,21 +175,398 @@ impl Configuration {
         self.map.get(key).cloned()
     }
 
-    pub(crate) fn get_urls_for_nameservice(&self, nameservice: &str) -> Vec<String> {
+    /// Get the umask applied to permissions of newly created files and directories. Only the
+    /// octal form of `fs.permissions.umask-mode` is supported, defaulting to 022.
+    pub(crate) fn get_umask(&self) -> u32 {
+        self.map
+            .get(UMASK)
+            .and_then(|umask| u32::from_str_radix(umask.trim(), 8).ok())
+            .unwrap_or(DEFAULT_UMASK)
+    }
+
+    /// Get the replication of new files from `dfs.replication`, if it is set
+    pub(crate) fn get_replication(&self) -> Option<u32> {
+        self.get_parsed(REPLICATION)
+    }
+
+    /// Get the block size of new files from `dfs.blocksize`, if it is set. Like the Java client,
+    /// the size can have a binary prefix suffix such as `128m`.
+    pub(crate) fn get_block_size(&self) -> Option<u64> {
+        self.map.get(BLOCK_SIZE).and_then(|size| parse_size(size))
+    }
+
+    /// Get the maximum size of the packets written to DataNodes from
+    /// `dfs.client-write-packet-size`, if it is set
+    pub(crate) fn get_write_packet_size(&self) -> Option<u32> {
+        self.get_parsed(WRITE_PACKET_SIZE)
+    }
+
+    /// Get the number of packets that can be sent to the DataNodes of a block before they've been
+    /// acknowledged, from `dfs.client.write.max-packets-in-flight`. Defaults to 80.
+    pub(crate) fn get_write_max_packets(&self) -> usize {
+        self.get_parsed(WRITE_MAX_PACKETS)
+            .unwrap_or(DEFAULT_WRITE_MAX_PACKETS)
+    }
+
+    /// Whether a writer dropped without being closed recovers the lease on its file right away,
+    /// from `dfs.client.write.recover-lease-on-drop`. Defaults to false.
+    pub(crate) fn get_recover_lease_on_drop(&self) -> bool {
+        self.get_parsed(RECOVER_LEASE_ON_DROP).unwrap_or(false)
+    }
+
+    /// Get the checksum of new files from `dfs.checksum.type`, if it is set
+    pub(crate) fn get_checksum_type(&self) -> Option<ChecksumType> {
+        self.get_parsed(CHECKSUM_TYPE)
+    }
+
+    /// Get the number of bytes covered by each checksum of new files from
+    /// `dfs.bytes-per-checksum`, if it is set
+    pub(crate) fn get_bytes_per_checksum(&self) -> Option<u32> {
+        self.get_parsed(BYTES_PER_CHECKSUM)
+    }
+
+    /// Get how many minutes files moved to the trash are kept for, from `fs.trash.interval`.
+    /// The trash is disabled when this is 0, which is the default.
+    pub(crate) fn get_trash_interval(&self) -> f64 {
+        self.get_parsed(TRASH_INTERVAL).unwrap_or(0.0)
+    }
+
+    /// Get the URI of the KMS that decrypts the keys of files in encryption zones, from
+    /// `hadoop.security.key.provider.path` or `dfs.encryption.key.provider.uri`, if either is
+    /// set. Otherwise the one from the NameNode's server defaults is used.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn get_key_provider_uri(&self) -> Option<String> {
+        self.map
+            .get(KEY_PROVIDER_PATH)
+            .or_else(|| self.map.get(KEY_PROVIDER_URI))
+            .map(|uri| uri.trim().to_string())
+            .filter(|uri| !uri.is_empty())
+    }
+
+    /// Get the timeout for requests to the KMS, from `hadoop.security.kms.client.timeout` in
+    /// seconds.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn get_kms_client_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.get_parsed(KMS_CLIENT_TIMEOUT)
+                .unwrap_or(DEFAULT_KMS_CLIENT_TIMEOUT_SECS),
+        )
+    }
+
+    /// Get the timeout for connecting to and reading from DataNodes, from
+    /// `dfs.client.socket-timeout` in milliseconds.
+    pub(crate) fn get_socket_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.get_parsed(SOCKET_TIMEOUT)
+                .unwrap_or(DEFAULT_SOCKET_TIMEOUT_MS),
+        )
+    }
+
+    /// Get the SASL qualities of protection to negotiate with DataNodes, from the comma
+    /// separated `authentication`, `integrity` and `privacy` values of
+    /// `dfs.data.transfer.protection`. Empty if data transfer protection isn't enabled.
+    pub(crate) fn get_data_transfer_protection(&self) -> Vec<&'static str> {
         self.map
+            .get(DATA_TRANSFER_PROTECTION)
+            .map(|protection| {
+                protection
+                    .split(',')
+                    .filter_map(|value| match value.trim().to_lowercase().as_str() {
+                        "authentication" => Some("auth"),
+                        "integrity" => Some("auth-int"),
+                        "privacy" => Some("auth-conf"),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the number of blocks to start fetching ahead of the block being read, from
+    /// `dfs.client.read-ahead.blocks`. Defaults to 1, and 0 disables read-ahead.
+    pub(crate) fn get_read_ahead_blocks(&self) -> usize {
+        self.get_parsed(READ_AHEAD_BLOCKS)
+            .unwrap_or(DEFAULT_READ_AHEAD_BLOCKS)
+    }
+
+    /// Get the number of blocks fetched at the same time by a read of a range that spans several
+    /// blocks, from `dfs.client.read.parallelism`. Defaults to 1, which reads them in order.
+    pub(crate) fn get_read_parallelism(&self) -> usize {
+        self.get_parsed(READ_PARALLELISM)
+            .unwrap_or(DEFAULT_READ_PARALLELISM)
+            .max(1)
+    }
+
+    /// Whether DataNodes send checksums with the data that's read and the client verifies them,
+    /// from `dfs.client.read.verify.checksum`. Defaults to true.
+    pub(crate) fn get_verify_checksum(&self) -> bool {
+        self.get_parsed(VERIFY_CHECKSUM).unwrap_or(true)
+    }
+
+    /// Whether DataNodes are connected to by the hostnames they registered with instead of
+    /// their IP addresses, from `dfs.client.use.datanode.hostname`. Defaults to false.
+    pub(crate) fn get_use_datanode_hostname(&self) -> bool {
+        self.get_parsed(USE_DATANODE_HOSTNAME).unwrap_or(false)
+    }
+
+    /// Get the maximum number of bytes of buffers kept for reuse by DataNode reads, from
+    /// `dfs.client.read.buffer.pool.size`. Defaults to 0, which allocates a new buffer for every
+    /// packet.
+    pub(crate) fn get_read_buffer_pool_size(&self) -> usize {
+        self.get_parsed(READ_BUFFER_POOL_SIZE).unwrap_or(0)
+    }
+
+    /// Get the maximum number of idle DataNode connections a file reader keeps open for reuse,
+    /// from `dfs.client.socketcache.capacity`. Defaults to 16, and 0 disables reuse.
+    pub(crate) fn get_socket_cache_capacity(&self) -> usize {
+        self.get_parsed(SOCKET_CACHE_CAPACITY)
+            .unwrap_or(DEFAULT_SOCKET_CACHE_CAPACITY)
+    }
+
+    /// Get how long an idle DataNode connection is kept for reuse, from
+    /// `dfs.client.socketcache.expiryMsec` in milliseconds.
+    pub(crate) fn get_socket_cache_expiry(&self) -> Duration {
+        Duration::from_millis(
+            self.get_parsed(SOCKET_CACHE_EXPIRY)
+                .unwrap_or(DEFAULT_SOCKET_CACHE_EXPIRY_MS),
+        )
+    }
+
+    /// Get the path of the UNIX domain socket of DataNodes for short-circuit reads, from
+    /// `dfs.domain.socket.path`. Only set if `dfs.client.read.shortcircuit` is true.
+    pub(crate) fn get_short_circuit_socket_path(&self) -> Option<String> {
+        if !self.get_parsed(SHORT_CIRCUIT_READS).unwrap_or(false) {
+            return None;
+        }
+        self.map
+            .get(DOMAIN_SOCKET_PATH)
+            .map(|path| path.trim().to_string())
+            .filter(|path| !path.is_empty())
+    }
+
+    /// Get how long to wait for the response to a NameNode call, from `ipc.client.rpc-timeout.ms`.
+    /// Defaults to 2 minutes, and 0 waits indefinitely.
+    pub(crate) fn get_rpc_timeout(&self) -> Option<Duration> {
+        Some(Duration::from_millis(
+            self.get_parsed(RPC_TIMEOUT)
+                .unwrap_or(DEFAULT_RPC_TIMEOUT_MS),
+        ))
+        .filter(|timeout| !timeout.is_zero())
+    }
+
+    /// Get the timeout for establishing a connection to a NameNode, from
+    /// `ipc.client.connect.timeout` in milliseconds.
+    pub(crate) fn get_connect_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.get_parsed(CONNECT_TIMEOUT)
+                .unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS),
+        )
+    }
+
+    /// Get how long a NameNode connection can go without sending anything before a ping is
+    /// sent to keep it open, from `ipc.ping.interval` in milliseconds. Defaults to 1 minute, and
+    /// `None` if `ipc.client.ping` is false or the interval is 0.
+    pub(crate) fn get_ping_interval(&self) -> Option<Duration> {
+        if !self.get_parsed(PING).unwrap_or(true) {
+            return None;
+        }
+        Some(Duration::from_millis(
+            self.get_parsed(PING_INTERVAL)
+                .unwrap_or(DEFAULT_PING_INTERVAL_MS),
+        ))
+        .filter(|interval| !interval.is_zero())
+    }
+
+    /// Get how long a NameNode connection is idle before TCP keepalive probes are sent, from
+    /// `ipc.client.tcp.keepalive.time.ms`. Defaults to `None`, which uses the OS setting.
+    pub(crate) fn get_tcp_keepalive_time(&self) -> Option<Duration> {
+        self.get_parsed(TCP_KEEPALIVE_TIME)
+            .map(Duration::from_millis)
+            .filter(|time| !time.is_zero())
+    }
+
+    /// Get the maximum number of times a NameNode call is retried after a transient failure,
+    /// from `dfs.client.retry.max.attempts`.
+    pub(crate) fn get_retry_max_attempts(&self) -> usize {
+        self.get_parsed(RETRY_MAX_ATTEMPTS)
+            .unwrap_or(DEFAULT_RETRY_MAX_ATTEMPTS)
+    }
+
+    /// Get the base and maximum exponential backoff between retries of a NameNode call, from
+    /// `dfs.client.retry.sleep.base.millis` and `dfs.client.retry.sleep.max.millis`.
+    pub(crate) fn get_retry_sleep(&self) -> (Duration, Duration) {
+        (
+            Duration::from_millis(
+                self.get_parsed(RETRY_SLEEP_BASE)
+                    .unwrap_or(DEFAULT_RETRY_SLEEP_BASE_MS),
+            ),
+            Duration::from_millis(
+                self.get_parsed(RETRY_SLEEP_MAX)
+                    .unwrap_or(DEFAULT_RETRY_SLEEP_MAX_MS),
+            ),
+        )
+    }
+
+    /// Get how long a NameNode call rejected because the NameNode is in safe mode is retried,
+    /// from `dfs.client.safemode.wait.millis`. Defaults to 0, which fails immediately.
+    pub(crate) fn get_safe_mode_wait(&self) -> Option<Duration> {
+        Some(Duration::from_millis(
+            self.get_parsed(SAFE_MODE_WAIT)
+                .unwrap_or(DEFAULT_SAFE_MODE_WAIT_MS),
+        ))
+        .filter(|wait| !wait.is_zero())
+    }
+
+    /// Get how often the lease on files open for writing is renewed, which is half of
+    /// `dfs.client.lease.soft-limit.millis`. Defaults to 30 seconds.
+    pub(crate) fn get_lease_renewal_interval(&self) -> Duration {
+        Duration::from_millis(
+            self.get_parsed(LEASE_SOFT_LIMIT)
+                .unwrap_or(DEFAULT_LEASE_SOFT_LIMIT_MS),
+        ) / 2
+    }
+
+    /// Get the maximum number of times a NameNode call fails over to another NameNode before
+    /// giving up, from `dfs.client.failover.max.attempts`.
+    pub(crate) fn get_failover_max_attempts(&self) -> usize {
+        self.get_parsed(FAILOVER_MAX_ATTEMPTS)
+            .unwrap_or(DEFAULT_FAILOVER_MAX_ATTEMPTS)
+    }
+
+    /// Get the base and maximum backoff between failover attempts once every NameNode has been
+    /// tried, from `dfs.client.failover.sleep.base.millis` and `dfs.client.failover.sleep.max.millis`.
+    pub(crate) fn get_failover_sleep(&self) -> (Duration, Duration) {
+        (
+            Duration::from_millis(
+                self.get_parsed(FAILOVER_SLEEP_BASE)
+                    .unwrap_or(DEFAULT_FAILOVER_SLEEP_BASE_MS),
+            ),
+            Duration::from_millis(
+                self.get_parsed(FAILOVER_SLEEP_MAX)
+                    .unwrap_or(DEFAULT_FAILOVER_SLEEP_MAX_MS),
+            ),
+        )
+    }
+
+    /// Whether read-only calls to a name service should be sent to Observer NameNodes. Like the
+    /// Java client, this is enabled by setting `dfs.client.failover.proxy.provider.<nameservice>`
+    /// to `ObserverReadProxyProvider`.
+    pub(crate) fn get_observer_reads_enabled(&self, nameservice: &str) -> bool {
+        self.map
+            .get(&format!(
+                "{}.{}",
+                FAILOVER_PROXY_PROVIDER_PREFIX, nameservice
+            ))
+            .is_some_and(|provider| OBSERVER_READ_PROXY_PROVIDERS.contains(&provider.trim()))
+    }
+
+    /// Whether `hadoop.security.authentication` is set to `simple`, in which case NameNode
+    /// connections skip the SASL exchange unless there is a delegation token to use
+    pub(crate) fn get_simple_auth_enabled(&self) -> bool {
+        self.map
+            .get(SECURITY_AUTHENTICATION)
+            .is_some_and(|auth| auth.trim().eq_ignore_ascii_case("simple"))
+    }
+
+    /// Whether `hadoop.security.authentication` is set to `kerberos`
+    #[cfg(any(feature = "kerberos", feature = "encryption", feature = "webhdfs"))]
+    pub(crate) fn get_kerberos_enabled(&self) -> bool {
+        self.map
+            .get(SECURITY_AUTHENTICATION)
+            .is_some_and(|auth| auth.trim().eq_ignore_ascii_case("kerberos"))
+    }
+
+    /// Get the command used to obtain and renew Kerberos tickets, from
+    /// `hadoop.kerberos.kinit.command`.
+    #[cfg(feature = "kerberos")]
+    pub(crate) fn get_kinit_command(&self) -> String {
+        self.map
+            .get(KINIT_COMMAND)
+            .map(|command| command.trim().to_string())
+            .unwrap_or(DEFAULT_KINIT_COMMAND.to_string())
+    }
+
+    /// Get the keytab and principal to log in with, if both `hadoop.kerberos.keytab` and
+    /// `hadoop.kerberos.principal` are set.
+    #[cfg(feature = "kerberos")]
+    pub(crate) fn get_kerberos_keytab(&self) -> Option<(String, String)> {
+        Some((
+            self.map.get(KERBEROS_KEYTAB)?.trim().to_string(),
+            self.map.get(KERBEROS_PRINCIPAL)?.trim().to_string(),
+        ))
+    }
+
+    /// Whether a delegation token loaded from `$HADOOP_TOKEN_FILE_LOCATION` should be renewed
+    /// before it expires, from `dfs.client.delegation.token.auto-renew`. Defaults to false.
+    pub(crate) fn get_token_auto_renew(&self) -> bool {
+        self.get_parsed(TOKEN_AUTO_RENEW).unwrap_or(false)
+    }
+
+    /// Applies settings on top of the ones already loaded, such as from the config files.
+    pub(crate) fn set_all(&mut self, settings: HashMap<String, String>) {
+        self.map.extend(settings);
+        Self::substitute_vars(&mut self.map);
+    }
+
+    fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.map
+            .get(key)
+            .and_then(|value| value.trim().parse().ok())
+    }
+
+    /// Get when a DataNode that fails during a write is replaced with a new one, from
+    /// `dfs.client.block.write.replace-datanode-on-failure.policy`. This is one of `NEVER`,
+    /// `DEFAULT` or `ALWAYS`, and is `NEVER` if
+    /// `dfs.client.block.write.replace-datanode-on-failure.enable` is false.
+    pub(crate) fn get_replace_datanode_policy(&self) -> String {
+        if !self.get_parsed(REPLACE_DATANODE_ON_FAILURE).unwrap_or(true) {
+            return "NEVER".to_string();
+        }
+        self.map
+            .get(REPLACE_DATANODE_POLICY)
+            .map(|policy| policy.trim().to_uppercase())
+            .unwrap_or(DEFAULT_REPLACE_DATANODE_POLICY.to_string())
+    }
+
+    /// Whether writing continues with the remaining DataNodes when a failed one can't be
+    /// replaced, from `dfs.client.block.write.replace-datanode-on-failure.best-effort`. Defaults
+    /// to false.
+    pub(crate) fn get_replace_datanode_best_effort(&self) -> bool {
+        self.get_parsed(REPLACE_DATANODE_BEST_EFFORT)
+            .unwrap_or(false)
+    }
+
+    /// Get the RPC addresses of the NameNodes for a name service. HA name services list their
+    /// NameNodes in `dfs.ha.namenodes.<nameservice>`, otherwise the single NameNode address from
+    /// `dfs.namenode.rpc-address.<nameservice>` is used if it exists.
+    pub(crate) fn get_urls_for_nameservice(&self, nameservice: &str) -> Vec<String> {
+        if let Some(namenodes) = self
+            .map
             .get(&format!("{}.{}", HA_NAMENODES_PREFIX, nameservice))
-            .into_iter()
-            .flat_map(|namenodes| {
-                namenodes.split(',').flat_map(|namenode_id| {
+        {
+            namenodes
+                .split(',')
+                .flat_map(|namenode_id| {
                     self.map
                         .get(&format!(
                             "{}.{}.{}",
-                            HA_NAMENODE_RPC_ADDRESS_PREFIX, nameservice, namenode_id
+                            HA_NAMENODE_RPC_ADDRESS_PREFIX,
+                            nameservice,
+                            namenode_id.trim()
                         ))
                         .map(|s| s.to_string())
                 })
-            })
-            .collect()
+                .collect()
+        } else {
+            self.map
+                .get(&format!(
+                    "{}.{}",
+                    HA_NAMENODE_RPC_ADDRESS_PREFIX, nameservice
+                ))
+                .into_iter()
+                .cloned()
+                .collect()
+        }
     }
 
     pub(crate) fn get_mount_table(&self, cluster: &str) -> Vec<(Option<String>, String)> {
@@ -86,9 +586,48 @@ impl Configuration {
             .collect()
     }
 
+    /// Expand `${name}` references in values like the Java client does. A name is looked up in
+    /// the config itself, or in the environment for `${env.NAME}`. References that can't be
+    /// resolved are left as is.
+    fn substitute_vars(map: &mut HashMap<String, String>) {
+        let expanded: Vec<(String, String)> = map
+            .iter()
+            .filter(|(_, value)| value.contains("${"))
+            .map(|(key, value)| (key.clone(), Self::substitute(value, map)))
+            .collect();
+        map.extend(expanded);
+    }
+
+    fn substitute(value: &str, map: &HashMap<String, String>) -> String {
+        let mut value = value.to_string();
+        for _ in 0..MAX_SUBSTITUTIONS {
+            let Some(start) = value.find("${") else {
+                break;
+            };
+            let Some(len) = value[start..].find('}') else {
+                break;
+            };
+            let name = &value[start + 2..start + len];
+            let replacement = match name.strip_prefix("env.") {
+                Some(var) => env::var(var).ok(),
+                None => map.get(name).cloned(),
+            };
+            match replacement {
+                Some(replacement) => value.replace_range(start..start + len + 1, &replacement),
+                None => break,
+            }
+        }
+        value
+    }
+
     fn read_from_file(path: &Path) -> io::Result<Vec<(String, String)>> {
         let content = fs::read_to_string(path)?;
-        let tree = roxmltree::Document::parse(&content).unwrap();
+        let tree = roxmltree::Document::parse(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to parse {}: {}", path.display(), e),
+            )
+        })?;
 
         let pairs = tree
             .root()
@@ -127,9 +666,44 @@ impl Configuration {
     }
 }
 
+/// Parses a number of bytes with an optional binary prefix suffix, such as `64k` or `1G`
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, exponent) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1),
+        'm' => (&value[..value.len() - 1], 2),
+        'g' => (&value[..value.len() - 1], 3),
+        't' => (&value[..value.len() - 1], 4),
+        'p' => (&value[..value.len() - 1], 5),
+        'e' => (&value[..value.len() - 1], 6),
+        _ => (value, 0),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(1024u64.pow(exponent))
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Configuration, VIEWFS_MOUNTTABLE_PREFIX};
+    use std::fs;
+    use std::io::Write;
+    use std::time::Duration;
+
+    use tempfile::NamedTempFile;
+
+    use super::{
+        parse_size, ChecksumType, Configuration, BLOCK_SIZE, BYTES_PER_CHECKSUM, CHECKSUM_TYPE,
+        CONNECT_TIMEOUT, DATA_TRANSFER_PROTECTION, FAILOVER_MAX_ATTEMPTS,
+        FAILOVER_PROXY_PROVIDER_PREFIX, FAILOVER_SLEEP_BASE, FAILOVER_SLEEP_MAX,
+        HA_NAMENODES_PREFIX, HA_NAMENODE_RPC_ADDRESS_PREFIX, PING, PING_INTERVAL,
+        READ_AHEAD_BLOCKS, READ_BUFFER_POOL_SIZE, READ_PARALLELISM, REPLACE_DATANODE_BEST_EFFORT,
+        REPLACE_DATANODE_ON_FAILURE, REPLACE_DATANODE_POLICY, REPLICATION, RETRY_MAX_ATTEMPTS,
+        RETRY_SLEEP_BASE, RPC_TIMEOUT, SAFE_MODE_WAIT, SECURITY_AUTHENTICATION,
+        SOCKET_CACHE_CAPACITY, SOCKET_CACHE_EXPIRY, TCP_KEEPALIVE_TIME, TRASH_INTERVAL, UMASK,
+        USE_DATANODE_HOSTNAME, VERIFY_CHECKSUM, VIEWFS_MOUNTTABLE_PREFIX, WRITE_MAX_PACKETS,
+    };
 
     #[test]
     fn test_mount_table_config() {
@@ -192,4 +766,454 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn test_umask_config() {
+        let config = Configuration {
+            map: [].into_iter().collect(),
+        };
+        assert_eq!(config.get_umask(), 0o022);
+
+        let config = Configuration {
+            map: [(UMASK.to_string(), "077".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        assert_eq!(config.get_umask(), 0o077);
+
+        let config = Configuration {
+            map: [(UMASK.to_string(), "u=rwx,g=rx,o=rx".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        assert_eq!(config.get_umask(), 0o022);
+    }
+
+    #[test]
+    fn test_read_from_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            br#"<?xml version="1.0"?>
+<configuration>
+  <property>
+    <name>fs.defaultFS</name>
+    <value>hdfs://${nameservice}</value>
+  </property>
+  <property>
+    <name>nameservice</name>
+    <value>ns1</value>
+  </property>
+  <property>
+    <name>home</name>
+    <value>${env.HDFS_NATIVE_TEST_MISSING_VAR}</value>
+  </property>
+</configuration>"#,
+        )
+        .unwrap();
+
+        let config = Configuration::from(
+            Configuration::read_from_file(file.path())
+                .unwrap()
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get("fs.defaultFS").unwrap(), "hdfs://ns1");
+        // Unresolved references are left alone
+        assert_eq!(
+            config.get("home").unwrap(),
+            "${env.HDFS_NATIVE_TEST_MISSING_VAR}"
+        );
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"<configuration>").unwrap();
+        assert!(Configuration::read_from_file(file.path()).is_err());
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("core-site.xml"),
+            r#"<configuration>
+  <property><name>fs.defaultFS</name><value>hdfs://core</value></property>
+  <property><name>dfs.replication</name><value>2</value></property>
+</configuration>"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("hdfs-site.xml"),
+            r#"<configuration>
+  <property><name>dfs.replication</name><value>1</value></property>
+</configuration>"#,
+        )
+        .unwrap();
+
+        // hdfs-site.xml takes precedence over core-site.xml
+        let config = Configuration::from_dir(dir.path()).unwrap();
+        assert_eq!(config.get("fs.defaultFS").unwrap(), "hdfs://core");
+        assert_eq!(config.get("dfs.replication").unwrap(), "1");
+
+        let config = Configuration::from_dir(&dir.path().join("missing")).unwrap();
+        assert!(config.get("fs.defaultFS").is_none());
+    }
+
+    #[test]
+    fn test_trash_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_trash_interval(), 0.0);
+
+        let config = Configuration::from(
+            [(TRASH_INTERVAL.to_string(), "1440".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_trash_interval(), 1440.0);
+    }
+
+    #[test]
+    fn test_write_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_replication(), None);
+        assert_eq!(config.get_block_size(), None);
+        assert_eq!(config.get_checksum_type(), None);
+        assert_eq!(config.get_bytes_per_checksum(), None);
+        assert_eq!(config.get_write_max_packets(), 80);
+        assert!(!config.get_recover_lease_on_drop());
+
+        let config = Configuration::from(
+            [
+                (REPLICATION.to_string(), "2".to_string()),
+                (BLOCK_SIZE.to_string(), "256m".to_string()),
+                (CHECKSUM_TYPE.to_string(), "crc32".to_string()),
+                (BYTES_PER_CHECKSUM.to_string(), "1024".to_string()),
+                (WRITE_MAX_PACKETS.to_string(), "16".to_string()),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_replication(), Some(2));
+        assert_eq!(config.get_block_size(), Some(256 * 1024 * 1024));
+        assert_eq!(config.get_checksum_type(), Some(ChecksumType::Crc32));
+        assert_eq!(config.get_bytes_per_checksum(), Some(1024));
+        assert_eq!(config.get_write_max_packets(), 16);
+
+        assert_eq!(parse_size("134217728"), Some(134217728));
+        assert_eq!(parse_size(" 64K "), Some(64 * 1024));
+        assert_eq!(parse_size("1g"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_size("m"), None);
+        assert_eq!(parse_size("16e"), None);
+    }
+
+    #[test]
+    fn test_rpc_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_rpc_timeout(), Some(Duration::from_secs(120)));
+        assert_eq!(config.get_connect_timeout(), Duration::from_secs(20));
+        assert_eq!(config.get_retry_max_attempts(), 10);
+        assert_eq!(
+            config.get_retry_sleep(),
+            (Duration::from_millis(100), Duration::from_millis(5000))
+        );
+        assert_eq!(config.get_safe_mode_wait(), None);
+        assert_eq!(config.get_ping_interval(), Some(Duration::from_secs(60)));
+        assert_eq!(config.get_tcp_keepalive_time(), None);
+
+        let config = Configuration::from(
+            [
+                (RPC_TIMEOUT.to_string(), "0".to_string()),
+                (CONNECT_TIMEOUT.to_string(), "500".to_string()),
+                (PING_INTERVAL.to_string(), "10000".to_string()),
+                (TCP_KEEPALIVE_TIME.to_string(), "30000".to_string()),
+                (RETRY_MAX_ATTEMPTS.to_string(), "2".to_string()),
+                (RETRY_SLEEP_BASE.to_string(), "10".to_string()),
+                (SAFE_MODE_WAIT.to_string(), "60000".to_string()),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_rpc_timeout(), None);
+        assert_eq!(config.get_connect_timeout(), Duration::from_millis(500));
+        assert_eq!(config.get_retry_max_attempts(), 2);
+        assert_eq!(
+            config.get_retry_sleep(),
+            (Duration::from_millis(10), Duration::from_millis(5000))
+        );
+        assert_eq!(config.get_safe_mode_wait(), Some(Duration::from_secs(60)));
+        assert_eq!(config.get_ping_interval(), Some(Duration::from_secs(10)));
+        assert_eq!(
+            config.get_tcp_keepalive_time(),
+            Some(Duration::from_secs(30))
+        );
+
+        let config = Configuration::from(
+            [(PING.to_string(), "false".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_ping_interval(), None);
+    }
+
+    #[test]
+    fn test_simple_auth_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert!(!config.get_simple_auth_enabled());
+
+        let config = Configuration::from(
+            [(SECURITY_AUTHENTICATION.to_string(), " SIMPLE ".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert!(config.get_simple_auth_enabled());
+
+        let config = Configuration::from(
+            [(SECURITY_AUTHENTICATION.to_string(), "kerberos".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert!(!config.get_simple_auth_enabled());
+    }
+
+    #[test]
+    fn test_failover_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_failover_max_attempts(), 15);
+        assert_eq!(
+            config.get_failover_sleep(),
+            (Duration::from_millis(500), Duration::from_millis(15000))
+        );
+
+        let config = Configuration::from(
+            [
+                (FAILOVER_MAX_ATTEMPTS.to_string(), "3".to_string()),
+                (FAILOVER_SLEEP_BASE.to_string(), "10".to_string()),
+                (FAILOVER_SLEEP_MAX.to_string(), "invalid".to_string()),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_failover_max_attempts(), 3);
+        assert_eq!(
+            config.get_failover_sleep(),
+            (Duration::from_millis(10), Duration::from_millis(15000))
+        );
+    }
+
+    #[test]
+    fn test_read_ahead_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_read_ahead_blocks(), 1);
+
+        let config = Configuration::from(
+            [(READ_AHEAD_BLOCKS.to_string(), "0".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_read_ahead_blocks(), 0);
+    }
+
+    #[test]
+    fn test_read_parallelism_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_read_parallelism(), 1);
+
+        let config = Configuration::from(
+            [(READ_PARALLELISM.to_string(), "8".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_read_parallelism(), 8);
+
+        // Reading no blocks at a time isn't possible
+        let config = Configuration::from(
+            [(READ_PARALLELISM.to_string(), "0".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_read_parallelism(), 1);
+    }
+
+    #[test]
+    fn test_verify_checksum_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert!(config.get_verify_checksum());
+
+        let config = Configuration::from(
+            [(VERIFY_CHECKSUM.to_string(), "false".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert!(!config.get_verify_checksum());
+    }
+
+    #[test]
+    fn test_use_datanode_hostname_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert!(!config.get_use_datanode_hostname());
+
+        let config = Configuration::from(
+            [(USE_DATANODE_HOSTNAME.to_string(), "true".to_string())]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert!(config.get_use_datanode_hostname());
+    }
+
+    #[test]
+    fn test_read_buffer_pool_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_read_buffer_pool_size(), 0);
+
+        let config = Configuration::from(
+            [(
+                READ_BUFFER_POOL_SIZE.to_string(),
+                (64 * 1024 * 1024).to_string(),
+            )]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_read_buffer_pool_size(), 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_socket_cache_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_socket_cache_capacity(), 16);
+        assert_eq!(
+            config.get_socket_cache_expiry(),
+            Duration::from_millis(3000)
+        );
+
+        let config = Configuration::from(
+            [
+                (SOCKET_CACHE_CAPACITY.to_string(), "0".to_string()),
+                (SOCKET_CACHE_EXPIRY.to_string(), "500".to_string()),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_socket_cache_capacity(), 0);
+        assert_eq!(config.get_socket_cache_expiry(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_replace_datanode_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert_eq!(config.get_replace_datanode_policy(), "DEFAULT");
+        assert!(!config.get_replace_datanode_best_effort());
+
+        let config = Configuration::from(
+            [
+                (REPLACE_DATANODE_POLICY.to_string(), " always ".to_string()),
+                (REPLACE_DATANODE_BEST_EFFORT.to_string(), "true".to_string()),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_replace_datanode_policy(), "ALWAYS");
+        assert!(config.get_replace_datanode_best_effort());
+
+        let config = Configuration::from(
+            [
+                (REPLACE_DATANODE_ON_FAILURE.to_string(), "false".to_string()),
+                (REPLACE_DATANODE_POLICY.to_string(), "ALWAYS".to_string()),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(config.get_replace_datanode_policy(), "NEVER");
+    }
+
+    #[test]
+    fn test_data_transfer_protection_config() {
+        let config = Configuration::from(std::collections::HashMap::new());
+        assert!(config.get_data_transfer_protection().is_empty());
+
+        let config = Configuration::from(
+            [(
+                DATA_TRANSFER_PROTECTION.to_string(),
+                "privacy, Integrity,unknown,authentication".to_string(),
+            )]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert_eq!(
+            config.get_data_transfer_protection(),
+            vec!["auth-conf", "auth-int", "auth"]
+        );
+    }
+
+    #[test]
+    fn test_set_all() {
+        let mut config = Configuration::from(
+            [
+                ("a".to_string(), "file".to_string()),
+                ("b".to_string(), "file".to_string()),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        config.set_all(
+            [("b".to_string(), "${a}-override".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(config.get("a"), Some("file".to_string()));
+        assert_eq!(config.get("b"), Some("file-override".to_string()));
+    }
+
+    #[test]
+    fn test_observer_reads_config() {
+        let config = Configuration::from(
+            [
+                (
+                    format!("{}.ns1", FAILOVER_PROXY_PROVIDER_PREFIX),
+                    "org.apache.hadoop.hdfs.server.namenode.ha.ObserverReadProxyProvider"
+                        .to_string(),
+                ),
+                (
+                    format!("{}.ns2", FAILOVER_PROXY_PROVIDER_PREFIX),
+                    "org.apache.hadoop.hdfs.server.namenode.ha.ConfiguredFailoverProxyProvider"
+                        .to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+        assert!(config.get_observer_reads_enabled("ns1"));
+        assert!(!config.get_observer_reads_enabled("ns2"));
+        assert!(!config.get_observer_reads_enabled("ns3"));
+    }
+
+    #[test]
+    fn test_nameservice_urls() {
+        let config = Configuration::from(
+            [
+                (
+                    format!("{}.ns1", HA_NAMENODES_PREFIX),
+                    "nn1, nn2".to_string(),
+                ),
+                (
+                    format!("{}.ns1.nn1", HA_NAMENODE_RPC_ADDRESS_PREFIX),
+                    "host1:8020".to_string(),
+                ),
+                (
+                    format!("{}.ns1.nn2", HA_NAMENODE_RPC_ADDRESS_PREFIX),
+                    "host2:8020".to_string(),
+                ),
+                (
+                    format!("{}.ns2", HA_NAMENODE_RPC_ADDRESS_PREFIX),
+                    "host3:8020".to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+        );
+
+        assert_eq!(
+            config.get_urls_for_nameservice("ns1"),
+            vec!["host1:8020", "host2:8020"]
+        );
+        assert_eq!(config.get_urls_for_nameservice("ns2"), vec!["host3:8020"]);
+        assert!(config.get_urls_for_nameservice("ns3").is_empty());
+    }
 }
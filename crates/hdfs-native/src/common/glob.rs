@@ -0,0 +1,359 @@
+//! Glob patterns with the same syntax as Hadoop's `GlobPattern`, used by
+//! [Client::glob](crate::Client::glob).
+use crate::error::{HdfsError, Result};
+
+#[derive(Debug, Clone)]
+pub(crate) enum Token {
+    Literal(char),
+    // `?`
+    AnyChar,
+    // `*`
+    AnyString,
+    // `[...]`, as inclusive ranges of characters
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    // `{a,b}`
+    Alternation(Vec<Vec<Token>>),
+}
+
+/// One `/` separated component of a glob pattern
+#[derive(Debug, Clone)]
+pub(crate) enum GlobComponent {
+    /// A file name without any wildcards, with escapes removed
+    Literal(String),
+    Pattern(Vec<Token>),
+}
+
+impl GlobComponent {
+    pub(crate) fn parse(component: &str) -> Result<Self> {
+        let chars: Vec<char> = component.chars().collect();
+        let mut pos = 0;
+        let tokens =
+            parse_sequence(&chars, &mut pos, false).map_err(|e| invalid_pattern(component, e))?;
+
+        if tokens.iter().all(|t| matches!(t, Token::Literal(_))) {
+            Ok(Self::Literal(
+                tokens
+                    .into_iter()
+                    .map(|t| match t {
+                        Token::Literal(c) => c,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+            ))
+        } else {
+            Ok(Self::Pattern(tokens))
+        }
+    }
+
+    /// Whether the file name `name` matches this component
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Literal(literal) => literal == name,
+            Self::Pattern(tokens) => {
+                let chars: Vec<char> = name.chars().collect();
+                matches(tokens, &chars)
+            }
+        }
+    }
+}
+
+fn invalid_pattern(pattern: &str, reason: &str) -> HdfsError {
+    HdfsError::InvalidArgument(format!("Invalid glob pattern {}: {}", pattern, reason))
+}
+
+/// Parses tokens until the end of the pattern, or until the `,` or `}` ending an alternative
+/// when `in_group` is set
+fn parse_sequence(
+    chars: &[char],
+    pos: &mut usize,
+    in_group: bool,
+) -> std::result::Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        if in_group && (c == ',' || c == '}') {
+            return Ok(tokens);
+        }
+        *pos += 1;
+        let token = match c {
+            '\\' => Token::Literal(escaped(chars, pos)?),
+            '?' => Token::AnyChar,
+            '*' => Token::AnyString,
+            '[' => parse_class(chars, pos)?,
+            '{' => {
+                let mut alternatives = Vec::new();
+                loop {
+                    alternatives.push(parse_sequence(chars, pos, true)?);
+                    match chars.get(*pos) {
+                        Some(',') => *pos += 1,
+                        Some('}') => {
+                            *pos += 1;
+                            break;
+                        }
+                        _ => return Err("unclosed group"),
+                    }
+                }
+                Token::Alternation(alternatives)
+            }
+            c => Token::Literal(c),
+        };
+        tokens.push(token);
+    }
+    if in_group {
+        Err("unclosed group")
+    } else {
+        Ok(tokens)
+    }
+}
+
+fn escaped(chars: &[char], pos: &mut usize) -> std::result::Result<char, &'static str> {
+    let c = *chars.get(*pos).ok_or("missing escaped character")?;
+    *pos += 1;
+    Ok(c)
+}
+
+/// Parses a character class after its opening `[`. A leading `!` or `^` negates it.
+fn parse_class(chars: &[char], pos: &mut usize) -> std::result::Result<Token, &'static str> {
+    let negated = matches!(chars.get(*pos), Some('!') | Some('^'));
+    if negated {
+        *pos += 1;
+    }
+    let mut ranges = Vec::new();
+    loop {
+        let start = match chars.get(*pos) {
+            None => return Err("unclosed character class"),
+            Some(']') => {
+                *pos += 1;
+                return Ok(Token::Class { negated, ranges });
+            }
+            Some('\\') => {
+                *pos += 1;
+                escaped(chars, pos)?
+            }
+            Some(c) => {
+                *pos += 1;
+                *c
+            }
+        };
+        if chars.get(*pos) == Some(&'-') && !matches!(chars.get(*pos + 1), None | Some(']')) {
+            *pos += 1;
+            let end = match chars[*pos] {
+                '\\' => {
+                    *pos += 1;
+                    escaped(chars, pos)?
+                }
+                c => {
+                    *pos += 1;
+                    c
+                }
+            };
+            if end < start {
+                return Err("invalid character range");
+            }
+            ranges.push((start, end));
+        } else {
+            ranges.push((start, start));
+        }
+    }
+}
+
+fn matches(tokens: &[Token], name: &[char]) -> bool {
+    let mut starts = vec![false; name.len() + 1];
+    starts[0] = true;
+    match_positions(tokens, name, &starts)[name.len()]
+}
+
+/// Matches `tokens` from every position of `name` set in `starts`, and returns the positions
+/// where a match can end. Tracking every position at once instead of backtracking bounds the
+/// work by the number of tokens times the length of the name, even for patterns like `*a*a*b`.
+fn match_positions(tokens: &[Token], name: &[char], starts: &[bool]) -> Vec<bool> {
+    let mut positions = starts.to_vec();
+    for token in tokens {
+        let mut next = vec![false; name.len() + 1];
+        match token {
+            Token::AnyString => {
+                if let Some(first) = positions.iter().position(|&p| p) {
+                    next[first..].fill(true);
+                }
+            }
+            Token::Alternation(alternatives) => {
+                for alternative in alternatives {
+                    let ends = match_positions(alternative, name, &positions);
+                    for (next, end) in next.iter_mut().zip(ends) {
+                        *next |= end;
+                    }
+                }
+            }
+            _ => {
+                for (i, c) in name.iter().enumerate() {
+                    if positions[i] && matches_char(token, *c) {
+                        next[i + 1] = true;
+                    }
+                }
+            }
+        }
+        if !next.contains(&true) {
+            return next;
+        }
+        positions = next;
+    }
+    positions
+}
+
+fn matches_char(token: &Token, c: char) -> bool {
+    match token {
+        Token::Literal(literal) => *literal == c,
+        Token::AnyChar => true,
+        Token::Class { negated, ranges } => {
+            let in_class = ranges.iter().any(|(start, end)| *start <= c && c <= *end);
+            in_class != *negated
+        }
+        Token::AnyString | Token::Alternation(_) => unreachable!(),
+    }
+}
+
+/// Expands the `{a,b}` groups of `pattern` that contain a `/` into separate patterns, the same
+/// as Hadoop's `GlobExpander`, so every remaining pattern can be matched one path component at
+/// a time.
+pub(crate) fn expand(pattern: &str) -> Result<Vec<String>> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut pos = 0;
+    while pos < chars.len() {
+        match chars[pos] {
+            '\\' => pos += 2,
+            '{' => {
+                let (alternatives, end) = group_alternatives(&chars, pos)
+                    .ok_or_else(|| invalid_pattern(pattern, "unclosed group"))?;
+                if alternatives.iter().any(|a| a.contains('/')) {
+                    let prefix: String = chars[..pos].iter().collect();
+                    let suffix: String = chars[end + 1..].iter().collect();
+                    let mut expanded = Vec::new();
+                    for alternative in alternatives {
+                        expanded.extend(expand(&format!("{}{}{}", prefix, alternative, suffix))?);
+                    }
+                    return Ok(expanded);
+                }
+                pos = end + 1;
+            }
+            _ => pos += 1,
+        }
+    }
+    Ok(vec![pattern.to_string()])
+}
+
+/// Splits the group opened at `start` into its top level alternatives, and returns them with the
+/// position of the closing `}`
+fn group_alternatives(chars: &[char], start: usize) -> Option<(Vec<String>, usize)> {
+    let mut alternatives = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut pos = start + 1;
+    while pos < chars.len() {
+        let c = chars[pos];
+        match c {
+            '\\' => {
+                current.push(c);
+                pos += 1;
+                current.push(*chars.get(pos)?);
+            }
+            '{' => {
+                depth += 1;
+                current.push(c);
+            }
+            '}' if depth == 0 => {
+                alternatives.push(current);
+                return Some((alternatives, pos));
+            }
+            '}' => {
+                depth -= 1;
+                current.push(c);
+            }
+            ',' if depth == 0 => alternatives.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+        pos += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expand, GlobComponent};
+
+    fn glob_matches(pattern: &str, name: &str) -> bool {
+        GlobComponent::parse(pattern).unwrap().matches(name)
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("part-*", "part-00000"));
+        assert!(glob_matches("part-*", "part-"));
+        assert!(!glob_matches("part-*", "_SUCCESS"));
+        assert!(glob_matches("*.parquet", "a.b.parquet"));
+        assert!(glob_matches("*", ".hidden"));
+        assert!(glob_matches("file?", "file1"));
+        assert!(!glob_matches("file?", "file"));
+        assert!(glob_matches("month=[0-9][0-9]", "month=07"));
+        assert!(!glob_matches("month=[0-9][0-9]", "month=7a"));
+        assert!(glob_matches("[!a]*", "bcd"));
+        assert!(!glob_matches("[^a]*", "abc"));
+        assert!(glob_matches("[ab-]", "-"));
+        assert!(glob_matches("{a,b}.txt", "b.txt"));
+        assert!(!glob_matches("{a,b}.txt", "c.txt"));
+        assert!(glob_matches("x{a,b{c,d}}y", "xbdy"));
+        assert!(glob_matches("{,prefix-}name", "name"));
+        assert!(glob_matches("\\*", "*"));
+        assert!(!glob_matches("\\*", "a"));
+        assert!(glob_matches("a,b}", "a,b}"));
+        assert!(glob_matches("*{a,ab}b", "xabb"));
+        assert!(!glob_matches("{a,b}*c", "ab"));
+    }
+
+    #[test]
+    fn test_glob_pathological() {
+        // These took exponential time when every `*` backtracked over the rest of the name
+        let name = "a".repeat(100);
+        assert!(!glob_matches("*a*a*a*a*a*a*a*a*a*a*b", &name));
+        assert!(glob_matches("*a*a*a*a*a*a*a*a*a*a*", &name));
+        assert!(!glob_matches("{*a,*a*a}*{*a,*a*a}*{*a,*a*a}*b", &name));
+    }
+
+    #[test]
+    fn test_glob_components() {
+        assert!(matches!(
+            GlobComponent::parse("year=2023").unwrap(),
+            GlobComponent::Literal(name) if name == "year=2023"
+        ));
+        assert!(matches!(
+            GlobComponent::parse("a\\*b").unwrap(),
+            GlobComponent::Literal(name) if name == "a*b"
+        ));
+        assert!(matches!(
+            GlobComponent::parse("month=*").unwrap(),
+            GlobComponent::Pattern(_)
+        ));
+
+        assert!(GlobComponent::parse("[abc").is_err());
+        assert!(GlobComponent::parse("{a,b").is_err());
+        assert!(GlobComponent::parse("a\\").is_err());
+        assert!(GlobComponent::parse("[z-a]").is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("/data/*.txt").unwrap(), vec!["/data/*.txt"]);
+        assert_eq!(expand("/data/{a,b}").unwrap(), vec!["/data/{a,b}"]);
+        assert_eq!(
+            expand("/data/{a/b,c}/*").unwrap(),
+            vec!["/data/a/b/*", "/data/c/*"]
+        );
+        assert_eq!(
+            expand("/{x,y/z}/{1,2/3}").unwrap(),
+            vec!["/x/1", "/x/2/3", "/y/z/1", "/y/z/2/3"]
+        );
+        assert!(expand("/data/{a/b").is_err());
+    }
+}
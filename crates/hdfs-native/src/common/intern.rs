@@ -0,0 +1,38 @@
+//! Sharing of the strings repeated across the statuses of a listing, such as the owner and group
+//! names that are usually the same for every file in a directory.
+use std::{collections::HashSet, sync::Arc};
+
+#[derive(Debug, Default)]
+pub(crate) struct StringInterner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl StringInterner {
+    /// Returns the shared copy of `value`, adding it if it hasn't been seen before
+    pub(crate) fn intern(&mut self, value: String) -> Arc<str> {
+        if let Some(existing) = self.strings.get(value.as_str()) {
+            return Arc::clone(existing);
+        }
+        let value: Arc<str> = Arc::from(value);
+        self.strings.insert(Arc::clone(&value));
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::StringInterner;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = StringInterner::default();
+        let a = interner.intern("hdfs".to_string());
+        let b = interner.intern("hdfs".to_string());
+        let c = interner.intern("supergroup".to_string());
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(&*c, "supergroup");
+        assert_eq!(interner.strings.len(), 2);
+    }
+}
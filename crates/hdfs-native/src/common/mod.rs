@@ -1 +1,4 @@
+pub(crate) mod buffer;
 pub mod config;
+pub(crate) mod glob;
+pub(crate) mod intern;
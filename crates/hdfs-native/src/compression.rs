@@ -0,0 +1,203 @@
+//! Streaming decompression of whole-file compressed data, enabled by the `compression`
+//! feature.
+//!
+//! ```no_run
+//! # use hdfs_native::Client;
+//! # use hdfs_native::compression::DecompressReader;
+//! # use tokio::io::AsyncReadExt;
+//! # async fn func() -> hdfs_native::Result<()> {
+//! let client = Client::new("hdfs://localhost:9000")?;
+//! let reader = client.read("/data/file.gz").await?.into_async_read();
+//! let mut reader = DecompressReader::detect(reader).await?;
+//! let mut contents = String::new();
+//! reader.read_to_string(&mut contents).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, ZstdDecoder};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, ReadBuf};
+
+use crate::file::FileAsyncReader;
+use crate::Result;
+
+/// Compression formats [DecompressReader] can decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Codec {
+    /// The codec for a file name extension, the same way as Hadoop's `CompressionCodecFactory`:
+    /// `.gz`, `.bz2` or `.zst`. Returns `None` for any other file.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension {
+            "gz" => Some(Self::Gzip),
+            "bz2" => Some(Self::Bzip2),
+            "zst" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The codec whose magic number `header` starts with, if any
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if header.starts_with(b"BZh") {
+            Some(Self::Bzip2)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+enum Decoder<R> {
+    Gzip(GzipDecoder<BufReader<R>>),
+    Bzip2(BzDecoder<BufReader<R>>),
+    Zstd(ZstdDecoder<BufReader<R>>),
+    Uncompressed(BufReader<R>),
+}
+
+/// [AsyncRead] wrapper that decompresses the data of a reader as it is read, usually a
+/// [FileAsyncReader] from [FileReader::into_async_read](crate::file::FileReader::into_async_read).
+/// Only a buffer of the compressed data is kept in memory at a time. Files made of several
+/// concatenated compressed streams, like the output of some Hadoop jobs, are read as one.
+pub struct DecompressReader<R = FileAsyncReader> {
+    decoder: Decoder<R>,
+}
+
+impl<R: AsyncRead + Unpin> DecompressReader<R> {
+    /// Decompresses `reader` with `codec`
+    pub fn new(reader: R, codec: Codec) -> Self {
+        Self::with_codec(BufReader::new(reader), Some(codec))
+    }
+
+    /// Detects the codec from the magic number at the start of `reader`. Data that doesn't start
+    /// with a known magic number is read as is.
+    pub async fn detect(reader: R) -> Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let codec = Codec::detect(reader.fill_buf().await?);
+        Ok(Self::with_codec(reader, codec))
+    }
+
+    fn with_codec(reader: BufReader<R>, codec: Option<Codec>) -> Self {
+        let decoder = match codec {
+            Some(Codec::Gzip) => {
+                let mut decoder = GzipDecoder::new(reader);
+                decoder.multiple_members(true);
+                Decoder::Gzip(decoder)
+            }
+            Some(Codec::Bzip2) => {
+                let mut decoder = BzDecoder::new(reader);
+                decoder.multiple_members(true);
+                Decoder::Bzip2(decoder)
+            }
+            Some(Codec::Zstd) => {
+                let mut decoder = ZstdDecoder::new(reader);
+                decoder.multiple_members(true);
+                Decoder::Zstd(decoder)
+            }
+            None => Decoder::Uncompressed(reader),
+        };
+        Self { decoder }
+    }
+
+    /// The codec the data is decompressed with, or `None` if it is read as is
+    pub fn codec(&self) -> Option<Codec> {
+        match self.decoder {
+            Decoder::Gzip(_) => Some(Codec::Gzip),
+            Decoder::Bzip2(_) => Some(Codec::Bzip2),
+            Decoder::Zstd(_) => Some(Codec::Zstd),
+            Decoder::Uncompressed(_) => None,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecompressReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match &mut self.decoder {
+            Decoder::Gzip(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Bzip2(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Zstd(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Uncompressed(reader) => Pin::new(reader).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_compression::tokio::write::{BzEncoder, GzipEncoder, ZstdEncoder};
+    use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use super::{Codec, DecompressReader};
+
+    async fn compress(mut encoder: impl AsyncWrite + Unpin, data: &[u8]) {
+        encoder.write_all(data).await.unwrap();
+        encoder.shutdown().await.unwrap();
+    }
+
+    async fn decompress(mut reader: DecompressReader<&[u8]>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_decompress() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_be_bytes()).collect();
+
+        let mut gzip = Vec::new();
+        compress(GzipEncoder::new(&mut gzip), &data).await;
+        let mut bzip2 = Vec::new();
+        compress(BzEncoder::new(&mut bzip2), &data).await;
+        let mut zstd = Vec::new();
+        compress(ZstdEncoder::new(&mut zstd), &data).await;
+
+        for (compressed, codec) in [
+            (&gzip, Codec::Gzip),
+            (&bzip2, Codec::Bzip2),
+            (&zstd, Codec::Zstd),
+        ] {
+            let reader = DecompressReader::new(compressed.as_slice(), codec);
+            assert_eq!(decompress(reader).await, data);
+
+            let reader = DecompressReader::detect(compressed.as_slice())
+                .await
+                .unwrap();
+            assert_eq!(reader.codec(), Some(codec));
+            assert_eq!(decompress(reader).await, data);
+        }
+
+        // Uncompressed data is passed through
+        let reader = DecompressReader::detect(data.as_slice()).await.unwrap();
+        assert_eq!(reader.codec(), None);
+        assert_eq!(decompress(reader).await, data);
+
+        // Concatenated streams are read as one
+        let mut concatenated = gzip.clone();
+        concatenated.extend_from_slice(&gzip);
+        let reader = DecompressReader::new(concatenated.as_slice(), Codec::Gzip);
+        assert_eq!(decompress(reader).await, [data.as_slice(), &data].concat());
+    }
+
+    #[test]
+    fn test_codec_from_path() {
+        assert_eq!(Codec::from_path("/data/part-0000.gz"), Some(Codec::Gzip));
+        assert_eq!(Codec::from_path("/data/file.tar.bz2"), Some(Codec::Bzip2));
+        assert_eq!(Codec::from_path("file.zst"), Some(Codec::Zstd));
+        assert_eq!(Codec::from_path("/data.gz/file"), None);
+        assert_eq!(Codec::from_path("/data/file"), None);
+    }
+}
@@ -11,24 +11,58 @@ pub enum HdfsError {
     IOError(#[from] io::Error),
     #[error("data transfer error")]
     DataTransferError(String),
-    #[error("checksums didn't match")]
-    ChecksumError,
+    #[error("invalid block token")]
+    InvalidBlockToken(String),
+    #[error("checksums didn't match for block {block} at offset {offset}")]
+    ChecksumError { block: u64, offset: u64 },
     #[error("invalid path")]
     InvalidPath(String),
     #[error("invalid argument")]
     InvalidArgument(String),
     #[error("failed to parse URL")]
     UrlParseError(#[from] url::ParseError),
+    #[error("invalid URL: {0}")]
+    InvalidUrl(String),
     #[error("file already exists")]
     AlreadyExists(String),
     #[error("operation failed")]
     OperationFailed(String),
     #[error("file not found")]
     FileNotFound(String),
+    #[error("parent directory not found")]
+    ParentNotFound(String),
     #[error("blocks not found")]
     BlocksNotFound(String),
     #[error("path is a directory")]
     IsADirectoryError(String),
+    #[error("directory is not empty")]
+    DirectoryNotEmpty(String),
+    #[error("NameNode is in safe mode")]
+    SafeMode(String),
+    #[error("permission denied")]
+    PermissionDenied(String),
+    /// The NameNode doesn't allow the authenticated user to impersonate the proxy user, which
+    /// needs the `hadoop.proxyuser.*` settings of the real user to include it
+    #[error("proxy user not allowed")]
+    ProxyUserNotAllowed(String),
+    #[error("quota exceeded")]
+    QuotaExceeded(String),
+    #[error("parent path is not a directory")]
+    ParentNotDirectory(String),
+    #[error("file is already being written")]
+    AlreadyBeingCreated(String),
+    #[error("lease expired")]
+    LeaseExpired(String),
+    #[error("path contains a symbolic link")]
+    UnresolvedLink(String),
+    #[error("path is not in a viewfs mount point")]
+    NotInMountpoint(String),
+    #[error("invalid viewfs mount table")]
+    InvalidMountTable(String),
+    #[error("operation across viewfs mount points")]
+    CrossMountpoint(String),
+    #[error("snapshot operation failed")]
+    SnapshotError(String),
     #[error("unsupported erasure coding policy")]
     UnsupportedErasureCodingPolicy(String),
     #[error("erasure coding error")]
@@ -39,10 +73,17 @@ pub enum HdfsError {
     InternalError(String),
     #[error("failed to decode RPC response")]
     InvalidRPCResponse(#[from] DecodeError),
+    /// A `RemoteException` from the NameNode, with the class name of the Java exception and its
+    /// message. Common exceptions are returned as their own variants instead, such as
+    /// [HdfsError::FileNotFound] and [HdfsError::PermissionDenied].
     #[error("RPC error")]
     RPCError(String, String),
+    /// A `RemoteException` after which the NameNode closed the connection, with the class name
+    /// of the Java exception and its message
     #[error("fatal RPC error")]
     FatalRPCError(String, String),
+    #[error("KMS error")]
+    KmsError(String),
     #[error("SASL error")]
     SASLError(String),
     #[cfg(feature = "kerberos")]
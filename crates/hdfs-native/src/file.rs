@@ -1,36 +1,69 @@
+use std::io;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{ready, Context, Poll};
 use std::time::Duration;
 
 use bytes::{BufMut, Bytes, BytesMut};
+use futures::future::BoxFuture;
 use futures::stream::BoxStream;
-use futures::{stream, Stream, StreamExt};
+use futures::{stream, FutureExt, Stream, StreamExt, TryStreamExt};
+use log::{debug, warn};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 use crate::ec::{resolve_ec_policy, EcSchema};
-use crate::hdfs::datanode::{get_block_stream, BlockWriter};
-use crate::hdfs::protocol::NamenodeProtocol;
+use crate::hdfs::connection::DatanodeConnectionCache;
+use crate::hdfs::datanode::{
+    get_block_stream, get_replica_visible_length, BlockRefetcher, BlockWriter, DatanodeConfig,
+    PipelineRecovery, ReadProgress,
+};
+use crate::hdfs::protocol::{LeaseGuard, NamenodeProtocol};
 use crate::proto::hdfs;
+use crate::security::encryption::FileCipher;
 use crate::{HdfsError, Result};
 
 const COMPLETE_RETRY_DELAY_MS: u64 = 500;
 const COMPLETE_RETRIES: u32 = 5;
+// Number of packets buffered for each block being read ahead, which bounds the memory used to
+// roughly 4 MiB per block
+const READ_AHEAD_BUFFER_PACKETS: usize = 64;
 
 pub struct FileReader {
+    protocol: Arc<NamenodeProtocol>,
+    src: String,
     status: hdfs::HdfsFileStatusProto,
     located_blocks: hdfs::LocatedBlocksProto,
     ec_schema: Option<EcSchema>,
+    datanode_config: DatanodeConfig,
+    connection_cache: Arc<DatanodeConnectionCache>,
+    // Decrypts the data of files in encryption zones
+    cipher: Option<Arc<FileCipher>>,
     position: usize,
 }
 
 impl FileReader {
     pub(crate) fn new(
+        protocol: Arc<NamenodeProtocol>,
+        src: String,
         status: hdfs::HdfsFileStatusProto,
         located_blocks: hdfs::LocatedBlocksProto,
         ec_schema: Option<EcSchema>,
+        datanode_config: DatanodeConfig,
+        cipher: Option<FileCipher>,
     ) -> Self {
+        let connection_cache = Arc::new(DatanodeConnectionCache::new(
+            datanode_config.connection_cache_capacity,
+            datanode_config.connection_cache_expiry,
+        ));
         Self {
+            protocol,
+            src,
             status,
             located_blocks,
             ec_schema,
+            datanode_config,
+            connection_cache,
+            cipher: cipher.map(Arc::new),
             position: 0,
         }
     }
@@ -39,6 +72,147 @@ impl FileReader {
         self.status.length as usize
     }
 
+    /// Fetches the current length and block locations of the file from the NameNode, in case it
+    /// has grown since it was opened. This uses `getLocatedFileInfo` like opening the file, so
+    /// polling doesn't update the access time of the file.
+    async fn refresh(&mut self) -> Result<()> {
+        let located_blocks = self
+            .protocol
+            .get_located_file_info(&self.src)
+            .await?
+            .fs
+            .and_then(|status| status.locations)
+            .ok_or_else(|| HdfsError::FileNotFound(self.src.clone()))?;
+
+        self.status.length = located_blocks.file_length;
+        self.located_blocks = located_blocks;
+        self.update_visible_length().await;
+        Ok(())
+    }
+
+    /// Includes the last block in the length of the file while it's still being written. The
+    /// NameNode only knows the length of that block as of the last time the writer updated it,
+    /// so the DataNodes are asked how much of it readers can see, and the block is read up to
+    /// that point. If none of them answer, the length from the NameNode is used.
+    pub(crate) async fn update_visible_length(&mut self) {
+        if self.located_blocks.is_last_block_complete {
+            return;
+        }
+        let Some(last_block) = self.located_blocks.last_block.as_mut() else {
+            return;
+        };
+
+        // Erasure coded files can't be read before they're closed
+        if self.ec_schema.is_none() && !last_block.locs.is_empty() {
+            match get_replica_visible_length(last_block, &self.datanode_config).await {
+                Ok(length) if length > last_block.b.num_bytes() => {
+                    last_block.b.num_bytes = Some(length);
+                    if let Some(block) = self
+                        .located_blocks
+                        .blocks
+                        .last_mut()
+                        .filter(|block| block.b.block_id == last_block.b.block_id)
+                    {
+                        block.b.num_bytes = Some(length);
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => warn!(
+                    "Failed to get the visible length of block {} of {}: {:?}",
+                    last_block.b.block_id, self.src, e
+                ),
+            }
+        }
+
+        self.status.length = u64::max(
+            self.status.length,
+            last_block.offset + last_block.b.num_bytes(),
+        );
+    }
+
+    /// Reads the last `n` bytes of the file, or the whole file if it's shorter than that. The
+    /// length and block locations of the file are fetched again first, so the tail includes any
+    /// data appended since the file was opened. This doesn't change the position in the file.
+    pub async fn read_tail(&mut self, n: usize) -> Result<Bytes> {
+        self.refresh().await?;
+        let len = usize::min(n, self.file_length());
+        self.read_range(self.file_length() - len, len).await
+    }
+
+    /// Returns a stream of the data of the file from the current position, which keeps
+    /// following the file as it grows, like `tail -f`. Once all the data that's currently
+    /// visible has been read, the length and block locations of the file are fetched again
+    /// every `interval`, and any new data is read. Data becomes visible once the writer flushes
+    /// it with [FileWriter::hflush] or [FileWriter::hsync], or closes the file.
+    ///
+    /// The stream ends once the file has been closed and all of its data has been read, and
+    /// after the first error. The position of the reader is advanced past the data returned.
+    pub fn follow(&mut self, interval: Duration) -> impl Stream<Item = Result<Bytes>> + '_ {
+        stream::unfold(
+            (self, None::<BoxStream<'static, Result<Bytes>>>, false),
+            move |(reader, mut current, failed)| async move {
+                if failed {
+                    return None;
+                }
+                loop {
+                    if let Some(stream) = current.as_mut() {
+                        match stream.next().await {
+                            Some(Ok(bytes)) => {
+                                reader.position += bytes.len();
+                                return Some((Ok(bytes), (reader, current, false)));
+                            }
+                            Some(Err(e)) => return Some((Err(e), (reader, None, true))),
+                            None => current = None,
+                        }
+                    }
+
+                    if reader.position < reader.file_length() {
+                        current = Some(
+                            reader
+                                .read_range_stream(reader.position, reader.remaining())
+                                .boxed(),
+                        );
+                        continue;
+                    }
+
+                    if !reader.located_blocks.under_construction {
+                        return None;
+                    }
+                    tokio::time::sleep(interval).await;
+                    if let Err(e) = reader.refresh().await {
+                        return Some((Err(e), (reader, None, true)));
+                    }
+                }
+            },
+        )
+    }
+
+    /// Sets how many blocks are fetched from DataNodes in the background while an earlier
+    /// block is being consumed, overriding `dfs.client.read-ahead.blocks`. Only blocks within
+    /// a requested range are fetched, and setting this to 0 reads one block at a time.
+    pub fn set_read_ahead_blocks(&mut self, blocks: usize) {
+        self.datanode_config.read_ahead_blocks = blocks;
+    }
+
+    /// Sets how many blocks are fetched at the same time by [FileReader::read_range] and the
+    /// other reads into a single buffer, overriding `dfs.client.read.parallelism`. Each block
+    /// is buffered in full before the blocks are joined in order, so this uses up to the size
+    /// of the range in memory like the read itself. Defaults to 1, which reads the blocks in
+    /// order, and values below 1 are treated as 1.
+    pub fn set_read_parallelism(&mut self, blocks: usize) {
+        self.datanode_config.read_parallelism = blocks.max(1);
+    }
+
+    /// Calls `callback` with the total number of bytes this reader has received from DataNodes
+    /// each time more data arrives, which is about every packet (64 KiB by default). This
+    /// includes data fetched ahead of the reads, and the totals can arrive out of order when
+    /// several blocks are fetched at once, so the largest total seen is the progress. The
+    /// callback runs in the read loop and has to return quickly, such as by storing the total
+    /// in an atomic for something else to report.
+    pub fn set_progress_callback(&mut self, callback: impl Fn(usize) + Send + Sync + 'static) {
+        self.datanode_config.progress = Some(ReadProgress::new(Arc::new(callback)));
+    }
+
     pub fn remaining(&self) -> usize {
         if self.position > self.status.length as usize {
             0
@@ -73,13 +247,81 @@ impl FileReader {
         }
     }
 
+    /// Reads `len` bytes at `offset` only from the replicas on `datanode`, which is either the
+    /// `ip:port` data transfer address or the hostname of a DataNode as listed in
+    /// [BlockLocation](crate::client::BlockLocation). Nothing is read from other replicas if it
+    /// fails, so this can be used to compare the replicas of a block. Checksums are verified, so
+    /// a corrupt replica fails with an [HdfsError::ChecksumError].
+    ///
+    /// Fails with an [HdfsError::BlocksNotFound] error if a block in the range has no replica on
+    /// `datanode`, and with an [HdfsError::InvalidArgument] error for erasure coded files, whose
+    /// blocks don't have replicas.
+    ///
+    /// Panics if the requested range is outside of the file
+    pub async fn read_range_from_datanode(
+        &self,
+        offset: usize,
+        len: usize,
+        datanode: &str,
+    ) -> Result<Bytes> {
+        if self.ec_schema.is_some() {
+            return Err(HdfsError::InvalidArgument(format!(
+                "{} is erasure coded and has no replicas",
+                self.src
+            )));
+        }
+
+        let mut buf = BytesMut::with_capacity(len);
+        for (block, block_start, block_len) in self.blocks_in_range(offset, len) {
+            let mut block = block.clone();
+            block.locs.retain(|loc| {
+                format!("{}:{}", loc.id.ip_addr, loc.id.xfer_port) == datanode
+                    || loc.id.host_name == datanode
+            });
+            if block.locs.is_empty() {
+                return Err(HdfsError::BlocksNotFound(format!(
+                    "Block {} has no replica on {}",
+                    block.b.block_id, datanode
+                )));
+            }
+            // Refetching the block token would also bring back the other replicas
+            let mut stream = self.block_stream(block, block_start, block_len, None);
+            while let Some(bytes) = stream.next().await.transpose()? {
+                buf.put(bytes);
+            }
+        }
+        Ok(buf.freeze())
+    }
+
     /// Read up to `len` bytes starting at `offset` into a new [Bytes] object. The returned buffer
     /// could be smaller than `len` if `offset + len` extends beyond the end of the file.
     ///
     /// Panics if the requested range is outside of the file
     pub async fn read_range(&self, offset: usize, len: usize) -> Result<Bytes> {
+        if self.datanode_config.read_parallelism > 1 {
+            let block_streams = self.block_streams(offset, len);
+            if block_streams.len() > 1 {
+                return read_concurrently(
+                    block_streams,
+                    self.datanode_config.read_parallelism,
+                    len,
+                )
+                .await;
+            }
+        }
+
         let mut stream = self.read_range_stream(offset, len).boxed();
+        let first = match stream.next().await.transpose()? {
+            Some(bytes) => bytes,
+            None => return Ok(Bytes::new()),
+        };
+        // A range within a single packet is returned as is, without copying it
+        if first.len() == len {
+            return Ok(first);
+        }
+
         let mut buf = BytesMut::with_capacity(len);
+        buf.put(first);
         while let Some(bytes) = stream.next().await.transpose()? {
             buf.put(bytes);
         }
@@ -90,6 +332,11 @@ impl FileReader {
     ///
     /// Panics if the requested range is outside of the file
     pub async fn read_range_buf(&self, mut buf: &mut [u8], offset: usize) -> Result<()> {
+        if self.datanode_config.read_parallelism > 1 {
+            buf.put(self.read_range(offset, buf.len()).await?);
+            return Ok(());
+        }
+
         let mut stream = self.read_range_stream(offset, buf.len()).boxed();
         while let Some(bytes) = stream.next().await.transpose()? {
             buf.put(bytes);
@@ -98,7 +345,36 @@ impl FileReader {
         Ok(())
     }
 
-    /// Return a stream of `Bytes` objects containing the content of the file
+    /// Read up to `buf.len()` bytes starting at `offset` into the provided slice, without changing
+    /// the internal position in the file. Returns the number of bytes read, which is only less
+    /// than `buf.len()` if the end of the file was reached. This allows reusing the same buffer
+    /// across many reads.
+    pub async fn read_into(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if offset >= self.file_length() {
+            return Ok(0);
+        }
+        let read_bytes = usize::min(buf.len(), self.file_length() - offset);
+        self.read_range_buf(&mut buf[..read_bytes], offset).await?;
+        Ok(read_bytes)
+    }
+
+    /// Convert this reader into an [AsyncRead] that sequentially streams the rest of the file,
+    /// starting at the current position (the beginning of the file for a newly opened reader).
+    /// Blocks are fetched from the DataNodes as they are needed.
+    pub fn into_async_read(self) -> FileAsyncReader {
+        let stream = self
+            .read_range_stream(self.position, self.remaining())
+            .boxed();
+        FileAsyncReader {
+            stream,
+            current: Bytes::new(),
+            position: self.position,
+        }
+    }
+
+    /// Return a stream of `Bytes` objects containing the content of the file. While a block is
+    /// being consumed, up to [FileReader::set_read_ahead_blocks] of the following blocks in the
+    /// range are fetched concurrently into bounded buffers.
     ///
     /// Panics if the requested range is outside of the file
     pub fn read_range_stream(
@@ -106,39 +382,206 @@ impl FileReader {
         offset: usize,
         len: usize,
     ) -> impl Stream<Item = Result<Bytes>> {
+        let block_streams = self.block_streams(offset, len);
+
+        let read_ahead_blocks = self.datanode_config.read_ahead_blocks;
+        if read_ahead_blocks == 0 {
+            stream::iter(block_streams).flatten().boxed()
+        } else {
+            stream::iter(block_streams)
+                .map(|block_stream| async move { read_ahead(block_stream) })
+                .buffered(read_ahead_blocks + 1)
+                .flatten()
+                .boxed()
+        }
+    }
+
+    /// Creates a stream of the data in each block that overlaps the range, decrypted if the
+    /// file is encrypted
+    ///
+    /// Panics if the requested range is outside of the file
+    fn block_streams(&self, offset: usize, len: usize) -> Vec<BoxStream<'static, Result<Bytes>>> {
+        let refetcher = BlockRefetcher {
+            protocol: Arc::clone(&self.protocol),
+            src: self.src.clone(),
+        };
+
+        self.blocks_in_range(offset, len)
+            .map(|(block, block_start, block_len)| {
+                self.block_stream(
+                    block.clone(),
+                    block_start,
+                    block_len,
+                    Some(refetcher.clone()),
+                )
+            })
+            .collect()
+    }
+
+    /// The blocks that overlap the range, with the offset and length of the part of each block
+    /// in the range
+    ///
+    /// Panics if the requested range is outside of the file
+    fn blocks_in_range(
+        &self,
+        offset: usize,
+        len: usize,
+    ) -> impl Iterator<Item = (&hdfs::LocatedBlockProto, usize, usize)> {
         if offset + len > self.file_length() {
             panic!("Cannot read past end of the file");
         }
 
-        let block_streams: Vec<BoxStream<Result<Bytes>>> = self
-            .located_blocks
-            .blocks
-            .iter()
-            .flat_map(move |block| {
-                let block_file_start = block.offset as usize;
-                let block_file_end = block_file_start + block.b.num_bytes() as usize;
-
-                if block_file_start < (offset + len) && block_file_end > offset {
-                    // We need to read this block
-                    let block_start = offset - usize::min(offset, block_file_start);
-                    let block_end = usize::min(offset + len, block_file_end) - block_file_start;
-                    Some(get_block_stream(
-                        block.clone(),
-                        block_start,
-                        block_end - block_start,
-                        self.ec_schema.clone(),
-                    ))
-                } else {
-                    // No data is needed from this block
-                    None
-                }
+        self.located_blocks.blocks.iter().filter_map(move |block| {
+            let block_file_start = block.offset as usize;
+            let block_file_end = block_file_start + block.b.num_bytes() as usize;
+
+            if block_file_start < (offset + len) && block_file_end > offset {
+                let block_start = offset - usize::min(offset, block_file_start);
+                let block_end = usize::min(offset + len, block_file_end) - block_file_start;
+                Some((block, block_start, block_end - block_start))
+            } else {
+                // No data is needed from this block
+                None
+            }
+        })
+    }
+
+    /// Creates a stream of `len` bytes at `offset` in `block`, decrypted if the file is
+    /// encrypted
+    fn block_stream(
+        &self,
+        block: hdfs::LocatedBlockProto,
+        offset: usize,
+        len: usize,
+        refetcher: Option<BlockRefetcher>,
+    ) -> BoxStream<'static, Result<Bytes>> {
+        let position = block.offset + offset as u64;
+        let block_stream = get_block_stream(
+            block,
+            offset,
+            len,
+            self.ec_schema.clone(),
+            self.datanode_config.clone(),
+            Arc::clone(&self.connection_cache),
+            refetcher,
+        );
+        match self.cipher.clone() {
+            Some(cipher) => decrypted(block_stream, cipher, position),
+            None => block_stream,
+        }
+    }
+}
+
+/// Decrypts the data of `block_stream`, which starts at `position` in the file
+fn decrypted(
+    block_stream: BoxStream<'static, Result<Bytes>>,
+    cipher: Arc<FileCipher>,
+    mut position: u64,
+) -> BoxStream<'static, Result<Bytes>> {
+    block_stream
+        .map(move |result| {
+            result.map(|bytes| {
+                let decrypted = cipher.apply(position, &bytes);
+                position += bytes.len() as u64;
+                decrypted
             })
-            .collect();
+        })
+        .boxed()
+}
+
+async fn read_block(mut block_stream: BoxStream<'static, Result<Bytes>>) -> Result<Bytes> {
+    let mut buf = BytesMut::new();
+    while let Some(bytes) = block_stream.next().await.transpose()? {
+        buf.put(bytes);
+    }
+    Ok(buf.freeze())
+}
+
+/// Reads up to `parallelism` blocks at a time into their own buffers, and joins them in order
+async fn read_concurrently(
+    block_streams: Vec<BoxStream<'static, Result<Bytes>>>,
+    parallelism: usize,
+    len: usize,
+) -> Result<Bytes> {
+    let blocks: Vec<Bytes> = stream::iter(block_streams)
+        .map(read_block)
+        .buffered(parallelism)
+        .boxed()
+        .try_collect()
+        .await?;
 
-        stream::iter(block_streams).flatten()
+    let mut buf = BytesMut::with_capacity(len);
+    for block in blocks {
+        buf.put(block);
     }
+    Ok(buf.freeze())
 }
 
+/// Starts reading `block_stream` in the background, buffering up to
+/// [READ_AHEAD_BUFFER_PACKETS] packets until they are consumed. The background task stops once
+/// the returned stream is dropped.
+fn read_ahead(
+    mut block_stream: BoxStream<'static, Result<Bytes>>,
+) -> BoxStream<'static, Result<Bytes>> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(READ_AHEAD_BUFFER_PACKETS);
+    tokio::spawn(async move {
+        while let Some(result) = block_stream.next().await {
+            let is_err = result.is_err();
+            if sender.send(result).await.is_err() || is_err {
+                break;
+            }
+        }
+    });
+
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|result| (result, receiver))
+    })
+    .boxed()
+}
+
+/// [AsyncRead] wrapper around a [FileReader], created with [FileReader::into_async_read]
+pub struct FileAsyncReader {
+    stream: BoxStream<'static, Result<Bytes>>,
+    current: Bytes,
+    position: usize,
+}
+
+impl FileAsyncReader {
+    /// The current position in the file
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl AsyncRead for FileAsyncReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.current.is_empty() {
+            match ready!(self.stream.poll_next_unpin(cx)) {
+                Some(Ok(bytes)) => self.current = bytes,
+                Some(Err(e)) => return Poll::Ready(Err(io::Error::other(e))),
+                // End of the file
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = usize::min(buf.remaining(), self.current.len());
+        buf.put_slice(&self.current.split_to(len));
+        self.position += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Writes a new file, or appends to an existing one. The file has to be closed with
+/// [FileWriter::close] to complete it on the NameNode. Dropping a writer that wasn't closed logs
+/// a warning and stops renewing its lease. The file then stays open, without any data after the
+/// last flush, until the NameNode recovers the lease once its hard limit expires, or right away
+/// if the client was built with
+/// [ClientBuilder::with_recover_lease_on_drop](crate::ClientBuilder::with_recover_lease_on_drop).
+#[must_use = "the file has to be closed with `FileWriter::close`"]
 pub struct FileWriter {
     src: String,
     protocol: Arc<NamenodeProtocol>,
@@ -146,6 +589,11 @@ pub struct FileWriter {
     server_defaults: hdfs::FsServerDefaultsProto,
     block_writer: Option<BlockWriter>,
     last_block: Option<hdfs::LocatedBlockProto>,
+    datanode_config: DatanodeConfig,
+    // Renews the lease on the file until it's closed
+    lease: Option<LeaseGuard>,
+    // Encrypts the data of files in encryption zones
+    cipher: Option<FileCipher>,
     closed: bool,
     bytes_written: usize,
 }
@@ -158,7 +606,10 @@ impl FileWriter {
         // Some for append, None for create
         last_block: Option<hdfs::LocatedBlockProto>,
         server_defaults: hdfs::FsServerDefaultsProto,
+        datanode_config: DatanodeConfig,
+        cipher: Option<FileCipher>,
     ) -> Self {
+        let lease = Some(protocol.begin_file_lease());
         Self {
             protocol,
             src,
@@ -166,18 +617,65 @@ impl FileWriter {
             server_defaults,
             block_writer: None,
             last_block,
+            datanode_config,
+            lease,
+            cipher,
             closed: false,
             bytes_written: 0,
         }
     }
 
+    fn pipeline_recovery(&self) -> PipelineRecovery {
+        PipelineRecovery {
+            protocol: Arc::clone(&self.protocol),
+            src: self.src.clone(),
+            file_id: self.status.file_id,
+            replication: self.status.block_replication() as usize,
+        }
+    }
+
+    /// Reopens the partial last block of a file for append. The namenode issues a new generation
+    /// stamp for the block, the pipeline is set up with it, and the namenode is then told the
+    /// pipeline has been updated.
+    async fn append_block_writer(&mut self, mut last_block: hdfs::LocatedBlockProto) -> Result<()> {
+        let old_block = last_block.b.clone();
+        let updated_block = self
+            .protocol
+            .update_block_for_pipeline(old_block.clone())
+            .await?
+            .block;
+        last_block.block_token = updated_block.block_token;
+
+        let block_writer = BlockWriter::append(
+            last_block.clone(),
+            updated_block.b.generation_stamp,
+            self.status.blocksize() as usize,
+            self.server_defaults.clone(),
+            &self.datanode_config,
+            self.pipeline_recovery(),
+        )
+        .await?;
+
+        self.protocol
+            .update_pipeline(
+                old_block,
+                block_writer.get_extended_block(),
+                last_block.locs.into_iter().map(|l| l.id).collect(),
+                last_block.storage_i_ds,
+            )
+            .await?;
+
+        self.block_writer = Some(block_writer);
+        Ok(())
+    }
+
     async fn create_block_writer(&mut self) -> Result<()> {
         let new_block = if let Some(last_block) = self.last_block.take() {
             // Append operation on first write. Erasure code appends always just create a new block.
             if last_block.b.num_bytes() < self.status.blocksize() && self.status.ec_policy.is_none()
             {
                 // The last block isn't full, just write data to it
-                last_block
+                return self.append_block_writer(last_block).await;
             } else {
                 // The last block is full, so create a new block to write to
                 self.protocol
@@ -207,6 +705,8 @@ impl FileWriter {
                 .map(resolve_ec_policy)
                 .transpose()?
                 .as_ref(),
+            &self.datanode_config,
+            self.pipeline_recovery(),
         )
         .await?;
 
@@ -231,8 +731,24 @@ impl FileWriter {
         Ok(self.block_writer.as_mut().unwrap())
     }
 
+    /// The position in the file the next write starts at, which is the number of bytes written
+    /// so far plus the length of the file before it was opened for appending. Written data may
+    /// still be buffered until [FileWriter::hflush], [FileWriter::hsync] or
+    /// [FileWriter::close] is called.
+    pub fn pos(&self) -> u64 {
+        self.status.length + self.bytes_written as u64
+    }
+
+    /// The size of the blocks of the file
+    pub fn block_size(&self) -> u64 {
+        self.status.blocksize()
+    }
+
     pub async fn write(&mut self, mut buf: Bytes) -> Result<usize> {
         let bytes_to_write = buf.len();
+        if let Some(cipher) = self.cipher.as_ref() {
+            buf = cipher.apply(self.pos(), &buf);
+        }
         // Create a shallow copy of the bytes instance to mutate and track what's been read
         while !buf.is_empty() {
             let block_writer = self.get_block_writer().await?;
@@ -241,10 +757,62 @@ impl FileWriter {
         }
 
         self.bytes_written += bytes_to_write;
+        self.datanode_config
+            .metrics
+            .record_bytes_written(bytes_to_write);
 
         Ok(bytes_to_write)
     }
 
+    /// Flushes all data written so far to the DataNodes and makes it visible to new readers,
+    /// like `hflush` in Hadoop's `Syncable`. The DataNodes are only guaranteed to hold the data
+    /// in memory, so it can still be lost if all of them fail at the same time. Use
+    /// [FileWriter::hsync] when the data needs to survive that. Erasure coded files don't
+    /// support flushing.
+    pub async fn hflush(&mut self) -> Result<()> {
+        self.flush(false).await
+    }
+
+    /// Like [FileWriter::hflush], but also waits until every DataNode has synced the data to
+    /// disk, like `hsync` in Hadoop's `Syncable`. This is slower, but the data survives the
+    /// DataNodes losing power.
+    pub async fn hsync(&mut self) -> Result<()> {
+        self.flush(true).await
+    }
+
+    async fn flush(&mut self, sync: bool) -> Result<()> {
+        if let Some(block_writer) = self.block_writer.as_mut() {
+            block_writer.flush(sync).await?;
+
+            // Update the length of the file on the NameNode so readers see the flushed data
+            self.protocol
+                .fsync(
+                    &self.src,
+                    self.status.file_id,
+                    block_writer.get_extended_block().num_bytes() as i64,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Convert this writer into an [AsyncWrite]. Each write is handed to the DataNodes in the
+    /// background, and writing is pending until the previous write has been sent, so a slow
+    /// pipeline applies backpressure. Errors from a write are returned by the next call.
+    /// Shutting down the writer closes the file.
+    pub fn into_async_write(self) -> FileAsyncWriter {
+        FileAsyncWriter {
+            writer: Some(self),
+            pending: None,
+        }
+    }
+
+    /// Writes any buffered data, waits for the DataNodes to acknowledge it, and completes the
+    /// file on the NameNode. If this fails, closing can be tried again.
+    ///
+    /// Unlike most close methods this doesn't consume the writer, since completing the file
+    /// can fail transiently, such as when the NameNode hasn't received the replicas of the last
+    /// block yet.
     pub async fn close(&mut self) -> Result<()> {
         if !self.closed {
             if let Some(block_writer) = self.block_writer.as_mut() {
@@ -266,6 +834,7 @@ impl FileWriter {
 
                 if successful {
                     self.closed = true;
+                    self.lease = None;
                     return Ok(());
                 }
 
@@ -280,3 +849,121 @@ impl FileWriter {
         ))
     }
 }
+
+impl Drop for FileWriter {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        // Recovering the lease needs a runtime to send the RPC from
+        let runtime = tokio::runtime::Handle::try_current()
+            .ok()
+            .filter(|_| self.datanode_config.recover_lease_on_drop);
+        let Some(runtime) = runtime else {
+            warn!(
+                "FileWriter for {} was dropped without being closed, so the file stays open until \
+                its lease is recovered",
+                self.src
+            );
+            return;
+        };
+
+        warn!(
+            "FileWriter for {} was dropped without being closed, recovering its lease",
+            self.src
+        );
+        self.lease = None;
+        let protocol = Arc::clone(&self.protocol);
+        let src = self.src.clone();
+        runtime.spawn(async move {
+            match protocol.recover_lease(&src).await {
+                Ok(response) if response.result => debug!("Closed {} by recovering its lease", src),
+                Ok(_) => debug!(
+                    "Started recovering the lease of {}, which is closed once its last block is \
+                    recovered",
+                    src
+                ),
+                Err(e) => warn!("Failed to recover the lease of {}: {:?}", src, e),
+            }
+        });
+    }
+}
+
+/// [AsyncWrite] wrapper around a [FileWriter], created with [FileWriter::into_async_write]
+pub struct FileAsyncWriter {
+    writer: Option<FileWriter>,
+    // A write or close in progress, which owns the writer until it completes
+    pending: Option<BoxFuture<'static, (FileWriter, Result<()>)>>,
+}
+
+impl FileAsyncWriter {
+    /// Waits for the operation in progress, if any, to complete
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(pending) = self.pending.as_mut() {
+            let (writer, result) = ready!(pending.poll_unpin(cx));
+            self.pending = None;
+            self.writer = Some(writer);
+            result.map_err(io::Error::other)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for FileAsyncWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.poll_pending(cx))?;
+
+        let mut writer = self.writer.take().unwrap();
+        if writer.closed {
+            self.writer = Some(writer);
+            return Poll::Ready(Err(io::Error::other(HdfsError::OperationFailed(
+                "File is already closed".to_string(),
+            ))));
+        }
+
+        // Only take up to a packet of data at a time, so large writes don't need to be copied
+        // all at once
+        let len = match writer.server_defaults.write_packet_size as usize {
+            0 => buf.len(),
+            packet_size => usize::min(buf.len(), packet_size),
+        };
+        let data = Bytes::copy_from_slice(&buf[..len]);
+        self.pending = Some(
+            async move {
+                let result = writer.write(data).await.map(|_| ());
+                (writer, result)
+            }
+            .boxed(),
+        );
+        Poll::Ready(Ok(len))
+    }
+
+    /// Waits until all written data has been sent to the DataNodes. This doesn't wait for the
+    /// DataNodes to acknowledge the data, see [FileWriter::hflush] for that.
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_pending(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            ready!(self.poll_pending(cx))?;
+
+            let mut writer = self.writer.take().unwrap();
+            if writer.closed {
+                self.writer = Some(writer);
+                return Poll::Ready(Ok(()));
+            }
+            self.pending = Some(
+                async move {
+                    let result = writer.close().await;
+                    (writer, result)
+                }
+                .boxed(),
+            );
+        }
+    }
+}
@@ -1,40 +1,48 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::default::Default;
-use std::io::ErrorKind;
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use crc::{Crc, CRC_32_CKSUM, CRC_32_ISCSI};
 use log::{debug, error, warn};
 use prost::Message;
-use socket2::SockRef;
+use socket2::{SockRef, TcpKeepalive};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{mpsc, oneshot};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
-    net::{
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-        TcpStream,
-    },
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
     task::{self, JoinHandle},
 };
 use uuid::Uuid;
 
+use crate::common::buffer::BufferPool;
+use crate::common::config::Configuration;
 use crate::proto::common::rpc_response_header_proto::RpcStatusProto;
 use crate::proto::{common, hdfs};
-use crate::security::sasl::{SaslReader, SaslRpcClient, SaslWriter};
-use crate::security::user::UserInfo;
+use crate::security::digest::{DigestSaslSession, Qop};
+use crate::security::sasl::{
+    negotiate_datanode, SaslDatanodeReader, SaslDatanodeWriter, SaslReader, SaslRpcClient,
+    SaslWriter, HDFS_DELEGATION_TOKEN,
+};
+use crate::security::user::{Token, User, UserInfo};
 use crate::{HdfsError, Result};
 
 const PROTOCOL: &str = "org.apache.hadoop.hdfs.protocol.ClientProtocol";
+const DATANODE_PROTOCOL: &str = "org.apache.hadoop.hdfs.protocol.ClientDatanodeProtocol";
 const DATA_TRANSFER_VERSION: u16 = 28;
+// Call ID of pings, which the NameNode doesn't respond to
+const PING_CALL_ID: i32 = -4;
+const AUTHORIZATION_EXCEPTION: &str = "org.apache.hadoop.security.authorize.AuthorizationException";
 
 const MAX_PACKET_HEADER_SIZE: usize = 33;
 
-const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
-const CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+pub(crate) const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
+pub(crate) const CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
 
 // Connect to a remote host and return a TcpStream with standard options we want
 async fn connect(addr: &str) -> Result<TcpStream> {
@@ -104,44 +112,152 @@ impl Default for AlignmentContext {
     }
 }
 
+/// Settings for connections to NameNodes
+#[derive(Debug, Clone)]
+pub(crate) struct RpcConfig {
+    /// Timeout for establishing a connection
+    pub(crate) connect_timeout: Duration,
+    /// Timeout for the response to each call, or `None` to wait indefinitely
+    pub(crate) rpc_timeout: Option<Duration>,
+    /// How long the connection can go without sending anything before a ping is sent, or
+    /// `None` to never ping
+    pub(crate) ping_interval: Option<Duration>,
+    /// Idle time before TCP keepalive probes are sent, or `None` for the OS default
+    pub(crate) tcp_keepalive_time: Option<Duration>,
+    /// Whether to skip the SASL exchange and only send the username when there's no
+    /// delegation token for the NameNode
+    pub(crate) simple_auth: bool,
+    /// Who the client authenticates and acts as
+    pub(crate) user: Arc<User>,
+}
+
+impl RpcConfig {
+    pub(crate) fn new(config: &Configuration, user: Arc<User>) -> Self {
+        Self {
+            connect_timeout: config.get_connect_timeout(),
+            rpc_timeout: config.get_rpc_timeout(),
+            ping_interval: config.get_ping_interval(),
+            tcp_keepalive_time: config.get_tcp_keepalive_time(),
+            simple_auth: config.get_simple_auth_enabled(),
+            user,
+        }
+    }
+}
+
 type CallResult = oneshot::Sender<Result<Bytes>>;
 
+fn connection_closed() -> HdfsError {
+    HdfsError::IOError(io::Error::new(
+        ErrorKind::ConnectionAborted,
+        "Connection to the NameNode was closed",
+    ))
+}
+
+fn timed_out(msg: &str) -> HdfsError {
+    HdfsError::IOError(io::Error::new(ErrorKind::TimedOut, msg))
+}
+
+/// Marks a connection as closed and fails every call still waiting for a response, since they
+/// will never receive one
+fn close_connection(alive: &AtomicBool, call_map: &Mutex<HashMap<i32, CallResult>>) {
+    alive.store(false, Ordering::SeqCst);
+    for (_, call) in call_map.lock().unwrap().drain() {
+        let _ = call.send(Err(connection_closed()));
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct RpcConnection {
     client_id: Vec<u8>,
     user_info: UserInfo,
     next_call_id: AtomicI32,
     alignment_context: Arc<Mutex<AlignmentContext>>,
+    protocol: &'static str,
     call_map: Arc<Mutex<HashMap<i32, CallResult>>>,
+    alive: Arc<AtomicBool>,
+    rpc_timeout: Option<Duration>,
     sender: mpsc::Sender<Vec<u8>>,
     listener: Option<JoinHandle<()>>,
 }
 
 impl RpcConnection {
+    /// Connects to the NameNode at `url`, failing if the connection isn't established within
+    /// the configured connect timeout. Calls on the connection fail if their response doesn't
+    /// arrive within the RPC timeout.
+    ///
+    /// Like the Java client, a ping is sent whenever nothing else has been sent for the ping
+    /// interval, so firewalls and load balancers don't drop the connection while it's idle. A
+    /// connection that turns out to be dead is closed, and the next call reconnects.
     pub(crate) async fn connect(
         url: &str,
         alignment_context: Arc<Mutex<AlignmentContext>>,
         nameservice: Option<&str>,
+        config: &RpcConfig,
+    ) -> Result<Self> {
+        let service = nameservice
+            .map(|ns| format!("ha-hdfs:{ns}"))
+            .unwrap_or(url.to_string());
+        let token = config.user.get_token(HDFS_DELEGATION_TOKEN, &service);
+        Self::connect_protocol(url, PROTOCOL, alignment_context, token, config).await
+    }
+
+    /// Connects to the IPC server of a DataNode at `url`, authenticating with the token of a
+    /// block it holds
+    pub(crate) async fn connect_datanode(
+        url: &str,
+        block_token: &common::TokenProto,
+        config: &RpcConfig,
+    ) -> Result<Self> {
+        // Block tokens are empty when they aren't enabled on the cluster
+        let token = (!block_token.identifier.is_empty()).then(|| Token::from(block_token.clone()));
+        Self::connect_protocol(
+            url,
+            DATANODE_PROTOCOL,
+            Arc::new(Mutex::new(AlignmentContext::default())),
+            token.as_ref(),
+            config,
+        )
+        .await
+    }
+
+    async fn connect_protocol(
+        url: &str,
+        protocol: &'static str,
+        alignment_context: Arc<Mutex<AlignmentContext>>,
+        token: Option<&Token>,
+        config: &RpcConfig,
     ) -> Result<Self> {
         let client_id = Uuid::new_v4().to_bytes_le().to_vec();
         let next_call_id = AtomicI32::new(0);
         let call_map = Arc::new(Mutex::new(HashMap::new()));
 
-        let mut stream = connect(url).await?;
+        // Same as the Java client, a token is always used through SASL. A custom auth provider
+        // always gets to choose how to authenticate.
+        let use_sasl =
+            !config.simple_auth || config.user.auth_provider().is_some() || token.is_some();
+
+        let mut stream = tokio::time::timeout(config.connect_timeout, connect(url))
+            .await
+            .map_err(|_| timed_out("Timed out connecting to the NameNode"))??;
+        if let Some(time) = config.tcp_keepalive_time {
+            SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
         stream.write_all("hrpc".as_bytes()).await?;
         // Current version
         stream.write_all(&[9u8]).await?;
         // Service class
         stream.write_all(&[0u8]).await?;
-        // Auth protocol
-        stream.write_all(&(-33i8).to_be_bytes()).await?;
+        // Auth protocol, either SASL or none for SIMPLE authentication
+        let auth_protocol: i8 = if use_sasl { -33 } else { 0 };
+        stream.write_all(&auth_protocol.to_be_bytes()).await?;
 
         let mut client = SaslRpcClient::create(stream);
 
-        let service = nameservice
-            .map(|ns| format!("ha-hdfs:{ns}"))
-            .unwrap_or(url.to_string());
-        let user_info = client.negotiate(service.as_str()).await?;
+        let user_info = if use_sasl {
+            client.negotiate(token, &config.user).await?
+        } else {
+            config.user.get_simpler_user()?
+        };
         let (reader, writer) = client.split();
         let (sender, receiver) = mpsc::channel::<Vec<u8>>(1000);
 
@@ -150,12 +266,23 @@ impl RpcConnection {
             user_info,
             next_call_id,
             alignment_context,
+            protocol,
             call_map,
+            alive: Arc::new(AtomicBool::new(true)),
+            rpc_timeout: config.rpc_timeout,
             listener: None,
             sender,
         };
 
-        conn.start_sender(receiver, writer);
+        let ping = config.ping_interval.map(|interval| {
+            let header = conn
+                .get_connection_header(PING_CALL_ID, -1)
+                .encode_length_delimited_to_vec();
+            let mut ping = (header.len() as u32).to_be_bytes().to_vec();
+            ping.extend(header);
+            (interval, ping)
+        });
+        conn.start_sender(receiver, writer, ping);
 
         let context_header = conn
             .get_connection_header(-3, -1)
@@ -171,12 +298,37 @@ impl RpcConnection {
         Ok(conn)
     }
 
-    fn start_sender(&mut self, mut rx: mpsc::Receiver<Vec<u8>>, mut writer: SaslWriter) {
+    /// Writes messages to the NameNode until the connection is dropped, and sends `ping` when
+    /// nothing else has been written for its interval
+    fn start_sender(
+        &mut self,
+        mut rx: mpsc::Receiver<Vec<u8>>,
+        mut writer: SaslWriter,
+        ping: Option<(Duration, Vec<u8>)>,
+    ) {
+        let call_map = Arc::clone(&self.call_map);
+        let alive = Arc::clone(&self.alive);
         task::spawn(async move {
-            while let Some(msg) = rx.recv().await {
-                match writer.write(&msg).await {
-                    Ok(_) => (),
-                    Err(_) => break,
+            loop {
+                let msg = match ping.as_ref() {
+                    Some((interval, ping)) => {
+                        match tokio::time::timeout(*interval, rx.recv()).await {
+                            Ok(msg) => msg,
+                            Err(_) => {
+                                debug!("Sending ping to the NameNode");
+                                Some(ping.clone())
+                            }
+                        }
+                    }
+                    None => rx.recv().await,
+                };
+                let Some(msg) = msg else {
+                    break;
+                };
+                if let Err(e) = writer.write(&msg).await {
+                    warn!("Failed to write to NameNode: {:?}", e);
+                    close_connection(&alive, &call_map);
+                    break;
                 }
             }
         });
@@ -184,9 +336,10 @@ impl RpcConnection {
 
     fn start_listener(&mut self, reader: SaslReader) -> Result<JoinHandle<()>> {
         let call_map = Arc::clone(&self.call_map);
+        let alive = Arc::clone(&self.alive);
         let alignment_context = self.alignment_context.clone();
         let listener = task::spawn(async move {
-            RpcListener::new(call_map, reader, alignment_context)
+            RpcListener::new(call_map, reader, alive, alignment_context)
                 .start()
                 .await;
         });
@@ -224,7 +377,7 @@ impl RpcConnection {
         };
 
         let context = common::IpcConnectionContextProto {
-            protocol: Some(PROTOCOL.to_string()),
+            protocol: Some(self.protocol.to_string()),
             user_info: Some(user_info),
         };
 
@@ -233,9 +386,11 @@ impl RpcConnection {
     }
 
     pub(crate) fn is_alive(&self) -> bool {
-        self.listener
-            .as_ref()
-            .is_some_and(|handle| !handle.is_finished())
+        self.alive.load(Ordering::SeqCst)
+            && self
+                .listener
+                .as_ref()
+                .is_some_and(|handle| !handle.is_finished())
     }
 
     pub(crate) async fn write_messages(&self, messages: &[&[u8]]) -> Result<()> {
@@ -251,9 +406,7 @@ impl RpcConnection {
             buf.extend(*msg);
         }
 
-        let _ = self.sender.send(buf).await;
-
-        Ok(())
+        self.sender.send(buf).await.map_err(|_| connection_closed())
     }
 
     pub(crate) async fn call(&self, method_name: &str, message: &[u8]) -> Result<Bytes> {
@@ -266,7 +419,7 @@ impl RpcConnection {
 
         let msg_header = common::RequestHeaderProto {
             method_name: method_name.to_string(),
-            declaring_class_protocol_name: PROTOCOL.to_string(),
+            declaring_class_protocol_name: self.protocol.to_string(),
             client_protocol_version: 1,
         };
         debug!("RPC request header: {:?}", msg_header);
@@ -277,17 +430,43 @@ impl RpcConnection {
 
         self.call_map.lock().unwrap().insert(call_id, sender);
 
-        self.write_messages(&[&conn_header_buf, &header_buf, message])
-            .await?;
+        // The connection may have closed before the call was registered, in which case it
+        // would never be failed by the listener
+        if !self.alive.load(Ordering::SeqCst) {
+            self.call_map.lock().unwrap().remove(&call_id);
+            return Err(connection_closed());
+        }
 
-        receiver.await.unwrap()
+        if let Err(e) = self
+            .write_messages(&[&conn_header_buf, &header_buf, message])
+            .await
+        {
+            self.call_map.lock().unwrap().remove(&call_id);
+            return Err(e);
+        }
+
+        let response = match self.rpc_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, receiver).await {
+                Ok(response) => response,
+                Err(_) => {
+                    self.call_map.lock().unwrap().remove(&call_id);
+                    return Err(timed_out(
+                        "Timed out waiting for a response from the NameNode",
+                    ));
+                }
+            },
+            None => receiver.await,
+        };
+        response.unwrap_or_else(|_| Err(connection_closed()))
     }
 }
 
+/// Reads responses from a NameNode and routes them to the waiting calls by call ID, so any
+/// number of calls can be in flight on one connection
 struct RpcListener {
     call_map: Arc<Mutex<HashMap<i32, CallResult>>>,
     reader: SaslReader,
-    alive: bool,
+    alive: Arc<AtomicBool>,
     alignment_context: Arc<Mutex<AlignmentContext>>,
 }
 
@@ -295,12 +474,13 @@ impl RpcListener {
     fn new(
         call_map: Arc<Mutex<HashMap<i32, CallResult>>>,
         reader: SaslReader,
+        alive: Arc<AtomicBool>,
         alignment_context: Arc<Mutex<AlignmentContext>>,
     ) -> Self {
         RpcListener {
             call_map,
             reader,
-            alive: true,
+            alive,
             alignment_context,
         }
     }
@@ -309,12 +489,15 @@ impl RpcListener {
         loop {
             if let Err(error) = self.read_response().await {
                 match error {
-                    HdfsError::IOError(e) if e.kind() == ErrorKind::UnexpectedEof => break,
-                    _ => panic!("{:?}", error),
+                    HdfsError::IOError(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                        debug!("NameNode closed the connection")
+                    }
+                    _ => warn!("Closing NameNode connection after error: {:?}", error),
                 }
+                break;
             }
         }
-        self.alive = false;
+        close_connection(&self.alive, &self.call_map);
     }
 
     async fn read_response(&mut self) -> Result<()> {
@@ -334,34 +517,44 @@ impl RpcListener {
 
         let call_id = rpc_response.call_id as i32;
 
+        if rpc_response.status() == RpcStatusProto::Fatal {
+            warn!(
+                "RPC fatal error: {}: {}",
+                rpc_response.exception_class_name(),
+                rpc_response.error_msg()
+            );
+            let fatal_error = || {
+                let class = rpc_response.exception_class_name();
+                let msg = rpc_response.error_msg().to_string();
+                if class == AUTHORIZATION_EXCEPTION && msg.contains("impersonate") {
+                    HdfsError::ProxyUserNotAllowed(msg)
+                } else {
+                    HdfsError::FatalRPCError(class.to_string(), msg)
+                }
+            };
+            // The server closes the connection after a fatal error, so every call on it fails
+            // with the error. Errors about the connection itself, like a proxy user that isn't
+            // allowed, don't have the ID of a call.
+            for (_, call) in self.call_map.lock().unwrap().drain() {
+                let _ = call.send(Err(fatal_error()));
+            }
+            return Err(fatal_error());
+        }
+
         let call = self.call_map.lock().unwrap().remove(&call_id);
 
         if let Some(call) = call {
-            match rpc_response.status() {
-                RpcStatusProto::Success => {
-                    self.alignment_context
-                        .lock()
-                        .unwrap()
-                        .update(rpc_response.state_id, rpc_response.router_federated_state)?;
-                    let _ = call.send(Ok(bytes));
-                }
-                RpcStatusProto::Error => {
-                    let _ = call.send(Err(HdfsError::RPCError(
-                        rpc_response.exception_class_name().to_string(),
-                        rpc_response.error_msg().to_string(),
-                    )));
-                }
-                RpcStatusProto::Fatal => {
-                    warn!(
-                        "RPC fatal error: {}: {}",
-                        rpc_response.exception_class_name(),
-                        rpc_response.error_msg()
-                    );
-                    return Err(HdfsError::FatalRPCError(
-                        rpc_response.exception_class_name().to_string(),
-                        rpc_response.error_msg().to_string(),
-                    ));
-                }
+            if rpc_response.status() == RpcStatusProto::Success {
+                self.alignment_context
+                    .lock()
+                    .unwrap()
+                    .update(rpc_response.state_id, rpc_response.router_federated_state)?;
+                let _ = call.send(Ok(bytes));
+            } else {
+                let _ = call.send(Err(HdfsError::RPCError(
+                    rpc_response.exception_class_name().to_string(),
+                    rpc_response.error_msg().to_string(),
+                )));
             }
         }
         Ok(())
@@ -371,6 +564,9 @@ impl RpcListener {
 pub(crate) enum Op {
     WriteBlock,
     ReadBlock,
+    BlockChecksum,
+    TransferBlock,
+    RequestShortCircuitFds,
 }
 
 impl Op {
@@ -378,10 +574,22 @@ impl Op {
         match self {
             Self::WriteBlock => 80,
             Self::ReadBlock => 81,
+            Self::BlockChecksum => 85,
+            Self::TransferBlock => 86,
+            Self::RequestShortCircuitFds => 87,
         }
     }
 }
 
+/// Encodes the request for a DataNode op, prefixed with the data transfer version and the op
+pub(crate) fn encode_op(op: Op, message: &impl Message) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&DATA_TRANSFER_VERSION.to_be_bytes());
+    buf.push(op.value());
+    buf.extend_from_slice(&message.encode_length_delimited_to_vec());
+    buf
+}
+
 const CHECKSUM_BYTES: usize = 4;
 
 pub(crate) struct Packet {
@@ -389,6 +597,7 @@ pub(crate) struct Packet {
     #[allow(dead_code)]
     checksum: BytesMut,
     data: BytesMut,
+    checksum_type: hdfs::ChecksumTypeProto,
     bytes_per_checksum: usize,
     max_data_size: usize,
 }
@@ -399,6 +608,7 @@ impl Packet {
             header,
             checksum,
             data,
+            checksum_type: hdfs::ChecksumTypeProto::ChecksumNull,
             bytes_per_checksum: 0,
             max_data_size: 0,
         }
@@ -407,6 +617,7 @@ impl Packet {
     pub(crate) fn empty(
         offset: i64,
         seqno: i64,
+        checksum_type: hdfs::ChecksumTypeProto,
         bytes_per_checksum: u32,
         max_packet_size: u32,
     ) -> Self {
@@ -416,28 +627,41 @@ impl Packet {
             ..Default::default()
         };
 
-        let num_chunks = Self::max_packet_chunks(bytes_per_checksum, max_packet_size);
+        let checksum_size = checksum_size(checksum_type);
+        let num_chunks =
+            Self::max_packet_chunks(bytes_per_checksum, checksum_size, max_packet_size);
 
         Self {
             header,
-            checksum: BytesMut::with_capacity(num_chunks * CHECKSUM_BYTES),
+            checksum: BytesMut::with_capacity(num_chunks * checksum_size),
             data: BytesMut::with_capacity(num_chunks * bytes_per_checksum as usize),
+            checksum_type,
             bytes_per_checksum: bytes_per_checksum as usize,
             max_data_size: num_chunks * bytes_per_checksum as usize,
         }
     }
 
+    /// Asks the DataNodes to sync the block to disk once this packet is written
+    pub(crate) fn set_sync_block(&mut self) {
+        self.header.sync_block = Some(true);
+    }
+
     pub(crate) fn set_last_packet(&mut self) {
         self.header.last_packet_in_block = true;
         // Opinionated: always sync block for safety
         self.header.sync_block = Some(true);
     }
 
-    fn max_packet_chunks(bytes_per_checksum: u32, max_packet_size: u32) -> usize {
+    fn max_packet_chunks(
+        bytes_per_checksum: u32,
+        checksum_size: usize,
+        max_packet_size: u32,
+    ) -> usize {
         if max_packet_size > 0 {
-            let data_size = max_packet_size as usize - MAX_PACKET_HEADER_SIZE;
-            let chunk_size = bytes_per_checksum as usize + CHECKSUM_BYTES;
-            data_size / chunk_size
+            let data_size = (max_packet_size as usize).saturating_sub(MAX_PACKET_HEADER_SIZE);
+            let chunk_size = bytes_per_checksum as usize + checksum_size;
+            // Packets always hold at least one chunk, even if the packet size is too small
+            usize::max(data_size / chunk_size, 1)
         } else {
             // Create a packet with a single chunk for appending to a file
             1
@@ -457,49 +681,65 @@ impl Packet {
         self.data.is_empty()
     }
 
-    fn finalize(&mut self) -> (hdfs::PacketHeaderProto, Bytes, Bytes) {
+    /// Computes the checksums of the data, leaving this packet empty
+    pub(crate) fn finalize(&mut self) -> WritePacket {
         let data = self.data.split().freeze();
 
-        let mut chunk_start = 0;
-        while chunk_start < data.len() {
-            let chunk_end = usize::min(chunk_start + self.bytes_per_checksum, data.len());
-            let chunk_checksum = CRC32C.checksum(&data[chunk_start..chunk_end]);
-            self.checksum.put_u32(chunk_checksum);
-            chunk_start += self.bytes_per_checksum;
+        if let Some(algorithm) = checksum_algorithm(self.checksum_type) {
+            for chunk in data.chunks(self.bytes_per_checksum) {
+                self.checksum.put_u32(algorithm.checksum(chunk));
+            }
         }
 
         let checksum = self.checksum.split().freeze();
 
         self.header.data_len = data.len() as i32;
 
-        (self.header.clone(), checksum, data)
+        WritePacket {
+            header: self.header.clone(),
+            checksum,
+            data,
+        }
     }
 
+    /// Flip a bit in the packet data so it no longer matches its checksums
+    #[cfg(feature = "integration-test")]
+    pub(crate) fn corrupt_data(&mut self) {
+        if let Some(byte) = self.data.first_mut() {
+            *byte ^= 1;
+        }
+    }
+
+    /// Returns the data in the packet, verifying the checksums if they were requested. A checksum
+    /// mismatch returns an [HdfsError::ChecksumError] for `block_id` with the offset in the block
+    /// of the corrupt chunk. The data is verified in place and isn't copied, so the returned
+    /// [Bytes] shares the buffer the packet was read into.
     pub(crate) fn get_data(
         self,
         checksum_info: &Option<hdfs::ReadOpChecksumInfoProto>,
+        block_id: u64,
     ) -> Result<Bytes> {
         // Verify the checksums if they were requested
         let mut checksums = self.checksum.freeze();
         let data = self.data.freeze();
         if let Some(info) = checksum_info {
-            let algorithm = match info.checksum.r#type() {
-                hdfs::ChecksumTypeProto::ChecksumCrc32 => Some(&CRC32),
-                hdfs::ChecksumTypeProto::ChecksumCrc32c => Some(&CRC32C),
-                hdfs::ChecksumTypeProto::ChecksumNull => None,
-            };
-
-            if let Some(algorithm) = algorithm {
+            if let Some(algorithm) = checksum_algorithm(info.checksum.r#type()) {
                 // Create a new Bytes view over the data that we can consume
                 let mut checksum_data = data.clone();
+                let mut chunk_offset = self.header.offset_in_block as u64;
                 while !checksum_data.is_empty() {
-                    let chunk_checksum = algorithm.checksum(&checksum_data.split_to(usize::min(
+                    let chunk_len = usize::min(
                         info.checksum.bytes_per_checksum as usize,
                         checksum_data.len(),
-                    )));
+                    );
+                    let chunk_checksum = algorithm.checksum(&checksum_data.split_to(chunk_len));
                     if chunk_checksum != checksums.get_u32() {
-                        return Err(HdfsError::ChecksumError);
+                        return Err(HdfsError::ChecksumError {
+                            block: block_id,
+                            offset: chunk_offset,
+                        });
                     }
+                    chunk_offset += chunk_len as u64;
                 }
             }
         }
@@ -507,18 +747,95 @@ impl Packet {
     }
 }
 
+fn checksum_algorithm(checksum_type: hdfs::ChecksumTypeProto) -> Option<&'static Crc<u32>> {
+    match checksum_type {
+        hdfs::ChecksumTypeProto::ChecksumCrc32 => Some(&CRC32),
+        hdfs::ChecksumTypeProto::ChecksumCrc32c => Some(&CRC32C),
+        hdfs::ChecksumTypeProto::ChecksumNull => None,
+    }
+}
+
+/// The number of bytes of each checksum of `checksum_type`
+fn checksum_size(checksum_type: hdfs::ChecksumTypeProto) -> usize {
+    match checksum_type {
+        hdfs::ChecksumTypeProto::ChecksumNull => 0,
+        _ => CHECKSUM_BYTES,
+    }
+}
+
+/// Reads exactly `len` bytes into a buffer from `buffers`, without zeroing it first
+async fn read_bytes(
+    reader: &mut (impl AsyncRead + Unpin),
+    len: usize,
+    buffers: &BufferPool,
+) -> io::Result<BytesMut> {
+    let mut buf = buffers.get(len);
+    while buf.len() < len {
+        let remaining = len - buf.len();
+        if reader.read_buf(&mut (&mut *buf).limit(remaining)).await? == 0 {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof));
+        }
+    }
+    Ok(buf.split())
+}
+
+/// A packet that is ready to be written. Writers keep it until the pipeline acknowledges it, so
+/// it can be sent again if the pipeline has to be recovered.
+#[derive(Debug, Clone)]
+pub(crate) struct WritePacket {
+    pub(crate) header: hdfs::PacketHeaderProto,
+    checksum: Bytes,
+    data: Bytes,
+}
+
+/// The host to connect to a DataNode with, its hostname with `dfs.client.use.datanode.hostname`
+/// and otherwise its IP address
+pub(crate) fn datanode_host(datanode: &hdfs::DatanodeIdProto, use_hostname: bool) -> &str {
+    if use_hostname {
+        &datanode.host_name
+    } else {
+        &datanode.ip_addr
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct DatanodeConnection {
     client_name: String,
-    reader: Option<BufReader<OwnedReadHalf>>,
-    writer: OwnedWriteHalf,
+    reader: Option<BufReader<SaslDatanodeReader>>,
+    writer: SaslDatanodeWriter,
 }
 
 impl DatanodeConnection {
-    pub(crate) async fn connect(url: &str) -> Result<Self> {
-        let stream = connect(url).await?;
-
-        let (reader, writer) = stream.into_split();
+    /// Connects to the data transfer port of a DataNode. With an `encryption_key`, the
+    /// connection is authenticated with it and everything sent over it is encrypted. Otherwise,
+    /// when `protection` is not empty, the connection is authenticated with the block token and
+    /// negotiates one of the given qualities of protection. Like the Java client, privileged
+    /// ports and blocks without tokens then skip the SASL handshake. The DataNode is connected
+    /// to by its hostname instead of its IP address with `use_hostname`.
+    pub(crate) async fn connect(
+        datanode: &hdfs::DatanodeIdProto,
+        token: &common::TokenProto,
+        protection: &[Qop],
+        encryption_key: Option<&hdfs::DataEncryptionKeyProto>,
+        use_hostname: bool,
+    ) -> Result<Self> {
+        let host = datanode_host(datanode, use_hostname);
+        let stream = connect(&format!("{}:{}", host, datanode.xfer_port)).await?;
+
+        let (reader, writer) = if let Some(key) = encryption_key {
+            negotiate_datanode(stream, DigestSaslSession::from_encryption_key(key)).await?
+        } else if protection.is_empty() || datanode.xfer_port < 1024 || token.identifier.is_empty()
+        {
+            let (reader, writer) = stream.into_split();
+            (
+                SaslDatanodeReader::new(reader, None),
+                SaslDatanodeWriter::new(writer, None),
+            )
+        } else {
+            let session =
+                DigestSaslSession::from_block_token(&token.identifier, &token.password, protection);
+            negotiate_datanode(stream, session).await?
+        };
 
         let conn = DatanodeConnection {
             client_name: Uuid::new_v4().to_string(),
@@ -529,13 +846,7 @@ impl DatanodeConnection {
     }
 
     pub(crate) async fn send(&mut self, op: Op, message: &impl Message) -> Result<()> {
-        self.writer
-            .write_all(&DATA_TRANSFER_VERSION.to_be_bytes())
-            .await?;
-        self.writer.write_all(&[op.value()]).await?;
-        self.writer
-            .write_all(&message.encode_length_delimited_to_vec())
-            .await?;
+        self.writer.write_all(&encode_op(op, message)).await?;
         self.writer.flush().await?;
         Ok(())
     }
@@ -572,7 +883,8 @@ impl DatanodeConnection {
         Ok(response)
     }
 
-    pub(crate) async fn read_packet(&mut self) -> Result<Packet> {
+    /// Reads the next packet of a block read, into a buffer taken from `buffers`
+    pub(crate) async fn read_packet(&mut self, buffers: &BufferPool) -> Result<Packet> {
         let reader = self.reader.as_mut().ok_or(HdfsError::DataTransferError(
             "Cannot read packets after starting to listen for packet acks".to_string(),
         ))?;
@@ -584,8 +896,10 @@ impl DatanodeConnection {
         let payload_length = u32::from_be_bytes(payload_len_buf) as usize;
         let header_length = u16::from_be_bytes(header_len_buf) as usize;
 
-        let mut remaining_buf = BytesMut::zeroed(payload_length - 4 + header_length);
-        reader.read_exact(&mut remaining_buf).await?;
+        // The header, checksums and data are read into a single buffer that they are all split
+        // from, so the data can be handed out without copying it
+        let mut remaining_buf =
+            read_bytes(reader, payload_length - 4 + header_length, buffers).await?;
 
         let header =
             hdfs::PacketHeaderProto::decode(remaining_buf.split_to(header_length).freeze())?;
@@ -597,18 +911,29 @@ impl DatanodeConnection {
         Ok(Packet::new(header, checksum, data))
     }
 
-    /// Create a buffer to send to the datanode
-    pub(crate) async fn write_packet(&mut self, packet: &mut Packet) -> Result<()> {
-        let (header, checksum, data) = packet.finalize();
+    /// Tells the DataNode the data of a block read was received, after which it waits for
+    /// another op on the same connection
+    pub(crate) async fn send_read_status(&mut self, status: hdfs::Status) -> Result<()> {
+        let message = hdfs::ClientReadStatusProto {
+            status: status as i32,
+        };
+        self.writer
+            .write_all(&message.encode_length_delimited_to_vec())
+            .await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
 
-        let payload_len = (checksum.len() + data.len() + 4) as u32;
-        let header_encoded = header.encode_to_vec();
+    /// Create a buffer to send to the datanode
+    pub(crate) async fn write_packet(&mut self, packet: &WritePacket) -> Result<()> {
+        let payload_len = (packet.checksum.len() + packet.data.len() + 4) as u32;
+        let header_encoded = packet.header.encode_to_vec();
 
         self.writer.write_u32(payload_len).await?;
         self.writer.write_u16(header_encoded.len() as u16).await?;
-        self.writer.write_all(&header.encode_to_vec()).await?;
-        self.writer.write_all(&checksum).await?;
-        self.writer.write_all(&data).await?;
+        self.writer.write_all(&header_encoded).await?;
+        self.writer.write_all(&packet.checksum).await?;
+        self.writer.write_all(&packet.data).await?;
         self.writer.flush().await?;
 
         Ok(())
@@ -643,7 +968,7 @@ impl DatanodeConnection {
     }
 
     async fn read_ack(
-        reader: &mut BufReader<OwnedReadHalf>,
+        reader: &mut BufReader<SaslDatanodeReader>,
     ) -> Result<Option<hdfs::PipelineAckProto>> {
         let buf = reader.fill_buf().await?;
 
@@ -663,15 +988,422 @@ impl DatanodeConnection {
     }
 }
 
+#[derive(Debug)]
+struct CachedConnection {
+    address: String,
+    released: Instant,
+    connection: DatanodeConnection,
+}
+
+/// Idle connections to DataNodes that finished a read and can be reused by later reads from the
+/// same DataNode. Connections idle for longer than the expiry are dropped, and the least
+/// recently used connection is dropped when the cache is full.
+#[derive(Debug)]
+pub(crate) struct DatanodeConnectionCache {
+    capacity: usize,
+    expiry: Duration,
+    connections: Mutex<VecDeque<CachedConnection>>,
+}
+
+impl DatanodeConnectionCache {
+    pub(crate) fn new(capacity: usize, expiry: Duration) -> Self {
+        Self {
+            capacity,
+            expiry,
+            connections: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn address(datanode: &hdfs::DatanodeIdProto) -> String {
+        format!("{}:{}", datanode.ip_addr, datanode.xfer_port)
+    }
+
+    /// Connections are ordered by when they were released, so expired ones are at the front
+    fn evict_expired(&self, connections: &mut VecDeque<CachedConnection>) {
+        while connections
+            .front()
+            .is_some_and(|cached| cached.released.elapsed() > self.expiry)
+        {
+            connections.pop_front();
+        }
+    }
+
+    /// Takes the most recently released connection to `datanode`, if there is one
+    pub(crate) fn get(&self, datanode: &hdfs::DatanodeIdProto) -> Option<DatanodeConnection> {
+        let address = Self::address(datanode);
+        let mut connections = self.connections.lock().unwrap();
+        self.evict_expired(&mut connections);
+        let index = connections
+            .iter()
+            .rposition(|cached| cached.address == address)?;
+        connections.remove(index).map(|cached| cached.connection)
+    }
+
+    /// Keeps a connection that is ready for another op for reuse
+    pub(crate) fn put(&self, datanode: &hdfs::DatanodeIdProto, connection: DatanodeConnection) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut connections = self.connections.lock().unwrap();
+        self.evict_expired(&mut connections);
+        if connections.len() >= self.capacity {
+            connections.pop_front();
+        }
+        connections.push_back(CachedConnection {
+            address: Self::address(datanode),
+            released: Instant::now(),
+            connection,
+        });
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     use prost::Message;
 
-    use crate::{hdfs::connection::MAX_PACKET_HEADER_SIZE, proto::hdfs};
+    use bytes::{Buf, BufMut, Bytes, BytesMut};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::{
+        common::buffer::BufferPool,
+        hdfs::connection::MAX_PACKET_HEADER_SIZE,
+        proto::{
+            common::{
+                self, rpc_response_header_proto::RpcStatusProto, rpc_sasl_proto::SaslAuth,
+                rpc_sasl_proto::SaslState,
+            },
+            hdfs,
+        },
+        security::user::User,
+        HdfsError,
+    };
+
+    use super::{
+        read_bytes, AlignmentContext, DatanodeConnection, DatanodeConnectionCache, Packet,
+        RpcConfig, RpcConnection, CRC32, CRC32C,
+    };
+
+    fn rpc_config(rpc_timeout: Option<Duration>) -> RpcConfig {
+        RpcConfig {
+            connect_timeout: Duration::from_secs(1),
+            rpc_timeout,
+            ping_interval: None,
+            tcp_keepalive_time: None,
+            simple_auth: false,
+            user: Arc::new(User::new(None, None, Vec::new())),
+        }
+    }
+
+    async fn read_frame(stream: &mut TcpStream) -> Bytes {
+        let length = stream.read_u32().await.unwrap();
+        let mut buf = BytesMut::zeroed(length as usize);
+        stream.read_exact(&mut buf).await.unwrap();
+        buf.freeze()
+    }
+
+    async fn write_frame(stream: &mut TcpStream, call_id: i32, message: &[u8]) {
+        let header = common::RpcResponseHeaderProto {
+            call_id: call_id as u32,
+            status: RpcStatusProto::Success as i32,
+            ..Default::default()
+        }
+        .encode_length_delimited_to_vec();
+        stream
+            .write_u32((header.len() + message.len()) as u32)
+            .await
+            .unwrap();
+        stream.write_all(&header).await.unwrap();
+        stream.write_all(message).await.unwrap();
+    }
+
+    /// Accepts a connection with SIMPLE authentication, then reads `calls` calls before
+    /// answering them in reverse order by echoing their messages. Another call is read but never
+    /// answered before the connection is closed.
+    async fn fake_namenode(listener: TcpListener, calls: usize) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut preamble = [0u8; 7];
+        stream.read_exact(&mut preamble).await.unwrap();
+        assert_eq!(&preamble[..4], b"hrpc");
+
+        // SASL negotiate, which only offers SIMPLE authentication
+        read_frame(&mut stream).await;
+        let negotiate = common::RpcSaslProto {
+            state: SaslState::Negotiate as i32,
+            auths: vec![SaslAuth {
+                method: "SIMPLE".to_string(),
+                mechanism: "".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        write_frame(
+            &mut stream,
+            -33,
+            &negotiate.encode_length_delimited_to_vec(),
+        )
+        .await;
+        // SASL initiate and the connection context
+        read_frame(&mut stream).await;
+        read_frame(&mut stream).await;
+
+        let mut requests = Vec::new();
+        for _ in 0..calls {
+            let mut frame = read_frame(&mut stream).await;
+            let header =
+                common::RpcRequestHeaderProto::decode_length_delimited(&mut frame).unwrap();
+            common::RequestHeaderProto::decode_length_delimited(&mut frame).unwrap();
+            requests.push((header.call_id, frame));
+        }
+        for (call_id, message) in requests.into_iter().rev() {
+            write_frame(&mut stream, call_id, &message).await;
+        }
 
-    use super::AlignmentContext;
+        read_frame(&mut stream).await;
+    }
+
+    #[tokio::test]
+    async fn test_multiplexed_calls() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(fake_namenode(listener, 8));
+
+        let connection = Arc::new(
+            RpcConnection::connect(
+                &url,
+                Arc::new(Mutex::new(AlignmentContext::default())),
+                None,
+                &rpc_config(None),
+            )
+            .await
+            .unwrap(),
+        );
+
+        // All calls are in flight on the same connection at once, and each gets its own response
+        let calls: Vec<_> = (0..8u8)
+            .map(|i| {
+                let connection = Arc::clone(&connection);
+                tokio::spawn(async move { connection.call("getFileInfo", &[i; 4]).await })
+            })
+            .collect();
+        for (i, call) in calls.into_iter().enumerate() {
+            let mut response = call.await.unwrap().unwrap();
+            assert_eq!(response.copy_to_bytes(4), Bytes::from(vec![i as u8; 4]));
+        }
+        assert!(connection.is_alive());
+
+        // A call in flight when the connection closes fails instead of waiting forever
+        let result = connection.call("getFileInfo", &[0; 4]).await;
+        assert!(matches!(result, Err(HdfsError::IOError(_))));
+        assert!(!connection.is_alive());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_simple_auth() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut preamble = [0u8; 7];
+            stream.read_exact(&mut preamble).await.unwrap();
+            // No SASL exchange, so the connection context is sent right away
+            assert_eq!(preamble[6], 0);
+            let mut frame = read_frame(&mut stream).await;
+            let header =
+                common::RpcRequestHeaderProto::decode_length_delimited(&mut frame).unwrap();
+            assert_eq!(header.call_id, -3);
+            let context =
+                common::IpcConnectionContextProto::decode_length_delimited(&mut frame).unwrap();
+            assert!(context.user_info.unwrap().effective_user.is_some());
+        });
+
+        let config = RpcConfig {
+            simple_auth: true,
+            ..rpc_config(None)
+        };
+        RpcConnection::connect(
+            &url,
+            Arc::new(Mutex::new(AlignmentContext::default())),
+            None,
+            &config,
+        )
+        .await
+        .unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut preamble = [0u8; 7];
+            stream.read_exact(&mut preamble).await.unwrap();
+            read_frame(&mut stream).await;
+
+            // Pings keep coming while the connection is idle, and are only a request header
+            for _ in 0..2 {
+                let mut frame = read_frame(&mut stream).await;
+                let header =
+                    common::RpcRequestHeaderProto::decode_length_delimited(&mut frame).unwrap();
+                assert_eq!(header.call_id, -4);
+                assert!(frame.is_empty());
+            }
+        });
+
+        let config = RpcConfig {
+            simple_auth: true,
+            ping_interval: Some(Duration::from_millis(50)),
+            tcp_keepalive_time: Some(Duration::from_secs(30)),
+            ..rpc_config(None)
+        };
+        let connection = RpcConnection::connect(
+            &url,
+            Arc::new(Mutex::new(AlignmentContext::default())),
+            None,
+            &config,
+        )
+        .await
+        .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        drop(connection);
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_proxy_user() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut preamble = [0u8; 7];
+            stream.read_exact(&mut preamble).await.unwrap();
+            let mut frame = read_frame(&mut stream).await;
+            common::RpcRequestHeaderProto::decode_length_delimited(&mut frame).unwrap();
+            let context =
+                common::IpcConnectionContextProto::decode_length_delimited(&mut frame).unwrap();
+            let user_info = context.user_info.unwrap();
+            assert_eq!(user_info.real_user.as_deref(), Some("service"));
+            assert_eq!(user_info.effective_user.as_deref(), Some("enduser"));
+
+            // The NameNode rejects the connection after the first call, without a call ID
+            read_frame(&mut stream).await;
+            let header = common::RpcResponseHeaderProto {
+                call_id: -1i32 as u32,
+                status: RpcStatusProto::Fatal as i32,
+                exception_class_name: Some(
+                    "org.apache.hadoop.security.authorize.AuthorizationException".to_string(),
+                ),
+                error_msg: Some("User: service is not allowed to impersonate enduser".to_string()),
+                ..Default::default()
+            }
+            .encode_length_delimited_to_vec();
+            stream.write_u32(header.len() as u32).await.unwrap();
+            stream.write_all(&header).await.unwrap();
+        });
+
+        let config = RpcConfig {
+            simple_auth: true,
+            user: Arc::new(User::new(
+                Some("service".to_string()),
+                Some("enduser".to_string()),
+                Vec::new(),
+            )),
+            ..rpc_config(None)
+        };
+        let connection = RpcConnection::connect(
+            &url,
+            Arc::new(Mutex::new(AlignmentContext::default())),
+            None,
+            &config,
+        )
+        .await
+        .unwrap();
+        let result = connection.call("getFileInfo", &[0; 4]).await;
+        assert!(matches!(
+            result,
+            Err(HdfsError::ProxyUserNotAllowed(msg)) if msg.contains("impersonate")
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rpc_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        // Waits for a second call before answering, so the first is never answered
+        let server = tokio::spawn(fake_namenode(listener, 2));
+
+        let connection = RpcConnection::connect(
+            &url,
+            Arc::new(Mutex::new(AlignmentContext::default())),
+            None,
+            &rpc_config(Some(Duration::from_millis(100))),
+        )
+        .await
+        .unwrap();
+
+        let result = connection.call("getFileInfo", &[0; 4]).await;
+        assert!(
+            matches!(result, Err(HdfsError::IOError(e)) if e.kind() == io::ErrorKind::TimedOut)
+        );
+        assert!(connection.is_alive());
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_datanode_connection_cache() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let datanode = |port| hdfs::DatanodeIdProto {
+            ip_addr: "127.0.0.1".to_string(),
+            xfer_port: port,
+            ..Default::default()
+        };
+        let port = listener.local_addr().unwrap().port() as u32;
+        let connect = || async {
+            DatanodeConnection::connect(&datanode(port), &Default::default(), &[], None, false)
+                .await
+                .unwrap()
+        };
+
+        let cache = DatanodeConnectionCache::new(2, Duration::from_secs(60));
+        assert!(cache.get(&datanode(port)).is_none());
+        cache.put(&datanode(port), connect().await);
+        assert!(cache.get(&datanode(port + 1)).is_none());
+        assert!(cache.get(&datanode(port)).is_some());
+        assert!(cache.get(&datanode(port)).is_none());
+
+        // The least recently released connection is evicted when the cache is full
+        cache.put(&datanode(port), connect().await);
+        cache.put(&datanode(port + 1), connect().await);
+        cache.put(&datanode(port + 1), connect().await);
+        assert!(cache.get(&datanode(port)).is_none());
+        assert!(cache.get(&datanode(port + 1)).is_some());
+
+        // Idle connections expire
+        let cache = DatanodeConnectionCache::new(2, Duration::from_millis(10));
+        cache.put(&datanode(port), connect().await);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(cache.get(&datanode(port)).is_none());
+
+        // Nothing is kept when caching is disabled
+        let cache = DatanodeConnectionCache::new(0, Duration::from_secs(60));
+        cache.put(&datanode(port), connect().await);
+        assert!(cache.get(&datanode(port)).is_none());
+    }
 
     #[test]
     fn test_max_packet_header_size() {
@@ -684,6 +1416,113 @@ mod test {
         assert_eq!(MAX_PACKET_HEADER_SIZE, header.encoded_len() + 4 + 2);
     }
 
+    #[test]
+    fn test_packet_checksum_error() {
+        let data = BytesMut::from(&[1u8; 1024][..]);
+        let mut checksums = BytesMut::new();
+        checksums.put_u32(CRC32C.checksum(&data[..512]));
+        checksums.put_u32(CRC32C.checksum(&data[..512]) ^ 1);
+
+        let header = hdfs::PacketHeaderProto {
+            offset_in_block: 1024,
+            data_len: 1024,
+            ..Default::default()
+        };
+        let checksum_info = Some(hdfs::ReadOpChecksumInfoProto {
+            checksum: hdfs::ChecksumProto {
+                r#type: hdfs::ChecksumTypeProto::ChecksumCrc32c as i32,
+                bytes_per_checksum: 512,
+            },
+            chunk_offset: 1024,
+        });
+
+        // The second chunk is corrupt
+        let packet = Packet::new(header, checksums, data);
+        assert!(matches!(
+            packet.get_data(&checksum_info, 5),
+            Err(HdfsError::ChecksumError {
+                block: 5,
+                offset: 1536
+            })
+        ));
+    }
+
+    #[test]
+    fn test_packet_checksum_types() {
+        let data = Bytes::from(vec![1u8; 1500]);
+        for (checksum_type, expected) in [
+            (
+                hdfs::ChecksumTypeProto::ChecksumCrc32,
+                [CRC32.checksum(&data[..1024]), CRC32.checksum(&data[1024..])].to_vec(),
+            ),
+            (
+                hdfs::ChecksumTypeProto::ChecksumCrc32c,
+                [
+                    CRC32C.checksum(&data[..1024]),
+                    CRC32C.checksum(&data[1024..]),
+                ]
+                .to_vec(),
+            ),
+            (hdfs::ChecksumTypeProto::ChecksumNull, vec![]),
+        ] {
+            let mut packet = Packet::empty(0, 0, checksum_type, 1024, 64 * 1024);
+            packet.write(&mut data.clone());
+            let written = packet.finalize();
+            assert_eq!(written.data, data);
+            let mut checksums = written.checksum.clone();
+            let mut actual = Vec::new();
+            while checksums.has_remaining() {
+                actual.push(checksums.get_u32());
+            }
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_packet_data_not_copied() {
+        let mut buf = BytesMut::new();
+        buf.put_u32(CRC32C.checksum(&[1u8; 512]));
+        buf.put(&[1u8; 512][..]);
+        let checksums = buf.split_to(4);
+        let data_ptr = buf.as_ptr();
+
+        let header = hdfs::PacketHeaderProto {
+            data_len: 512,
+            ..Default::default()
+        };
+        let checksum_info = Some(hdfs::ReadOpChecksumInfoProto {
+            checksum: hdfs::ChecksumProto {
+                r#type: hdfs::ChecksumTypeProto::ChecksumCrc32c as i32,
+                bytes_per_checksum: 512,
+            },
+            chunk_offset: 0,
+        });
+
+        let data = Packet::new(header, checksums, buf)
+            .get_data(&checksum_info, 5)
+            .unwrap();
+        assert_eq!(data.len(), 512);
+        assert_eq!(data.as_ptr(), data_ptr);
+    }
+
+    #[tokio::test]
+    async fn test_read_bytes() {
+        let buffers = BufferPool::new(1024);
+        let mut reader = &[1u8, 2, 3, 4, 5][..];
+        assert_eq!(
+            &read_bytes(&mut reader, 3, &buffers).await.unwrap()[..],
+            &[1, 2, 3]
+        );
+        assert_eq!(reader, &[4, 5]);
+        assert_eq!(
+            read_bytes(&mut reader, 3, &buffers)
+                .await
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+
     fn encode_router_state(map: &HashMap<String, i64>) -> Vec<u8> {
         hdfs::RouterFederatedStateProto {
             namespace_state_ids: map.clone(),
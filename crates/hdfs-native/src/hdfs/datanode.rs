@@ -1,4 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::{
@@ -6,107 +12,803 @@ use futures::{
     stream::{self, BoxStream},
     Stream, StreamExt,
 };
-use log::{debug, error};
-use tokio::sync::{mpsc, oneshot};
+use log::{debug, error, warn};
+use prost::Message;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
 
 use crate::{
+    common::{buffer::BufferPool, config::Configuration},
     ec::{gf256::Coder, EcSchema},
-    hdfs::connection::{DatanodeConnection, Op},
+    hdfs::connection::{
+        datanode_host, DatanodeConnection, DatanodeConnectionCache, Op, RpcConfig, RpcConnection,
+        WritePacket,
+    },
+    hdfs::protocol::NamenodeProtocol,
+    metrics::Metrics,
     proto::{common, hdfs},
+    security::{digest::Qop, user::User},
     HdfsError, Result,
 };
 
 use super::connection::Packet;
 
+#[cfg(unix)]
+use crate::hdfs::shortcircuit::{self, LocalBlockReader};
+
+// Short-circuit reads need UNIX domain sockets, so there is never a local reader without them
+#[cfg(not(unix))]
+type LocalBlockReader = std::convert::Infallible;
+
 const HEART_BEAT_SEQNO: i64 = -1;
 const UNKNOWN_SEQNO: i64 = -1;
+// How many times in a row the pipeline of a block is rebuilt before the write fails
+const MAX_PIPELINE_RECOVERIES: usize = 5;
+// How much of a block is read at a time by a short-circuit read, the same as a packet
+#[cfg(unix)]
+const LOCAL_READ_SIZE: usize = 64 * 1024;
+
+/// Settings used when connecting to DataNodes to read and write blocks
+#[derive(Debug, Clone)]
+pub(crate) struct DatanodeConfig {
+    /// Timeout for connecting to a DataNode and for each read from it
+    pub(crate) socket_timeout: Duration,
+    /// Acceptable SASL qualities of protection for data transfer, in order of preference. Empty
+    /// if data transfer isn't protected.
+    pub(crate) protection: Vec<Qop>,
+    /// Number of blocks to fetch concurrently ahead of the block being read
+    pub(crate) read_ahead_blocks: usize,
+    /// Number of blocks fetched at the same time by a read of a range into a single buffer
+    pub(crate) read_parallelism: usize,
+    /// Whether checksums are requested and verified when reading blocks
+    pub(crate) verify_checksums: bool,
+    /// Maximum number of idle connections a reader keeps for reuse
+    pub(crate) connection_cache_capacity: usize,
+    /// How long an idle connection is kept for reuse
+    pub(crate) connection_cache_expiry: Duration,
+    /// When a DataNode that fails during a write is replaced with a new one
+    pub(crate) replace_datanode_policy: ReplaceDatanodePolicy,
+    /// Whether a write continues with the remaining DataNodes if a failed one can't be replaced
+    pub(crate) replace_datanode_best_effort: bool,
+    /// Number of packets a write sends to the DataNodes of a block before waiting for them to be
+    /// acknowledged
+    pub(crate) write_max_packets: usize,
+    /// Whether a writer dropped without being closed recovers the lease on its file right away
+    pub(crate) recover_lease_on_drop: bool,
+    /// Key that data transfer is encrypted with when the NameNode requires it. Keys last for
+    /// hours, so the key fetched when a reader or writer is opened is used for its whole life.
+    pub(crate) encryption_key: Option<hdfs::DataEncryptionKeyProto>,
+    /// Whether DataNodes are connected to by their hostnames instead of their IP addresses
+    pub(crate) use_datanode_hostname: bool,
+    /// Domain socket path of DataNodes on this host, if short-circuit reads are enabled
+    pub(crate) short_circuit_socket_path: Option<String>,
+    /// Buffers shared by every reader of the client that packets are read into
+    pub(crate) buffer_pool: BufferPool,
+    /// Settings for RPCs to the IPC servers of DataNodes
+    pub(crate) rpc: RpcConfig,
+    pub(crate) metrics: Metrics,
+    /// Progress of the reader this config belongs to, if a callback was set
+    pub(crate) progress: Option<ReadProgress>,
+}
+
+impl DatanodeConfig {
+    pub(crate) fn new(
+        config: &Configuration,
+        user: Arc<User>,
+        buffer_pool: BufferPool,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            socket_timeout: config.get_socket_timeout(),
+            protection: config
+                .get_data_transfer_protection()
+                .iter()
+                .filter_map(|qop| Qop::parse(qop))
+                .collect(),
+            read_ahead_blocks: config.get_read_ahead_blocks(),
+            read_parallelism: config.get_read_parallelism(),
+            verify_checksums: config.get_verify_checksum(),
+            connection_cache_capacity: config.get_socket_cache_capacity(),
+            connection_cache_expiry: config.get_socket_cache_expiry(),
+            replace_datanode_policy: ReplaceDatanodePolicy::parse(
+                &config.get_replace_datanode_policy(),
+            ),
+            replace_datanode_best_effort: config.get_replace_datanode_best_effort(),
+            // Nothing could ever be sent without room for at least one packet
+            write_max_packets: config.get_write_max_packets().max(1),
+            recover_lease_on_drop: config.get_recover_lease_on_drop(),
+            encryption_key: None,
+            use_datanode_hostname: config.get_use_datanode_hostname(),
+            short_circuit_socket_path: config.get_short_circuit_socket_path(),
+            buffer_pool,
+            rpc: RpcConfig::new(config, user),
+            metrics,
+            progress: None,
+        }
+    }
+
+    fn record_bytes_read(&self, bytes: usize) {
+        self.metrics.record_bytes_read(bytes);
+        if let Some(progress) = self.progress.as_ref() {
+            progress.add(bytes);
+        }
+    }
+}
+
+/// Counts the bytes a reader has received from DataNodes and passes the total to the callback
+/// set with [FileReader::set_progress_callback](crate::file::FileReader::set_progress_callback)
+#[derive(Clone)]
+pub(crate) struct ReadProgress {
+    bytes: Arc<AtomicUsize>,
+    callback: Arc<dyn Fn(usize) + Send + Sync>,
+}
+
+impl ReadProgress {
+    pub(crate) fn new(callback: Arc<dyn Fn(usize) + Send + Sync>) -> Self {
+        Self {
+            bytes: Arc::new(AtomicUsize::new(0)),
+            callback,
+        }
+    }
+
+    fn add(&self, bytes: usize) {
+        let total = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        (self.callback)(total);
+    }
+}
+
+impl fmt::Debug for ReadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadProgress")
+            .field("bytes", &self.bytes)
+            .finish_non_exhaustive()
+    }
+}
+
+/// When a DataNode that fails during a write is replaced, matching the policies of
+/// `ReplaceDatanodeOnFailure` in the Java client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReplaceDatanodePolicy {
+    Never,
+    Default,
+    Always,
+}
+
+impl ReplaceDatanodePolicy {
+    fn parse(policy: &str) -> Self {
+        match policy {
+            "NEVER" => Self::Never,
+            "ALWAYS" => Self::Always,
+            _ => Self::Default,
+        }
+    }
+
+    /// Whether a DataNode should be added to a pipeline of `remaining` DataNodes for a file with
+    /// `replication` replicas
+    fn should_replace(
+        &self,
+        replication: usize,
+        remaining: usize,
+        append_or_flushed: bool,
+    ) -> bool {
+        if remaining == 0 || remaining >= replication {
+            return false;
+        }
+        match self {
+            Self::Never => false,
+            Self::Always => true,
+            // Replace if half of the replicas are gone, or if readers may already depend on them
+            Self::Default => {
+                replication >= 3 && (remaining <= replication / 2 || append_or_flushed)
+            }
+        }
+    }
+}
+
+/// The `ip:port` data transfer address of a DataNode
+fn datanode_address(datanode: &hdfs::DatanodeIdProto) -> String {
+    format!("{}:{}", datanode.ip_addr, datanode.xfer_port)
+}
+
+/// The checksum the DataNodes are asked to store for blocks written with `server_defaults`
+fn requested_checksum(server_defaults: &hdfs::FsServerDefaultsProto) -> hdfs::ChecksumProto {
+    hdfs::ChecksumProto {
+        r#type: server_defaults.checksum_type() as i32,
+        bytes_per_checksum: server_defaults.bytes_per_checksum,
+    }
+}
+
+/// Records a read of a block from a single DataNode as a `hdfs.read` span when the `tracing`
+/// feature is enabled. The byte count and latency are recorded when it's dropped.
+struct ReadSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    start: std::time::Instant,
+    #[cfg(feature = "tracing")]
+    bytes: usize,
+}
+
+impl ReadSpan {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn new(block: &hdfs::ExtendedBlockProto, datanode: &hdfs::DatanodeIdProto) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "hdfs.read",
+                block = block.block_id,
+                datanode = datanode_address(datanode),
+                bytes = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+            ),
+            #[cfg(feature = "tracing")]
+            start: std::time::Instant::now(),
+            #[cfg(feature = "tracing")]
+            bytes: 0,
+        }
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn add_bytes(&mut self, bytes: usize) {
+        #[cfg(feature = "tracing")]
+        {
+            self.bytes += bytes;
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for ReadSpan {
+    fn drop(&mut self) {
+        self.span.record("bytes", self.bytes);
+        self.span
+            .record("latency_ms", self.start.elapsed().as_millis() as u64);
+    }
+}
+
+/// Runs a DataNode operation, failing with a [io::ErrorKind::TimedOut] error if it doesn't
+/// complete within `timeout`
+async fn with_timeout<T>(timeout: Duration, future: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(timeout, future).await.map_err(|_| {
+        HdfsError::IOError(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "Timed out waiting for DataNode",
+        ))
+    })?
+}
+
+/// Starts reading `len` bytes at `offset` of a block from a DataNode, reusing an idle connection
+/// to it from `connection_cache` if there is one. The DataNode closes idle connections after a
+/// while, so if a cached connection fails the request is sent again on a new connection.
+async fn start_block_read(
+    datanode: &hdfs::DatanodeIdProto,
+    block: &hdfs::ExtendedBlockProto,
+    token: &common::TokenProto,
+    offset: usize,
+    len: usize,
+    config: &DatanodeConfig,
+    connection_cache: &DatanodeConnectionCache,
+) -> Result<(DatanodeConnection, hdfs::BlockOpResponseProto)> {
+    let message = |connection: &DatanodeConnection| hdfs::OpReadBlockProto {
+        header: connection.build_header(block, Some(token.clone())),
+        offset: offset as u64,
+        len: len as u64,
+        send_checksums: Some(config.verify_checksums),
+        ..Default::default()
+    };
+
+    let mut result = None;
+    if let Some(mut connection) = connection_cache.get(datanode) {
+        let message = message(&connection);
+        match send_read_block(&mut connection, &message, config).await {
+            Ok(response) => result = Some((connection, response)),
+            Err(e) => debug!(
+                "Cached connection to {:?} failed, reconnecting: {:?}",
+                datanode, e
+            ),
+        }
+    }
+    let (connection, response) = match result {
+        Some(result) => result,
+        None => {
+            let mut connection = with_timeout(
+                config.socket_timeout,
+                DatanodeConnection::connect(
+                    datanode,
+                    token,
+                    &config.protection,
+                    config.encryption_key.as_ref(),
+                    config.use_datanode_hostname,
+                ),
+            )
+            .await?;
+            let message = message(&connection);
+            let response = send_read_block(&mut connection, &message, config).await?;
+            (connection, response)
+        }
+    };
+
+    match response.status() {
+        // Nothing is verified even if the DataNode sends checksums anyway
+        hdfs::Status::Success if !config.verify_checksums => Ok((
+            connection,
+            hdfs::BlockOpResponseProto {
+                read_op_checksum_info: None,
+                ..response
+            },
+        )),
+        hdfs::Status::Success => Ok((connection, response)),
+        hdfs::Status::ErrorAccessToken => {
+            Err(HdfsError::InvalidBlockToken(response.message().to_string()))
+        }
+        _ => Err(HdfsError::DataTransferError(response.message().to_string())),
+    }
+}
+
+async fn send_read_block(
+    connection: &mut DatanodeConnection,
+    message: &hdfs::OpReadBlockProto,
+    config: &DatanodeConfig,
+) -> Result<hdfs::BlockOpResponseProto> {
+    debug!("Block read op request {:?}", message);
+    connection.send(Op::ReadBlock, message).await?;
+    let response = with_timeout(config.socket_timeout, connection.read_block_op_response()).await?;
+    debug!("Block read op response {:?}", response);
+    Ok(response)
+}
+
+/// Finishes a block read whose whole range has been received by reading the empty packet that
+/// ends it and acknowledging the data, and then caches the connection for another read. The
+/// connection is closed instead if this fails.
+async fn release_connection(
+    mut connection: DatanodeConnection,
+    datanode: &hdfs::DatanodeIdProto,
+    config: &DatanodeConfig,
+    connection_cache: &DatanodeConnectionCache,
+) {
+    if !connection_cache.is_enabled() {
+        return;
+    }
+    let result = async {
+        let packet = with_timeout(
+            config.socket_timeout,
+            connection.read_packet(&config.buffer_pool),
+        )
+        .await?;
+        if packet.header.data_len != 0 || !packet.header.last_packet_in_block {
+            return Err(HdfsError::DataTransferError(
+                "Expected an empty packet at the end of the block read".to_string(),
+            ));
+        }
+        connection.send_read_status(hdfs::Status::ChecksumOk).await
+    }
+    .await;
+
+    match result {
+        Ok(()) => connection_cache.put(datanode, connection),
+        Err(e) => debug!("Not reusing connection to {:?}: {:?}", datanode, e),
+    }
+}
+
+/// Fetches a block's locations again from the NameNode when a DataNode rejects its block token,
+/// usually because the token expired while the file was open
+#[derive(Clone)]
+pub(crate) struct BlockRefetcher {
+    pub(crate) protocol: Arc<NamenodeProtocol>,
+    pub(crate) src: String,
+}
+
+impl BlockRefetcher {
+    /// Gets the current locations and tokens of `block`. This uses `getLocatedFileInfo` instead
+    /// of `getBlockLocations`, which would update the access time of the file.
+    async fn refetch(&self, block: &hdfs::LocatedBlockProto) -> Result<hdfs::LocatedBlockProto> {
+        debug!(
+            "Fetching new block token for block {} of {}",
+            block.b.block_id, self.src
+        );
+        let located_blocks = self
+            .protocol
+            .get_located_file_info(&self.src)
+            .await?
+            .fs
+            .and_then(|status| status.locations)
+            .ok_or_else(|| HdfsError::FileNotFound(self.src.clone()))?;
+
+        located_blocks
+            .blocks
+            .into_iter()
+            .chain(located_blocks.last_block)
+            .find(|b| b.b.block_id == block.b.block_id)
+            .ok_or_else(|| {
+                HdfsError::BlocksNotFound(format!(
+                    "Block {} is no longer part of {}",
+                    block.b.block_id, self.src
+                ))
+            })
+    }
+}
 
 pub(crate) fn get_block_stream(
     block: hdfs::LocatedBlockProto,
     offset: usize,
     len: usize,
     ec_schema: Option<EcSchema>,
+    config: DatanodeConfig,
+    connection_cache: Arc<DatanodeConnectionCache>,
+    refetcher: Option<BlockRefetcher>,
 ) -> BoxStream<'static, Result<Bytes>> {
     if let Some(ec_schema) = ec_schema {
-        StripedBlockStream::new(block, offset, len, ec_schema)
-            .into_stream()
-            .boxed()
+        StripedBlockStream::new(
+            block,
+            offset,
+            len,
+            ec_schema,
+            config,
+            connection_cache,
+            refetcher,
+        )
+        .into_stream()
+        .boxed()
     } else {
-        ReplicatedBlockStream::new(block, offset, len)
+        ReplicatedBlockStream::new(block, offset, len, config, connection_cache, refetcher)
             .into_stream()
             .boxed()
     }
 }
 
+/// Requests the MD5-of-CRCs checksum of the first `len` bytes of a replicated block, trying each
+/// replica until one succeeds.
+pub(crate) async fn get_block_checksum(
+    block: &hdfs::LocatedBlockProto,
+    len: u64,
+    config: &DatanodeConfig,
+) -> Result<hdfs::OpBlockChecksumResponseProto> {
+    let mut extended_block = block.b.clone();
+    extended_block.num_bytes = Some(len);
+
+    // Like the Java client, DataNodes get extra time for each replica they may need to read
+    let timeout = config.socket_timeout + Duration::from_secs(3) * block.locs.len() as u32;
+    let mut last_error = HdfsError::DataTransferError("No DataNodes for block".to_string());
+    for location in block.locs.iter() {
+        let datanode = &location.id;
+        let result: Result<hdfs::OpBlockChecksumResponseProto> = with_timeout(timeout, async {
+            let mut connection = DatanodeConnection::connect(
+                datanode,
+                &block.block_token,
+                &config.protection,
+                config.encryption_key.as_ref(),
+                config.use_datanode_hostname,
+            )
+            .await?;
+
+            let message = hdfs::OpBlockChecksumProto {
+                header: connection
+                    .build_header(&extended_block, Some(block.block_token.clone()))
+                    .base_header,
+                block_checksum_options: Some(hdfs::BlockChecksumOptionsProto {
+                    block_checksum_type: Some(hdfs::BlockChecksumTypeProto::Md5crc as i32),
+                    stripe_length: None,
+                }),
+            };
+            debug!("Block checksum op request {:?}", &message);
+
+            connection.send(Op::BlockChecksum, &message).await?;
+            let response = connection.read_block_op_response().await?;
+            debug!("Block checksum op response {:?}", response);
+
+            if response.status() != hdfs::Status::Success {
+                return Err(HdfsError::DataTransferError(response.message().to_string()));
+            }
+
+            response
+                .checksum_response
+                .ok_or(HdfsError::DataTransferError(
+                    "Block checksum response missing checksum".to_string(),
+                ))
+        })
+        .await;
+
+        match result {
+            Ok(checksum) => return Ok(checksum),
+            Err(e) => {
+                debug!("Failed to get block checksum from {:?}: {:?}", datanode, e);
+                last_error = e;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Asks the DataNodes of a block that's still being written how much of it readers can see,
+/// trying each replica until one succeeds. The NameNode only learns the length of a block
+/// once it's complete.
+pub(crate) async fn get_replica_visible_length(
+    block: &hdfs::LocatedBlockProto,
+    config: &DatanodeConfig,
+) -> Result<u64> {
+    let message = hdfs::GetReplicaVisibleLengthRequestProto {
+        block: block.b.clone(),
+    };
+
+    let mut last_error = HdfsError::DataTransferError("No DataNodes for block".to_string());
+    for location in block.locs.iter() {
+        let datanode = &location.id;
+        let result: Result<u64> = async {
+            let host = datanode_host(datanode, config.use_datanode_hostname);
+            let url = format!("{}:{}", host, datanode.ipc_port);
+            let connection =
+                RpcConnection::connect_datanode(&url, &block.block_token, &config.rpc).await?;
+
+            debug!("getReplicaVisibleLength request: {:?}", &message);
+            let response = connection
+                .call(
+                    "getReplicaVisibleLength",
+                    &message.encode_length_delimited_to_vec(),
+                )
+                .await?;
+            let decoded =
+                hdfs::GetReplicaVisibleLengthResponseProto::decode_length_delimited(response)?;
+            debug!("getReplicaVisibleLength response: {:?}", &decoded);
+            Ok(decoded.length)
+        }
+        .await;
+
+        match result {
+            Ok(length) => return Ok(length),
+            Err(e) => {
+                debug!(
+                    "Failed to get replica visible length from {:?}: {:?}",
+                    datanode, e
+                );
+                last_error = e;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
 struct ReplicatedBlockStream {
     block: hdfs::LocatedBlockProto,
     offset: usize,
     len: usize,
+    config: DatanodeConfig,
+    connection_cache: Arc<DatanodeConnectionCache>,
+    refetcher: Option<BlockRefetcher>,
 
     connection: Option<DatanodeConnection>,
+    // Set instead of the connection when the replica is read with a short-circuit read
+    local_reader: Option<LocalBlockReader>,
     checksum_info: Option<hdfs::ReadOpChecksumInfoProto>,
     current_replica: usize,
+    read_span: Option<ReadSpan>,
+    // Whether the block token was refetched since the last packet was read, so a token that's
+    // rejected again fails the replica instead of being refetched forever
+    token_refetched: bool,
 }
 
 impl ReplicatedBlockStream {
-    fn new(block: hdfs::LocatedBlockProto, offset: usize, len: usize) -> Self {
+    fn new(
+        block: hdfs::LocatedBlockProto,
+        offset: usize,
+        len: usize,
+        config: DatanodeConfig,
+        connection_cache: Arc<DatanodeConnectionCache>,
+        refetcher: Option<BlockRefetcher>,
+    ) -> Self {
         Self {
             block,
             offset,
             len,
+            config,
+            connection_cache,
+            refetcher,
             connection: None,
+            local_reader: None,
             checksum_info: None,
             current_replica: 0,
+            read_span: None,
+            token_refetched: false,
         }
     }
 
-    async fn select_next_datanode(&mut self) -> Result<()> {
-        if self.connection.is_some() {
-            self.current_replica += 1;
-            if self.current_replica >= self.block.locs.len() {
-                return Err(HdfsError::DataTransferError(
-                    "All DataNodes failed".to_string(),
-                ));
-            }
-        }
+    /// Starts reading the remaining range of the block from the current replica. Replicas on
+    /// this host are read directly from their files if short-circuit reads are enabled, falling
+    /// back to reading them over TCP if the DataNode doesn't pass the files.
+    async fn connect(&mut self) -> Result<()> {
         let datanode = &self.block.locs[self.current_replica].id;
-        let mut connection =
-            DatanodeConnection::connect(&format!("{}:{}", datanode.ip_addr, datanode.xfer_port))
-                .await?;
-
-        let message = hdfs::OpReadBlockProto {
-            header: connection.build_header(&self.block.b, Some(self.block.block_token.clone())),
-            offset: self.offset as u64,
-            len: self.len as u64,
-            send_checksums: Some(true),
-            ..Default::default()
-        };
+        self.read_span = Some(ReadSpan::new(&self.block.b, datanode));
 
-        debug!("Block read op request {:?}", &message);
+        #[cfg(feature = "integration-test")]
+        if let Some(fault_injection) = crate::test::REPLICA_FAULT_INJECTOR.lock().unwrap().as_ref()
+        {
+            if fault_injection
+                .fail_replicas
+                .contains(&self.current_replica)
+            {
+                debug!("Failing connection to replica {}", self.current_replica);
+                return Err(HdfsError::InternalError("Testing error".to_string()));
+            }
+        }
 
-        connection.send(Op::ReadBlock, &message).await?;
-        let response = connection.read_block_op_response().await?;
-        debug!("Block read op response {:?}", response);
+        #[cfg(not(unix))]
+        if self.config.short_circuit_socket_path.is_some() {
+            debug!("Short-circuit reads need UNIX domain sockets, reading remotely");
+        }
 
-        if response.status() != hdfs::Status::Success {
-            return Err(HdfsError::DataTransferError(response.message().to_string()));
+        #[cfg(unix)]
+        if let Some(socket_path) = self.config.short_circuit_socket_path.as_ref() {
+            if shortcircuit::is_local(datanode) {
+                match LocalBlockReader::open(
+                    shortcircuit::socket_path(socket_path, datanode),
+                    &self.block.b,
+                    &self.block.block_token,
+                    self.config.socket_timeout,
+                )
+                .await
+                {
+                    Ok(local_reader) => {
+                        self.local_reader = Some(if self.config.verify_checksums {
+                            local_reader
+                        } else {
+                            local_reader.without_checksums()
+                        });
+                        return Ok(());
+                    }
+                    Err(e) => debug!(
+                        "Short-circuit read of block {} from {:?} failed, reading remotely: {:?}",
+                        self.block.b.block_id, datanode, e
+                    ),
+                }
+            }
         }
 
+        let (connection, response) = start_block_read(
+            datanode,
+            &self.block.b,
+            &self.block.block_token,
+            self.offset,
+            self.len,
+            &self.config,
+            &self.connection_cache,
+        )
+        .await?;
+
         self.connection = Some(connection);
         self.checksum_info = response.read_op_checksum_info;
 
         Ok(())
     }
 
+    /// Reads the next packet of the block. If the current replica fails for any reason, such as
+    /// a connection error, timeout, or corrupt data, the read resumes from the next replica. An
+    /// error is only returned once all replicas have failed. If a DataNode rejects the block
+    /// token, a new one is fetched from the NameNode and the read starts over from the first
+    /// replica.
     async fn next_packet(&mut self) -> Result<Option<Bytes>> {
         if self.len == 0 {
+            self.read_span = None;
+            self.local_reader = None;
+            if let Some(connection) = self.connection.take() {
+                release_connection(
+                    connection,
+                    &self.block.locs[self.current_replica].id,
+                    &self.config,
+                    &self.connection_cache,
+                )
+                .await;
+            }
             return Ok(None);
         }
-        if self.connection.is_none() {
-            self.select_next_datanode().await?;
+        if self.block.locs.is_empty() {
+            return Err(HdfsError::BlocksNotFound(format!(
+                "No DataNodes found for block {}",
+                self.block.b.block_id
+            )));
+        }
+        loop {
+            let result = if self.connection.is_some() || self.local_reader.is_some() {
+                self.read_packet().await
+            } else {
+                match self.connect().await {
+                    Ok(()) => self.read_packet().await,
+                    Err(e) => Err(e),
+                }
+            };
+
+            match result {
+                Ok(data) => {
+                    self.token_refetched = false;
+                    return Ok(Some(data));
+                }
+                Err(HdfsError::InvalidBlockToken(msg))
+                    if !self.token_refetched && self.refetcher.is_some() =>
+                {
+                    debug!(
+                        "Block token of block {} was rejected: {}",
+                        self.block.b.block_id, msg
+                    );
+                    self.connection = None;
+                    self.local_reader = None;
+                    self.read_span = None;
+                    self.token_refetched = true;
+                    let block = self
+                        .refetcher
+                        .as_ref()
+                        .unwrap()
+                        .refetch(&self.block)
+                        .await?;
+                    if block.locs.is_empty() {
+                        return Err(HdfsError::BlocksNotFound(format!(
+                            "No DataNodes found for block {}",
+                            self.block.b.block_id
+                        )));
+                    }
+                    self.block.block_token = block.block_token;
+                    self.block.locs = block.locs;
+                    self.current_replica = 0;
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to read block {} from {:?}: {:?}",
+                        self.block.b.block_id, self.block.locs[self.current_replica].id, e
+                    );
+                    self.config.metrics.record_datanode_error(&datanode_address(
+                        &self.block.locs[self.current_replica].id,
+                    ));
+                    self.connection = None;
+                    self.local_reader = None;
+                    self.read_span = None;
+                    self.current_replica += 1;
+                    if self.current_replica >= self.block.locs.len() {
+                        return Err(match e {
+                            // Corrupt data is reported as is
+                            HdfsError::ChecksumError { .. } => e,
+                            _ => HdfsError::BlocksNotFound(format!(
+                                "Could not read block {} from any DataNode: {:?}",
+                                self.block.b.block_id, e
+                            )),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    async fn read_packet(&mut self) -> Result<Bytes> {
+        #[cfg(unix)]
+        if let Some(local_reader) = self.local_reader.as_ref() {
+            let len = usize::min(self.len, LOCAL_READ_SIZE);
+            let data = local_reader.read(self.offset, len).await?;
+            self.offset += len;
+            self.len -= len;
+            if let Some(read_span) = self.read_span.as_mut() {
+                read_span.add_bytes(len);
+            }
+            self.config.record_bytes_read(len);
+            self.config.metrics.record_short_circuit_bytes_read(len);
+            return Ok(data);
         }
+
         let conn = self.connection.as_mut().unwrap();
-        let packet = conn.read_packet().await?;
+        #[allow(unused_mut)]
+        let mut packet = with_timeout(
+            self.config.socket_timeout,
+            conn.read_packet(&self.config.buffer_pool),
+        )
+        .await?;
+
+        #[cfg(feature = "integration-test")]
+        if let Some(fault_injection) = crate::test::REPLICA_FAULT_INJECTOR.lock().unwrap().as_ref()
+        {
+            if fault_injection
+                .corrupt_replicas
+                .contains(&self.current_replica)
+            {
+                packet.corrupt_data();
+            }
+        }
 
         let packet_offset = if self.offset > packet.header.offset_in_block as usize {
             self.offset - packet.header.offset_in_block as usize
@@ -114,14 +816,16 @@ impl ReplicatedBlockStream {
             0
         };
         let packet_len = usize::min(packet.header.data_len as usize - packet_offset, self.len);
-        let packet_data = packet.get_data(&self.checksum_info)?;
+        let packet_data = packet.get_data(&self.checksum_info, self.block.b.block_id)?;
 
         self.offset += packet_len;
         self.len -= packet_len;
+        if let Some(read_span) = self.read_span.as_mut() {
+            read_span.add_bytes(packet_len);
+        }
+        self.config.record_bytes_read(packet_len);
 
-        Ok(Some(
-            packet_data.slice(packet_offset..(packet_offset + packet_len)),
-        ))
+        Ok(packet_data.slice(packet_offset..(packet_offset + packet_len)))
     }
 
     fn into_stream(self) -> impl Stream<Item = Result<Bytes>> {
@@ -137,22 +841,56 @@ struct StripedBlockStream {
     offset: usize,
     len: usize,
     ec_schema: EcSchema,
+    config: DatanodeConfig,
+    connection_cache: Arc<DatanodeConnectionCache>,
+    refetcher: Option<BlockRefetcher>,
 }
 
 impl StripedBlockStream {
-    fn new(block: hdfs::LocatedBlockProto, offset: usize, len: usize, ec_schema: EcSchema) -> Self {
+    fn new(
+        block: hdfs::LocatedBlockProto,
+        offset: usize,
+        len: usize,
+        ec_schema: EcSchema,
+        config: DatanodeConfig,
+        connection_cache: Arc<DatanodeConnectionCache>,
+        refetcher: Option<BlockRefetcher>,
+    ) -> Self {
         Self {
             block,
             offset,
             len,
             ec_schema,
+            config,
+            connection_cache,
+            refetcher,
         }
     }
 
     /// Hacky "stream" of a single value to match replicated behavior
     /// TODO: Stream the results based on rows of cells?
-    fn into_stream(self) -> impl Stream<Item = Result<Bytes>> {
-        stream::once(async move { self.read_striped().await })
+    fn into_stream(mut self) -> impl Stream<Item = Result<Bytes>> {
+        stream::once(async move {
+            match self.read_striped().await {
+                Err(HdfsError::InvalidBlockToken(msg)) if self.refetcher.is_some() => {
+                    debug!(
+                        "Block tokens of block group {} were rejected: {}",
+                        self.block.b.block_id, msg
+                    );
+                    let block = self
+                        .refetcher
+                        .as_ref()
+                        .unwrap()
+                        .refetch(&self.block)
+                        .await?;
+                    self.block.block_tokens = block.block_tokens;
+                    self.block.block_indices = block.block_indices;
+                    self.block.locs = block.locs;
+                    self.read_striped().await
+                }
+                result => result,
+            }
+        })
     }
 
     /// Erasure coded data is stored in "cells" that are striped across Data Nodes.
@@ -224,13 +962,14 @@ impl StripedBlockStream {
             ));
         }
 
-        // Do the actual reads and count how many data blocks failed
+        // Do the actual reads and count how many data blocks failed. Rejected block tokens are
+        // returned so they can be refetched, as the parity blocks would be rejected as well.
         let mut failed_data_blocks = 0usize;
         for (index, result) in join_all(futures).await.into_iter().enumerate() {
-            if let Ok(bytes) = result {
-                stripe_results[index] = Some(bytes);
-            } else {
-                failed_data_blocks += 1;
+            match result {
+                Ok(bytes) => stripe_results[index] = Some(bytes),
+                Err(e @ HdfsError::InvalidBlockToken(_)) => return Err(e),
+                Err(_) => failed_data_blocks += 1,
             }
         }
 
@@ -319,8 +1058,15 @@ impl StripedBlockStream {
                 .position(|x| *x == index)
                 .unwrap()];
 
-            self.read_from_datanode(&datanode_info.id, &block, token, offset, read_len, &mut buf)
-                .await?;
+            if let Err(e) = self
+                .read_from_datanode(&datanode_info.id, &block, token, offset, read_len, &mut buf)
+                .await
+            {
+                self.config
+                    .metrics
+                    .record_datanode_error(&datanode_address(&datanode_info.id));
+                return Err(e);
+            }
         }
 
         Ok(buf.freeze())
@@ -338,52 +1084,44 @@ impl StripedBlockStream {
         if len == 0 {
             return Ok(());
         }
+        let mut read_span = ReadSpan::new(block, datanode);
 
-        let mut conn =
-            DatanodeConnection::connect(&format!("{}:{}", datanode.ip_addr, datanode.xfer_port))
-                .await?;
-
-        let message = hdfs::OpReadBlockProto {
-            header: conn.build_header(block, Some(token.clone())),
-            offset: offset as u64,
-            len: len as u64,
-            send_checksums: Some(true),
-            ..Default::default()
-        };
-        debug!("Block read op request {:?}", &message);
-
-        conn.send(Op::ReadBlock, &message).await?;
-        let response = conn.read_block_op_response().await?;
-        debug!("Block read op response {:?}", response);
-
-        if response.status() != hdfs::Status::Success {
-            return Err(HdfsError::DataTransferError(response.message().to_string()));
-        }
+        let (mut conn, response) = start_block_read(
+            datanode,
+            block,
+            token,
+            offset,
+            len,
+            &self.config,
+            &self.connection_cache,
+        )
+        .await?;
 
         // First handle the offset into the first packet
-        let mut packet = conn.read_packet().await?;
+        let mut packet = conn.read_packet(&self.config.buffer_pool).await?;
         let packet_offset = offset - packet.header.offset_in_block as usize;
         let data_len = packet.header.data_len as usize - packet_offset;
         let data_to_read = usize::min(data_len, len);
         let mut data_left = len - data_to_read;
 
-        let packet_data = packet.get_data(&response.read_op_checksum_info)?;
+        let packet_data = packet.get_data(&response.read_op_checksum_info, block.block_id)?;
         buf.put(packet_data.slice(packet_offset..(packet_offset + data_to_read)));
 
         while data_left > 0 {
-            packet = conn.read_packet().await?;
+            packet = conn.read_packet(&self.config.buffer_pool).await?;
             // TODO: Error checking
             let data_to_read = usize::min(data_left, packet.header.data_len as usize);
             buf.put(
                 packet
-                    .get_data(&response.read_op_checksum_info)?
+                    .get_data(&response.read_op_checksum_info, block.block_id)?
                     .slice(0..data_to_read),
             );
             data_left -= data_to_read;
         }
 
-        // There should be one last empty packet after we are done
-        conn.read_packet().await?;
+        release_connection(conn, datanode, &self.config, &self.connection_cache).await;
+        read_span.add_bytes(len);
+        self.config.record_bytes_read(len);
 
         Ok(())
     }
@@ -402,6 +1140,8 @@ impl BlockWriter {
         block_size: usize,
         server_defaults: hdfs::FsServerDefaultsProto,
         ec_schema: Option<&EcSchema>,
+        config: &DatanodeConfig,
+        recovery: PipelineRecovery,
     ) -> Result<Self> {
         let block_writer = if let Some(ec_schema) = ec_schema {
             Self::Striped(StripedBlockWriter::new(
@@ -409,13 +1149,47 @@ impl BlockWriter {
                 ec_schema,
                 block_size,
                 server_defaults,
+                config,
             ))
         } else {
-            Self::Replicated(ReplicatedBlockWriter::new(block, block_size, server_defaults).await?)
+            Self::Replicated(
+                ReplicatedBlockWriter::new(
+                    block,
+                    None,
+                    block_size,
+                    server_defaults,
+                    config,
+                    Some(recovery),
+                )
+                .await?,
+            )
         };
         Ok(block_writer)
     }
 
+    /// Reopens the pipeline for the partially filled last block of a replicated file.
+    /// `new_generation_stamp` is the bumped generation stamp from `updateBlockForPipeline`.
+    pub(crate) async fn append(
+        block: hdfs::LocatedBlockProto,
+        new_generation_stamp: u64,
+        block_size: usize,
+        server_defaults: hdfs::FsServerDefaultsProto,
+        config: &DatanodeConfig,
+        recovery: PipelineRecovery,
+    ) -> Result<Self> {
+        Ok(Self::Replicated(
+            ReplicatedBlockWriter::new(
+                block,
+                Some(new_generation_stamp),
+                block_size,
+                server_defaults,
+                config,
+                Some(recovery),
+            )
+            .await?,
+        ))
+    }
+
     pub(crate) async fn write(&mut self, buf: &mut Bytes) -> Result<()> {
         match self {
             Self::Replicated(writer) => writer.write(buf).await,
@@ -437,6 +1211,17 @@ impl BlockWriter {
         }
     }
 
+    /// Sends any buffered data and waits until it has been acknowledged by every DataNode in
+    /// the pipeline. If `sync` is set, the DataNodes also sync the block to disk first.
+    pub(crate) async fn flush(&mut self, sync: bool) -> Result<()> {
+        match self {
+            Self::Replicated(writer) => writer.flush(sync).await,
+            Self::Striped(_) => Err(HdfsError::UnsupportedFeature(
+                "Flushing erasure coded files".to_string(),
+            )),
+        }
+    }
+
     pub(crate) async fn close(&mut self) -> Result<()> {
         match self {
             Self::Replicated(writer) => writer.close().await,
@@ -445,53 +1230,164 @@ impl BlockWriter {
     }
 }
 
+/// What a block writer needs from the NameNode to recover its pipeline after a DataNode fails
+#[derive(Clone)]
+pub(crate) struct PipelineRecovery {
+    pub(crate) protocol: Arc<NamenodeProtocol>,
+    pub(crate) src: String,
+    pub(crate) file_id: Option<u64>,
+    /// Replication factor of the file
+    pub(crate) replication: usize,
+}
+
+/// A failure of the write pipeline
+#[derive(Debug)]
+struct PipelineError {
+    /// Index in the pipeline of the DataNode that failed
+    bad_node: usize,
+    error: HdfsError,
+}
+
+impl PipelineError {
+    fn new(bad_node: usize, error: HdfsError) -> Self {
+        Self { bad_node, error }
+    }
+}
+
+type PipelineStatus = std::result::Result<(), PipelineError>;
+
+/// Receives the acknowledgements of a pipeline in the background
+struct AckListener {
+    // Set to an Err if any error occurs receiving acknowledgements. Set to Ok(()) when the last
+    // acknowledgement is received.
+    status: oneshot::Receiver<PipelineStatus>,
+    // The sequence number of the last packet acknowledged by the pipeline
+    acked_seqno: watch::Receiver<i64>,
+    handle: JoinHandle<()>,
+}
+
 pub(crate) struct ReplicatedBlockWriter {
     block: hdfs::LocatedBlockProto,
     block_size: usize,
     server_defaults: hdfs::FsServerDefaultsProto,
+    config: DatanodeConfig,
+    // Not set for the cells of erasure coded blocks, which have a single DataNode each
+    recovery: Option<Arc<PipelineRecovery>>,
 
     next_seqno: i64,
     connection: DatanodeConnection,
     current_packet: Packet,
 
-    // Tracks the state of acknowledgements. Set to an Err if any error occurs doing receiving
-    // acknowledgements. Set to Ok(()) when the last acknowledgement is received.
-    status: Option<oneshot::Receiver<Result<()>>>,
-    ack_queue: mpsc::Sender<(i64, bool)>,
+    acks: AckListener,
+    // Packets that have been sent but not acknowledged yet, so they can be sent again on a
+    // recovered pipeline
+    ack_queue: Arc<Mutex<VecDeque<WritePacket>>>,
+    // Whether the last packet has been acknowledged
+    closed: bool,
+    // Whether the last packet has been sent
+    closing: bool,
+    // Whether the replicas may already be read, so a failed DataNode is replaced more eagerly
+    append_or_flushed: bool,
+    // DataNodes removed from the pipeline, which aren't used again for this block
+    failed_nodes: Vec<hdfs::DatanodeInfoProto>,
 }
 
 impl ReplicatedBlockWriter {
+    /// Creates the write pipeline for `block`. `new_generation_stamp` is only provided when
+    /// appending to an existing block.
     async fn new(
-        block: hdfs::LocatedBlockProto,
+        mut block: hdfs::LocatedBlockProto,
+        new_generation_stamp: Option<u64>,
         block_size: usize,
         server_defaults: hdfs::FsServerDefaultsProto,
+        config: &DatanodeConfig,
+        recovery: Option<PipelineRecovery>,
     ) -> Result<Self> {
-        let datanode = &block.locs[0].id;
-        let mut connection =
-            DatanodeConnection::connect(&format!("{}:{}", datanode.ip_addr, datanode.xfer_port))
-                .await?;
+        let append = new_generation_stamp.is_some();
 
-        let checksum = hdfs::ChecksumProto {
-            r#type: hdfs::ChecksumTypeProto::ChecksumCrc32c as i32,
-            bytes_per_checksum: server_defaults.bytes_per_checksum,
+        let stage = if append {
+            hdfs::op_write_block_proto::BlockConstructionStage::PipelineSetupAppend
+        } else {
+            hdfs::op_write_block_proto::BlockConstructionStage::PipelineSetupCreate
         };
 
-        let append = block.b.num_bytes() > 0;
+        let mut connection = Self::setup_pipeline(
+            &block,
+            stage,
+            new_generation_stamp.unwrap_or(block.b.generation_stamp),
+            block.b.num_bytes(),
+            requested_checksum(&server_defaults),
+            config,
+        )
+        .await
+        .map_err(|e| e.error)?;
+
+        // The datanode has moved the replica to the new generation stamp
+        if let Some(generation_stamp) = new_generation_stamp {
+            block.b.generation_stamp = generation_stamp;
+        }
+
+        let ack_queue = Arc::new(Mutex::new(VecDeque::new()));
+        let acks = Self::listen_for_acks(
+            &mut connection,
+            Arc::clone(&ack_queue),
+            -1,
+            config.socket_timeout,
+        )?;
 
-        let stage = if append {
-            hdfs::op_write_block_proto::BlockConstructionStage::PipelineSetupAppend as i32
-        } else {
-            hdfs::op_write_block_proto::BlockConstructionStage::PipelineSetupCreate as i32
+        let mut this = Self {
+            block,
+            block_size,
+            server_defaults,
+            config: config.clone(),
+            recovery: recovery.map(Arc::new),
+            next_seqno: 0,
+            connection,
+            current_packet: Packet::empty(0, 0, hdfs::ChecksumTypeProto::ChecksumNull, 0, 0),
+            acks,
+            ack_queue,
+            closed: false,
+            closing: false,
+            append_or_flushed: append,
+            failed_nodes: vec![],
         };
+        this.create_next_packet();
+
+        Ok(this)
+    }
+
+    /// Connects to the first DataNode of `block` and sets up the pipeline through the rest of
+    /// them. The replicas are expected to hold `block.b.num_bytes()` to `max_bytes_rcvd` bytes.
+    async fn setup_pipeline(
+        block: &hdfs::LocatedBlockProto,
+        stage: hdfs::op_write_block_proto::BlockConstructionStage,
+        latest_generation_stamp: u64,
+        max_bytes_rcvd: u64,
+        checksum: hdfs::ChecksumProto,
+        config: &DatanodeConfig,
+    ) -> std::result::Result<DatanodeConnection, PipelineError> {
+        let datanode = &block.locs[0].id;
+        let mut connection = with_timeout(
+            config.socket_timeout,
+            DatanodeConnection::connect(
+                datanode,
+                &block.block_token,
+                &config.protection,
+                config.encryption_key.as_ref(),
+                config.use_datanode_hostname,
+            ),
+        )
+        .await
+        .map_err(|e| PipelineError::new(0, e))?;
 
         let message = hdfs::OpWriteBlockProto {
             header: connection.build_header(&block.b, Some(block.block_token.clone())),
-            stage,
+            stage: stage as i32,
             targets: block.locs[1..].to_vec(),
             pipeline_size: block.locs.len() as u32,
-            latest_generation_stamp: block.b.generation_stamp,
+            latest_generation_stamp,
             min_bytes_rcvd: block.b.num_bytes(),
-            max_bytes_rcvd: block.b.num_bytes(),
+            max_bytes_rcvd,
             requested_checksum: checksum,
             storage_type: Some(block.storage_types[0]),
             target_storage_types: block.storage_types[1..].to_vec(),
@@ -502,163 +1398,451 @@ impl ReplicatedBlockWriter {
 
         debug!("Block write request: {:?}", &message);
 
-        connection.send(Op::WriteBlock, &message).await?;
-        let response = connection.read_block_op_response().await?;
+        let response = async {
+            connection.send(Op::WriteBlock, &message).await?;
+            with_timeout(config.socket_timeout, connection.read_block_op_response()).await
+        }
+        .await
+        .map_err(|e| PipelineError::new(0, e))?;
         debug!("Block write response: {:?}", response);
 
-        // Channel for receiving acks from the datanode
-        let (ack_response_sender, ack_response_receiver) =
-            mpsc::channel::<hdfs::PipelineAckProto>(100);
-        // Channel for tracking packets that need to be acked
-        let (ack_queue_sender, ack_queue_receiever) = mpsc::channel::<(i64, bool)>(100);
-        // Channel for tracking errors that occur listening for acks or successful ack of the last packet
-        let (status_sender, status_receiver) = oneshot::channel::<Result<()>>();
-
-        connection.read_acks(ack_response_sender)?;
+        if response.status() != hdfs::Status::Success {
+            // The first DataNode reports which downstream DataNode it couldn't connect to
+            let bad_node = response
+                .first_bad_link
+                .as_ref()
+                .and_then(|link| {
+                    block
+                        .locs
+                        .iter()
+                        .position(|loc| &datanode_address(&loc.id) == link)
+                })
+                .unwrap_or(0);
+            return Err(PipelineError::new(
+                bad_node,
+                HdfsError::DataTransferError(response.message().to_string()),
+            ));
+        }
 
-        let bytes_per_checksum = server_defaults.bytes_per_checksum;
-        let write_packet_size = server_defaults.write_packet_size;
+        Ok(connection)
+    }
 
-        let bytes_left_in_chunk = server_defaults.bytes_per_checksum
-            - (block.b.num_bytes() % server_defaults.bytes_per_checksum as u64) as u32;
-        let current_packet = if append && bytes_left_in_chunk > 0 {
-            // When appending, we want to first send a packet with a single chunk of the data required
-            // to get the block to a multiple of bytes_per_checksum. After that, things work the same
-            // as create.
-            Packet::empty(block.b.num_bytes() as i64, 0, bytes_left_in_chunk, 0)
-        } else {
+    fn create_next_packet(&mut self) {
+        let num_bytes = self.block.b.num_bytes();
+        let checksum_type = self.server_defaults.checksum_type();
+        let bytes_per_checksum = self.server_defaults.bytes_per_checksum;
+        let bytes_left_in_chunk =
+            bytes_per_checksum - (num_bytes % bytes_per_checksum as u64) as u32;
+        self.current_packet = if bytes_left_in_chunk < bytes_per_checksum {
+            // When the block ends in a partial chunk after an append or a flush, we want to first
+            // send a packet with a single chunk of the data required to get the block to a
+            // multiple of bytes_per_checksum. After that, packets are filled as usual.
             Packet::empty(
-                block.b.num_bytes() as i64,
+                num_bytes as i64,
+                self.next_seqno,
+                checksum_type,
+                bytes_left_in_chunk,
                 0,
+            )
+        } else {
+            Packet::empty(
+                num_bytes as i64,
+                self.next_seqno,
+                checksum_type,
                 bytes_per_checksum,
-                write_packet_size,
+                self.server_defaults.write_packet_size,
             )
         };
-
-        let this = Self {
-            block,
-            block_size,
-            server_defaults,
-            next_seqno: 1,
-            connection,
-            current_packet,
-            status: Some(status_receiver),
-            ack_queue: ack_queue_sender,
-        };
-        this.listen_for_acks(ack_response_receiver, ack_queue_receiever, status_sender);
-
-        Ok(this)
-    }
-
-    fn create_next_packet(&mut self) {
-        self.current_packet = Packet::empty(
-            self.block.b.num_bytes() as i64,
-            self.next_seqno,
-            self.server_defaults.bytes_per_checksum,
-            self.server_defaults.write_packet_size,
-        );
         self.next_seqno += 1;
     }
 
-    async fn queue_ack(&self) -> Result<()> {
-        self.ack_queue
-            .send((
-                self.current_packet.header.seqno,
-                self.current_packet.header.last_packet_in_block,
-            ))
-            .await
-            .map_err(|_| HdfsError::DataTransferError("Failed to send to ack queue".to_string()))
-    }
-
     async fn send_current_packet(&mut self) -> Result<()> {
-        // Queue up the sequence number for acknowledgement
-        self.queue_ack().await?;
+        let packet = self.current_packet.finalize();
 
-        // Send the packet
-        self.connection
-            .write_packet(&mut self.current_packet)
+        // Apply backpressure while the maximum number of packets are waiting to be acknowledged
+        self.wait_for_ack(packet.header.seqno - self.config.write_max_packets as i64)
             .await?;
 
+        self.closing = packet.header.last_packet_in_block;
+
+        // Queue up the packet for acknowledgement
+        {
+            let mut ack_queue = self.ack_queue.lock().unwrap();
+            ack_queue.push_back(packet.clone());
+            #[cfg(feature = "integration-test")]
+            crate::test::MAX_PACKETS_IN_FLIGHT
+                .fetch_max(ack_queue.len(), std::sync::atomic::Ordering::SeqCst);
+        }
+
         // Create a fresh packet
         self.create_next_packet();
 
+        // Send the packet. If that fails it is still queued, so it's sent again once the pipeline
+        // is recovered.
+        if let Err(e) = self.connection.write_packet(&packet).await {
+            let error = self
+                .take_pipeline_error()
+                .unwrap_or_else(|| PipelineError::new(0, e));
+            self.recover(error).await?;
+        }
+
         Ok(())
     }
 
-    fn check_error(&mut self) -> Result<()> {
-        if let Some(status) = self.status.as_mut() {
-            match status.try_recv() {
-                Ok(result) => result?,
-                Err(oneshot::error::TryRecvError::Empty) => (),
-                Err(oneshot::error::TryRecvError::Closed) => {
-                    return Err(HdfsError::DataTransferError(
-                        "Status channel closed prematurely".to_string(),
-                    ))
-                }
+    /// Takes the error that stopped the ack listener, if there is one
+    fn take_pipeline_error(&mut self) -> Option<PipelineError> {
+        if self.closed {
+            return None;
+        }
+        match self.acks.status.try_recv() {
+            Ok(Ok(())) => {
+                self.closed = true;
+                None
             }
+            Ok(Err(error)) => Some(error),
+            Err(oneshot::error::TryRecvError::Empty) => None,
+            Err(oneshot::error::TryRecvError::Closed) => Some(PipelineError::new(
+                0,
+                HdfsError::DataTransferError("Status channel closed prematurely".to_string()),
+            )),
         }
+    }
 
+    async fn check_error(&mut self) -> Result<()> {
+        if let Some(error) = self.take_pipeline_error() {
+            self.recover(error).await?;
+        }
         Ok(())
     }
 
     fn listen_for_acks(
-        &self,
-        mut ack_receiver: mpsc::Receiver<hdfs::PipelineAckProto>,
-        mut ack_queue: mpsc::Receiver<(i64, bool)>,
-        status: oneshot::Sender<Result<()>>,
-    ) {
-        tokio::spawn(async move {
-            loop {
-                let next_ack_opt = ack_receiver.recv().await;
-                if next_ack_opt.is_none() {
-                    error!("Channel closed while waiting for next ack");
-                    break;
-                }
-                let next_ack = next_ack_opt.unwrap();
-                for reply in next_ack.reply.iter() {
-                    if *reply != hdfs::Status::Success as i32 {
-                        let _ = status.send(Err(HdfsError::DataTransferError(format!(
-                            "Received non-success status in datanode ack: {:?}",
-                            hdfs::Status::from_i32(*reply)
-                        ))));
-                        return;
+        connection: &mut DatanodeConnection,
+        ack_queue: Arc<Mutex<VecDeque<WritePacket>>>,
+        acked_seqno: i64,
+        timeout: Duration,
+    ) -> Result<AckListener> {
+        // Channel for receiving acks from the datanode
+        let (ack_response_sender, mut ack_receiver) = mpsc::channel::<hdfs::PipelineAckProto>(100);
+        // Channel for tracking errors that occur listening for acks or successful ack of the last packet
+        let (status_sender, status_receiver) = oneshot::channel::<PipelineStatus>();
+        // Channel for tracking how far the pipeline has acknowledged packets
+        let (acked_sender, acked_receiver) = watch::channel(acked_seqno);
+
+        connection.read_acks(ack_response_sender)?;
+
+        let handle = tokio::spawn(async move {
+            let status =
+                loop {
+                    let next_ack = match tokio::time::timeout(timeout, ack_receiver.recv()).await {
+                        Ok(Some(next_ack)) => next_ack,
+                        Ok(None) => {
+                            break Err(PipelineError::new(
+                                0,
+                                HdfsError::DataTransferError(
+                                    "Channel closed while waiting for next ack".to_string(),
+                                ),
+                            ))
+                        }
+                        // Nothing is expected while there are no packets waiting to be acknowledged
+                        Err(_) if ack_queue.lock().unwrap().is_empty() => continue,
+                        Err(_) => {
+                            break Err(PipelineError::new(
+                                0,
+                                HdfsError::IOError(io::Error::new(
+                                    io::ErrorKind::TimedOut,
+                                    "Timed out waiting for a packet ack",
+                                )),
+                            ))
+                        }
+                    };
+
+                    // The first DataNode that didn't succeed is the one that failed
+                    if let Some(bad_node) = next_ack
+                        .reply
+                        .iter()
+                        .position(|reply| *reply != hdfs::Status::Success as i32)
+                    {
+                        break Err(PipelineError::new(
+                            bad_node,
+                            HdfsError::DataTransferError(format!(
+                                "Received non-success status in datanode ack: {:?}",
+                                hdfs::Status::from_i32(next_ack.reply[bad_node])
+                            )),
+                        ));
                     }
-                }
 
-                if next_ack.seqno == HEART_BEAT_SEQNO {
-                    continue;
-                }
-                if next_ack.seqno == UNKNOWN_SEQNO {
-                    let _ = status.send(Err(HdfsError::DataTransferError(
-                        "Received unknown seqno for successful ack".to_string(),
-                    )));
-                    return;
-                }
+                    if next_ack.seqno == HEART_BEAT_SEQNO {
+                        continue;
+                    }
+                    if next_ack.seqno == UNKNOWN_SEQNO {
+                        break Err(PipelineError::new(
+                            0,
+                            HdfsError::DataTransferError(
+                                "Received unknown seqno for successful ack".to_string(),
+                            ),
+                        ));
+                    }
 
-                let next_seqno = ack_queue.recv().await;
-                if next_seqno.is_none() {
-                    let _ = status.send(Err(HdfsError::DataTransferError(
-                        "Channel closed while getting next seqno to acknowledge".to_string(),
-                    )));
-                    return;
-                }
+                    let last_packet = {
+                        let mut ack_queue = ack_queue.lock().unwrap();
+                        match ack_queue.front() {
+                            Some(packet) if packet.header.seqno == next_ack.seqno => ack_queue
+                                .pop_front()
+                                .map(|packet| packet.header.last_packet_in_block),
+                            _ => None,
+                        }
+                    };
+
+                    match last_packet {
+                        Some(last_packet) => {
+                            acked_sender.send_replace(next_ack.seqno);
+                            if last_packet {
+                                break Ok(());
+                            }
+                        }
+                        None => break Err(PipelineError::new(
+                            0,
+                            HdfsError::DataTransferError(
+                                "Received acknowledgement does not match expected sequence number"
+                                    .to_string(),
+                            ),
+                        )),
+                    }
+                };
+            if let Err(error) = status.as_ref() {
+                error!("Write pipeline failed: {:?}", error);
+            }
+            let _ = status_sender.send(status);
+        });
+
+        Ok(AckListener {
+            status: status_receiver,
+            acked_seqno: acked_receiver,
+            handle,
+        })
+    }
+
+    /// Removes the failed DataNode from the pipeline and rebuilds it with the remaining ones,
+    /// adding a replacement DataNode if the configured policy asks for one. Any packets that
+    /// weren't acknowledged yet are sent again on the new pipeline.
+    async fn recover(&mut self, mut error: PipelineError) -> Result<()> {
+        let Some(recovery) = self.recovery.clone() else {
+            return Err(error.error);
+        };
 
-                let (seqno, last_packet) = next_seqno.unwrap();
+        for _ in 0..MAX_PIPELINE_RECOVERIES {
+            if self.block.locs.len() <= 1 || error.bad_node >= self.block.locs.len() {
+                return Err(error.error);
+            }
 
-                if next_ack.seqno != seqno {
-                    let _ = status.send(Err(HdfsError::DataTransferError(
-                        "Received acknowledgement does not match expected sequence number"
-                            .to_string(),
-                    )));
-                    return;
-                }
+            let bad_node = self.block.locs.remove(error.bad_node);
+            let address = datanode_address(&bad_node.id);
+            warn!(
+                "Removing DataNode {} from the pipeline of block {} after error: {:?}",
+                address, self.block.b.block_id, error.error
+            );
+            self.config.metrics.record_datanode_error(&address);
+            self.failed_nodes.push(bad_node);
+            if error.bad_node < self.block.storage_types.len() {
+                self.block.storage_types.remove(error.bad_node);
+            }
+            if error.bad_node < self.block.storage_i_ds.len() {
+                self.block.storage_i_ds.remove(error.bad_node);
+            }
+
+            match self.rebuild_pipeline(&recovery).await? {
+                Ok(()) => return Ok(()),
+                Err(e) => error = e,
+            }
+        }
 
-                if last_packet {
-                    let _ = status.send(Ok(()));
-                    return;
+        Err(error.error)
+    }
+
+    /// Sets up a new pipeline with the DataNodes left in `self.block`. Errors from the NameNode
+    /// fail the write, while errors from the DataNodes are returned so the recovery can be
+    /// tried again without the DataNode to blame.
+    async fn rebuild_pipeline(&mut self, recovery: &PipelineRecovery) -> Result<PipelineStatus> {
+        // Stop listening to the old pipeline before taking over its unacknowledged packets
+        self.acks.handle.abort();
+        let _ = (&mut self.acks.handle).await;
+        let acked_seqno = *self.acks.acked_seqno.borrow();
+
+        // The DataNodes keep what was acknowledged, and may have anything up to what was sent
+        let bytes_sent = self.current_packet.header.offset_in_block as u64;
+        let bytes_acked = self
+            .ack_queue
+            .lock()
+            .unwrap()
+            .front()
+            .map(|packet| packet.header.offset_in_block as u64)
+            .unwrap_or(bytes_sent);
+        let mut acked_block = self.block.b.clone();
+        acked_block.num_bytes = Some(bytes_acked);
+
+        if self.config.replace_datanode_policy.should_replace(
+            recovery.replication,
+            self.block.locs.len(),
+            self.append_or_flushed,
+        ) {
+            if let Err(e) = self.add_datanode(recovery, &acked_block).await {
+                if !self.config.replace_datanode_best_effort {
+                    return Err(e);
                 }
+                warn!("Continuing without replacing the failed DataNode: {:?}", e);
             }
-        });
+        }
+
+        // A new generation stamp marks the replicas left on the failed DataNodes as stale
+        let updated = recovery
+            .protocol
+            .update_block_for_pipeline(self.block.b.clone())
+            .await?
+            .block;
+        let new_generation_stamp = updated.b.generation_stamp;
+        self.block.block_token = updated.block_token;
+
+        let stage = if self.closing {
+            hdfs::op_write_block_proto::BlockConstructionStage::PipelineCloseRecovery
+        } else {
+            hdfs::op_write_block_proto::BlockConstructionStage::PipelineSetupStreamingRecovery
+        };
+        let mut recovery_block = self.block.clone();
+        recovery_block.b = acked_block.clone();
+        let mut connection = match Self::setup_pipeline(
+            &recovery_block,
+            stage,
+            new_generation_stamp,
+            bytes_sent,
+            requested_checksum(&self.server_defaults),
+            &self.config,
+        )
+        .await
+        {
+            Ok(connection) => connection,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        let mut new_block = acked_block.clone();
+        new_block.generation_stamp = new_generation_stamp;
+        recovery
+            .protocol
+            .update_pipeline(
+                acked_block,
+                new_block,
+                self.block.locs.iter().map(|loc| loc.id.clone()).collect(),
+                self.block.storage_i_ds.clone(),
+            )
+            .await?;
+        self.block.b.generation_stamp = new_generation_stamp;
+
+        self.acks = Self::listen_for_acks(
+            &mut connection,
+            Arc::clone(&self.ack_queue),
+            acked_seqno,
+            self.config.socket_timeout,
+        )?;
+        self.connection = connection;
+
+        let packets: Vec<WritePacket> = self.ack_queue.lock().unwrap().iter().cloned().collect();
+        for packet in packets.iter() {
+            if let Err(e) = self.connection.write_packet(packet).await {
+                return Ok(Err(self
+                    .take_pipeline_error()
+                    .unwrap_or_else(|| PipelineError::new(0, e))));
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// Gets a new DataNode for the pipeline from the NameNode, and copies the acknowledged data
+    /// of the block to it from its neighbor in the pipeline
+    async fn add_datanode(
+        &mut self,
+        recovery: &PipelineRecovery,
+        acked_block: &hdfs::ExtendedBlockProto,
+    ) -> Result<()> {
+        let located = recovery
+            .protocol
+            .get_additional_datanode(
+                &recovery.src,
+                recovery.file_id,
+                acked_block.clone(),
+                self.block.locs.clone(),
+                self.block.storage_i_ds.clone(),
+                self.failed_nodes.clone(),
+                1,
+            )
+            .await?
+            .block;
+
+        let new_index = located
+            .locs
+            .iter()
+            .position(|loc| {
+                !self
+                    .block
+                    .locs
+                    .iter()
+                    .any(|existing| existing.id.datanode_uuid == loc.id.datanode_uuid)
+            })
+            .ok_or(HdfsError::DataTransferError(
+                "NameNode didn't return an additional DataNode".to_string(),
+            ))?;
+        let source = if new_index == 0 {
+            &located.locs[1]
+        } else {
+            &located.locs[new_index - 1]
+        };
+
+        let mut connection = with_timeout(
+            self.config.socket_timeout,
+            DatanodeConnection::connect(
+                &source.id,
+                &located.block_token,
+                &self.config.protection,
+                self.config.encryption_key.as_ref(),
+                self.config.use_datanode_hostname,
+            ),
+        )
+        .await?;
+
+        let message = hdfs::OpTransferBlockProto {
+            header: connection.build_header(acked_block, Some(located.block_token.clone())),
+            targets: vec![located.locs[new_index].clone()],
+            target_storage_types: located
+                .storage_types
+                .get(new_index)
+                .copied()
+                .into_iter()
+                .collect(),
+            target_storage_ids: located
+                .storage_i_ds
+                .get(new_index)
+                .cloned()
+                .into_iter()
+                .collect(),
+        };
+
+        debug!("Block transfer request: {:?}", &message);
+        connection.send(Op::TransferBlock, &message).await?;
+        let response = with_timeout(
+            self.config.socket_timeout,
+            connection.read_block_op_response(),
+        )
+        .await?;
+        debug!("Block transfer response: {:?}", response);
+
+        if response.status() != hdfs::Status::Success {
+            return Err(HdfsError::DataTransferError(response.message().to_string()));
+        }
+
+        self.block.locs = located.locs;
+        self.block.storage_types = located.storage_types;
+        self.block.storage_i_ds = located.storage_i_ds;
+        self.block.block_token = located.block_token;
+        Ok(())
     }
 
     fn is_full(&self) -> bool {
@@ -670,7 +1854,7 @@ impl ReplicatedBlockWriter {
     }
 
     async fn write(&mut self, buf: &mut Bytes) -> Result<()> {
-        self.check_error()?;
+        self.check_error().await?;
 
         // Only write up to what's left in this block
         let bytes_to_write = usize::min(
@@ -694,9 +1878,55 @@ impl ReplicatedBlockWriter {
         Ok(())
     }
 
+    async fn flush(&mut self, sync: bool) -> Result<()> {
+        self.check_error().await?;
+        self.append_or_flushed = true;
+
+        // A sync is always sent in a packet, even if there's no new data to go with it
+        if sync {
+            self.current_packet.set_sync_block();
+        }
+        if sync || !self.current_packet.is_empty() {
+            self.send_current_packet().await?;
+        }
+
+        self.wait_for_ack(self.current_packet.header.seqno - 1)
+            .await
+    }
+
+    /// Waits until the pipeline has acknowledged the packet with `seqno`, recovering the pipeline
+    /// if it fails in the meantime
+    async fn wait_for_ack(&mut self, seqno: i64) -> Result<()> {
+        while self
+            .acks
+            .acked_seqno
+            .wait_for(|acked_seqno| *acked_seqno >= seqno)
+            .await
+            .is_err()
+        {
+            // The ack listener stopped, so there should be an error waiting
+            let error = self.take_pipeline_error().unwrap_or_else(|| {
+                PipelineError::new(
+                    0,
+                    HdfsError::DataTransferError(format!(
+                        "Stopped receiving acknowledgements before packet {} was acknowledged",
+                        seqno
+                    )),
+                )
+            });
+            self.recover(error).await?;
+        }
+        Ok(())
+    }
+
     /// Send a packet with any remaining data and then send a last packet
     async fn close(&mut self) -> Result<()> {
-        self.check_error()?;
+        if self.closed || self.closing {
+            return Err(HdfsError::DataTransferError(
+                "Block already closed".to_string(),
+            ));
+        }
+        self.check_error().await?;
 
         // Send a packet with any remaining data
         if !self.current_packet.is_empty() {
@@ -708,17 +1938,21 @@ impl ReplicatedBlockWriter {
         self.send_current_packet().await?;
 
         // Wait for the channel to close, meaning all acks have been received or an error occured
-        if let Some(status) = self.status.take() {
-            let result = status.await.map_err(|_| {
-                HdfsError::DataTransferError(
-                    "Status channel closed while waiting for final ack".to_string(),
-                )
-            })?;
-            result?;
-        } else {
-            return Err(HdfsError::DataTransferError(
-                "Block already closed".to_string(),
-            ));
+        while !self.closed {
+            let error = match (&mut self.acks.status).await {
+                Ok(Ok(())) => {
+                    self.closed = true;
+                    continue;
+                }
+                Ok(Err(error)) => error,
+                Err(_) => PipelineError::new(
+                    0,
+                    HdfsError::DataTransferError(
+                        "Status channel closed while waiting for final ack".to_string(),
+                    ),
+                ),
+            };
+            self.recover(error).await?;
         }
 
         Ok(())
@@ -814,6 +2048,7 @@ pub(crate) struct StripedBlockWriter {
     block: hdfs::LocatedBlockProto,
     server_defaults: hdfs::FsServerDefaultsProto,
     block_size: usize,
+    config: DatanodeConfig,
     block_writers: Vec<Option<ReplicatedBlockWriter>>,
     cell_buffer: CellBuffer,
     bytes_written: usize,
@@ -826,6 +2061,7 @@ impl StripedBlockWriter {
         ec_schema: &EcSchema,
         block_size: usize,
         server_defaults: hdfs::FsServerDefaultsProto,
+        config: &DatanodeConfig,
     ) -> Self {
         let block_writers = (0..block.block_indices().len()).map(|_| None).collect();
 
@@ -833,6 +2069,7 @@ impl StripedBlockWriter {
             block,
             block_size,
             server_defaults,
+            config: config.clone(),
             block_writers,
             cell_buffer: CellBuffer::new(ec_schema),
             bytes_written: 0,
@@ -869,8 +2106,11 @@ impl StripedBlockWriter {
                 *writer = Some(
                     ReplicatedBlockWriter::new(
                         cloned,
+                        None,
                         self.block_size,
                         self.server_defaults.clone(),
+                        &self.config,
+                        None,
                     )
                     .await?,
                 )
@@ -935,3 +2175,77 @@ impl StripedBlockWriter {
         extended_block
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use tokio::net::TcpListener;
+
+    use super::{get_block_checksum, DatanodeConfig, ReplaceDatanodePolicy};
+    use crate::common::buffer::BufferPool;
+    use crate::common::config::Configuration;
+    use crate::metrics::Metrics;
+    use crate::proto::hdfs;
+    use crate::security::user::User;
+    use crate::HdfsError;
+
+    #[tokio::test]
+    async fn test_block_checksum_timeout() {
+        // A DataNode that accepts the connection but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            drop(stream);
+        });
+
+        let mut config = DatanodeConfig::new(
+            &Configuration::new().unwrap(),
+            Arc::new(User::new(None, None, Vec::new())),
+            BufferPool::new(0),
+            Metrics::default(),
+        );
+        config.socket_timeout = Duration::from_millis(100);
+        let block = hdfs::LocatedBlockProto {
+            locs: vec![hdfs::DatanodeInfoProto {
+                id: hdfs::DatanodeIdProto {
+                    ip_addr: "127.0.0.1".to_string(),
+                    xfer_port: port as u32,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let result = get_block_checksum(&block, 0, &config).await;
+        assert!(matches!(
+            result,
+            Err(HdfsError::IOError(e)) if e.kind() == std::io::ErrorKind::TimedOut
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        server.abort();
+    }
+
+    #[test]
+    fn test_replace_datanode_policy() {
+        let policy = ReplaceDatanodePolicy::parse("DEFAULT");
+        assert_eq!(policy, ReplaceDatanodePolicy::Default);
+        assert!(!policy.should_replace(3, 2, false));
+        assert!(policy.should_replace(3, 2, true));
+        assert!(policy.should_replace(3, 1, false));
+        assert!(!policy.should_replace(2, 1, true));
+        assert!(!policy.should_replace(3, 0, true));
+
+        let policy = ReplaceDatanodePolicy::parse("ALWAYS");
+        assert!(policy.should_replace(2, 1, false));
+        assert!(!policy.should_replace(2, 2, false));
+
+        let policy = ReplaceDatanodePolicy::parse("NEVER");
+        assert!(!policy.should_replace(3, 1, true));
+    }
+}
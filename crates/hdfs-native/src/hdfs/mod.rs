@@ -2,3 +2,5 @@ pub(crate) mod connection;
 pub(crate) mod datanode;
 pub(crate) mod protocol;
 pub(crate) mod proxy;
+#[cfg(unix)]
+pub(crate) mod shortcircuit;
@@ -1,8 +1,13 @@
-use log::debug;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use log::{debug, warn};
 use prost::Message;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
-use crate::proto::hdfs;
+use crate::proto::{common, hdfs};
 use crate::Result;
 
 use super::proxy::NameServiceProxy;
@@ -11,12 +16,121 @@ use super::proxy::NameServiceProxy;
 pub(crate) struct NamenodeProtocol {
     proxy: NameServiceProxy,
     client_name: String,
+    // Server defaults rarely change, so they are only fetched once
+    server_defaults: tokio::sync::OnceCell<hdfs::FsServerDefaultsProto>,
+    // Fetched again once it expires, like the Java client
+    data_encryption_key: tokio::sync::Mutex<Option<hdfs::DataEncryptionKeyProto>>,
+    lease_renewer: Mutex<LeaseRenewer>,
+    lease_renewal_interval: Duration,
+}
+
+/// Renews the lease of the client on its files open for writing in the background, like the
+/// Java client's `LeaseRenewer`. The renewal task only runs while at least one file is open.
+#[derive(Debug, Default)]
+struct LeaseRenewer {
+    open_files: usize,
+    task: Option<JoinHandle<()>>,
+}
+
+/// Keeps the lease on a file open for writing renewed until it's dropped
+#[derive(Debug)]
+pub(crate) struct LeaseGuard {
+    protocol: Arc<NamenodeProtocol>,
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        let mut renewer = self.protocol.lease_renewer.lock().unwrap();
+        renewer.open_files -= 1;
+        if renewer.open_files == 0 {
+            if let Some(task) = renewer.task.take() {
+                task.abort();
+            }
+        }
+    }
 }
 
 impl NamenodeProtocol {
-    pub(crate) fn new(proxy: NameServiceProxy) -> Self {
+    pub(crate) fn new(proxy: NameServiceProxy, lease_renewal_interval: Duration) -> Self {
         let client_name = format!("hdfs_native_client-{}", Uuid::new_v4().as_hyphenated());
-        NamenodeProtocol { proxy, client_name }
+        NamenodeProtocol {
+            proxy,
+            client_name,
+            server_defaults: tokio::sync::OnceCell::new(),
+            data_encryption_key: tokio::sync::Mutex::new(None),
+            lease_renewer: Mutex::new(LeaseRenewer::default()),
+            lease_renewal_interval,
+        }
+    }
+
+    /// Starts renewing the lease of the client for a file that has been opened for writing,
+    /// until the returned guard is dropped
+    pub(crate) fn begin_file_lease(self: &Arc<Self>) -> LeaseGuard {
+        let mut renewer = self.lease_renewer.lock().unwrap();
+        renewer.open_files += 1;
+        if renewer.task.is_none() {
+            // The task only holds a weak reference, since its handle is owned by the protocol
+            let protocol = Arc::downgrade(self);
+            renewer.task = Some(tokio::spawn(renew_leases(
+                protocol,
+                self.lease_renewal_interval,
+            )));
+        }
+        LeaseGuard {
+            protocol: Arc::clone(self),
+        }
+    }
+
+    /// The number of files the lease is being renewed for
+    #[cfg(test)]
+    pub(crate) fn open_files(&self) -> usize {
+        self.lease_renewer.lock().unwrap().open_files
+    }
+
+    /// The service delegation tokens for this NameNode are looked up by
+    pub(crate) fn token_service(&self) -> &str {
+        self.proxy.token_service()
+    }
+
+    /// Sends an RPC to the NameNode. With the `tracing` feature, the call is wrapped in a span
+    /// recording the method, the path it operates on, its latency and the response size.
+    async fn call(
+        &self,
+        method_name: &'static str,
+        path: Option<&str>,
+        message: Vec<u8>,
+    ) -> Result<Bytes> {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+
+            let span = tracing::info_span!(
+                "hdfs.rpc",
+                method = method_name,
+                path,
+                latency_ms = tracing::field::Empty,
+                response_bytes = tracing::field::Empty,
+                error = tracing::field::Empty,
+            );
+            let start = std::time::Instant::now();
+            let response = self
+                .proxy
+                .call(method_name, message)
+                .instrument(span.clone())
+                .await;
+            span.record("latency_ms", start.elapsed().as_millis() as u64);
+            match response.as_ref() {
+                Ok(response) => span.record("response_bytes", response.len()),
+                Err(error) => span.record("error", tracing::field::debug(error)),
+            };
+            response
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = path;
+            self.proxy.call(method_name, message).await
+        }
     }
 
     pub(crate) async fn get_file_info(&self, src: &str) -> Result<hdfs::GetFileInfoResponseProto> {
@@ -26,8 +140,11 @@ impl NamenodeProtocol {
         debug!("get_file_info request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("getFileInfo", message.encode_length_delimited_to_vec())
+            .call(
+                "getFileInfo",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::GetFileInfoResponseProto::decode_length_delimited(response)?;
@@ -36,6 +153,28 @@ impl NamenodeProtocol {
         Ok(decoded)
     }
 
+    pub(crate) async fn get_file_link_info(
+        &self,
+        src: &str,
+    ) -> Result<hdfs::GetFileLinkInfoResponseProto> {
+        let message = hdfs::GetFileLinkInfoRequestProto {
+            src: src.to_string(),
+        };
+        debug!("get_file_link_info request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getFileLinkInfo",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetFileLinkInfoResponseProto::decode_length_delimited(response)?;
+        debug!("get_file_link_info response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
     pub(crate) async fn get_listing(
         &self,
         src: &str,
@@ -50,8 +189,11 @@ impl NamenodeProtocol {
         debug!("get_listing request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("getListing", message.encode_length_delimited_to_vec())
+            .call(
+                "getListing",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::GetListingResponseProto::decode_length_delimited(response)?;
@@ -70,9 +212,9 @@ impl NamenodeProtocol {
         debug!("getLocatedFileInfo request: {:?}", &message);
 
         let response = self
-            .proxy
             .call(
                 "getLocatedFileInfo",
+                Some(src),
                 message.encode_length_delimited_to_vec(),
             )
             .await?;
@@ -82,22 +224,101 @@ impl NamenodeProtocol {
         Ok(decoded)
     }
 
-    pub(crate) async fn get_server_defaults(&self) -> Result<hdfs::GetServerDefaultsResponseProto> {
-        let message = hdfs::GetServerDefaultsRequestProto::default();
+    pub(crate) async fn get_fs_stats(&self) -> Result<hdfs::GetFsStatsResponseProto> {
+        let message = hdfs::GetFsStatusRequestProto::default();
+        debug!("get_fs_stats request: {:?}", &message);
+
+        let response = self
+            .call("getFsStats", None, message.encode_length_delimited_to_vec())
+            .await?;
+
+        let decoded = hdfs::GetFsStatsResponseProto::decode_length_delimited(response)?;
+        debug!("get_fs_stats response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_safe_mode(
+        &self,
+        action: hdfs::SafeModeActionProto,
+        checked: bool,
+    ) -> Result<hdfs::SetSafeModeResponseProto> {
+        let message = hdfs::SetSafeModeRequestProto {
+            action: action as i32,
+            checked: Some(checked),
+        };
+        debug!("set_safe_mode request: {:?}", &message);
 
         let response = self
-            .proxy
             .call(
-                "getServerDefaults",
+                "setSafeMode",
+                None,
                 message.encode_length_delimited_to_vec(),
             )
             .await?;
 
-        let decoded = hdfs::GetServerDefaultsResponseProto::decode_length_delimited(response)?;
-        debug!("get_server_defaults response: {:?}", &decoded);
+        let decoded = hdfs::SetSafeModeResponseProto::decode_length_delimited(response)?;
+        debug!("set_safe_mode response: {:?}", &decoded);
         Ok(decoded)
     }
 
+    /// Gets the server defaults, which are cached after the first successful call
+    pub(crate) async fn get_server_defaults(&self) -> Result<hdfs::FsServerDefaultsProto> {
+        self.server_defaults
+            .get_or_try_init(|| async {
+                let message = hdfs::GetServerDefaultsRequestProto::default();
+
+                let response = self
+                    .call(
+                        "getServerDefaults",
+                        None,
+                        message.encode_length_delimited_to_vec(),
+                    )
+                    .await?;
+
+                let decoded =
+                    hdfs::GetServerDefaultsResponseProto::decode_length_delimited(response)?;
+                debug!("get_server_defaults response: {:?}", &decoded);
+                Ok(decoded.server_defaults)
+            })
+            .await
+            .cloned()
+    }
+
+    /// Gets the key DataNode connections are encrypted with, or `None` if the NameNode doesn't
+    /// require data transfer to be encrypted. The key is cached until it expires.
+    pub(crate) async fn get_data_encryption_key(
+        &self,
+    ) -> Result<Option<hdfs::DataEncryptionKeyProto>> {
+        if !self.get_server_defaults().await?.encrypt_data_transfer() {
+            return Ok(None);
+        }
+
+        let mut cached = self.data_encryption_key.lock().await;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        if let Some(key) = cached.as_ref().filter(|key| key.expiry_date > now) {
+            return Ok(Some(key.clone()));
+        }
+
+        let message = hdfs::GetDataEncryptionKeyRequestProto::default();
+        debug!("get_data_encryption_key request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getDataEncryptionKey",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetDataEncryptionKeyResponseProto::decode_length_delimited(response)?;
+        debug!("get_data_encryption_key response: {:?}", &decoded);
+        cached.clone_from(&decoded.data_encryption_key);
+        Ok(decoded.data_encryption_key)
+    }
+
     pub(crate) async fn create(
         &self,
         src: &str,
@@ -121,14 +342,19 @@ impl NamenodeProtocol {
             replication,
             block_size,
             create_flag,
+            // Files in encryption zones are encrypted with AES-CTR like the Java client
+            crypto_protocol_version: vec![hdfs::CryptoProtocolVersionProto::EncryptionZones as i32],
             ..Default::default()
         };
 
         debug!("create request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("create", message.encode_length_delimited_to_vec())
+            .call(
+                "create",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::CreateResponseProto::decode_length_delimited(response)?;
@@ -155,8 +381,11 @@ impl NamenodeProtocol {
         debug!("append request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("append", message.encode_length_delimited_to_vec())
+            .call(
+                "append",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::AppendResponseProto::decode_length_delimited(response)?;
@@ -181,8 +410,11 @@ impl NamenodeProtocol {
         debug!("add_block request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("addBlock", message.encode_length_delimited_to_vec())
+            .call(
+                "addBlock",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::AddBlockResponseProto::decode_length_delimited(response)?;
@@ -190,6 +422,97 @@ impl NamenodeProtocol {
         Ok(decoded)
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn get_additional_datanode(
+        &self,
+        src: &str,
+        file_id: Option<u64>,
+        blk: hdfs::ExtendedBlockProto,
+        existings: Vec<hdfs::DatanodeInfoProto>,
+        existing_storage_uuids: Vec<String>,
+        excludes: Vec<hdfs::DatanodeInfoProto>,
+        num_additional_nodes: u32,
+    ) -> Result<hdfs::GetAdditionalDatanodeResponseProto> {
+        let message = hdfs::GetAdditionalDatanodeRequestProto {
+            src: src.to_string(),
+            blk,
+            existings,
+            excludes,
+            num_additional_nodes,
+            client_name: self.client_name.clone(),
+            existing_storage_uuids,
+            file_id,
+        };
+
+        debug!("get_additional_datanode request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getAdditionalDatanode",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetAdditionalDatanodeResponseProto::decode_length_delimited(response)?;
+        debug!("get_additional_datanode response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn update_block_for_pipeline(
+        &self,
+        block: hdfs::ExtendedBlockProto,
+    ) -> Result<hdfs::UpdateBlockForPipelineResponseProto> {
+        let message = hdfs::UpdateBlockForPipelineRequestProto {
+            block,
+            client_name: self.client_name.clone(),
+        };
+
+        debug!("update_block_for_pipeline request: {:?}", &message);
+
+        let response = self
+            .call(
+                "updateBlockForPipeline",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::UpdateBlockForPipelineResponseProto::decode_length_delimited(response)?;
+        debug!("update_block_for_pipeline response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn update_pipeline(
+        &self,
+        old_block: hdfs::ExtendedBlockProto,
+        new_block: hdfs::ExtendedBlockProto,
+        new_nodes: Vec<hdfs::DatanodeIdProto>,
+        storage_ids: Vec<String>,
+    ) -> Result<hdfs::UpdatePipelineResponseProto> {
+        let message = hdfs::UpdatePipelineRequestProto {
+            client_name: self.client_name.clone(),
+            old_block,
+            new_block,
+            new_nodes,
+            storage_i_ds: storage_ids,
+        };
+
+        debug!("update_pipeline request: {:?}", &message);
+
+        let response = self
+            .call(
+                "updatePipeline",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::UpdatePipelineResponseProto::decode_length_delimited(response)?;
+        debug!("update_pipeline response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
     pub(crate) async fn complete(
         &self,
         src: &str,
@@ -205,8 +528,11 @@ impl NamenodeProtocol {
         debug!("complete request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("complete", message.encode_length_delimited_to_vec())
+            .call(
+                "complete",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::CompleteResponseProto::decode_length_delimited(response)?;
@@ -231,8 +557,11 @@ impl NamenodeProtocol {
         debug!("mkdirs request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("mkdirs", message.encode_length_delimited_to_vec())
+            .call(
+                "mkdirs",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::MkdirsResponseProto::decode_length_delimited(response)?;
@@ -240,6 +569,58 @@ impl NamenodeProtocol {
         Ok(decoded)
     }
 
+    pub(crate) async fn create_symlink(
+        &self,
+        target: &str,
+        link: &str,
+        dir_permission: u32,
+        create_parent: bool,
+    ) -> Result<hdfs::CreateSymlinkResponseProto> {
+        let message = hdfs::CreateSymlinkRequestProto {
+            target: target.to_string(),
+            link: link.to_string(),
+            dir_perm: hdfs::FsPermissionProto {
+                perm: dir_permission,
+            },
+            create_parent,
+        };
+        debug!("create_symlink request: {:?}", &message);
+
+        let response = self
+            .call(
+                "createSymlink",
+                Some(link),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::CreateSymlinkResponseProto::decode_length_delimited(response)?;
+        debug!("create_symlink response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_link_target(
+        &self,
+        path: &str,
+    ) -> Result<hdfs::GetLinkTargetResponseProto> {
+        let message = hdfs::GetLinkTargetRequestProto {
+            path: path.to_string(),
+        };
+        debug!("get_link_target request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getLinkTarget",
+                Some(path),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetLinkTargetResponseProto::decode_length_delimited(response)?;
+        debug!("get_link_target response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
     pub(crate) async fn rename(
         &self,
         src: &str,
@@ -255,8 +636,11 @@ impl NamenodeProtocol {
         debug!("rename request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("rename2", message.encode_length_delimited_to_vec())
+            .call(
+                "rename2",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::Rename2ResponseProto::decode_length_delimited(response)?;
@@ -264,6 +648,30 @@ impl NamenodeProtocol {
         Ok(decoded)
     }
 
+    pub(crate) async fn concat(
+        &self,
+        trg: &str,
+        srcs: Vec<String>,
+    ) -> Result<hdfs::ConcatResponseProto> {
+        let message = hdfs::ConcatRequestProto {
+            trg: trg.to_string(),
+            srcs,
+        };
+        debug!("concat request: {:?}", &message);
+
+        let response = self
+            .call(
+                "concat",
+                Some(trg),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::ConcatResponseProto::decode_length_delimited(response)?;
+        debug!("concat response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
     pub(crate) async fn delete(
         &self,
         src: &str,
@@ -276,12 +684,1048 @@ impl NamenodeProtocol {
         debug!("delete request: {:?}", &message);
 
         let response = self
-            .proxy
-            .call("delete", message.encode_length_delimited_to_vec())
+            .call(
+                "delete",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
             .await?;
 
         let decoded = hdfs::DeleteResponseProto::decode_length_delimited(response)?;
         debug!("delete response: {:?}", &decoded);
         Ok(decoded)
     }
+
+    pub(crate) async fn set_permission(
+        &self,
+        src: &str,
+        permission: u32,
+    ) -> Result<hdfs::SetPermissionResponseProto> {
+        let message = hdfs::SetPermissionRequestProto {
+            src: src.to_string(),
+            permission: hdfs::FsPermissionProto { perm: permission },
+        };
+        debug!("set_permission request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setPermission",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetPermissionResponseProto::decode_length_delimited(response)?;
+        debug!("set_permission response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_owner(
+        &self,
+        src: &str,
+        owner: Option<&str>,
+        group: Option<&str>,
+    ) -> Result<hdfs::SetOwnerResponseProto> {
+        let message = hdfs::SetOwnerRequestProto {
+            src: src.to_string(),
+            username: owner.map(str::to_string),
+            groupname: group.map(str::to_string),
+        };
+        debug!("set_owner request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setOwner",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetOwnerResponseProto::decode_length_delimited(response)?;
+        debug!("set_owner response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_replication(
+        &self,
+        src: &str,
+        replication: u32,
+    ) -> Result<hdfs::SetReplicationResponseProto> {
+        let message = hdfs::SetReplicationRequestProto {
+            src: src.to_string(),
+            replication,
+        };
+        debug!("set_replication request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setReplication",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetReplicationResponseProto::decode_length_delimited(response)?;
+        debug!("set_replication response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_quota(
+        &self,
+        path: &str,
+        namespace_quota: u64,
+        storagespace_quota: u64,
+        storage_type: Option<hdfs::StorageTypeProto>,
+    ) -> Result<hdfs::SetQuotaResponseProto> {
+        let message = hdfs::SetQuotaRequestProto {
+            path: path.to_string(),
+            namespace_quota,
+            storagespace_quota,
+            storage_type: storage_type.map(|t| t as i32),
+        };
+        debug!("set_quota request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setQuota",
+                Some(path),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetQuotaResponseProto::decode_length_delimited(response)?;
+        debug!("set_quota response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_times(
+        &self,
+        src: &str,
+        mtime: u64,
+        atime: u64,
+    ) -> Result<hdfs::SetTimesResponseProto> {
+        let message = hdfs::SetTimesRequestProto {
+            src: src.to_string(),
+            mtime,
+            atime,
+        };
+        debug!("set_times request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setTimes",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetTimesResponseProto::decode_length_delimited(response)?;
+        debug!("set_times response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_storage_policy(
+        &self,
+        src: &str,
+        policy_name: &str,
+    ) -> Result<hdfs::SetStoragePolicyResponseProto> {
+        let message = hdfs::SetStoragePolicyRequestProto {
+            src: src.to_string(),
+            policy_name: policy_name.to_string(),
+        };
+        debug!("set_storage_policy request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setStoragePolicy",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetStoragePolicyResponseProto::decode_length_delimited(response)?;
+        debug!("set_storage_policy response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn unset_storage_policy(
+        &self,
+        src: &str,
+    ) -> Result<hdfs::UnsetStoragePolicyResponseProto> {
+        let message = hdfs::UnsetStoragePolicyRequestProto {
+            src: src.to_string(),
+        };
+        debug!("unset_storage_policy request: {:?}", &message);
+
+        let response = self
+            .call(
+                "unsetStoragePolicy",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::UnsetStoragePolicyResponseProto::decode_length_delimited(response)?;
+        debug!("unset_storage_policy response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_storage_policy(
+        &self,
+        path: &str,
+    ) -> Result<hdfs::GetStoragePolicyResponseProto> {
+        let message = hdfs::GetStoragePolicyRequestProto {
+            path: path.to_string(),
+        };
+        debug!("get_storage_policy request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getStoragePolicy",
+                Some(path),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetStoragePolicyResponseProto::decode_length_delimited(response)?;
+        debug!("get_storage_policy response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_storage_policies(
+        &self,
+    ) -> Result<hdfs::GetStoragePoliciesResponseProto> {
+        let message = hdfs::GetStoragePoliciesRequestProto {};
+        debug!("get_storage_policies request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getStoragePolicies",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetStoragePoliciesResponseProto::decode_length_delimited(response)?;
+        debug!("get_storage_policies response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_erasure_coding_policy(
+        &self,
+        src: &str,
+        ec_policy_name: Option<&str>,
+    ) -> Result<hdfs::SetErasureCodingPolicyResponseProto> {
+        let message = hdfs::SetErasureCodingPolicyRequestProto {
+            src: src.to_string(),
+            ec_policy_name: ec_policy_name.map(|n| n.to_string()),
+        };
+        debug!("set_erasure_coding_policy request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setErasureCodingPolicy",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetErasureCodingPolicyResponseProto::decode_length_delimited(response)?;
+        debug!("set_erasure_coding_policy response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn unset_erasure_coding_policy(
+        &self,
+        src: &str,
+    ) -> Result<hdfs::UnsetErasureCodingPolicyResponseProto> {
+        let message = hdfs::UnsetErasureCodingPolicyRequestProto {
+            src: src.to_string(),
+        };
+        debug!("unset_erasure_coding_policy request: {:?}", &message);
+
+        let response = self
+            .call(
+                "unsetErasureCodingPolicy",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded =
+            hdfs::UnsetErasureCodingPolicyResponseProto::decode_length_delimited(response)?;
+        debug!("unset_erasure_coding_policy response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_erasure_coding_policy(
+        &self,
+        src: &str,
+    ) -> Result<hdfs::GetErasureCodingPolicyResponseProto> {
+        let message = hdfs::GetErasureCodingPolicyRequestProto {
+            src: src.to_string(),
+        };
+        debug!("get_erasure_coding_policy request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getErasureCodingPolicy",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetErasureCodingPolicyResponseProto::decode_length_delimited(response)?;
+        debug!("get_erasure_coding_policy response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_erasure_coding_policies(
+        &self,
+    ) -> Result<hdfs::GetErasureCodingPoliciesResponseProto> {
+        let message = hdfs::GetErasureCodingPoliciesRequestProto {};
+        debug!("get_erasure_coding_policies request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getErasureCodingPolicies",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded =
+            hdfs::GetErasureCodingPoliciesResponseProto::decode_length_delimited(response)?;
+        debug!("get_erasure_coding_policies response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn fsync(
+        &self,
+        src: &str,
+        file_id: Option<u64>,
+        last_block_length: i64,
+    ) -> Result<hdfs::FsyncResponseProto> {
+        let message = hdfs::FsyncRequestProto {
+            src: src.to_string(),
+            client: self.client_name.clone(),
+            last_block_length: Some(last_block_length),
+            file_id,
+        };
+        debug!("fsync request: {:?}", &message);
+
+        let response = self
+            .call("fsync", Some(src), message.encode_length_delimited_to_vec())
+            .await?;
+
+        let decoded = hdfs::FsyncResponseProto::decode_length_delimited(response)?;
+        debug!("fsync response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn truncate(
+        &self,
+        src: &str,
+        new_length: u64,
+    ) -> Result<hdfs::TruncateResponseProto> {
+        let message = hdfs::TruncateRequestProto {
+            src: src.to_string(),
+            new_length,
+            client_name: self.client_name.clone(),
+        };
+        debug!("truncate request: {:?}", &message);
+
+        let response = self
+            .call(
+                "truncate",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::TruncateResponseProto::decode_length_delimited(response)?;
+        debug!("truncate response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_xattr(
+        &self,
+        src: &str,
+        x_attr: hdfs::XAttrProto,
+        flag: u32,
+    ) -> Result<hdfs::SetXAttrResponseProto> {
+        let message = hdfs::SetXAttrRequestProto {
+            src: src.to_string(),
+            x_attr: Some(x_attr),
+            flag: Some(flag),
+        };
+        debug!("set_xattr request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setXAttr",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetXAttrResponseProto::decode_length_delimited(response)?;
+        debug!("set_xattr response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_xattrs(
+        &self,
+        src: &str,
+        x_attrs: Vec<hdfs::XAttrProto>,
+    ) -> Result<hdfs::GetXAttrsResponseProto> {
+        let message = hdfs::GetXAttrsRequestProto {
+            src: src.to_string(),
+            x_attrs,
+        };
+        debug!("get_xattrs request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getXAttrs",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetXAttrsResponseProto::decode_length_delimited(response)?;
+        debug!("get_xattrs response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn list_xattrs(&self, src: &str) -> Result<hdfs::ListXAttrsResponseProto> {
+        let message = hdfs::ListXAttrsRequestProto {
+            src: src.to_string(),
+        };
+        debug!("list_xattrs request: {:?}", &message);
+
+        let response = self
+            .call(
+                "listXAttrs",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::ListXAttrsResponseProto::decode_length_delimited(response)?;
+        debug!("list_xattrs response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn remove_xattr(
+        &self,
+        src: &str,
+        x_attr: hdfs::XAttrProto,
+    ) -> Result<hdfs::RemoveXAttrResponseProto> {
+        let message = hdfs::RemoveXAttrRequestProto {
+            src: src.to_string(),
+            x_attr: Some(x_attr),
+        };
+        debug!("remove_xattr request: {:?}", &message);
+
+        let response = self
+            .call(
+                "removeXAttr",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RemoveXAttrResponseProto::decode_length_delimited(response)?;
+        debug!("remove_xattr response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn modify_acl_entries(
+        &self,
+        src: &str,
+        acl_spec: Vec<hdfs::AclEntryProto>,
+    ) -> Result<hdfs::ModifyAclEntriesResponseProto> {
+        let message = hdfs::ModifyAclEntriesRequestProto {
+            src: src.to_string(),
+            acl_spec,
+        };
+        debug!("modify_acl_entries request: {:?}", &message);
+
+        let response = self
+            .call(
+                "modifyAclEntries",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::ModifyAclEntriesResponseProto::decode_length_delimited(response)?;
+        debug!("modify_acl_entries response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn remove_acl_entries(
+        &self,
+        src: &str,
+        acl_spec: Vec<hdfs::AclEntryProto>,
+    ) -> Result<hdfs::RemoveAclEntriesResponseProto> {
+        let message = hdfs::RemoveAclEntriesRequestProto {
+            src: src.to_string(),
+            acl_spec,
+        };
+        debug!("remove_acl_entries request: {:?}", &message);
+
+        let response = self
+            .call(
+                "removeAclEntries",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RemoveAclEntriesResponseProto::decode_length_delimited(response)?;
+        debug!("remove_acl_entries response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn remove_default_acl(
+        &self,
+        src: &str,
+    ) -> Result<hdfs::RemoveDefaultAclResponseProto> {
+        let message = hdfs::RemoveDefaultAclRequestProto {
+            src: src.to_string(),
+        };
+        debug!("remove_default_acl request: {:?}", &message);
+
+        let response = self
+            .call(
+                "removeDefaultAcl",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RemoveDefaultAclResponseProto::decode_length_delimited(response)?;
+        debug!("remove_default_acl response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn remove_acl(&self, src: &str) -> Result<hdfs::RemoveAclResponseProto> {
+        let message = hdfs::RemoveAclRequestProto {
+            src: src.to_string(),
+        };
+        debug!("remove_acl request: {:?}", &message);
+
+        let response = self
+            .call(
+                "removeAcl",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RemoveAclResponseProto::decode_length_delimited(response)?;
+        debug!("remove_acl response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn set_acl(
+        &self,
+        src: &str,
+        acl_spec: Vec<hdfs::AclEntryProto>,
+    ) -> Result<hdfs::SetAclResponseProto> {
+        let message = hdfs::SetAclRequestProto {
+            src: src.to_string(),
+            acl_spec,
+        };
+        debug!("set_acl request: {:?}", &message);
+
+        let response = self
+            .call(
+                "setAcl",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::SetAclResponseProto::decode_length_delimited(response)?;
+        debug!("set_acl response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn check_access(
+        &self,
+        path: &str,
+        mode: hdfs::acl_entry_proto::FsActionProto,
+    ) -> Result<hdfs::CheckAccessResponseProto> {
+        let message = hdfs::CheckAccessRequestProto {
+            path: path.to_string(),
+            mode: mode as i32,
+        };
+        debug!("check_access request: {:?}", &message);
+
+        let response = self
+            .call(
+                "checkAccess",
+                Some(path),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::CheckAccessResponseProto::decode_length_delimited(response)?;
+        debug!("check_access response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_acl_status(
+        &self,
+        src: &str,
+    ) -> Result<hdfs::GetAclStatusResponseProto> {
+        let message = hdfs::GetAclStatusRequestProto {
+            src: src.to_string(),
+        };
+        debug!("get_acl_status request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getAclStatus",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetAclStatusResponseProto::decode_length_delimited(response)?;
+        debug!("get_acl_status response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn allow_snapshot(
+        &self,
+        snapshot_root: &str,
+    ) -> Result<hdfs::AllowSnapshotResponseProto> {
+        let message = hdfs::AllowSnapshotRequestProto {
+            snapshot_root: snapshot_root.to_string(),
+        };
+        debug!("allow_snapshot request: {:?}", &message);
+
+        let response = self
+            .call(
+                "allowSnapshot",
+                Some(snapshot_root),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::AllowSnapshotResponseProto::decode_length_delimited(response)?;
+        debug!("allow_snapshot response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn disallow_snapshot(
+        &self,
+        snapshot_root: &str,
+    ) -> Result<hdfs::DisallowSnapshotResponseProto> {
+        let message = hdfs::DisallowSnapshotRequestProto {
+            snapshot_root: snapshot_root.to_string(),
+        };
+        debug!("disallow_snapshot request: {:?}", &message);
+
+        let response = self
+            .call(
+                "disallowSnapshot",
+                Some(snapshot_root),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::DisallowSnapshotResponseProto::decode_length_delimited(response)?;
+        debug!("disallow_snapshot response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn create_snapshot(
+        &self,
+        snapshot_root: &str,
+        snapshot_name: Option<&str>,
+    ) -> Result<hdfs::CreateSnapshotResponseProto> {
+        let message = hdfs::CreateSnapshotRequestProto {
+            snapshot_root: snapshot_root.to_string(),
+            snapshot_name: snapshot_name.map(|n| n.to_string()),
+        };
+        debug!("create_snapshot request: {:?}", &message);
+
+        let response = self
+            .call(
+                "createSnapshot",
+                Some(snapshot_root),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::CreateSnapshotResponseProto::decode_length_delimited(response)?;
+        debug!("create_snapshot response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn delete_snapshot(
+        &self,
+        snapshot_root: &str,
+        snapshot_name: &str,
+    ) -> Result<hdfs::DeleteSnapshotResponseProto> {
+        let message = hdfs::DeleteSnapshotRequestProto {
+            snapshot_root: snapshot_root.to_string(),
+            snapshot_name: snapshot_name.to_string(),
+        };
+        debug!("delete_snapshot request: {:?}", &message);
+
+        let response = self
+            .call(
+                "deleteSnapshot",
+                Some(snapshot_root),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::DeleteSnapshotResponseProto::decode_length_delimited(response)?;
+        debug!("delete_snapshot response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn rename_snapshot(
+        &self,
+        snapshot_root: &str,
+        snapshot_old_name: &str,
+        snapshot_new_name: &str,
+    ) -> Result<hdfs::RenameSnapshotResponseProto> {
+        let message = hdfs::RenameSnapshotRequestProto {
+            snapshot_root: snapshot_root.to_string(),
+            snapshot_old_name: snapshot_old_name.to_string(),
+            snapshot_new_name: snapshot_new_name.to_string(),
+        };
+        debug!("rename_snapshot request: {:?}", &message);
+
+        let response = self
+            .call(
+                "renameSnapshot",
+                Some(snapshot_root),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RenameSnapshotResponseProto::decode_length_delimited(response)?;
+        debug!("rename_snapshot response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_block_locations(
+        &self,
+        src: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<hdfs::GetBlockLocationsResponseProto> {
+        let message = hdfs::GetBlockLocationsRequestProto {
+            src: src.to_string(),
+            offset,
+            length,
+        };
+        debug!("get_block_locations request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getBlockLocations",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetBlockLocationsResponseProto::decode_length_delimited(response)?;
+        debug!("get_block_locations response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_content_summary(
+        &self,
+        path: &str,
+    ) -> Result<hdfs::GetContentSummaryResponseProto> {
+        let message = hdfs::GetContentSummaryRequestProto {
+            path: path.to_string(),
+        };
+        debug!("get_content_summary request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getContentSummary",
+                Some(path),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetContentSummaryResponseProto::decode_length_delimited(response)?;
+        debug!("get_content_summary response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_delegation_token(
+        &self,
+        renewer: &str,
+    ) -> Result<common::GetDelegationTokenResponseProto> {
+        let message = common::GetDelegationTokenRequestProto {
+            renewer: renewer.to_string(),
+        };
+        debug!("get_delegation_token request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getDelegationToken",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = common::GetDelegationTokenResponseProto::decode_length_delimited(response)?;
+        debug!("get_delegation_token response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn renew_delegation_token(
+        &self,
+        token: common::TokenProto,
+    ) -> Result<common::RenewDelegationTokenResponseProto> {
+        let message = common::RenewDelegationTokenRequestProto { token };
+        debug!("renew_delegation_token request: {:?}", &message);
+
+        let response = self
+            .call(
+                "renewDelegationToken",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = common::RenewDelegationTokenResponseProto::decode_length_delimited(response)?;
+        debug!("renew_delegation_token response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn cancel_delegation_token(
+        &self,
+        token: common::TokenProto,
+    ) -> Result<common::CancelDelegationTokenResponseProto> {
+        let message = common::CancelDelegationTokenRequestProto { token };
+        debug!("cancel_delegation_token request: {:?}", &message);
+
+        let response = self
+            .call(
+                "cancelDelegationToken",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded =
+            common::CancelDelegationTokenResponseProto::decode_length_delimited(response)?;
+        debug!("cancel_delegation_token response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn renew_lease(&self) -> Result<hdfs::RenewLeaseResponseProto> {
+        let message = hdfs::RenewLeaseRequestProto {
+            client_name: self.client_name.clone(),
+            namespaces: Vec::new(),
+        };
+        debug!("renew_lease request: {:?}", &message);
+
+        let response = self
+            .call("renewLease", None, message.encode_length_delimited_to_vec())
+            .await?;
+
+        let decoded = hdfs::RenewLeaseResponseProto::decode_length_delimited(response)?;
+        debug!("renew_lease response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn recover_lease(&self, src: &str) -> Result<hdfs::RecoverLeaseResponseProto> {
+        let message = hdfs::RecoverLeaseRequestProto {
+            src: src.to_string(),
+            client_name: self.client_name.clone(),
+        };
+        debug!("recover_lease request: {:?}", &message);
+
+        let response = self
+            .call(
+                "recoverLease",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RecoverLeaseResponseProto::decode_length_delimited(response)?;
+        debug!("recover_lease response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn add_cache_directive(
+        &self,
+        info: hdfs::CacheDirectiveInfoProto,
+    ) -> Result<hdfs::AddCacheDirectiveResponseProto> {
+        let src = info.path.clone();
+        let message = hdfs::AddCacheDirectiveRequestProto {
+            info,
+            cache_flags: None,
+        };
+        debug!("add_cache_directive request: {:?}", &message);
+
+        let response = self
+            .call(
+                "addCacheDirective",
+                src.as_deref(),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::AddCacheDirectiveResponseProto::decode_length_delimited(response)?;
+        debug!("add_cache_directive response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn remove_cache_directive(
+        &self,
+        id: i64,
+    ) -> Result<hdfs::RemoveCacheDirectiveResponseProto> {
+        let message = hdfs::RemoveCacheDirectiveRequestProto { id };
+        debug!("remove_cache_directive request: {:?}", &message);
+
+        let response = self
+            .call(
+                "removeCacheDirective",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RemoveCacheDirectiveResponseProto::decode_length_delimited(response)?;
+        debug!("remove_cache_directive response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn list_cache_directives(
+        &self,
+        prev_id: i64,
+        filter: hdfs::CacheDirectiveInfoProto,
+    ) -> Result<hdfs::ListCacheDirectivesResponseProto> {
+        let message = hdfs::ListCacheDirectivesRequestProto { prev_id, filter };
+        debug!("list_cache_directives request: {:?}", &message);
+
+        let response = self
+            .call(
+                "listCacheDirectives",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::ListCacheDirectivesResponseProto::decode_length_delimited(response)?;
+        debug!("list_cache_directives response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn add_cache_pool(
+        &self,
+        info: hdfs::CachePoolInfoProto,
+    ) -> Result<hdfs::AddCachePoolResponseProto> {
+        let message = hdfs::AddCachePoolRequestProto { info };
+        debug!("add_cache_pool request: {:?}", &message);
+
+        let response = self
+            .call(
+                "addCachePool",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::AddCachePoolResponseProto::decode_length_delimited(response)?;
+        debug!("add_cache_pool response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn remove_cache_pool(
+        &self,
+        pool_name: &str,
+    ) -> Result<hdfs::RemoveCachePoolResponseProto> {
+        let message = hdfs::RemoveCachePoolRequestProto {
+            pool_name: pool_name.to_string(),
+        };
+        debug!("remove_cache_pool request: {:?}", &message);
+
+        let response = self
+            .call(
+                "removeCachePool",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::RemoveCachePoolResponseProto::decode_length_delimited(response)?;
+        debug!("remove_cache_pool response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn list_cache_pools(
+        &self,
+        prev_pool_name: &str,
+    ) -> Result<hdfs::ListCachePoolsResponseProto> {
+        let message = hdfs::ListCachePoolsRequestProto {
+            prev_pool_name: prev_pool_name.to_string(),
+        };
+        debug!("list_cache_pools request: {:?}", &message);
+
+        let response = self
+            .call(
+                "listCachePools",
+                None,
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::ListCachePoolsResponseProto::decode_length_delimited(response)?;
+        debug!("list_cache_pools response: {:?}", &decoded);
+        Ok(decoded)
+    }
+
+    pub(crate) async fn get_ez_for_path(
+        &self,
+        src: &str,
+    ) -> Result<hdfs::GetEzForPathResponseProto> {
+        let message = hdfs::GetEzForPathRequestProto {
+            src: src.to_string(),
+        };
+        debug!("get_ez_for_path request: {:?}", &message);
+
+        let response = self
+            .call(
+                "getEZForPath",
+                Some(src),
+                message.encode_length_delimited_to_vec(),
+            )
+            .await?;
+
+        let decoded = hdfs::GetEzForPathResponseProto::decode_length_delimited(response)?;
+        debug!("get_ez_for_path response: {:?}", &decoded);
+        Ok(decoded)
+    }
+}
+
+async fn renew_leases(protocol: Weak<NamenodeProtocol>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(protocol) = protocol.upgrade() else {
+            return;
+        };
+        // Failures are retried on the next interval, which is still within the soft limit
+        if let Err(e) = protocol.renew_lease().await {
+            warn!("Failed to renew lease: {:?}", e);
+        }
+    }
 }
@@ -2,31 +2,99 @@ use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc, Mutex,
 };
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use bytes::Bytes;
-use log::warn;
+use log::{debug, warn};
 use prost::Message;
+use tokio::task::JoinHandle;
 use url::Url;
 
 use crate::{
     common::config::Configuration,
-    hdfs::connection::{AlignmentContext, RpcConnection},
-    proto::hdfs,
+    hdfs::connection::{AlignmentContext, RpcConfig, RpcConnection},
+    metrics::Metrics,
+    proto::{
+        common::{self, HaServiceStateProto},
+        hdfs,
+    },
+    security::{
+        sasl::HDFS_DELEGATION_TOKEN,
+        user::{Token, User},
+    },
     HdfsError, Result,
 };
 
+const DEFAULT_NAMENODE_PORT: u16 = 8020;
+
 // RPC exceptions that should be tried
 const STANDBY_EXCEPTION: &str = "org.apache.hadoop.ipc.StandbyException";
 const OBSERVER_RETRY_EXCEPTION: &str = "org.apache.hadoop.ipc.ObserverRetryOnActiveException";
+// Thrown when a NameNode is temporarily unable to serve a call, such as during safe mode startup
+const RETRIABLE_EXCEPTION: &str = "org.apache.hadoop.ipc.RetriableException";
+const SAFE_MODE_EXCEPTION: &str = "org.apache.hadoop.hdfs.server.namenode.SafeModeException";
+
+// Same as the Java client, renew after 80% of the remaining token lifetime has passed
+const TOKEN_RENEW_WINDOW: f64 = 0.8;
+const TOKEN_RENEW_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+// How long the Observers found are used before the HA states of the NameNodes are checked again,
+// the same as the Java client's default `dfs.client.failover.observer.probe.retry.period`
+const OBSERVER_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Read-only RPCs that can be served by an Observer NameNode
+const READ_ONLY_METHODS: [&str; 13] = [
+    "getFileInfo",
+    "getFileLinkInfo",
+    "getLinkTarget",
+    "getListing",
+    "getLocatedFileInfo",
+    "getBlockLocations",
+    "getContentSummary",
+    "getServerDefaults",
+    "getFsStats",
+    "listCacheDirectives",
+    "listCachePools",
+    "getEZForPath",
+    "checkAccess",
+];
+
+// Calls other than the read-only ones that are safe to repeat after a lost connection
+const IDEMPOTENT_METHODS: [&str; 6] = [
+    "getHAServiceState",
+    "msync",
+    "fsync",
+    "setSafeMode",
+    "renewLease",
+    "recoverLease",
+];
+
+/// How a call to a single NameNode failed
+#[derive(Debug)]
+enum CallError {
+    /// No connection could be established, so the NameNode never received the call
+    Connect(HdfsError),
+    /// The call was sent, but it failed or its response was lost
+    Call(HdfsError),
+}
+
+impl From<CallError> for HdfsError {
+    fn from(err: CallError) -> Self {
+        match err {
+            CallError::Connect(e) | CallError::Call(e) => e,
+        }
+    }
+}
 
-/// Lazily creates a connection to a host, and recreates the connection
-/// on fatal errors.
+/// Lazily creates a connection to a NameNode, which is shared by all concurrent calls and
+/// recreated if it is lost.
 #[derive(Debug)]
 struct ProxyConnection {
     url: String,
-    inner: Option<RpcConnection>,
+    inner: tokio::sync::Mutex<Option<Arc<RpcConnection>>>,
     alignment_context: Arc<Mutex<AlignmentContext>>,
     nameservice: Option<String>,
+    config: RpcConfig,
 }
 
 impl ProxyConnection {
@@ -34,78 +102,337 @@ impl ProxyConnection {
         url: String,
         alignment_context: Arc<Mutex<AlignmentContext>>,
         nameservice: Option<String>,
+        config: &Configuration,
+        user: Arc<User>,
     ) -> Self {
         ProxyConnection {
             url,
-            inner: None,
+            inner: tokio::sync::Mutex::new(None),
             alignment_context,
             nameservice,
+            config: RpcConfig::new(config, user),
         }
     }
 
-    async fn get_connection(&mut self) -> Result<&RpcConnection> {
-        if self.inner.is_none() || !self.inner.as_ref().unwrap().is_alive() {
-            self.inner = Some(
-                RpcConnection::connect(
-                    &self.url,
-                    self.alignment_context.clone(),
-                    self.nameservice.as_deref(),
-                )
-                .await?,
-            );
+    /// Returns the current connection, connecting first if there isn't a live one. The lock is
+    /// only held while connecting, so calls on the same connection are multiplexed.
+    async fn get_connection(&self) -> Result<Arc<RpcConnection>> {
+        let mut inner = self.inner.lock().await;
+        match inner.as_ref() {
+            Some(connection) if connection.is_alive() => Ok(Arc::clone(connection)),
+            _ => {
+                let connection = Arc::new(
+                    RpcConnection::connect(
+                        &self.url,
+                        self.alignment_context.clone(),
+                        self.nameservice.as_deref(),
+                        &self.config,
+                    )
+                    .await?,
+                );
+                *inner = Some(Arc::clone(&connection));
+                Ok(connection)
+            }
+        }
+    }
+
+    async fn call(&self, method_name: &str, message: &[u8]) -> Result<Bytes> {
+        Ok(self.try_call(method_name, message).await?)
+    }
+
+    /// Sends a call, reporting whether it failed before it could reach the NameNode
+    async fn try_call(
+        &self,
+        method_name: &str,
+        message: &[u8],
+    ) -> std::result::Result<Bytes, CallError> {
+        let connection = self.get_connection().await.map_err(CallError::Connect)?;
+        match connection.call(method_name, message).await {
+            // The connection was lost before the response arrived, so reconnect and send the
+            // call again if it is safe to repeat
+            Err(HdfsError::IOError(e)) if !connection.is_alive() && is_idempotent(method_name) => {
+                debug!(
+                    "Retrying {} on a new connection after: {:?}",
+                    method_name, e
+                );
+                self.get_connection()
+                    .await
+                    .map_err(CallError::Connect)?
+                    .call(method_name, message)
+                    .await
+                    .map_err(CallError::Call)
+            }
+            result => result.map_err(CallError::Call),
         }
-        Ok(self.inner.as_ref().unwrap())
     }
+}
+
+/// Whether a call can be sent again without changing its outcome, if it isn't known whether
+/// the NameNode received it
+fn is_idempotent(method_name: &str) -> bool {
+    READ_ONLY_METHODS.contains(&method_name) || IDEMPOTENT_METHODS.contains(&method_name)
+}
+
+/// Renews a loaded delegation token in the background before it expires, like the Java client's
+/// `DelegationTokenRenewer`. The NameNode only renews tokens for callers authenticated with
+/// Kerberos, so the renewal uses its own connections that never authenticate with the token.
+#[derive(Debug)]
+struct TokenRenewer {
+    // Waiting for the first call to start the task, since there may be no runtime before that
+    pending: Option<TokenRenewal>,
+    task: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct TokenRenewal {
+    token: common::TokenProto,
+    proxy_connections: Vec<ProxyConnection>,
+}
+
+impl TokenRenewal {
+    /// Renews the token with the first NameNode that accepts it, returning how long to wait
+    /// before renewing it again
+    async fn renew(&self) -> Result<Duration> {
+        let message = common::RenewDelegationTokenRequestProto {
+            token: self.token.clone(),
+        }
+        .encode_length_delimited_to_vec();
+
+        let mut result = Err(HdfsError::OperationFailed(
+            "No NameNode to renew the delegation token with".to_string(),
+        ));
+        for proxy_connection in self.proxy_connections.iter() {
+            result = proxy_connection
+                .call("renewDelegationToken", &message)
+                .await
+                .and_then(|response| {
+                    Ok(
+                        common::RenewDelegationTokenResponseProto::decode_length_delimited(
+                            response,
+                        )?,
+                    )
+                });
+            // A standby NameNode rejects the renewal, so try the next one
+            if result.is_ok() {
+                break;
+            }
+        }
 
-    async fn call(&mut self, method_name: &str, message: &[u8]) -> Result<Bytes> {
-        self.get_connection()
-            .await?
-            .call(method_name, message)
-            .await
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Duration::from_millis(result?.new_expiry_time)
+            .saturating_sub(now)
+            .mul_f64(TOKEN_RENEW_WINDOW))
+    }
+
+    async fn run(self) {
+        loop {
+            let wait = match self.renew().await {
+                Ok(wait) => {
+                    debug!("Renewed delegation token, renewing again in {:?}", wait);
+                    wait
+                }
+                Err(e) => {
+                    warn!("Failed to renew delegation token: {:?}", e);
+                    TOKEN_RENEW_RETRY_INTERVAL
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
     }
 }
 
 #[derive(Debug)]
 pub(crate) struct NameServiceProxy {
-    proxy_connections: Vec<Arc<tokio::sync::Mutex<ProxyConnection>>>,
+    proxy_connections: Vec<ProxyConnection>,
     current_index: AtomicUsize,
     msycned: AtomicBool,
+    max_failovers: usize,
+    failover_sleep_base: Duration,
+    failover_sleep_max: Duration,
+    retry_max_attempts: usize,
+    retry_sleep_base: Duration,
+    retry_sleep_max: Duration,
+    safe_mode_wait: Option<Duration>,
+    observer_reads: bool,
+    observers: tokio::sync::RwLock<Observers>,
+    current_observer: AtomicUsize,
+    token_service: String,
+    token_renewer: Option<Mutex<TokenRenewer>>,
+    metrics: Metrics,
+}
+
+/// The indices of the NameNodes last found to be Observers, and when they were found. They are
+/// found again once `refreshed` is too old or has been cleared.
+#[derive(Debug, Default)]
+struct Observers {
+    indices: Vec<usize>,
+    refreshed: Option<Instant>,
+}
+
+impl Observers {
+    fn is_fresh(&self) -> bool {
+        self.refreshed
+            .is_some_and(|refreshed| refreshed.elapsed() < OBSERVER_REFRESH_INTERVAL)
+    }
+}
+
+impl Drop for NameServiceProxy {
+    fn drop(&mut self) {
+        if let Some(task) = self
+            .token_renewer
+            .as_ref()
+            .and_then(|token_renewer| token_renewer.lock().unwrap().task.take())
+        {
+            task.abort();
+        }
+    }
 }
 
 impl NameServiceProxy {
     /// Creates a new proxy for a name service. If the URL contains a port,
-    /// it is assumed to be for a single NameNode.
-    pub(crate) fn new(nameservice: &Url, config: &Configuration) -> Self {
+    /// it is assumed to be for a single NameNode. Otherwise the host is resolved
+    /// as a name service from the config, falling back to a single NameNode on
+    /// the default port if it isn't one. Connections authenticate as `user`.
+    pub(crate) fn new(
+        nameservice: &Url,
+        config: &Configuration,
+        metrics: Metrics,
+        user: Arc<User>,
+    ) -> Self {
         let alignment_context = Arc::new(Mutex::new(AlignmentContext::default()));
 
-        let proxy_connections = if let Some(port) = nameservice.port() {
+        let (proxy_connections, token_service) = if let Some(port) = nameservice.port() {
             let url = format!("{}:{}", nameservice.host_str().unwrap(), port);
-            vec![Arc::new(tokio::sync::Mutex::new(ProxyConnection::new(
+            (
+                vec![ProxyConnection::new(
+                    url.clone(),
+                    alignment_context.clone(),
+                    None,
+                    config,
+                    Arc::clone(&user),
+                )],
                 url,
-                alignment_context.clone(),
-                None,
-            )))]
+            )
         } else if let Some(host) = nameservice.host_str() {
-            // TODO: Add check for no configured namenodes
-            config
-                .get_urls_for_nameservice(host)
-                .into_iter()
-                .map(|url| {
-                    Arc::new(tokio::sync::Mutex::new(ProxyConnection::new(
-                        url,
+            let urls = config.get_urls_for_nameservice(host);
+            if urls.is_empty() {
+                // Not a configured name service, so treat it as a single NameNode on the default port
+                let url = format!("{}:{}", host, DEFAULT_NAMENODE_PORT);
+                (
+                    vec![ProxyConnection::new(
+                        url.clone(),
                         alignment_context.clone(),
-                        Some(host.to_string()),
-                    )))
-                })
-                .collect()
+                        None,
+                        config,
+                        Arc::clone(&user),
+                    )],
+                    url,
+                )
+            } else {
+                (
+                    urls.into_iter()
+                        .map(|url| {
+                            ProxyConnection::new(
+                                url,
+                                alignment_context.clone(),
+                                Some(host.to_string()),
+                                config,
+                                Arc::clone(&user),
+                            )
+                        })
+                        .collect(),
+                    format!("ha-hdfs:{host}"),
+                )
+            }
         } else {
             todo!()
         };
 
+        let token_renewer = if config.get_token_auto_renew() {
+            user.get_token(HDFS_DELEGATION_TOKEN, &token_service)
+                .map(|token| {
+                    Mutex::new(TokenRenewer {
+                        pending: Some(Self::token_renewal(
+                            token,
+                            &proxy_connections,
+                            config,
+                            &user,
+                        )),
+                        task: None,
+                    })
+                })
+        } else {
+            None
+        };
+
+        let (failover_sleep_base, failover_sleep_max) = config.get_failover_sleep();
+        let (retry_sleep_base, retry_sleep_max) = config.get_retry_sleep();
+        let observer_reads = proxy_connections.len() > 1
+            && nameservice
+                .host_str()
+                .is_some_and(|host| config.get_observer_reads_enabled(host));
+
         NameServiceProxy {
             proxy_connections,
             current_index: AtomicUsize::new(0),
             msycned: AtomicBool::new(false),
+            max_failovers: config.get_failover_max_attempts(),
+            failover_sleep_base,
+            failover_sleep_max,
+            retry_max_attempts: config.get_retry_max_attempts(),
+            retry_sleep_base,
+            retry_sleep_max,
+            safe_mode_wait: config.get_safe_mode_wait(),
+            observer_reads,
+            observers: tokio::sync::RwLock::new(Observers::default()),
+            current_observer: AtomicUsize::new(0),
+            token_service,
+            token_renewer,
+            metrics,
+        }
+    }
+
+    pub(crate) fn token_service(&self) -> &str {
+        &self.token_service
+    }
+
+    /// Sets up renewing `token` over new connections to the same NameNodes as
+    /// `proxy_connections` that authenticate with Kerberos
+    fn token_renewal(
+        token: &Token,
+        proxy_connections: &[ProxyConnection],
+        config: &Configuration,
+        user: &User,
+    ) -> TokenRenewal {
+        let alignment_context = Arc::new(Mutex::new(AlignmentContext::default()));
+        let renewal_user = Arc::new(user.for_token_renewal());
+        TokenRenewal {
+            token: token.into(),
+            proxy_connections: proxy_connections
+                .iter()
+                .map(|proxy_connection| {
+                    ProxyConnection::new(
+                        proxy_connection.url.clone(),
+                        alignment_context.clone(),
+                        proxy_connection.nameservice.clone(),
+                        config,
+                        Arc::clone(&renewal_user),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Starts renewing the loaded delegation token in the background if auto renewal is enabled
+    fn start_token_renewal(&self) {
+        let Some(token_renewer) = self.token_renewer.as_ref() else {
+            return;
+        };
+        let mut token_renewer = token_renewer.lock().unwrap();
+        if let Some(renewal) = token_renewer.pending.take() {
+            token_renewer.task = Some(tokio::spawn(renewal.run()));
         }
     }
 
@@ -129,49 +456,463 @@ impl NameServiceProxy {
     }
 
     pub(crate) async fn call(&self, method_name: &'static str, message: Vec<u8>) -> Result<Bytes> {
-        self.msync_if_needed().await?;
-        self.call_inner(method_name, message).await
+        let start = Instant::now();
+        let result = async {
+            self.msync_if_needed().await?;
+            self.start_token_renewal();
+            if self.observer_reads && READ_ONLY_METHODS.contains(&method_name) {
+                if let Some(result) = self.call_observers(method_name, &message).await {
+                    return result;
+                }
+            }
+            self.call_inner(method_name, message).await
+        }
+        .await;
+        self.metrics
+            .record_rpc(method_name, start.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Finds which NameNodes are currently Observers
+    async fn find_observers(&self) -> Vec<usize> {
+        let message = hdfs::HaServiceStateRequestProto::default().encode_length_delimited_to_vec();
+        let mut observers = Vec::new();
+        for (index, proxy_connection) in self.proxy_connections.iter().enumerate() {
+            let state = proxy_connection
+                .call("getHAServiceState", &message)
+                .await
+                .and_then(|response| {
+                    Ok(hdfs::HaServiceStateResponseProto::decode_length_delimited(response)?.state)
+                });
+            match state {
+                Ok(state) if state == HaServiceStateProto::Observer as i32 => observers.push(index),
+                Ok(_) => (),
+                Err(e) => warn!("Failed to get HA state of NameNode: {:?}", e),
+            }
+        }
+        observers
+    }
+
+    /// Returns the Observers, finding them again if they were found too long ago or one of them
+    /// stopped serving reads
+    async fn get_observers(&self) -> Vec<usize> {
+        {
+            let observers = self.observers.read().await;
+            if observers.is_fresh() {
+                return observers.indices.clone();
+            }
+        }
+        let mut observers = self.observers.write().await;
+        // Another call may have found them while this one waited for the lock
+        if !observers.is_fresh() {
+            observers.indices = self.find_observers().await;
+            observers.refreshed = Some(Instant::now());
+            self.current_observer.store(0, Ordering::SeqCst);
+        }
+        observers.indices.clone()
+    }
+
+    /// Tries a read-only call on each Observer NameNode, starting with the last one that
+    /// succeeded. Returns `None` if no Observer could serve the call, in which case it should be
+    /// sent to the active NameNode instead. Once an Observer rejects a call because it is now a
+    /// standby or wants it sent to the active NameNode, the Observers are found again for the
+    /// next call.
+    async fn call_observers(
+        &self,
+        method_name: &'static str,
+        message: &[u8],
+    ) -> Option<Result<Bytes>> {
+        let observers = self.get_observers().await;
+        let mut stale = false;
+        let start = self.current_observer.load(Ordering::SeqCst);
+        for i in 0..observers.len() {
+            let observer_index = (start + i) % observers.len();
+            let result = self.proxy_connections[observers[observer_index]]
+                .call(method_name, message)
+                .await;
+
+            match result {
+                Ok(bytes) => {
+                    if stale {
+                        self.observers.write().await.refreshed = None;
+                    }
+                    self.current_observer
+                        .store(observer_index, Ordering::SeqCst);
+                    return Some(Ok(bytes));
+                }
+                Err(HdfsError::RPCError(exception, msg)) if !Self::is_retriable(&exception) => {
+                    return Some(Err(Self::convert_rpc_error(exception, msg)));
+                }
+                Err(e) => {
+                    warn!("{:?}", e);
+                    stale |= matches!(e, HdfsError::RPCError(_, _));
+                }
+            }
+        }
+        if stale {
+            self.observers.write().await.refreshed = None;
+        }
+        None
+    }
+
+    /// Whether the NameNode rejected a call because it's in safe mode. An active HA NameNode
+    /// that is still starting up wraps the `SafeModeException` in a `RetriableException`.
+    fn is_safe_mode(exception: &str, msg: &str) -> bool {
+        exception == SAFE_MODE_EXCEPTION
+            || (exception == RETRIABLE_EXCEPTION && msg.contains(SAFE_MODE_EXCEPTION))
     }
 
     fn is_retriable(exception: &str) -> bool {
         exception == STANDBY_EXCEPTION || exception == OBSERVER_RETRY_EXCEPTION
     }
 
+    /// Calls the NameNode that last succeeded, failing over to the next NameNode on connection
+    /// errors or standby exceptions. Failovers are bounded by `dfs.client.failover.max.attempts`,
+    /// and once every NameNode has been tried there is an exponential backoff between attempts.
+    ///
+    /// Transient failures of a single NameNode, like a `RetriableException` or a lost
+    /// connection, are retried with an exponential backoff up to `dfs.client.retry.max.attempts`
+    /// times. A call that may have reached the NameNode is only sent again if it is idempotent.
+    /// Calls rejected because the NameNode is in safe mode are retried with the same backoff
+    /// until `dfs.client.safemode.wait.millis` has passed.
     async fn call_inner(&self, method_name: &'static str, message: Vec<u8>) -> Result<Bytes> {
         let mut proxy_index = self.current_index.load(Ordering::SeqCst);
-        let mut attempts = 0;
+        let mut failovers = 0;
+        let mut retries = 0;
+        let mut safe_mode_deadline: Option<Instant> = None;
+        let mut safe_mode_retries = 0;
         loop {
             let result = self.proxy_connections[proxy_index]
-                .lock()
-                .await
-                .call(method_name, &message)
+                .try_call(method_name, &message)
                 .await;
 
-            match result {
+            let error = match result {
                 Ok(bytes) => {
                     self.current_index.store(proxy_index, Ordering::SeqCst);
                     return Ok(bytes);
                 }
+                // Safe mode rejects the call without running it, so it can be sent again once
+                // the NameNode leaves safe mode
+                Err(CallError::Call(HdfsError::RPCError(exception, msg)))
+                    if Self::is_safe_mode(&exception, &msg) && self.safe_mode_wait.is_some() =>
+                {
+                    let now = Instant::now();
+                    let deadline = *safe_mode_deadline
+                        .get_or_insert_with(|| now + self.safe_mode_wait.unwrap_or_default());
+                    if now >= deadline {
+                        return Err(Self::convert_rpc_error(exception, msg));
+                    }
+                    warn!("Waiting for safe mode to end: {}", msg);
+                    safe_mode_retries += 1;
+                    self.metrics.record_rpc_retry(method_name);
+                    tokio::time::sleep(self.retry_sleep(safe_mode_retries).min(deadline - now))
+                        .await;
+                    continue;
+                }
+                // The NameNode rejected the call without running it, so it can be sent again
+                Err(CallError::Call(HdfsError::RPCError(exception, msg)))
+                    if exception == RETRIABLE_EXCEPTION =>
+                {
+                    if retries >= self.retry_max_attempts {
+                        return Err(Self::convert_rpc_error(exception, msg));
+                    }
+                    warn!("{}: {}", exception, msg);
+                    retries += 1;
+                    self.metrics.record_rpc_retry(method_name);
+                    tokio::time::sleep(self.retry_sleep(retries)).await;
+                    continue;
+                }
                 // RPCError indicates the call was successfully attempted but had an error, so should be returned immediately
-                Err(HdfsError::RPCError(exception, msg)) if !Self::is_retriable(&exception) => {
+                Err(CallError::Call(HdfsError::RPCError(exception, msg)))
+                    if !Self::is_retriable(&exception) =>
+                {
                     warn!("{}: {}", exception, msg);
                     return Err(Self::convert_rpc_error(exception, msg));
                 }
-                Err(_) if attempts >= self.proxy_connections.len() - 1 => return result,
-                Err(e) => {
-                    warn!("{:?}", e);
+                // Every connection to the NameNode would be rejected the same way
+                Err(CallError::Call(e @ HdfsError::ProxyUserNotAllowed(_))) => return Err(e),
+                // The call may have been run before the connection failed, so it can only be
+                // repeated if that is safe
+                Err(CallError::Call(e))
+                    if !matches!(e, HdfsError::RPCError(..)) && !is_idempotent(method_name) =>
+                {
+                    return Err(e);
                 }
+                Err(e) => HdfsError::from(e),
+            };
+
+            if self.proxy_connections.len() == 1 {
+                // A standby exception can't be resolved without another NameNode to fail over to
+                if matches!(error, HdfsError::RPCError(..)) || retries >= self.retry_max_attempts {
+                    return Err(error);
+                }
+                warn!("{:?}", error);
+                retries += 1;
+                self.metrics.record_rpc_retry(method_name);
+                tokio::time::sleep(self.retry_sleep(retries)).await;
+                continue;
             }
 
+            if failovers >= self.max_failovers {
+                return Err(error);
+            }
+            warn!("{:?}", error);
             proxy_index = (proxy_index + 1) % self.proxy_connections.len();
-            attempts += 1;
+            failovers += 1;
+            self.metrics.record_failover(method_name);
+
+            if failovers % self.proxy_connections.len() == 0 {
+                let cycles = (failovers / self.proxy_connections.len()) as u32;
+                let sleep = self
+                    .failover_sleep_base
+                    .saturating_mul(2u32.saturating_pow(cycles - 1))
+                    .min(self.failover_sleep_max);
+                tokio::time::sleep(sleep).await;
+            }
         }
     }
 
-    fn convert_rpc_error(exception: String, msg: String) -> HdfsError {
+    /// The backoff before the given retry of a call, starting from 1
+    fn retry_sleep(&self, retry: usize) -> Duration {
+        self.retry_sleep_base
+            .saturating_mul(2u32.saturating_pow(retry as u32 - 1))
+            .min(self.retry_sleep_max)
+    }
+
+    pub(crate) fn convert_rpc_error(exception: String, msg: String) -> HdfsError {
         match exception.as_ref() {
             "org.apache.hadoop.fs.FileAlreadyExistsException" => HdfsError::AlreadyExists(msg),
+            "java.io.FileNotFoundException" => HdfsError::FileNotFound(msg),
+            "org.apache.hadoop.fs.PathIsNotEmptyDirectoryException" => {
+                HdfsError::DirectoryNotEmpty(msg)
+            }
+            "org.apache.hadoop.HadoopIllegalArgumentException" => HdfsError::InvalidArgument(msg),
+            "org.apache.hadoop.fs.UnresolvedLinkException"
+            | "org.apache.hadoop.hdfs.protocol.UnresolvedPathException" => {
+                HdfsError::UnresolvedLink(msg)
+            }
+            "org.apache.hadoop.fs.InvalidPathException" => HdfsError::InvalidPath(msg),
+            "org.apache.hadoop.fs.ParentNotDirectoryException" => {
+                HdfsError::ParentNotDirectory(msg)
+            }
+            "org.apache.hadoop.hdfs.protocol.SnapshotException" => HdfsError::SnapshotError(msg),
+            "org.apache.hadoop.security.AccessControlException"
+            | "org.apache.hadoop.hdfs.protocol.SnapshotAccessControlException" => {
+                HdfsError::PermissionDenied(msg)
+            }
+            "org.apache.hadoop.hdfs.protocol.QuotaExceededException"
+            | "org.apache.hadoop.hdfs.protocol.NSQuotaExceededException"
+            | "org.apache.hadoop.hdfs.protocol.DSQuotaExceededException"
+            | "org.apache.hadoop.hdfs.protocol.QuotaByStorageTypeExceededException" => {
+                HdfsError::QuotaExceeded(msg)
+            }
+            "org.apache.hadoop.hdfs.protocol.AlreadyBeingCreatedException" => {
+                HdfsError::AlreadyBeingCreated(msg)
+            }
+            "org.apache.hadoop.hdfs.server.namenode.LeaseExpiredException" => {
+                HdfsError::LeaseExpired(msg)
+            }
+            e if Self::is_safe_mode(e, &msg) => HdfsError::SafeMode(msg),
             _ => HdfsError::RPCError(exception, msg),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use url::Url;
+
+    use super::{NameServiceProxy, OBSERVER_REFRESH_INTERVAL};
+    use crate::common::config::Configuration;
+    use crate::metrics::{Metrics, MetricsRecorder};
+    use crate::security::user::User;
+    use crate::HdfsError;
+
+    #[derive(Default)]
+    struct TestRecorder {
+        rpcs: Mutex<Vec<(String, bool)>>,
+        retries: Mutex<Vec<String>>,
+    }
+
+    impl MetricsRecorder for TestRecorder {
+        fn record_rpc(&self, method: &str, _latency: Duration, success: bool) {
+            self.rpcs
+                .lock()
+                .unwrap()
+                .push((method.to_string(), success));
+        }
+
+        fn record_rpc_retry(&self, method: &str) {
+            self.retries.lock().unwrap().push(method.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_attempts() {
+        let config = Configuration::from(
+            [
+                ("dfs.ha.namenodes.test", "nn1,nn2"),
+                ("dfs.namenode.rpc-address.test.nn1", "127.0.0.1:1"),
+                ("dfs.namenode.rpc-address.test.nn2", "127.0.0.1:2"),
+                ("dfs.client.failover.max.attempts", "4"),
+                ("dfs.client.failover.sleep.base.millis", "50"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>(),
+        );
+        let proxy = NameServiceProxy::new(
+            &Url::parse("hdfs://test").unwrap(),
+            &config,
+            Metrics::default(),
+            Arc::new(User::new(None, None, Vec::new())),
+        );
+
+        // Neither NameNode is reachable, so the call gives up after trying each one a few
+        // times, backing off between each full pass
+        let start = Instant::now();
+        let result = proxy.call("getFileInfo", Vec::new()).await;
+        assert!(matches!(result, Err(HdfsError::IOError(_))));
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+    #[tokio::test]
+    async fn test_retry_attempts() {
+        let config = Configuration::from(
+            [
+                ("dfs.client.retry.max.attempts", "2"),
+                ("dfs.client.retry.sleep.base.millis", "50"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>(),
+        );
+        let proxy = NameServiceProxy::new(
+            &Url::parse("hdfs://127.0.0.1:1").unwrap(),
+            &config,
+            Metrics::default(),
+            Arc::new(User::new(None, None, Vec::new())),
+        );
+
+        // The connection can't be established, so even a call that isn't idempotent is retried
+        let start = Instant::now();
+        let result = proxy.call_inner("create", Vec::new()).await;
+        assert!(matches!(result, Err(HdfsError::IOError(_))));
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let config = Configuration::from(
+            [
+                ("dfs.client.retry.max.attempts", "1"),
+                ("dfs.client.retry.sleep.base.millis", "1"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>(),
+        );
+        let recorder = Arc::new(TestRecorder::default());
+        let proxy = NameServiceProxy::new(
+            &Url::parse("hdfs://127.0.0.1:1").unwrap(),
+            &config,
+            Metrics::new(recorder.clone()),
+            Arc::new(User::new(None, None, Vec::new())),
+        );
+
+        // The msync sent before the first call is retried, and the call is recorded once
+        assert!(proxy.call("getFileInfo", Vec::new()).await.is_err());
+        assert_eq!(
+            *recorder.rpcs.lock().unwrap(),
+            vec![("getFileInfo".to_string(), false)]
+        );
+        assert_eq!(*recorder.retries.lock().unwrap(), vec!["msync".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_observers_refresh() {
+        let config = Configuration::from(
+            [
+                ("dfs.ha.namenodes.test", "nn1,nn2"),
+                ("dfs.namenode.rpc-address.test.nn1", "127.0.0.1:1"),
+                ("dfs.namenode.rpc-address.test.nn2", "127.0.0.1:2"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>(),
+        );
+        let proxy = NameServiceProxy::new(
+            &Url::parse("hdfs://test").unwrap(),
+            &config,
+            Metrics::default(),
+            Arc::new(User::new(None, None, Vec::new())),
+        );
+
+        // Unreachable NameNodes aren't Observers, and they aren't checked again until the
+        // Observers are stale
+        assert!(proxy.get_observers().await.is_empty());
+        let refreshed = proxy.observers.read().await.refreshed;
+        assert!(refreshed.is_some());
+        proxy.get_observers().await;
+        assert_eq!(proxy.observers.read().await.refreshed, refreshed);
+
+        proxy.observers.write().await.refreshed = None;
+        proxy.get_observers().await;
+        assert!(proxy.observers.read().await.refreshed > refreshed);
+
+        proxy.observers.write().await.refreshed =
+            Instant::now().checked_sub(OBSERVER_REFRESH_INTERVAL);
+        assert!(!proxy.observers.read().await.is_fresh());
+    }
+
+    #[test]
+    fn test_convert_rpc_error() {
+        let convert = |exception: &str| {
+            NameServiceProxy::convert_rpc_error(exception.to_string(), "message".to_string())
+        };
+        assert!(matches!(
+            convert("java.io.FileNotFoundException"),
+            HdfsError::FileNotFound(msg) if msg == "message"
+        ));
+        assert!(matches!(
+            convert("org.apache.hadoop.security.AccessControlException"),
+            HdfsError::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            convert("org.apache.hadoop.hdfs.protocol.DSQuotaExceededException"),
+            HdfsError::QuotaExceeded(_)
+        ));
+        assert!(matches!(
+            convert("org.apache.hadoop.fs.ParentNotDirectoryException"),
+            HdfsError::ParentNotDirectory(_)
+        ));
+
+        assert!(matches!(
+            convert("org.apache.hadoop.hdfs.server.namenode.SafeModeException"),
+            HdfsError::SafeMode(_)
+        ));
+        assert!(matches!(
+            NameServiceProxy::convert_rpc_error(
+                "org.apache.hadoop.ipc.RetriableException".to_string(),
+                "org.apache.hadoop.hdfs.server.namenode.SafeModeException: Cannot create \
+                 directory /dir. Name node is in safe mode."
+                    .to_string()
+            ),
+            HdfsError::SafeMode(_)
+        ));
+        assert!(matches!(
+            convert("org.apache.hadoop.ipc.RetriableException"),
+            HdfsError::RPCError(_, _)
+        ));
+
+        // Other exceptions keep their class name
+        assert!(matches!(
+            convert("java.lang.IllegalStateException"),
+            HdfsError::RPCError(class, msg)
+                if class == "java.lang.IllegalStateException" && msg == "message"
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+//! Short-circuit local reads. A DataNode on the same host passes open file descriptors for the
+//! data and checksums of a block over the UNIX domain socket at `dfs.domain.socket.path`, and
+//! the client reads the block from them directly instead of streaming it over TCP. This is the
+//! same as the Java client with the shared memory segment for replica state disabled.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{IpAddr, UdpSocket};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use crc::Crc;
+use log::debug;
+use prost::Message;
+
+use crate::hdfs::connection::{encode_op, Op, CRC32, CRC32C};
+use crate::proto::{common, hdfs};
+use crate::{HdfsError, Result};
+
+// The only on-disk block format
+const BLOCK_FORMAT_VERSION: u32 = 1;
+// The metadata file starts with a 2 byte version, then the checksum type and bytes per checksum
+const META_HEADER_LEN: u64 = 7;
+const CHECKSUM_BYTES: usize = 4;
+
+/// Whether `datanode` is on this host, which is the case if its address can be bound to
+pub(crate) fn is_local(datanode: &hdfs::DatanodeIdProto) -> bool {
+    match datanode.ip_addr.parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback() || UdpSocket::bind((ip, 0)).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// The domain socket of `datanode`, with `_PORT` in `path` replaced by its data transfer port
+/// the same as the DataNode does
+pub(crate) fn socket_path(path: &str, datanode: &hdfs::DatanodeIdProto) -> String {
+    path.replace("_PORT", &datanode.xfer_port.to_string())
+}
+
+/// Reads a replica of a block from the files passed by a local DataNode, verifying checksums
+pub(crate) struct LocalBlockReader {
+    data: Arc<File>,
+    meta: Arc<File>,
+    algorithm: Option<&'static Crc<u32>>,
+    bytes_per_checksum: usize,
+    block_id: u64,
+}
+
+impl LocalBlockReader {
+    /// Requests the files of `block` from the DataNode listening on `socket_path`
+    pub(crate) async fn open(
+        socket_path: String,
+        block: &hdfs::ExtendedBlockProto,
+        token: &common::TokenProto,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let message = hdfs::OpRequestShortCircuitAccessProto {
+            header: hdfs::BaseHeaderProto {
+                block: block.clone(),
+                token: Some(token.clone()),
+                ..Default::default()
+            },
+            max_version: BLOCK_FORMAT_VERSION,
+            slot_id: None,
+            supports_receipt_verification: Some(false),
+        };
+        let block_id = block.block_id;
+        tokio::task::spawn_blocking(move || {
+            let (data, meta) = request_fds(&socket_path, &message, timeout)?;
+            Self::new(data, meta, block_id)
+        })
+        .await
+        .map_err(|e| HdfsError::InternalError(e.to_string()))?
+    }
+
+    fn new(data: File, meta: File, block_id: u64) -> Result<Self> {
+        let mut header = [0u8; META_HEADER_LEN as usize];
+        meta.read_exact_at(&mut header, 0)?;
+        let mut header = &header[2..];
+        let algorithm = match header.get_u8() {
+            0 => None,
+            1 => Some(&CRC32),
+            2 => Some(&CRC32C),
+            checksum_type => {
+                return Err(HdfsError::DataTransferError(format!(
+                    "Unsupported checksum type {} for block {}",
+                    checksum_type, block_id
+                )))
+            }
+        };
+        Ok(Self {
+            data: Arc::new(data),
+            meta: Arc::new(meta),
+            algorithm,
+            bytes_per_checksum: header.get_u32() as usize,
+            block_id,
+        })
+    }
+
+    /// Skips verifying checksums, reading only the data file
+    pub(crate) fn without_checksums(mut self) -> Self {
+        self.algorithm = None;
+        self
+    }
+
+    /// Reads `len` bytes at `offset` in the block. The whole checksum chunks the range is in are
+    /// read so they can be verified, and a mismatch returns an [HdfsError::ChecksumError].
+    pub(crate) async fn read(&self, offset: usize, len: usize) -> Result<Bytes> {
+        let data = Arc::clone(&self.data);
+        let meta = Arc::clone(&self.meta);
+        let algorithm = self.algorithm;
+        let bytes_per_checksum = self.bytes_per_checksum;
+        let block_id = self.block_id;
+        tokio::task::spawn_blocking(move || {
+            let Some(algorithm) = algorithm else {
+                return Ok(read_at(&data, offset as u64, len)?.freeze());
+            };
+
+            let start = offset / bytes_per_checksum * bytes_per_checksum;
+            let end = (offset + len).div_ceil(bytes_per_checksum) * bytes_per_checksum;
+            // The last chunk of the block may be partial
+            let chunks = read_at(&data, start as u64, end - start)?.freeze();
+            let mut checksums = read_at(
+                &meta,
+                META_HEADER_LEN + (start / bytes_per_checksum * CHECKSUM_BYTES) as u64,
+                chunks.len().div_ceil(bytes_per_checksum) * CHECKSUM_BYTES,
+            )?;
+
+            for (i, chunk) in chunks.chunks(bytes_per_checksum).enumerate() {
+                if checksums.remaining() < CHECKSUM_BYTES
+                    || algorithm.checksum(chunk) != checksums.get_u32()
+                {
+                    return Err(HdfsError::ChecksumError {
+                        block: block_id,
+                        offset: (start + i * bytes_per_checksum) as u64,
+                    });
+                }
+            }
+            if chunks.len() < offset - start + len {
+                return Err(HdfsError::IOError(io::Error::from(
+                    io::ErrorKind::UnexpectedEof,
+                )));
+            }
+            Ok(chunks.slice(offset - start..offset - start + len))
+        })
+        .await
+        .map_err(|e| HdfsError::InternalError(e.to_string()))?
+    }
+}
+
+/// Reads up to `len` bytes at `offset` of `file`, stopping early at the end of the file
+fn read_at(file: &File, offset: u64, len: usize) -> io::Result<BytesMut> {
+    let mut buf = BytesMut::zeroed(len);
+    let mut read = 0;
+    while read < len {
+        match file.read_at(&mut buf[read..], offset + read as u64)? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    buf.truncate(read);
+    Ok(buf)
+}
+
+/// Sends the short-circuit request and receives the data and metadata files of the block
+fn request_fds(
+    socket_path: &str,
+    message: &hdfs::OpRequestShortCircuitAccessProto,
+    timeout: Duration,
+) -> Result<(File, File)> {
+    let mut socket = UnixStream::connect(socket_path)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+
+    debug!("Short-circuit access request {:?}", message);
+    socket.write_all(&encode_op(Op::RequestShortCircuitFds, message))?;
+
+    let response = hdfs::BlockOpResponseProto::decode(read_delimited(&mut socket)?.as_slice())?;
+    debug!("Short-circuit access response {:?}", response);
+    if response.status() != hdfs::Status::Success {
+        return Err(HdfsError::DataTransferError(response.message().to_string()));
+    }
+
+    let mut files = recv_fds(&socket, 2)?;
+    if files.len() != 2 {
+        return Err(HdfsError::DataTransferError(format!(
+            "Expected 2 file descriptors from the DataNode, got {}",
+            files.len()
+        )));
+    }
+    let meta = files.pop().unwrap();
+    let data = files.pop().unwrap();
+    Ok((data, meta))
+}
+
+/// Reads a length delimited message one byte at a time for the length, so nothing after the
+/// message is consumed. The file descriptors are attached to the byte that follows it.
+fn read_delimited(socket: &mut UnixStream) -> Result<Vec<u8>> {
+    let mut length = Vec::new();
+    loop {
+        let mut byte = [0u8; 1];
+        socket.read_exact(&mut byte)?;
+        length.push(byte[0]);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut message = vec![0u8; prost::decode_length_delimiter(length.as_slice())?];
+    socket.read_exact(&mut message)?;
+    Ok(message)
+}
+
+/// Receives a single byte with up to `max_fds` file descriptors passed as `SCM_RIGHTS`
+fn recv_fds(socket: &UnixStream, max_fds: usize) -> io::Result<Vec<File>> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+        iov_len: byte.len(),
+    };
+    let control_len = unsafe { libc::CMSG_SPACE((max_fds * mem::size_of::<RawFd>()) as u32) };
+    let mut control = vec![0u8; control_len as usize];
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control_len as _;
+
+    #[cfg(target_os = "linux")]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(target_os = "linux"))]
+    let flags = 0;
+
+    let received = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, flags) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    } else if received == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+
+    let mut files = Vec::new();
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        unsafe {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                    / mem::size_of::<RawFd>();
+                for i in 0..count {
+                    files.push(File::from_raw_fd(std::ptr::read_unaligned(data.add(i))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "File descriptors from the DataNode were truncated",
+        ));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write};
+    use std::mem;
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::{AsRawFd, RawFd};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::time::Duration;
+
+    use bytes::{BufMut, BytesMut};
+    use prost::Message;
+
+    use crate::hdfs::connection::CRC32C;
+    use crate::proto::{common, hdfs};
+    use crate::HdfsError;
+
+    use super::{socket_path, LocalBlockReader};
+
+    const BYTES_PER_CHECKSUM: usize = 512;
+
+    /// Sends `byte` with `files` attached as `SCM_RIGHTS`, the same as a DataNode
+    fn send_fds(socket: &UnixStream, files: &[&File]) {
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+            iov_len: 1,
+        };
+        let fds_len = files.len() * mem::size_of::<RawFd>();
+        let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len as u32) } as usize];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len as u32) as _;
+            let data = libc::CMSG_DATA(cmsg) as *mut RawFd;
+            for (i, file) in files.iter().enumerate() {
+                std::ptr::write_unaligned(data.add(i), file.as_raw_fd());
+            }
+            assert_eq!(libc::sendmsg(socket.as_raw_fd(), &msg, 0), 1);
+        }
+    }
+
+    /// Handles `requests` short-circuit requests for `data`, with the checksums in a metadata
+    /// file the same as a DataNode stores them
+    fn fake_datanode(listener: UnixListener, data: Vec<u8>, requests: usize) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut meta = BytesMut::new();
+        meta.put_u16(1);
+        meta.put_u8(2);
+        meta.put_u32(BYTES_PER_CHECKSUM as u32);
+        for chunk in data.chunks(BYTES_PER_CHECKSUM) {
+            meta.put_u32(CRC32C.checksum(chunk));
+        }
+        std::fs::write(dir.path().join("blk"), &data).unwrap();
+        std::fs::write(dir.path().join("blk.meta"), &meta).unwrap();
+
+        for _ in 0..requests {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut header = [0u8; 3];
+            socket.read_exact(&mut header).unwrap();
+            assert_eq!(header[2], 87);
+            let mut buf = vec![0u8; 1024];
+            let len = socket.read(&mut buf).unwrap();
+            let request =
+                hdfs::OpRequestShortCircuitAccessProto::decode_length_delimited(&buf[..len])
+                    .unwrap();
+            assert_eq!(request.max_version, 1);
+
+            let response = hdfs::BlockOpResponseProto {
+                status: hdfs::Status::Success as i32,
+                short_circuit_access_version: Some(1),
+                ..Default::default()
+            };
+            socket
+                .write_all(&response.encode_length_delimited_to_vec())
+                .unwrap();
+            // Writable so tests can corrupt the block
+            let data_file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(dir.path().join("blk"))
+                .unwrap();
+            let meta_file = File::open(dir.path().join("blk.meta")).unwrap();
+            send_fds(&socket, &[&data_file, &meta_file]);
+        }
+    }
+
+    #[test]
+    fn test_socket_path() {
+        let datanode = hdfs::DatanodeIdProto {
+            xfer_port: 9866,
+            ..Default::default()
+        };
+        assert_eq!(
+            socket_path("/var/run/hdfs/dn._PORT", &datanode),
+            "/var/run/hdfs/dn.9866"
+        );
+        assert_eq!(socket_path("/var/run/dn", &datanode), "/var/run/dn");
+    }
+
+    #[tokio::test]
+    async fn test_local_block_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dn_socket").to_str().unwrap().to_string();
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let expected = data.clone();
+        let server = std::thread::spawn(move || fake_datanode(listener, data, 1));
+
+        let block = hdfs::ExtendedBlockProto {
+            block_id: 1,
+            ..Default::default()
+        };
+        let reader = LocalBlockReader::open(
+            path,
+            &block,
+            &common::TokenProto::default(),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        server.join().unwrap();
+
+        for (offset, len) in [
+            (0, 10_000),
+            (0, 512),
+            (100, 1000),
+            (9_000, 1000),
+            (9_999, 1),
+        ] {
+            assert_eq!(
+                reader.read(offset, len).await.unwrap(),
+                &expected[offset..offset + len]
+            );
+        }
+        assert!(matches!(
+            reader.read(9_000, 2000).await,
+            Err(HdfsError::IOError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_local_block_reader_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dn_socket").to_str().unwrap().to_string();
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let data = vec![1u8; 2048];
+        let server = std::thread::spawn(move || fake_datanode(listener, data, 1));
+        let block = hdfs::ExtendedBlockProto {
+            block_id: 1,
+            ..Default::default()
+        };
+        let reader = LocalBlockReader::open(
+            path,
+            &block,
+            &common::TokenProto::default(),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        server.join().unwrap();
+
+        // Corrupt the second chunk through the shared file descriptor
+        reader.data.write_at(&[0u8], 600).unwrap();
+        assert!(reader.read(0, 512).await.is_ok());
+        assert!(matches!(
+            reader.read(0, 1024).await,
+            Err(HdfsError::ChecksumError {
+                block: 1,
+                offset: 512
+            })
+        ));
+    }
+}
@@ -23,6 +23,19 @@
 //! # }
 //! ```
 //!
+//! Create a client with settings that take precedence over the Hadoop config files
+//! ```rust
+//! use std::time::Duration;
+//! use hdfs_native::ClientBuilder;
+//! # use hdfs_native::Result;
+//! # fn main() -> Result<()> {
+//! let client = ClientBuilder::new("hdfs://ns")
+//!     .with_rpc_timeout(Duration::from_secs(30))
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! # Optional cargo package features
 //! - `kerberos` - include support for Kerberos authentication. Uses the libgssapi package. Supports all RPC authentication and encryption methods.
 //! - `token` - include support for Token authentication. Uses the gsasl native library. Only
@@ -30,6 +43,8 @@
 
 pub mod client;
 pub(crate) mod common;
+#[cfg(feature = "compression")]
+pub mod compression;
 #[cfg(feature = "benchmark")]
 pub mod ec;
 #[cfg(not(feature = "benchmark"))]
@@ -37,15 +52,27 @@ pub(crate) mod ec;
 pub(crate) mod error;
 pub mod file;
 pub(crate) mod hdfs;
+pub mod metrics;
 #[cfg(feature = "integration-test")]
 pub mod minidfs;
 pub(crate) mod proto;
 pub(crate) mod security;
+#[cfg(feature = "webhdfs")]
+pub mod webhdfs;
 
 pub use client::Client;
+pub use client::ClientBuilder;
 pub use client::WriteOptions;
 pub use error::HdfsError;
 pub use error::Result;
+#[cfg(feature = "kerberos")]
+pub use security::sasl::KerberosAuth;
+#[cfg(feature = "token")]
+pub use security::sasl::TokenAuth;
+pub use security::sasl::{
+    AuthProvider, Authentication, DefaultAuth, SaslAuthMethod, SaslMechanism, SimpleAuth,
+};
+pub use security::user::Token;
 
 // Module for testing hooks into non-test code
 #[cfg(feature = "integration-test")]
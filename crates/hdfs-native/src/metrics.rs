@@ -0,0 +1,72 @@
+//! Hooks for exporting client metrics, such as to a Prometheus registry.
+//!
+//! Implement [MetricsRecorder] and pass it to
+//! [ClientBuilder::with_metrics](crate::ClientBuilder::with_metrics). The client calls it inline
+//! as operations complete, so implementations should only update counters and histograms.
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Receives metrics from a client. Every method does nothing by default, so implementations
+/// only need to handle the metrics they export.
+#[allow(unused_variables)]
+pub trait MetricsRecorder: Send + Sync {
+    /// A NameNode RPC finished after `latency`, which includes any retries and failovers
+    fn record_rpc(&self, method: &str, latency: Duration, success: bool) {}
+
+    /// A NameNode RPC is sent again to the same NameNode after a transient failure
+    fn record_rpc_retry(&self, method: &str) {}
+
+    /// A NameNode RPC failed over to the next NameNode of the name service
+    fn record_failover(&self, method: &str) {}
+
+    /// File data was read from DataNodes
+    fn record_bytes_read(&self, bytes: usize) {}
+
+    /// File data was read directly from the files of a local replica with a short-circuit read.
+    /// These bytes are also recorded by [MetricsRecorder::record_bytes_read].
+    fn record_short_circuit_bytes_read(&self, bytes: usize) {}
+
+    /// File data was written by a [FileWriter](crate::file::FileWriter)
+    fn record_bytes_written(&self, bytes: usize) {}
+
+    /// Reading from or writing to the DataNode at `datanode`, as `ip:port`, failed
+    fn record_datanode_error(&self, datanode: &str) {}
+}
+
+/// A [MetricsRecorder] that ignores everything, used when no recorder is configured
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetricsRecorder;
+
+impl MetricsRecorder for NoopMetricsRecorder {}
+
+/// The recorder shared by everything created from a client
+#[derive(Clone)]
+pub(crate) struct Metrics(Arc<dyn MetricsRecorder>);
+
+impl Metrics {
+    pub(crate) fn new(recorder: Arc<dyn MetricsRecorder>) -> Self {
+        Self(recorder)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self(Arc::new(NoopMetricsRecorder))
+    }
+}
+
+impl Deref for Metrics {
+    type Target = dyn MetricsRecorder;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
@@ -1,21 +1,29 @@
 use std::{
     collections::HashSet,
     env,
-    io::{BufRead, BufReader, Write},
+    io::{BufRead, BufReader, Lines, Write},
     path::PathBuf,
-    process::{Child, Command, Stdio},
+    process::{Child, ChildStdout, Command, Stdio},
 };
 use which::which;
 
 #[derive(PartialEq, Eq, Hash, Debug)]
+#[allow(non_camel_case_types)]
 pub enum DfsFeatures {
     SECURITY,
+    DATA_TRANSFER_SECURITY,
+    DATA_TRANSFER_ENCRYPTION,
     TOKEN,
     PRIVACY,
     HA,
     VIEWFS,
     EC,
     RBF,
+    SHORT_CIRCUIT,
+    BLOCK_TOKEN_EXPIRY,
+    SECOND_CLUSTER,
+    TOKEN_EXPIRY,
+    KMS,
 }
 
 impl DfsFeatures {
@@ -26,8 +34,15 @@ impl DfsFeatures {
             DfsFeatures::VIEWFS => "viewfs",
             DfsFeatures::PRIVACY => "privacy",
             DfsFeatures::SECURITY => "security",
+            DfsFeatures::DATA_TRANSFER_SECURITY => "data_transfer_security",
+            DfsFeatures::DATA_TRANSFER_ENCRYPTION => "data_transfer_encryption",
             DfsFeatures::TOKEN => "token",
             DfsFeatures::RBF => "rbf",
+            DfsFeatures::SHORT_CIRCUIT => "short_circuit",
+            DfsFeatures::BLOCK_TOKEN_EXPIRY => "block_token_expiry",
+            DfsFeatures::SECOND_CLUSTER => "second_cluster",
+            DfsFeatures::TOKEN_EXPIRY => "token_expiry",
+            DfsFeatures::KMS => "kms",
         }
     }
 
@@ -35,9 +50,18 @@ impl DfsFeatures {
         match value {
             "ec" => Some(DfsFeatures::EC),
             "ha" => Some(DfsFeatures::HA),
+            "viewfs" => Some(DfsFeatures::VIEWFS),
             "privacy" => Some(DfsFeatures::PRIVACY),
             "security" => Some(DfsFeatures::SECURITY),
+            "data_transfer_security" => Some(DfsFeatures::DATA_TRANSFER_SECURITY),
+            "data_transfer_encryption" => Some(DfsFeatures::DATA_TRANSFER_ENCRYPTION),
             "token" => Some(DfsFeatures::TOKEN),
+            "rbf" => Some(DfsFeatures::RBF),
+            "short_circuit" => Some(DfsFeatures::SHORT_CIRCUIT),
+            "block_token_expiry" => Some(DfsFeatures::BLOCK_TOKEN_EXPIRY),
+            "second_cluster" => Some(DfsFeatures::SECOND_CLUSTER),
+            "token_expiry" => Some(DfsFeatures::TOKEN_EXPIRY),
+            "kms" => Some(DfsFeatures::KMS),
             _ => None,
         }
     }
@@ -45,6 +69,7 @@ impl DfsFeatures {
 
 pub struct MiniDfs {
     process: Child,
+    output: Lines<BufReader<ChildStdout>>,
     pub url: String,
 }
 
@@ -126,17 +151,37 @@ impl MiniDfs {
             "hdfs://fed"
         } else if features.contains(&DfsFeatures::HA) {
             "hdfs://minidfs-ns"
+        } else if features.contains(&DfsFeatures::SECOND_CLUSTER) {
+            "hdfs://127.0.0.1:9010"
         } else {
             "hdfs://127.0.0.1:9000"
         };
 
-        env::set_var("HADOOP_CONF_DIR", "target/test");
+        // A second cluster runs next to a main one, so leave the default config pointing to that
+        if !features.contains(&DfsFeatures::SECOND_CLUSTER) {
+            env::set_var("HADOOP_CONF_DIR", "target/test");
+        }
 
         MiniDfs {
             process: child,
+            output,
             url: url.to_string(),
         }
     }
+
+    /// Stops the first DataNode in the write pipeline of the last block of `path`, which must be
+    /// open for writing, returning its `ip:port` data transfer address
+    pub fn stop_pipeline_datanode(&mut self, path: &str) -> String {
+        let stdin = self.process.stdin.as_mut().unwrap();
+        writeln!(stdin, "stop_datanode {}", path).unwrap();
+        stdin.flush().unwrap();
+
+        let response = self.output.next().unwrap().unwrap();
+        match response.strip_prefix("Stopped ") {
+            Some(name) => name.to_string(),
+            None => panic!("Failed to stop DataNode: {}", response),
+        }
+    }
 }
 
 impl Drop for MiniDfs {
@@ -148,3 +193,31 @@ impl Drop for MiniDfs {
         self.process.wait().unwrap();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::DfsFeatures;
+
+    #[test]
+    fn test_feature_names() {
+        for feature in [
+            DfsFeatures::SECURITY,
+            DfsFeatures::DATA_TRANSFER_SECURITY,
+            DfsFeatures::DATA_TRANSFER_ENCRYPTION,
+            DfsFeatures::TOKEN,
+            DfsFeatures::PRIVACY,
+            DfsFeatures::HA,
+            DfsFeatures::VIEWFS,
+            DfsFeatures::EC,
+            DfsFeatures::RBF,
+            DfsFeatures::SHORT_CIRCUIT,
+            DfsFeatures::BLOCK_TOKEN_EXPIRY,
+            DfsFeatures::SECOND_CLUSTER,
+            DfsFeatures::TOKEN_EXPIRY,
+            DfsFeatures::KMS,
+        ] {
+            assert_eq!(DfsFeatures::from(feature.as_str()), Some(feature));
+        }
+        assert_eq!(DfsFeatures::from("unknown"), None);
+    }
+}
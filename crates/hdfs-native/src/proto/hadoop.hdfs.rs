@@ -3700,6 +3700,264 @@ impl OpenFilesTypeProto {
         }
     }
 }
+/// * Asks NN/DN to reload configuration file.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StartReconfigurationRequestProto {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StartReconfigurationResponseProto {}
+/// * Query the running status of reconfiguration process
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetReconfigurationStatusRequestProto {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetReconfigurationStatusConfigChangeProto {
+    #[prost(string, required, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, required, tag = "2")]
+    pub old_value: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "3")]
+    pub new_value: ::core::option::Option<::prost::alloc::string::String>,
+    /// It is empty if success.
+    #[prost(string, optional, tag = "4")]
+    pub error_message: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetReconfigurationStatusResponseProto {
+    #[prost(int64, required, tag = "1")]
+    pub start_time: i64,
+    #[prost(int64, optional, tag = "2")]
+    pub end_time: ::core::option::Option<i64>,
+    #[prost(message, repeated, tag = "3")]
+    pub changes: ::prost::alloc::vec::Vec<GetReconfigurationStatusConfigChangeProto>,
+}
+/// * Query the reconfigurable properties on NN/DN.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListReconfigurablePropertiesRequestProto {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListReconfigurablePropertiesResponseProto {
+    #[prost(string, repeated, tag = "1")]
+    pub name: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// *
+/// block - block for which visible length is requested
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetReplicaVisibleLengthRequestProto {
+    #[prost(message, required, tag = "1")]
+    pub block: ExtendedBlockProto,
+}
+/// *
+/// length - visible length of the block
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetReplicaVisibleLengthResponseProto {
+    #[prost(uint64, required, tag = "1")]
+    pub length: u64,
+}
+/// *
+/// void request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RefreshNamenodesRequestProto {}
+/// *
+/// void response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RefreshNamenodesResponseProto {}
+/// *
+/// blockPool - block pool to be deleted
+/// force - if false, delete the block pool only if it is empty.
+///          if true, delete the block pool even if it has blocks.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteBlockPoolRequestProto {
+    #[prost(string, required, tag = "1")]
+    pub block_pool: ::prost::alloc::string::String,
+    #[prost(bool, required, tag = "2")]
+    pub force: bool,
+}
+/// *
+/// void response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteBlockPoolResponseProto {}
+/// *
+/// Gets the file information where block and its metadata is stored
+/// block - block for which path information is being requested
+/// token - block token
+///
+/// This message is deprecated in favor of file descriptor passing.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetBlockLocalPathInfoRequestProto {
+    #[prost(message, required, tag = "1")]
+    pub block: ExtendedBlockProto,
+    #[prost(message, required, tag = "2")]
+    pub token: super::common::TokenProto,
+}
+/// *
+/// block - block for which file path information is being returned
+/// localPath - file path where the block data is stored
+/// localMetaPath - file path where the block meta data is stored
+///
+/// This message is deprecated in favor of file descriptor passing.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetBlockLocalPathInfoResponseProto {
+    #[prost(message, required, tag = "1")]
+    pub block: ExtendedBlockProto,
+    #[prost(string, required, tag = "2")]
+    pub local_path: ::prost::alloc::string::String,
+    #[prost(string, required, tag = "3")]
+    pub local_meta_path: ::prost::alloc::string::String,
+}
+/// *
+/// forUpgrade - if true, clients are advised to wait for restart and quick
+///               upgrade restart is instrumented. Otherwise, datanode does
+///               the regular shutdown.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ShutdownDatanodeRequestProto {
+    #[prost(bool, required, tag = "1")]
+    pub for_upgrade: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ShutdownDatanodeResponseProto {}
+/// * Tell datanode to evict active clients that are writing
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EvictWritersRequestProto {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EvictWritersResponseProto {}
+/// *
+/// Ping datanode for liveness and quick info
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetDatanodeInfoRequestProto {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetDatanodeInfoResponseProto {
+    #[prost(message, required, tag = "1")]
+    pub local_info: DatanodeLocalInfoProto,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetVolumeReportRequestProto {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetVolumeReportResponseProto {
+    #[prost(message, repeated, tag = "1")]
+    pub volume_info: ::prost::alloc::vec::Vec<DatanodeVolumeInfoProto>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TriggerBlockReportRequestProto {
+    #[prost(bool, required, tag = "1")]
+    pub incremental: bool,
+    #[prost(string, optional, tag = "2")]
+    pub nn_address: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TriggerBlockReportResponseProto {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetBalancerBandwidthRequestProto {}
+/// *
+/// bandwidth - balancer bandwidth value of the datanode.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetBalancerBandwidthResponseProto {
+    #[prost(uint64, required, tag = "1")]
+    pub bandwidth: u64,
+}
+/// *
+/// This message allows a client to submit a disk
+/// balancer plan to a data node.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubmitDiskBalancerPlanRequestProto {
+    /// A hash of the plan like SHA-1
+    #[prost(string, required, tag = "1")]
+    pub plan_id: ::prost::alloc::string::String,
+    /// Plan file data in Json format
+    #[prost(string, required, tag = "2")]
+    pub plan: ::prost::alloc::string::String,
+    /// Plan version number
+    #[prost(uint64, optional, tag = "3")]
+    pub plan_version: ::core::option::Option<u64>,
+    /// Ignore date checks on this plan.
+    #[prost(bool, optional, tag = "4")]
+    pub ignore_date_check: ::core::option::Option<bool>,
+    /// Plan file path
+    #[prost(string, required, tag = "5")]
+    pub plan_file: ::prost::alloc::string::String,
+}
+/// *
+/// Response from the DataNode on Plan Submit request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubmitDiskBalancerPlanResponseProto {}
+/// *
+/// This message describes a request to cancel an
+/// outstanding disk balancer plan
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelPlanRequestProto {
+    #[prost(string, required, tag = "1")]
+    pub plan_id: ::prost::alloc::string::String,
+}
+/// *
+/// This is the response for the cancellation request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelPlanResponseProto {}
+/// *
+/// This message allows a client to query data node to see
+/// if a disk balancer plan is executing and if so what is
+/// the status.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct QueryPlanStatusRequestProto {}
+/// *
+/// This message describes a plan if it is in progress
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct QueryPlanStatusResponseProto {
+    #[prost(uint32, optional, tag = "1")]
+    pub result: ::core::option::Option<u32>,
+    #[prost(string, optional, tag = "2")]
+    pub plan_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "3")]
+    pub current_status: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "4")]
+    pub plan_file: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// *
+/// This message sends a request to data node get a specific setting
+/// that is used by disk balancer.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DiskBalancerSettingRequestProto {
+    #[prost(string, required, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+}
+/// *
+/// Response that describes the value of requested disk balancer setting.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DiskBalancerSettingResponseProto {
+    #[prost(string, required, tag = "1")]
+    pub value: ::prost::alloc::string::String,
+}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DataTransferEncryptorMessageProto {
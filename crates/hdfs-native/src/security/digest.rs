@@ -0,0 +1,616 @@
+//! DIGEST-MD5 SASL client (RFC 2831), used to authenticate DataNode connections with block
+//! tokens or data encryption keys and to wrap data transfer when integrity or privacy
+//! protection is negotiated.
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use log::debug;
+use md5::{Digest, Md5};
+use rc4::{consts::U16, KeyInit, StreamCipher};
+use uuid::Uuid;
+
+use crate::proto::hdfs::DataEncryptionKeyProto;
+use crate::{HdfsError, Result};
+
+use super::sasl::SaslMechanism;
+
+const NONCE_COUNT: &str = "00000001";
+const MAX_RECEIVE_BUFFER: usize = 65536;
+const MAC_LENGTH: usize = 10;
+const MESSAGE_TYPE: [u8; 2] = [0, 1];
+
+const CLIENT_SIGNING_MAGIC: &[u8] =
+    b"Digest session key to client-to-server signing key magic constant";
+const SERVER_SIGNING_MAGIC: &[u8] =
+    b"Digest session key to server-to-client signing key magic constant";
+const CLIENT_SEALING_MAGIC: &[u8] = b"Digest H(A1) to client-to-server sealing key magic constant";
+const SERVER_SEALING_MAGIC: &[u8] = b"Digest H(A1) to server-to-client sealing key magic constant";
+
+/// SASL quality of protection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Qop {
+    /// Authentication only
+    Auth,
+    /// Authentication with integrity protection of wrapped messages
+    AuthInt,
+    /// Authentication with integrity and privacy protection of wrapped messages
+    AuthConf,
+}
+
+impl Qop {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "auth" => Some(Self::Auth),
+            "auth-int" => Some(Self::AuthInt),
+            "auth-conf" => Some(Self::AuthConf),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::AuthInt => "auth-int",
+            Self::AuthConf => "auth-conf",
+        }
+    }
+}
+
+/// RC4 ciphers supported for privacy, strongest first, with the number of bytes of H(A1) used to
+/// derive their keys
+const CIPHERS: [(&str, usize); 3] = [("rc4", 16), ("rc4-56", 7), ("rc4-40", 5)];
+
+enum DigestState {
+    Pending,
+    Responded {
+        hash_a1: [u8; 16],
+        qop: Qop,
+        cipher_key_len: usize,
+        expected_rspauth: String,
+    },
+    Completed(Option<Box<SecurityLayer>>),
+}
+
+pub(crate) struct DigestSaslSession {
+    protocol: String,
+    server_id: String,
+    username: String,
+    password: String,
+    qops: Vec<Qop>,
+    state: DigestState,
+}
+
+impl DigestSaslSession {
+    /// Creates a session that accepts any of `qops`, in order of preference
+    pub(crate) fn new(
+        protocol: &str,
+        server_id: &str,
+        username: &str,
+        password: &str,
+        qops: &[Qop],
+    ) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+            server_id: server_id.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            qops: qops.to_vec(),
+            state: DigestState::Pending,
+        }
+    }
+
+    /// Creates a session authenticating with a block token, the way DataNodes expect
+    pub(crate) fn from_block_token(identifier: &[u8], password: &[u8], qops: &[Qop]) -> Self {
+        Self::new(
+            "hdfs",
+            "0",
+            &general_purpose::STANDARD.encode(identifier),
+            &general_purpose::STANDARD.encode(password),
+            qops,
+        )
+    }
+
+    /// Creates a session authenticating with a data encryption key from the NameNode, which
+    /// always encrypts data transfer like `dfs.encrypt.data.transfer` requires
+    pub(crate) fn from_encryption_key(key: &DataEncryptionKeyProto) -> Self {
+        Self::new(
+            "hdfs",
+            "0",
+            &format!(
+                "{} {} {}",
+                key.key_id,
+                key.block_pool_id,
+                general_purpose::STANDARD.encode(&key.nonce)
+            ),
+            &general_purpose::STANDARD.encode(&key.encryption_key),
+            &[Qop::AuthConf],
+        )
+    }
+
+    /// The quality of protection agreed with the server, once negotiation is complete
+    pub(crate) fn negotiated_qop(&self) -> Option<Qop> {
+        match &self.state {
+            DigestState::Completed(None) => Some(Qop::Auth),
+            DigestState::Completed(Some(layer)) if layer.ciphers.is_some() => Some(Qop::AuthConf),
+            DigestState::Completed(Some(_)) => Some(Qop::AuthInt),
+            _ => None,
+        }
+    }
+
+    fn digest_uri(&self) -> String {
+        format!("{}/{}", self.protocol, self.server_id)
+    }
+
+    fn respond(&mut self, challenge: &[u8], cnonce: &str) -> Result<Vec<u8>> {
+        let directives = parse_directives(challenge)?;
+        debug!("DIGEST-MD5 challenge: {:?}", directives);
+
+        let nonce = directives.get("nonce").ok_or(HdfsError::SASLError(
+            "Challenge is missing a nonce".to_string(),
+        ))?;
+        let realm = directives.get("realm").map(String::as_str).unwrap_or("");
+        let offered_qops: Vec<Qop> = directives
+            .get("qop")
+            .map(String::as_str)
+            .unwrap_or("auth")
+            .split(',')
+            .filter_map(Qop::parse)
+            .collect();
+
+        let qop = *self
+            .qops
+            .iter()
+            .find(|qop| offered_qops.contains(qop))
+            .ok_or(HdfsError::SASLError(format!(
+                "No common quality of protection, server offered {:?} but {:?} are allowed",
+                offered_qops, self.qops
+            )))?;
+
+        let cipher = if qop == Qop::AuthConf {
+            let offered_ciphers: Vec<&str> = directives
+                .get("cipher")
+                .map(|ciphers| ciphers.split(',').map(str::trim).collect())
+                .unwrap_or_default();
+            Some(
+                CIPHERS
+                    .iter()
+                    .find(|(name, _)| offered_ciphers.contains(name))
+                    .ok_or(HdfsError::SASLError(format!(
+                        "No supported cipher, server offered {:?}",
+                        offered_ciphers
+                    )))?,
+            )
+        } else {
+            None
+        };
+
+        let digest_uri = self.digest_uri();
+
+        let mut a1 =
+            Md5::digest(format!("{}:{}:{}", self.username, realm, self.password).as_bytes())
+                .to_vec();
+        a1.extend_from_slice(format!(":{}:{}", nonce, cnonce).as_bytes());
+        let hash_a1: [u8; 16] = Md5::digest(&a1).into();
+
+        let response = response_value(&hash_a1, nonce, cnonce, qop, "AUTHENTICATE", &digest_uri);
+        let expected_rspauth = response_value(&hash_a1, nonce, cnonce, qop, "", &digest_uri);
+
+        let mut message = Vec::new();
+        if directives.contains_key("charset") {
+            message.push("charset=utf-8".to_string());
+        }
+        message.push(format!("username={}", quote(&self.username)));
+        message.push(format!("realm={}", quote(realm)));
+        message.push(format!("nonce={}", quote(nonce)));
+        message.push(format!("nc={}", NONCE_COUNT));
+        message.push(format!("cnonce={}", quote(cnonce)));
+        message.push(format!("digest-uri={}", quote(&digest_uri)));
+        message.push(format!("maxbuf={}", MAX_RECEIVE_BUFFER));
+        message.push(format!("response={}", response));
+        message.push(format!("qop={}", qop.as_str()));
+        if let Some((name, _)) = cipher {
+            message.push(format!("cipher={}", quote(name)));
+        }
+
+        self.state = DigestState::Responded {
+            hash_a1,
+            qop,
+            cipher_key_len: cipher.map(|(_, len)| *len).unwrap_or_default(),
+            expected_rspauth,
+        };
+
+        Ok(message.join(",").into_bytes())
+    }
+
+    fn security_layer(&self) -> Option<&SecurityLayer> {
+        match &self.state {
+            DigestState::Completed(layer) => layer.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn security_layer_mut(&mut self) -> Result<&mut SecurityLayer> {
+        match &mut self.state {
+            DigestState::Completed(Some(layer)) => Ok(layer),
+            _ => Err(HdfsError::SASLError(
+                "No security layer was negotiated".to_string(),
+            )),
+        }
+    }
+}
+
+impl SaslMechanism for DigestSaslSession {
+    fn step(&mut self, token: Option<&[u8]>) -> Result<(Vec<u8>, bool)> {
+        match (&self.state, token) {
+            // DIGEST-MD5 has no initial response
+            (DigestState::Pending, None) => Ok((Vec::new(), false)),
+            (DigestState::Pending, Some(challenge)) => {
+                let cnonce = general_purpose::STANDARD.encode(Uuid::new_v4().as_bytes().as_slice());
+                Ok((self.respond(challenge, &cnonce)?, false))
+            }
+            (
+                DigestState::Responded {
+                    hash_a1,
+                    qop,
+                    cipher_key_len,
+                    expected_rspauth,
+                },
+                Some(token),
+            ) => {
+                let directives = parse_directives(token)?;
+                if directives.get("rspauth") != Some(expected_rspauth) {
+                    return Err(HdfsError::SASLError(
+                        "Server failed to authenticate during DIGEST-MD5 negotiation".to_string(),
+                    ));
+                }
+                let layer = match qop {
+                    Qop::Auth => None,
+                    Qop::AuthInt => Some(SecurityLayer::new(hash_a1, None, true)),
+                    Qop::AuthConf => Some(SecurityLayer::new(hash_a1, Some(*cipher_key_len), true)),
+                }
+                .map(Box::new);
+                self.state = DigestState::Completed(layer);
+                Ok((Vec::new(), true))
+            }
+            _ => Err(HdfsError::SASLError(
+                "Unexpected DIGEST-MD5 negotiation step".to_string(),
+            )),
+        }
+    }
+
+    fn has_security_layer(&self) -> bool {
+        self.security_layer().is_some()
+    }
+
+    fn encode(&mut self, buf: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.security_layer_mut()?.wrap(buf))
+    }
+
+    fn decode(&mut self, buf: &[u8]) -> Result<Vec<u8>> {
+        self.security_layer_mut()?.unwrap(buf)
+    }
+}
+
+/// Integrity and optional privacy protection of messages after negotiation
+struct SecurityLayer {
+    send_integrity_key: [u8; 16],
+    receive_integrity_key: [u8; 16],
+    send_sequence: u32,
+    receive_sequence: u32,
+    // Send and receive ciphers when privacy was negotiated
+    ciphers: Option<(Rc4, Rc4)>,
+}
+
+impl SecurityLayer {
+    fn new(hash_a1: &[u8; 16], cipher_key_len: Option<usize>, client: bool) -> Self {
+        let derive = |base: &[u8], magic: &[u8]| {
+            let mut hasher = Md5::new();
+            hasher.update(base);
+            hasher.update(magic);
+            <[u8; 16]>::from(hasher.finalize())
+        };
+
+        let (send_signing, receive_signing, send_sealing, receive_sealing) = if client {
+            (
+                CLIENT_SIGNING_MAGIC,
+                SERVER_SIGNING_MAGIC,
+                CLIENT_SEALING_MAGIC,
+                SERVER_SEALING_MAGIC,
+            )
+        } else {
+            (
+                SERVER_SIGNING_MAGIC,
+                CLIENT_SIGNING_MAGIC,
+                SERVER_SEALING_MAGIC,
+                CLIENT_SEALING_MAGIC,
+            )
+        };
+
+        let ciphers = cipher_key_len.map(|len| {
+            (
+                Rc4::new(&derive(&hash_a1[..len], send_sealing).into()),
+                Rc4::new(&derive(&hash_a1[..len], receive_sealing).into()),
+            )
+        });
+
+        Self {
+            send_integrity_key: derive(hash_a1, send_signing),
+            receive_integrity_key: derive(hash_a1, receive_signing),
+            send_sequence: 0,
+            receive_sequence: 0,
+            ciphers,
+        }
+    }
+
+    fn mac(key: &[u8], sequence: u32, message: &[u8]) -> [u8; MAC_LENGTH] {
+        let mut hmac =
+            <Hmac<Md5> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        hmac.update(&sequence.to_be_bytes());
+        hmac.update(message);
+        hmac.finalize().into_bytes()[..MAC_LENGTH]
+            .try_into()
+            .unwrap()
+    }
+
+    fn wrap(&mut self, message: &[u8]) -> Vec<u8> {
+        let mac = Self::mac(&self.send_integrity_key, self.send_sequence, message);
+
+        let mut wrapped = message.to_vec();
+        wrapped.extend_from_slice(&mac);
+        if let Some((cipher, _)) = self.ciphers.as_mut() {
+            cipher.apply_keystream(&mut wrapped);
+        }
+        wrapped.extend_from_slice(&MESSAGE_TYPE);
+        wrapped.extend_from_slice(&self.send_sequence.to_be_bytes());
+
+        self.send_sequence = self.send_sequence.wrapping_add(1);
+        wrapped
+    }
+
+    fn unwrap(&mut self, wrapped: &[u8]) -> Result<Vec<u8>> {
+        if wrapped.len() < MAC_LENGTH + 6 {
+            return Err(HdfsError::SASLError(
+                "Wrapped message is too short".to_string(),
+            ));
+        }
+
+        let (body, trailer) = wrapped.split_at(wrapped.len() - 6);
+        let sequence = u32::from_be_bytes(trailer[2..].try_into().unwrap());
+        if trailer[..2] != MESSAGE_TYPE || sequence != self.receive_sequence {
+            return Err(HdfsError::SASLError(
+                "Wrapped message is out of sequence".to_string(),
+            ));
+        }
+
+        let mut body = body.to_vec();
+        if let Some((_, cipher)) = self.ciphers.as_mut() {
+            cipher.apply_keystream(&mut body);
+        }
+
+        let mac = body.split_off(body.len() - MAC_LENGTH);
+        if mac != Self::mac(&self.receive_integrity_key, sequence, &body) {
+            return Err(HdfsError::SASLError(
+                "Wrapped message failed integrity check".to_string(),
+            ));
+        }
+
+        self.receive_sequence = self.receive_sequence.wrapping_add(1);
+        Ok(body)
+    }
+}
+
+/// RC4 keyed with a 16 byte sealing key, keeping its state between messages as DIGEST-MD5
+/// requires
+type Rc4 = rc4::Rc4<U16>;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Computes the `response` value when `method` is `AUTHENTICATE`, or the expected `rspauth`
+/// when it is empty
+fn response_value(
+    hash_a1: &[u8; 16],
+    nonce: &str,
+    cnonce: &str,
+    qop: Qop,
+    method: &str,
+    digest_uri: &str,
+) -> String {
+    let mut a2 = format!("{}:{}", method, digest_uri);
+    if qop != Qop::Auth {
+        a2.push_str(":00000000000000000000000000000000");
+    }
+    let kd = format!(
+        "{}:{}:{}:{}:{}:{}",
+        hex(hash_a1),
+        nonce,
+        NONCE_COUNT,
+        cnonce,
+        qop.as_str(),
+        hex(&Md5::digest(a2.as_bytes()))
+    );
+    hex(&Md5::digest(kd.as_bytes()))
+}
+
+/// Parses a comma separated list of `key=value` directives, where values may be quoted. Only
+/// the first value of repeated keys is kept.
+fn parse_directives(message: &[u8]) -> Result<HashMap<String, String>> {
+    let message = std::str::from_utf8(message)
+        .map_err(|_| HdfsError::SASLError("DIGEST-MD5 message is not UTF-8".to_string()))?;
+
+    let mut directives = HashMap::new();
+    let mut chars = message.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=')).collect();
+        if chars.next() != Some('=') {
+            return Err(HdfsError::SASLError(format!(
+                "Invalid DIGEST-MD5 directive {}",
+                key.trim()
+            )));
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let value = if chars.next_if_eq(&'"').is_some() {
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('\\') => value.extend(chars.next()),
+                    Some('"') => break,
+                    Some(c) => value.push(c),
+                    None => {
+                        return Err(HdfsError::SASLError(
+                            "Unterminated quoted DIGEST-MD5 value".to_string(),
+                        ))
+                    }
+                }
+            }
+            value
+        } else {
+            std::iter::from_fn(|| chars.next_if(|c| *c != ','))
+                .collect::<String>()
+                .trim()
+                .to_string()
+        };
+
+        directives.entry(key.trim().to_lowercase()).or_insert(value);
+    }
+    Ok(directives)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::proto::hdfs::DataEncryptionKeyProto;
+    use crate::security::sasl::SaslMechanism;
+
+    use super::{parse_directives, DigestSaslSession, Qop, SecurityLayer};
+
+    #[test]
+    fn test_parse_directives() {
+        let directives = parse_directives(
+            br#"realm="0",nonce="a\"b",qop="auth,auth-int", charset=utf-8,algorithm=md5-sess"#,
+        )
+        .unwrap();
+        assert_eq!(directives["realm"], "0");
+        assert_eq!(directives["nonce"], "a\"b");
+        assert_eq!(directives["qop"], "auth,auth-int");
+        assert_eq!(directives["charset"], "utf-8");
+        assert_eq!(directives["algorithm"], "md5-sess");
+    }
+
+    #[test]
+    fn test_digest_response() {
+        // Example from RFC 2831
+        let mut session = DigestSaslSession::new(
+            "imap",
+            "elwood.innosoft.com",
+            "chris",
+            "secret",
+            &[Qop::Auth],
+        );
+        let response = session
+            .respond(
+                br#"realm="elwood.innosoft.com",nonce="OA6MG9tEQGm2hh",qop="auth",algorithm=md5-sess,charset=utf-8"#,
+                "OA6MHXh6VqTrRk",
+            )
+            .unwrap();
+        let directives = parse_directives(&response).unwrap();
+        assert_eq!(directives["response"], "d388dad90d4bbd760a152321f2143af7");
+        assert_eq!(directives["digest-uri"], "imap/elwood.innosoft.com");
+        assert_eq!(directives["qop"], "auth");
+
+        assert!(session
+            .step(Some(b"rspauth=00000000000000000000000000000000"))
+            .is_err());
+
+        let (_, finished) = session
+            .step(Some(b"rspauth=ea40f60335c427b5527b84dbabcdfffd"))
+            .unwrap();
+        assert!(finished);
+        assert!(!session.has_security_layer());
+        assert_eq!(session.negotiated_qop(), Some(Qop::Auth));
+    }
+
+    #[test]
+    fn test_qop_negotiation() {
+        let challenge = br#"realm="0",nonce="abc",qop="auth,auth-int,auth-conf",charset=utf-8,cipher="rc4,rc4-40,des""#;
+
+        let mut session =
+            DigestSaslSession::from_block_token(b"id", b"password", &[Qop::AuthConf, Qop::Auth]);
+        let directives = parse_directives(&session.respond(challenge, "xyz").unwrap()).unwrap();
+        assert_eq!(directives["qop"], "auth-conf");
+        assert_eq!(directives["cipher"], "rc4");
+        assert_eq!(directives["digest-uri"], "hdfs/0");
+        assert_eq!(directives["username"], "aWQ=");
+
+        let mut session = DigestSaslSession::from_block_token(b"id", b"password", &[Qop::AuthConf]);
+        assert!(session
+            .respond(br#"realm="0",nonce="abc",qop="auth""#, "xyz")
+            .is_err());
+    }
+
+    #[test]
+    fn test_encryption_key_session() {
+        let key = DataEncryptionKeyProto {
+            key_id: 12,
+            block_pool_id: "BP-1".to_string(),
+            nonce: b"nonce".to_vec(),
+            encryption_key: b"key".to_vec(),
+            ..Default::default()
+        };
+        let mut session = DigestSaslSession::from_encryption_key(&key);
+        let challenge = br#"realm="0",nonce="abc",qop="auth-conf",charset=utf-8,cipher="3des,rc4""#;
+        let directives = parse_directives(&session.respond(challenge, "xyz").unwrap()).unwrap();
+        assert_eq!(directives["username"], "12 BP-1 bm9uY2U=");
+        assert_eq!(directives["qop"], "auth-conf");
+        assert_eq!(directives["cipher"], "rc4");
+
+        // Data must be encrypted, so weaker protection isn't accepted
+        let mut session = DigestSaslSession::from_encryption_key(&key);
+        assert!(session
+            .respond(br#"realm="0",nonce="abc",qop="auth,auth-int""#, "xyz")
+            .is_err());
+    }
+
+    #[test]
+    fn test_security_layer() {
+        let hash_a1 = [7u8; 16];
+        for cipher_key_len in [None, Some(16), Some(5)] {
+            let mut client = SecurityLayer::new(&hash_a1, cipher_key_len, true);
+            let mut server = SecurityLayer::new(&hash_a1, cipher_key_len, false);
+
+            for i in 0..3 {
+                let message = format!("message {}", i).into_bytes();
+                let wrapped = client.wrap(&message);
+                assert_eq!(wrapped.len(), message.len() + 16);
+                assert_eq!(
+                    wrapped[..message.len()] == message[..],
+                    cipher_key_len.is_none()
+                );
+                assert_eq!(server.unwrap(&wrapped).unwrap(), message);
+
+                let reply = server.wrap(&message);
+                assert_eq!(client.unwrap(&reply).unwrap(), message);
+            }
+
+            // Replayed and corrupted messages are rejected
+            let wrapped = client.wrap(b"data");
+            assert!(server.unwrap(&wrapped).is_ok());
+            assert!(server.unwrap(&wrapped).is_err());
+
+            let mut corrupted = client.wrap(b"data");
+            corrupted[0] ^= 1;
+            assert!(server.unwrap(&corrupted).is_err());
+        }
+    }
+}
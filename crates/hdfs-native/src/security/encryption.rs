@@ -0,0 +1,471 @@
+//! Transparent encryption of files in encryption zones. The key of each file is stored on the
+//! NameNode encrypted with the key of its zone, and is decrypted by the KMS from
+//! `hadoop.security.key.provider.path` or `dfs.encryption.key.provider.uri`.
+use std::sync::Arc;
+
+use aes::cipher::{InnerIvInit, KeyInit, StreamCipher, StreamCipherSeek};
+use aes::{Aes128, Aes192, Aes256};
+use base64::alphabet;
+use base64::engine::general_purpose::{self, GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine as _;
+use bytes::Bytes;
+use ctr::{Ctr128BE, CtrCore};
+use log::{debug, warn};
+use url::Url;
+
+use crate::common::config::Configuration;
+use crate::proto::hdfs;
+use crate::{HdfsError, Result};
+
+use super::http;
+use super::user::User;
+
+// Keys are returned URL safe encoded by the KMS, with or without padding
+const KEY_MATERIAL_ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Client for the REST API of a Hadoop KMS, which is only used to decrypt the keys of files
+#[derive(Debug)]
+pub(crate) struct KmsClient {
+    // The base URL of each KMS instance, tried in order until one responds
+    urls: Vec<Url>,
+    client: reqwest::Client,
+    user: Arc<User>,
+    kerberos: bool,
+}
+
+impl KmsClient {
+    /// Creates a client for the KMS instances of a key provider URI such as
+    /// `kms://https@kms1;kms2:9600/kms`
+    pub(crate) fn new(uri: &str, config: &Configuration, user: Arc<User>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.get_kms_client_timeout())
+            .build()
+            .map_err(|e| HdfsError::KmsError(e.to_string()))?;
+        Ok(Self {
+            urls: parse_key_provider_uri(uri)?,
+            client,
+            user,
+            kerberos: config.get_kerberos_enabled(),
+        })
+    }
+
+    /// Decrypts the key of a file with the key of its encryption zone
+    pub(crate) async fn decrypt_key(
+        &self,
+        encryption_info: &hdfs::FileEncryptionInfoProto,
+    ) -> Result<Vec<u8>> {
+        let body = serde_json::json!({
+            "name": encryption_info.key_name,
+            "iv": general_purpose::STANDARD.encode(&encryption_info.iv),
+            "material": general_purpose::STANDARD.encode(&encryption_info.key),
+        });
+
+        let mut last_error = None;
+        for base_url in self.urls.iter() {
+            let mut url = base_url.clone();
+            url.path_segments_mut()
+                .map_err(|_| HdfsError::InvalidArgument(format!("Invalid KMS URL {}", base_url)))?
+                .pop_if_empty()
+                .extend([
+                    "v1",
+                    "keyversion",
+                    &encryption_info.ez_key_version_name,
+                    "_eek",
+                ]);
+            url.query_pairs_mut().append_pair("eek_op", "decrypt");
+
+            let authorization = http::authenticate(&mut url, &self.user, self.kerberos)?;
+            debug!("KMS decrypt request: {}", url);
+            let mut request = self.client.post(url);
+            if let Some(authorization) = authorization {
+                request = request.header(reqwest::header::AUTHORIZATION, authorization);
+            }
+            let response = match request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string())
+                .send()
+                .await
+            {
+                Ok(response) => response,
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    warn!("Failed to connect to KMS {}: {:?}", base_url, e);
+                    last_error = Some(e);
+                    continue;
+                }
+                Err(e) => return Err(HdfsError::KmsError(e.to_string())),
+            };
+
+            let status = response.status();
+            let text = response
+                .text()
+                .await
+                .map_err(|e| HdfsError::KmsError(e.to_string()))?;
+            if !status.is_success() {
+                return Err(HdfsError::KmsError(remote_exception_message(status, &text)));
+            }
+            return decode_key_material(&text);
+        }
+
+        Err(HdfsError::KmsError(match last_error {
+            Some(e) => e.to_string(),
+            None => "No KMS URLs".to_string(),
+        }))
+    }
+}
+
+/// Builds the base URL of each KMS instance of a `kms://<http|https>@<hosts>[:port]/<path>`
+/// URI, where the hosts are separated by `;`
+fn parse_key_provider_uri(uri: &str) -> Result<Vec<Url>> {
+    let invalid = || HdfsError::InvalidArgument(format!("Invalid key provider URI {}", uri));
+
+    let rest = uri
+        .strip_prefix("kms://")
+        .ok_or_else(|| HdfsError::UnsupportedFeature(format!("Key provider {}", uri)))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let (scheme, hosts) = authority.split_once('@').ok_or_else(invalid)?;
+    if scheme != "http" && scheme != "https" {
+        return Err(invalid());
+    }
+    let (hosts, port) = match hosts.rsplit_once(':') {
+        Some((hosts, port)) => (hosts, Some(port)),
+        None => (hosts, None),
+    };
+
+    hosts
+        .split(';')
+        .map(|host| {
+            let url = match port {
+                Some(port) => format!("{}://{}:{}{}", scheme, host, port, path),
+                None => format!("{}://{}{}", scheme, host, path),
+            };
+            Url::parse(&url).map_err(|_| invalid())
+        })
+        .collect()
+}
+
+/// The message of the `RemoteException` the KMS responds to a failed request with
+fn remote_exception_message(status: reqwest::StatusCode, text: &str) -> String {
+    let exception = serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|json| json.get("RemoteException").cloned());
+    let field = |name: &str| {
+        exception
+            .as_ref()
+            .and_then(|e| e.get(name))
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    };
+    match (field("exception"), field("message")) {
+        (Some(exception), Some(message)) => format!("{}: {}", exception, message),
+        (None, Some(message)) => message,
+        _ => format!("{}: {}", status, text),
+    }
+}
+
+fn decode_key_material(text: &str) -> Result<Vec<u8>> {
+    let json: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| HdfsError::KmsError(format!("Invalid KMS response: {}", e)))?;
+    let material = json
+        .get("material")
+        .and_then(|material| material.as_str())
+        .ok_or(HdfsError::KmsError(
+            "KMS response is missing the key material".to_string(),
+        ))?;
+    // Accept the standard alphabet as well
+    KEY_MATERIAL_ENGINE
+        .decode(material.replace('+', "-").replace('/', "_"))
+        .map_err(|e| HdfsError::KmsError(format!("Invalid key material: {}", e)))
+}
+
+/// Encrypts and decrypts the data of a file with AES-CTR, using its decrypted key and the IV
+/// from its [hdfs::FileEncryptionInfoProto]
+pub(crate) struct FileCipher {
+    key: AesKey,
+    iv: [u8; 16],
+}
+
+enum AesKey {
+    Aes128(Aes128),
+    Aes192(Aes192),
+    Aes256(Aes256),
+}
+
+impl FileCipher {
+    /// Checks the file uses a cipher suite and protocol version that are supported, and decrypts
+    /// its key with the KMS
+    pub(crate) async fn new(
+        encryption_info: &hdfs::FileEncryptionInfoProto,
+        kms: &KmsClient,
+    ) -> Result<Self> {
+        if encryption_info.suite() != hdfs::CipherSuiteProto::AesCtrNopadding {
+            return Err(HdfsError::UnsupportedFeature(format!(
+                "Cipher suite {}",
+                encryption_info.suite().as_str_name()
+            )));
+        }
+        if encryption_info.crypto_protocol_version()
+            != hdfs::CryptoProtocolVersionProto::EncryptionZones
+        {
+            return Err(HdfsError::UnsupportedFeature(format!(
+                "Crypto protocol version {}",
+                encryption_info.crypto_protocol_version().as_str_name()
+            )));
+        }
+
+        let key = kms.decrypt_key(encryption_info).await?;
+        Self::with_key(&key, &encryption_info.iv)
+    }
+
+    fn with_key(key: &[u8], iv: &[u8]) -> Result<Self> {
+        let invalid_key =
+            || HdfsError::InvalidArgument(format!("Invalid AES key length {}", key.len()));
+        let key = match key.len() {
+            16 => AesKey::Aes128(Aes128::new_from_slice(key).map_err(|_| invalid_key())?),
+            24 => AesKey::Aes192(Aes192::new_from_slice(key).map_err(|_| invalid_key())?),
+            32 => AesKey::Aes256(Aes256::new_from_slice(key).map_err(|_| invalid_key())?),
+            _ => return Err(invalid_key()),
+        };
+        Ok(Self {
+            key,
+            iv: iv.try_into().map_err(|_| {
+                HdfsError::InvalidArgument(format!("Invalid IV length {}", iv.len()))
+            })?,
+        })
+    }
+
+    /// Encrypts or decrypts `data`, which is at `position` in the file
+    pub(crate) fn apply(&self, position: u64, data: &[u8]) -> Bytes {
+        let mut buf = data.to_vec();
+        let iv = &self.iv.into();
+        match &self.key {
+            AesKey::Aes128(aes) => apply_ctr(
+                Ctr128BE::from_core(CtrCore::inner_iv_init(aes.clone(), iv)),
+                position,
+                &mut buf,
+            ),
+            AesKey::Aes192(aes) => apply_ctr(
+                Ctr128BE::from_core(CtrCore::inner_iv_init(aes.clone(), iv)),
+                position,
+                &mut buf,
+            ),
+            AesKey::Aes256(aes) => apply_ctr(
+                Ctr128BE::from_core(CtrCore::inner_iv_init(aes.clone(), iv)),
+                position,
+                &mut buf,
+            ),
+        }
+        Bytes::from(buf)
+    }
+}
+
+/// Applies the key stream from `position` on, where the counter is the IV plus the number of
+/// the block as a 128 bit big endian integer like the Java client
+fn apply_ctr(mut cipher: impl StreamCipher + StreamCipherSeek, position: u64, buf: &mut [u8]) {
+    cipher.seek(position);
+    cipher.apply_keystream(buf);
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use base64::{engine::general_purpose, Engine as _};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::{parse_key_provider_uri, FileCipher, KmsClient};
+    use crate::common::config::Configuration;
+    use crate::proto::hdfs;
+    use crate::security::user::User;
+    use crate::HdfsError;
+
+    #[test]
+    fn test_parse_key_provider_uri() {
+        let urls: Vec<String> = parse_key_provider_uri("kms://https@kms1;kms2:9600/kms")
+            .unwrap()
+            .iter()
+            .map(|url| url.to_string())
+            .collect();
+        assert_eq!(urls, vec!["https://kms1:9600/kms", "https://kms2:9600/kms"]);
+
+        let urls = parse_key_provider_uri("kms://http@localhost").unwrap();
+        assert_eq!(urls[0].as_str(), "http://localhost/");
+
+        assert!(parse_key_provider_uri("kms://localhost:9600/kms").is_err());
+        assert!(parse_key_provider_uri("kms://ftp@localhost:9600/kms").is_err());
+        assert!(matches!(
+            parse_key_provider_uri("jceks://file/keys.jceks"),
+            Err(HdfsError::UnsupportedFeature(_))
+        ));
+    }
+
+    /// Accepts one connection and responds to its request, returning the request
+    async fn respond(listener: &TcpListener, status: &str, body: &str) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..len]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((headers, content)) = text.split_once("\r\n\r\n") {
+                let content_length = headers
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|len| len.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if content.len() >= content_length {
+                    break;
+                }
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_kms_decrypt_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!(
+            "kms://http@127.0.0.1:{}/kms",
+            listener.local_addr().unwrap().port()
+        );
+        let kms = KmsClient::new(
+            &uri,
+            &Configuration::from(HashMap::new()),
+            Arc::new(User::new(Some("alice".to_string()), None, Vec::new())),
+        )
+        .unwrap();
+
+        let encryption_info = hdfs::FileEncryptionInfoProto {
+            suite: hdfs::CipherSuiteProto::AesCtrNopadding as i32,
+            crypto_protocol_version: hdfs::CryptoProtocolVersionProto::EncryptionZones as i32,
+            key: vec![0xfb; 16],
+            iv: vec![0xfa; 16],
+            key_name: "key".to_string(),
+            ez_key_version_name: "key@0".to_string(),
+        };
+
+        let key = [0xff; 16];
+        let response = format!(
+            r#"{{"name":"key","versionName":"EEK","material":"{}"}}"#,
+            general_purpose::URL_SAFE_NO_PAD.encode(key)
+        );
+        let (request, cipher) = tokio::join!(
+            respond(&listener, "200 OK", &response),
+            FileCipher::new(&encryption_info, &kms)
+        );
+        assert!(request.starts_with(
+            "POST /kms/v1/keyversion/key@0/_eek?eek_op=decrypt&user.name=alice HTTP/1.1\r\n"
+        ));
+        let (_, body) = request.split_once("\r\n\r\n").unwrap();
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["name"], "key");
+        assert_eq!(body["iv"], general_purpose::STANDARD.encode([0xfa; 16]));
+        assert_eq!(
+            body["material"],
+            general_purpose::STANDARD.encode([0xfb; 16])
+        );
+
+        // Encrypting is the same as decrypting, and the data depends on its position
+        let cipher = cipher.unwrap();
+        let encrypted = cipher.apply(5, b"hello world");
+        assert_ne!(&encrypted[..], b"hello world");
+        assert_eq!(&cipher.apply(5, &encrypted)[..], b"hello world");
+        assert_eq!(cipher.apply(0, b"xxxxxhello world")[5..], encrypted[..]);
+
+        let error = r#"{"RemoteException":{"message":"User alice is not authorized","exception":"AuthorizationException"}}"#;
+        let (_, result) = tokio::join!(
+            respond(&listener, "403 Forbidden", error),
+            kms.decrypt_key(&encryption_info)
+        );
+        assert!(matches!(
+            result,
+            Err(HdfsError::KmsError(message))
+                if message == "AuthorizationException: User alice is not authorized"
+        ));
+    }
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_file_cipher_ctr() {
+        // Test vector from NIST SP 800-38A F.5.1
+        let cipher = FileCipher::with_key(
+            &unhex("2b7e151628aed2a6abf7158809cf4f3c"),
+            &unhex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"),
+        )
+        .unwrap();
+        let plaintext = unhex(concat!(
+            "6bc1bee22e409f96e93d7e117393172a",
+            "ae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52ef",
+            "f69f2445df4f9b17ad2b417be66c3710"
+        ));
+        let ciphertext = unhex(concat!(
+            "874d6191b620e3261bef6864990db6ce",
+            "9806f66b7970fdff8617187bb9fffdff",
+            "5ae4df3edbd5d35e5b4f09020db03eab",
+            "1e031dda2fbe03d1792170a0f3009cee"
+        ));
+        assert_eq!(cipher.apply(0, &plaintext)[..], ciphertext[..]);
+        assert_eq!(cipher.apply(0, &ciphertext)[..], plaintext[..]);
+
+        // Any range can be decrypted on its own, including ones that don't start at a block
+        for (start, end) in [(5, 37), (16, 32), (31, 64), (63, 64)] {
+            assert_eq!(
+                cipher.apply(start as u64, &ciphertext[start..end])[..],
+                plaintext[start..end]
+            );
+        }
+
+        // The counter carries over the whole IV
+        let cipher = FileCipher::with_key(&[0u8; 16], &[0xff; 16]).unwrap();
+        let zero_iv = FileCipher::with_key(&[0u8; 16], &[0; 16]).unwrap();
+        assert_eq!(cipher.apply(16, &[0; 16]), zero_iv.apply(0, &[0; 16]));
+
+        // AES-256 keys are supported, and other lengths aren't
+        assert!(FileCipher::with_key(&[0u8; 32], &[0; 16]).is_ok());
+        assert!(FileCipher::with_key(&[0u8; 20], &[0; 16]).is_err());
+        assert!(FileCipher::with_key(&[0u8; 16], &[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_file_cipher_unsupported() {
+        let encryption_info = hdfs::FileEncryptionInfoProto {
+            suite: hdfs::CipherSuiteProto::Sm4CtrNopadding as i32,
+            crypto_protocol_version: hdfs::CryptoProtocolVersionProto::EncryptionZones as i32,
+            ..Default::default()
+        };
+        let kms = KmsClient::new(
+            "kms://http@localhost:9600/kms",
+            &Configuration::from(HashMap::new()),
+            Arc::new(User::new(None, None, Vec::new())),
+        )
+        .unwrap();
+        let result = futures::executor::block_on(FileCipher::new(&encryption_info, &kms));
+        assert!(matches!(result, Err(HdfsError::UnsupportedFeature(_))));
+    }
+}
@@ -1,4 +1,8 @@
 use core::fmt;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::time::Duration;
+
 use libgssapi::context::{ClientCtx, CtxFlags, SecurityContext};
 use libgssapi::credential::{Cred, CredUsage};
 use libgssapi::name::Name;
@@ -6,7 +10,7 @@ use libgssapi::oid::{OidSet, GSS_MECH_KRB5, GSS_NT_HOSTBASED_SERVICE};
 
 use crate::{HdfsError, Result};
 
-use super::sasl::SaslSession;
+use super::sasl::SaslMechanism;
 use super::user::User;
 
 #[repr(u8)]
@@ -40,16 +44,64 @@ impl fmt::Debug for GssapiState {
     }
 }
 
+extern "C" {
+    // Part of the krb5 mechanism extensions that MIT, Heimdal and Apple's GSSAPI libraries all
+    // export, but libgssapi doesn't wrap
+    fn gss_krb5_ccache_name(
+        minor_status: *mut u32,
+        name: *const c_char,
+        out_name: *mut *const c_char,
+    ) -> u32;
+}
+
+/// Acquires the Kerberos credentials in the ticket cache `ccache`, or the default ticket cache
+/// if it is `None`. The cache is only switched for the current thread while the credentials
+/// are acquired, and they keep using it afterwards, so clients with their own caches don't
+/// affect each other.
+fn acquire_cred(ccache: Option<&str>) -> Result<Cred> {
+    let mut krb5 = OidSet::new()?;
+    krb5.add(&GSS_MECH_KRB5)?;
+
+    let Some(ccache) = ccache else {
+        return Ok(Cred::acquire(None, None, CredUsage::Initiate, Some(&krb5))?);
+    };
+    let name = CString::new(ccache).map_err(|_| {
+        HdfsError::InvalidArgument(format!("Invalid Kerberos ticket cache {}", ccache))
+    })?;
+    let mut minor = 0;
+    let mut previous: *const c_char = ptr::null();
+    // SAFETY: `name` outlives the call, and the previous name is copied before the next call
+    // invalidates it
+    let previous = unsafe {
+        if gss_krb5_ccache_name(&mut minor, name.as_ptr(), &mut previous) != 0 {
+            return Err(HdfsError::OperationFailed(format!(
+                "Failed to use Kerberos ticket cache {}",
+                ccache
+            )));
+        }
+        (!previous.is_null()).then(|| CStr::from_ptr(previous).to_owned())
+    };
+    let cred = Cred::acquire(None, None, CredUsage::Initiate, Some(&krb5));
+    // SAFETY: `previous` outlives the call
+    unsafe {
+        gss_krb5_ccache_name(
+            &mut minor,
+            previous.as_ref().map_or(ptr::null(), |name| name.as_ptr()),
+            ptr::null_mut(),
+        );
+    }
+    Ok(cred?)
+}
+
 impl GssapiSession {
-    pub(crate) fn new(service: &str, hostname: &str) -> Result<Self> {
+    /// Starts authenticating to `service` on `hostname` with the credentials in `ccache`, or
+    /// the default ticket cache
+    pub(crate) fn new(service: &str, hostname: &str, ccache: Option<&str>) -> Result<Self> {
         let targ_name = format!("{}@{}", service, hostname);
 
         let target = Name::new(targ_name.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))?;
 
-        let mut krb5 = OidSet::new()?;
-        krb5.add(&GSS_MECH_KRB5)?;
-
-        let cred = Cred::acquire(None, None, CredUsage::Initiate, Some(&krb5))?;
+        let cred = acquire_cred(ccache)?;
         let principal = cred.name()?.to_string();
 
         let state = GssapiState::Pending(ClientCtx::new(
@@ -64,7 +116,36 @@ impl GssapiSession {
     }
 }
 
-impl SaslSession for GssapiSession {
+/// Returns how much longer the Kerberos credentials in `ccache`, or the default ticket cache,
+/// are valid for
+pub(crate) fn get_credential_lifetime(ccache: Option<&str>) -> Result<Duration> {
+    Ok(acquire_cred(ccache)?.lifetime()?)
+}
+
+/// Returns the principal of the Kerberos credentials in `ccache`, or the default ticket cache
+pub(crate) fn get_default_principal(ccache: Option<&str>) -> Result<String> {
+    Ok(acquire_cred(ccache)?.name()?.to_string())
+}
+
+/// Creates the token that authenticates with SPNEGO to the `HTTP` service on `hostname`, such
+/// as a KMS or WebHDFS. Like Hadoop's `KerberosAuthenticator`, a single Kerberos token is sent in the
+/// `Authorization: Negotiate` header, and the reply from the server isn't verified.
+#[cfg(any(feature = "encryption", feature = "webhdfs"))]
+pub(crate) fn get_spnego_token(hostname: &str, ccache: Option<&str>) -> Result<Vec<u8>> {
+    let target = Name::new(
+        format!("HTTP@{}", hostname).as_bytes(),
+        Some(&GSS_NT_HOSTBASED_SERVICE),
+    )?;
+
+    let cred = acquire_cred(ccache)?;
+    let mut ctx = ClientCtx::new(cred, target, CtxFlags::all(), Some(&GSS_MECH_KRB5));
+    let token = ctx.step(None, None)?.ok_or(HdfsError::OperationFailed(
+        "No SPNEGO token was created".to_string(),
+    ))?;
+    Ok(token.to_vec())
+}
+
+impl SaslMechanism for GssapiSession {
     fn step(&mut self, token: Option<&[u8]>) -> crate::Result<(Vec<u8>, bool)> {
         match core::mem::replace(&mut self.state, GssapiState::Errored) {
             GssapiState::Pending(mut ctx) => {
@@ -149,7 +230,7 @@ impl SaslSession for GssapiSession {
         }
     }
 
-    fn get_user_info(&self) -> crate::Result<super::user::UserInfo> {
-        Ok(User::get_user_info_from_principal(&self.principal))
+    fn authenticated_user(&self) -> Option<String> {
+        Some(User::get_user_from_principal(&self.principal))
     }
 }
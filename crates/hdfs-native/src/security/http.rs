@@ -0,0 +1,45 @@
+//! Authentication of requests to Hadoop's HTTP services, such as the KMS and WebHDFS
+#[cfg(feature = "kerberos")]
+use base64::{engine::general_purpose, Engine as _};
+use url::Url;
+
+use crate::Result;
+
+use super::user::User;
+
+/// Adds the user to a request to `url`. With Kerberos the request is authenticated with SPNEGO,
+/// and otherwise the user is passed with `user.name` like Hadoop's pseudo authentication.
+/// Proxy users are impersonated with `doAs`. Returns the `Authorization` header to send.
+pub(crate) fn authenticate(url: &mut Url, user: &User, kerberos: bool) -> Result<Option<String>> {
+    #[cfg(feature = "kerberos")]
+    if kerberos {
+        let host = url.host_str().unwrap_or_default().to_string();
+        let token = super::gssapi::get_spnego_token(&host, user.kerberos_ccache())?;
+        let principal = super::gssapi::get_default_principal(user.kerberos_ccache())?;
+        let user_info = user.get_proxied_user_info(User::get_user_from_principal(&principal));
+        if let Some(proxy_user) = user_info.effective_user {
+            url.query_pairs_mut().append_pair("doAs", &proxy_user);
+        }
+        return Ok(Some(format!(
+            "Negotiate {}",
+            general_purpose::STANDARD.encode(token)
+        )));
+    }
+    #[cfg(not(feature = "kerberos"))]
+    let _ = kerberos;
+
+    let user_info = user.get_simpler_user()?;
+    match (user_info.real_user, user_info.effective_user) {
+        (Some(real_user), Some(proxy_user)) => {
+            url.query_pairs_mut()
+                .append_pair("user.name", &real_user)
+                .append_pair("doAs", &proxy_user);
+        }
+        (real_user, effective_user) => {
+            if let Some(user) = real_user.or(effective_user) {
+                url.query_pairs_mut().append_pair("user.name", &user);
+            }
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,162 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, warn};
+use uuid::Uuid;
+
+use crate::common::config::Configuration;
+use crate::{HdfsError, Result};
+
+use super::gssapi::get_credential_lifetime;
+
+// Same as the Java client, renew after 80% of the remaining ticket lifetime has passed
+const TICKET_RENEW_WINDOW: f64 = 0.8;
+// Minimum time between renewal attempts, so failures don't turn into a busy loop
+const MIN_RENEW_INTERVAL: Duration = Duration::from_secs(60);
+
+// The renewer of the default ticket cache, which is shared by the clients that don't log in
+// with a keytab
+static DEFAULT_RENEWER: Mutex<Weak<KerberosRenewer>> = Mutex::new(Weak::new());
+
+struct Kinit {
+    command: String,
+    keytab: Option<(String, String)>,
+    // The path of the ticket cache the keytab logs in to
+    ccache_path: Option<String>,
+}
+
+impl Kinit {
+    /// Gets a new ticket from the keytab into the client's own ticket cache if there is one,
+    /// otherwise renews the existing ticket in the default ticket cache.
+    fn run(&self) -> Result<()> {
+        let mut command = Command::new(&self.command);
+        match (self.keytab.as_ref(), self.ccache_path.as_ref()) {
+            (Some((keytab, principal)), Some(ccache_path)) => {
+                command.args(["-c", ccache_path, "-kt", keytab, principal]);
+            }
+            _ => {
+                command.arg("-R");
+            }
+        }
+
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(HdfsError::OperationFailed(format!(
+                "{} failed: {}",
+                self.command,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        debug!("Refreshed Kerberos ticket using {}", self.command);
+        Ok(())
+    }
+
+    fn ccache(&self) -> Option<String> {
+        self.ccache_path
+            .as_ref()
+            .map(|path| format!("FILE:{}", path))
+    }
+
+    fn next_renewal(&self) -> Duration {
+        match get_credential_lifetime(self.ccache().as_deref()) {
+            Ok(lifetime) => lifetime
+                .mul_f64(TICKET_RENEW_WINDOW)
+                .max(MIN_RENEW_INTERVAL),
+            Err(e) => {
+                warn!("Failed to get Kerberos ticket lifetime: {:?}", e);
+                MIN_RENEW_INTERVAL
+            }
+        }
+    }
+}
+
+/// Keeps the TGT in a Kerberos ticket cache from expiring for long running clients. A thread
+/// wakes up before the ticket expires and either logs in again with the configured keytab, or
+/// renews the current ticket with `kinit -R`.
+///
+/// A client that logs in with a keytab gets its own ticket cache, so clients in the same
+/// process can authenticate as different principals. The other clients share one renewer of
+/// the default ticket cache. Dropping the last reference to a renewer stops its thread without
+/// waiting for it, and the thread then removes the client's ticket cache.
+#[derive(Debug)]
+pub(crate) struct KerberosRenewer {
+    ccache: Option<String>,
+    // Dropping this wakes up the renewal thread so it exits
+    _stop: Sender<()>,
+}
+
+impl KerberosRenewer {
+    /// Starts renewing credentials if Kerberos authentication is enabled or a keytab is
+    /// configured. When using a keytab, the initial login happens before this returns.
+    pub(crate) fn start(config: &Configuration) -> Result<Option<Arc<Self>>> {
+        let keytab = config.get_kerberos_keytab();
+        if keytab.is_none() && !config.get_kerberos_enabled() {
+            return Ok(None);
+        }
+
+        let kinit = match keytab {
+            Some(keytab) => {
+                let ccache_path = env::temp_dir()
+                    .join(format!("krb5cc_hdfs_native_{}", Uuid::new_v4()))
+                    .to_string_lossy()
+                    .into_owned();
+                let kinit = Kinit {
+                    command: config.get_kinit_command(),
+                    keytab: Some(keytab),
+                    ccache_path: Some(ccache_path),
+                };
+                kinit.run()?;
+                kinit
+            }
+            None => {
+                let mut default_renewer = DEFAULT_RENEWER.lock().unwrap();
+                if let Some(renewer) = default_renewer.upgrade() {
+                    return Ok(Some(renewer));
+                }
+                let renewer = Arc::new(Self::spawn(Kinit {
+                    command: config.get_kinit_command(),
+                    keytab: None,
+                    ccache_path: None,
+                })?);
+                *default_renewer = Arc::downgrade(&renewer);
+                return Ok(Some(renewer));
+            }
+        };
+        Ok(Some(Arc::new(Self::spawn(kinit)?)))
+    }
+
+    fn spawn(kinit: Kinit) -> Result<Self> {
+        let ccache = kinit.ccache();
+        let (stop, stopped) = mpsc::channel::<()>();
+        thread::Builder::new()
+            .name("hdfs-kerberos-renewer".to_string())
+            .spawn(move || {
+                // Anything other than a timeout means the renewer has been dropped
+                while let Err(RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(kinit.next_renewal())
+                {
+                    if let Err(e) = kinit.run() {
+                        warn!("Failed to renew Kerberos ticket: {:?}", e);
+                    }
+                }
+                if let Some(ccache_path) = kinit.ccache_path.as_ref() {
+                    let _ = fs::remove_file(ccache_path);
+                }
+            })?;
+
+        Ok(Self {
+            ccache,
+            _stop: stop,
+        })
+    }
+
+    /// The ticket cache the client authenticates with, or `None` for the default ticket cache
+    pub(crate) fn ccache(&self) -> Option<&str> {
+        self.ccache.as_deref()
+    }
+}
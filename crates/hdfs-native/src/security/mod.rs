@@ -1,4 +1,25 @@
+pub(crate) mod digest;
+#[cfg(feature = "encryption")]
+pub(crate) mod encryption;
+#[cfg(not(feature = "encryption"))]
+pub(crate) mod encryption {
+    use bytes::Bytes;
+
+    /// Files in encryption zones can only be read and written with the `encryption` feature,
+    /// so without it there is never a cipher to apply
+    pub(crate) enum FileCipher {}
+
+    impl FileCipher {
+        pub(crate) fn apply(&self, _position: u64, _data: &[u8]) -> Bytes {
+            match *self {}
+        }
+    }
+}
 #[cfg(feature = "kerberos")]
-mod gssapi;
+pub(crate) mod gssapi;
+#[cfg(any(feature = "encryption", feature = "webhdfs"))]
+pub(crate) mod http;
+#[cfg(feature = "kerberos")]
+pub(crate) mod kerberos;
 pub mod sasl;
 pub mod user;
@@ -2,10 +2,12 @@ use bytes::{Buf, Bytes, BytesMut};
 use log::{debug, warn};
 use prost::Message;
 use std::io;
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
 use tokio::net::tcp::OwnedWriteHalf;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
     net::tcp::OwnedReadHalf,
     net::TcpStream,
 };
@@ -15,10 +17,11 @@ use crate::proto::common::rpc_sasl_proto::{SaslAuth, SaslState};
 use crate::proto::common::{
     RpcKindProto, RpcRequestHeaderProto, RpcResponseHeaderProto, RpcSaslProto,
 };
+use crate::proto::hdfs::data_transfer_encryptor_message_proto::DataTransferEncryptorStatus;
+use crate::proto::hdfs::DataTransferEncryptorMessageProto;
 use crate::{HdfsError, Result};
 #[cfg(feature = "token")]
 use {
-    super::user::Token,
     base64::{engine::general_purpose, Engine as _},
     gsasl_sys as gsasl,
     libc::{c_char, c_void, memcpy},
@@ -27,12 +30,16 @@ use {
     std::sync::atomic::AtomicPtr,
 };
 
+use super::digest::DigestSaslSession;
 #[cfg(feature = "kerberos")]
 use super::gssapi::GssapiSession;
-use super::user::{User, UserInfo};
+use super::user::{Token, User, UserInfo};
 
 const SASL_CALL_ID: i32 = -33;
-const HDFS_DELEGATION_TOKEN: &str = "HDFS_DELEGATION_TOKEN";
+const SASL_TRANSFER_MAGIC_NUMBER: u32 = 0xDEADBEEF;
+// Largest amount of data wrapped into a single message on DataNode connections
+const MAX_DATANODE_WRAP_SIZE: usize = 32 * 1024;
+pub(crate) const HDFS_DELEGATION_TOKEN: &str = "HDFS_DELEGATION_TOKEN";
 
 pub(crate) enum AuthMethod {
     Simple,
@@ -50,22 +57,226 @@ impl AuthMethod {
     }
 }
 
-pub(crate) trait SaslSession: Send + Sync {
+/// The client side of a SASL handshake, and the security layer it negotiates
+pub trait SaslMechanism: Send + Sync {
+    /// Processes a challenge from the server, or creates the initial response if `token` is
+    /// `None`. Returns the response to send and whether the client has finished the handshake.
     fn step(&mut self, token: Option<&[u8]>) -> Result<(Vec<u8>, bool)>;
 
-    fn has_security_layer(&self) -> bool;
+    /// Whether messages are wrapped with [SaslMechanism::encode] and unwrapped with
+    /// [SaslMechanism::decode] after the handshake, for integrity or privacy protection
+    fn has_security_layer(&self) -> bool {
+        false
+    }
+
+    fn encode(&mut self, _buf: &[u8]) -> Result<Vec<u8>> {
+        Err(HdfsError::SASLError(
+            "SASL session doesn't have security layer".to_string(),
+        ))
+    }
+
+    fn decode(&mut self, _buf: &[u8]) -> Result<Vec<u8>> {
+        Err(HdfsError::SASLError(
+            "SASL session doesn't have security layer".to_string(),
+        ))
+    }
+
+    /// The user the handshake authenticated, such as the short name of a Kerberos principal,
+    /// or `None` if the server already knows who the client is, like with a token
+    fn authenticated_user(&self) -> Option<String> {
+        None
+    }
+}
 
-    fn encode(&mut self, buf: &[u8]) -> Result<Vec<u8>>;
+/// One of the ways of authenticating offered by a NameNode
+#[derive(Debug, Clone)]
+pub struct SaslAuthMethod {
+    /// The Hadoop auth method, such as `SIMPLE`, `KERBEROS` or `TOKEN`
+    pub method: String,
+    /// The SASL mechanism, such as `GSSAPI` or `DIGEST-MD5`, or empty for `SIMPLE`
+    pub mechanism: String,
+    pub protocol: String,
+    pub server_id: String,
+    /// The first challenge of the handshake, if the NameNode sent one
+    pub challenge: Option<Vec<u8>>,
+}
+
+impl From<&SaslAuth> for SaslAuthMethod {
+    fn from(value: &SaslAuth) -> Self {
+        Self {
+            method: value.method.clone(),
+            mechanism: value.mechanism.clone(),
+            protocol: value.protocol().to_string(),
+            server_id: value.server_id().to_string(),
+            challenge: value.challenge.clone(),
+        }
+    }
+}
+
+/// How a connection authenticates with the method chosen by an [AuthProvider]
+pub enum Authentication {
+    /// Only sends the user name, without a SASL handshake
+    Simple,
+    Sasl(Box<dyn SaslMechanism>),
+}
 
-    fn decode(&mut self, buf: &[u8]) -> Result<Vec<u8>>;
+/// Chooses how connections to NameNodes authenticate, see
+/// [ClientBuilder::with_auth](crate::ClientBuilder::with_auth). Without a provider the client
+/// uses [DefaultAuth].
+pub trait AuthProvider: Send + Sync {
+    /// Starts authenticating with `auth`, or returns `None` to try the next method. Methods are
+    /// offered in the NameNode's order of preference. `token` is the client's delegation token
+    /// for the NameNode, if it has one.
+    fn start(&self, auth: &SaslAuthMethod, token: Option<&Token>)
+        -> Result<Option<Authentication>>;
+}
 
-    fn get_user_info(&self) -> Result<UserInfo>;
+impl std::fmt::Debug for dyn AuthProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AuthProvider")
+    }
+}
+
+/// Simple authentication, where the NameNode trusts the user name sent by the client
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimpleAuth;
+
+impl AuthProvider for SimpleAuth {
+    fn start(
+        &self,
+        auth: &SaslAuthMethod,
+        _token: Option<&Token>,
+    ) -> Result<Option<Authentication>> {
+        Ok(match AuthMethod::parse(&auth.method) {
+            Some(AuthMethod::Simple) => Some(Authentication::Simple),
+            _ => None,
+        })
+    }
+}
+
+/// Kerberos authentication with GSSAPI, using the credentials in the default ticket cache
+/// unless another cache is chosen with [KerberosAuth::with_ccache]
+#[cfg(feature = "kerberos")]
+#[derive(Debug, Clone, Default)]
+pub struct KerberosAuth {
+    ccache: Option<String>,
+}
+
+#[cfg(feature = "kerberos")]
+impl KerberosAuth {
+    /// Uses the credentials in the ticket cache named `ccache`, such as
+    /// `FILE:/tmp/krb5cc_hdfs`, instead of the default ticket cache
+    pub fn with_ccache(ccache: impl Into<String>) -> Self {
+        Self {
+            ccache: Some(ccache.into()),
+        }
+    }
+}
+
+#[cfg(feature = "kerberos")]
+impl AuthProvider for KerberosAuth {
+    fn start(
+        &self,
+        auth: &SaslAuthMethod,
+        _token: Option<&Token>,
+    ) -> Result<Option<Authentication>> {
+        Ok(match AuthMethod::parse(&auth.method) {
+            Some(AuthMethod::Kerberos) => Some(Authentication::Sasl(Box::new(GssapiSession::new(
+                &auth.protocol,
+                &auth.server_id,
+                self.ccache.as_deref(),
+            )?))),
+            _ => None,
+        })
+    }
+}
+
+/// Delegation token authentication with DIGEST-MD5, used when the client has a token for the
+/// NameNode
+#[cfg(feature = "token")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenAuth;
+
+#[cfg(feature = "token")]
+impl AuthProvider for TokenAuth {
+    fn start(
+        &self,
+        auth: &SaslAuthMethod,
+        token: Option<&Token>,
+    ) -> Result<Option<Authentication>> {
+        Ok(match (AuthMethod::parse(&auth.method), token) {
+            (Some(AuthMethod::Token), Some(token)) => {
+                debug!("Using token {:?}", token);
+                Some(Authentication::Sasl(Box::new(GSASLSession::new(
+                    &auth.protocol,
+                    &auth.server_id,
+                    token,
+                )?)))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Authenticates with the first method offered by the NameNode that the client supports:
+/// simple, Kerberos when the `kerberos` feature is enabled, or a delegation token when the
+/// `token` feature is enabled and the client has a token for the NameNode
+#[derive(Debug, Clone, Default)]
+pub struct DefaultAuth {
+    #[cfg(feature = "kerberos")]
+    kerberos: KerberosAuth,
+}
+
+impl DefaultAuth {
+    /// Authenticates with Kerberos using `kerberos` instead of the default ticket cache
+    #[cfg(feature = "kerberos")]
+    pub fn with_kerberos(kerberos: KerberosAuth) -> Self {
+        Self { kerberos }
+    }
+}
+
+impl AuthProvider for DefaultAuth {
+    fn start(
+        &self,
+        auth: &SaslAuthMethod,
+        token: Option<&Token>,
+    ) -> Result<Option<Authentication>> {
+        let providers: &[&dyn AuthProvider] = &[
+            &SimpleAuth,
+            #[cfg(feature = "kerberos")]
+            &self.kerberos,
+            #[cfg(feature = "token")]
+            &TokenAuth,
+        ];
+        for provider in providers {
+            if let Some(authentication) = provider.start(auth, token)? {
+                return Ok(Some(authentication));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Picks the first of `auths` that `provider` can authenticate with
+fn select_auth(
+    auths: &[SaslAuth],
+    token: Option<&Token>,
+    provider: &dyn AuthProvider,
+) -> Result<(SaslAuth, Option<Box<dyn SaslMechanism>>)> {
+    for auth in auths.iter() {
+        match provider.start(&SaslAuthMethod::from(auth), token)? {
+            Some(Authentication::Simple) => return Ok((auth.clone(), None)),
+            Some(Authentication::Sasl(session)) => return Ok((auth.clone(), Some(session))),
+            None => (),
+        }
+    }
+    Err(HdfsError::NoSASLMechanism)
 }
 
 pub struct SaslRpcClient {
     reader: SaslReader,
     writer: SaslWriter,
-    session: Option<Arc<Mutex<Box<dyn SaslSession>>>>,
+    session: Option<Arc<Mutex<Box<dyn SaslMechanism>>>>,
 }
 
 impl SaslRpcClient {
@@ -78,9 +289,13 @@ impl SaslRpcClient {
         }
     }
 
-    /// Service should be the connection host:port for a single NameNode connection, or the
-    /// name service name when connecting to HA NameNodes.
-    pub(crate) async fn negotiate(&mut self, service: &str) -> Result<UserInfo> {
+    /// `token` is offered to the server for TOKEN authentication, and user names come from
+    /// `user`.
+    pub(crate) async fn negotiate(
+        &mut self,
+        token: Option<&Token>,
+        user: &User,
+    ) -> Result<UserInfo> {
         let rpc_sasl = RpcSaslProto {
             state: SaslState::Negotiate as i32,
             ..Default::default()
@@ -89,15 +304,19 @@ impl SaslRpcClient {
         self.writer.send_sasl_message(&rpc_sasl).await?;
 
         let mut done = false;
-        let mut session: Option<Box<dyn SaslSession>> = None;
+        let mut session: Option<Box<dyn SaslMechanism>> = None;
         while !done {
             let mut response: Option<RpcSaslProto> = None;
             let message = self.reader.read_response().await?;
             debug!("Handling SASL message: {:?}", message);
             match SaslState::from_i32(message.state).unwrap() {
                 SaslState::Negotiate => {
-                    let (mut selected_auth, selected_session) =
-                        self.select_method(&message.auths, service)?;
+                    let default_auth = user.default_auth();
+                    let (mut selected_auth, selected_session) = select_auth(
+                        &message.auths,
+                        token,
+                        user.auth_provider().unwrap_or(&default_auth),
+                    )?;
                     session = selected_session;
 
                     let token = if let Some(session) = session.as_mut() {
@@ -158,10 +377,15 @@ impl SaslRpcClient {
             }
         }
 
-        let user_info = if let Some(s) = session.as_ref() {
-            s.get_user_info()?
-        } else {
-            User::get_simpler_user()
+        let user_info = match session.as_ref() {
+            Some(session) => match session.authenticated_user() {
+                Some(real_user) => user.get_proxied_user_info(real_user),
+                None => UserInfo {
+                    real_user: None,
+                    effective_user: None,
+                },
+            },
+            None => user.get_simpler_user()?,
         };
         self.session = session
             .filter(|x| {
@@ -173,38 +397,6 @@ impl SaslRpcClient {
         Ok(user_info)
     }
 
-    fn select_method(
-        &mut self,
-        auths: &[SaslAuth],
-        service: &str,
-    ) -> Result<(SaslAuth, Option<Box<dyn SaslSession>>)> {
-        let user = User::get();
-        for auth in auths.iter() {
-            match (
-                AuthMethod::parse(&auth.method),
-                user.get_token(HDFS_DELEGATION_TOKEN, service),
-            ) {
-                (Some(AuthMethod::Simple), _) => {
-                    return Ok((auth.clone(), None));
-                }
-                #[cfg(feature = "kerberos")]
-                (Some(AuthMethod::Kerberos), _) => {
-                    let session = GssapiSession::new(auth.protocol(), auth.server_id())?;
-                    return Ok((auth.clone(), Some(Box::new(session))));
-                }
-                #[cfg(feature = "token")]
-                (Some(AuthMethod::Token), Some(token)) => {
-                    debug!("Using token {:?}", token);
-                    let session = GSASLSession::new(auth.protocol(), auth.server_id(), token)?;
-
-                    return Ok((auth.clone(), Some(Box::new(session))));
-                }
-                _ => (),
-            }
-        }
-        Err(HdfsError::NoSASLMechanism)
-    }
-
     pub(crate) fn split(self) -> (SaslReader, SaslWriter) {
         let mut reader = self.reader;
         let mut writer = self.writer;
@@ -218,7 +410,7 @@ impl SaslRpcClient {
 
 pub(crate) struct SaslReader {
     stream: OwnedReadHalf,
-    session: Option<Arc<Mutex<Box<dyn SaslSession>>>>,
+    session: Option<Arc<Mutex<Box<dyn SaslMechanism>>>>,
     buffer: Bytes,
 }
 
@@ -231,7 +423,7 @@ impl SaslReader {
         }
     }
 
-    fn set_session(&mut self, session: Arc<Mutex<Box<dyn SaslSession>>>) {
+    fn set_session(&mut self, session: Arc<Mutex<Box<dyn SaslMechanism>>>) {
         self.session = Some(session);
     }
 
@@ -322,7 +514,7 @@ impl SaslReader {
 
 pub(crate) struct SaslWriter {
     stream: OwnedWriteHalf,
-    session: Option<Arc<Mutex<Box<dyn SaslSession>>>>,
+    session: Option<Arc<Mutex<Box<dyn SaslMechanism>>>>,
 }
 
 impl SaslWriter {
@@ -333,7 +525,7 @@ impl SaslWriter {
         }
     }
 
-    fn set_session(&mut self, session: Arc<Mutex<Box<dyn SaslSession>>>) {
+    fn set_session(&mut self, session: Arc<Mutex<Box<dyn SaslMechanism>>>) {
         self.session = Some(session);
     }
 
@@ -397,6 +589,291 @@ impl std::fmt::Debug for SaslWriter {
     }
 }
 
+/// Performs the SASL handshake on a DataNode data transfer connection, authenticating with
+/// `session`. The returned halves of the stream sign or encrypt everything sent over them when
+/// integrity or privacy protection was negotiated.
+pub(crate) async fn negotiate_datanode(
+    mut stream: TcpStream,
+    mut session: DigestSaslSession,
+) -> Result<(SaslDatanodeReader, SaslDatanodeWriter)> {
+    stream.write_u32(SASL_TRANSFER_MAGIC_NUMBER).await?;
+
+    let (initial_response, _) = session.step(None)?;
+    send_datanode_sasl_message(&mut stream, initial_response).await?;
+
+    let challenge = read_datanode_sasl_message(&mut stream).await?;
+    let (response, _) = session.step(Some(&challenge))?;
+    send_datanode_sasl_message(&mut stream, response).await?;
+
+    let rspauth = read_datanode_sasl_message(&mut stream).await?;
+    let (_, finished) = session.step(Some(&rspauth))?;
+    if !finished {
+        return Err(HdfsError::SASLError(
+            "DataNode SASL negotiation did not complete".to_string(),
+        ));
+    }
+    debug!(
+        "Negotiated {:?} protection with DataNode",
+        session.negotiated_qop()
+    );
+
+    let session: Option<Arc<Mutex<Box<dyn SaslMechanism>>>> = if session.has_security_layer() {
+        Some(Arc::new(Mutex::new(Box::new(session))))
+    } else {
+        None
+    };
+
+    let (reader, writer) = stream.into_split();
+    Ok((
+        SaslDatanodeReader::new(reader, session.clone()),
+        SaslDatanodeWriter::new(writer, session),
+    ))
+}
+
+async fn send_datanode_sasl_message(stream: &mut TcpStream, payload: Vec<u8>) -> Result<()> {
+    let message = DataTransferEncryptorMessageProto {
+        status: DataTransferEncryptorStatus::Success as i32,
+        payload: Some(payload),
+        ..Default::default()
+    };
+    debug!("Sending DataNode SASL message {:?}", message);
+    stream
+        .write_all(&message.encode_length_delimited_to_vec())
+        .await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn read_datanode_sasl_message(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut length_buf = Vec::new();
+    loop {
+        let byte = stream.read_u8().await?;
+        length_buf.push(byte);
+        if byte & 0x80 == 0 || length_buf.len() >= 10 {
+            break;
+        }
+    }
+    let length = prost::decode_length_delimiter(&length_buf[..])?;
+
+    let mut buf = vec![0u8; length];
+    stream.read_exact(&mut buf).await?;
+
+    let message = DataTransferEncryptorMessageProto::decode(&buf[..])?;
+    debug!("Received DataNode SASL message {:?}", message);
+    match message.status() {
+        DataTransferEncryptorStatus::Success => Ok(message.payload.unwrap_or_default()),
+        status => Err(HdfsError::SASLError(format!(
+            "DataNode SASL negotiation failed with {}: {}",
+            status.as_str_name(),
+            message.message()
+        ))),
+    }
+}
+
+/// Read half of a DataNode connection, unwrapping data if a security layer was negotiated. Each
+/// wrapped message is framed by a four byte length.
+pub(crate) struct SaslDatanodeReader {
+    stream: OwnedReadHalf,
+    unwrapper: Option<Box<Unwrapper>>,
+}
+
+struct Unwrapper {
+    session: Arc<Mutex<Box<dyn SaslMechanism>>>,
+    // The wrapped message currently being read, including its length
+    frame: BytesMut,
+    // Unwrapped data that hasn't been returned yet
+    decoded: Bytes,
+}
+
+impl SaslDatanodeReader {
+    pub(crate) fn new(
+        stream: OwnedReadHalf,
+        session: Option<Arc<Mutex<Box<dyn SaslMechanism>>>>,
+    ) -> Self {
+        Self {
+            stream,
+            unwrapper: session.map(|session| {
+                Box::new(Unwrapper {
+                    session,
+                    frame: BytesMut::new(),
+                    decoded: Bytes::new(),
+                })
+            }),
+        }
+    }
+}
+
+impl std::fmt::Debug for SaslDatanodeReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaslDatanodeReader")
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl AsyncRead for SaslDatanodeReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let Some(unwrapper) = this.unwrapper.as_mut() else {
+            return Pin::new(&mut this.stream).poll_read(cx, buf);
+        };
+
+        while unwrapper.decoded.is_empty() {
+            let frame_len = if unwrapper.frame.len() >= 4 {
+                4 + u32::from_be_bytes(unwrapper.frame[..4].try_into().unwrap()) as usize
+            } else {
+                4
+            };
+
+            if frame_len > 4 && unwrapper.frame.len() == frame_len {
+                let decoded = unwrapper
+                    .session
+                    .lock()
+                    .unwrap()
+                    .decode(&unwrapper.frame[4..])
+                    .map_err(io::Error::other)?;
+                unwrapper.decoded = Bytes::from(decoded);
+                unwrapper.frame.clear();
+                continue;
+            }
+
+            let mut chunk = [0u8; 8192];
+            let remaining = usize::min(frame_len - unwrapper.frame.len(), chunk.len());
+            let mut chunk_buf = ReadBuf::new(&mut chunk[..remaining]);
+            ready!(Pin::new(&mut this.stream).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                if unwrapper.frame.is_empty() {
+                    // The stream has been closed
+                    return Poll::Ready(Ok(()));
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            unwrapper.frame.extend_from_slice(chunk_buf.filled());
+        }
+
+        let len = usize::min(buf.remaining(), unwrapper.decoded.len());
+        buf.put_slice(&unwrapper.decoded.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Write half of a DataNode connection, wrapping data if a security layer was negotiated. Data
+/// is buffered and wrapped when flushed or when the buffer is full.
+pub(crate) struct SaslDatanodeWriter {
+    stream: OwnedWriteHalf,
+    wrapper: Option<Box<Wrapper>>,
+}
+
+struct Wrapper {
+    session: Arc<Mutex<Box<dyn SaslMechanism>>>,
+    // Data that hasn't been wrapped yet
+    buffer: BytesMut,
+    // Wrapped messages that haven't been written to the stream yet
+    pending: Bytes,
+}
+
+impl SaslDatanodeWriter {
+    pub(crate) fn new(
+        stream: OwnedWriteHalf,
+        session: Option<Arc<Mutex<Box<dyn SaslMechanism>>>>,
+    ) -> Self {
+        Self {
+            stream,
+            wrapper: session.map(|session| {
+                Box::new(Wrapper {
+                    session,
+                    buffer: BytesMut::new(),
+                    pending: Bytes::new(),
+                })
+            }),
+        }
+    }
+
+    /// Wraps and writes out everything that has been buffered
+    fn poll_write_buffer(
+        stream: &mut OwnedWriteHalf,
+        wrapper: &mut Wrapper,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            while !wrapper.pending.is_empty() {
+                let written = ready!(Pin::new(&mut *stream).poll_write(cx, &wrapper.pending))?;
+                if written == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                wrapper.pending.advance(written);
+            }
+
+            if wrapper.buffer.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let chunk = wrapper
+                .buffer
+                .split_to(usize::min(wrapper.buffer.len(), MAX_DATANODE_WRAP_SIZE));
+            let wrapped = wrapper
+                .session
+                .lock()
+                .unwrap()
+                .encode(&chunk)
+                .map_err(io::Error::other)?;
+
+            let mut frame = BytesMut::with_capacity(wrapped.len() + 4);
+            frame.extend_from_slice(&(wrapped.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&wrapped);
+            wrapper.pending = frame.freeze();
+        }
+    }
+}
+
+impl std::fmt::Debug for SaslDatanodeWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaslDatanodeWriter")
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl AsyncWrite for SaslDatanodeWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(wrapper) = this.wrapper.as_mut() else {
+            return Pin::new(&mut this.stream).poll_write(cx, buf);
+        };
+
+        if wrapper.buffer.len() >= MAX_DATANODE_WRAP_SIZE {
+            ready!(Self::poll_write_buffer(&mut this.stream, wrapper, cx))?;
+        }
+        let len = usize::min(buf.len(), MAX_DATANODE_WRAP_SIZE - wrapper.buffer.len());
+        wrapper.buffer.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(wrapper) = this.wrapper.as_mut() {
+            ready!(Self::poll_write_buffer(&mut this.stream, wrapper, cx))?;
+        }
+        Pin::new(&mut this.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(wrapper) = this.wrapper.as_mut() {
+            ready!(Self::poll_write_buffer(&mut this.stream, wrapper, cx))?;
+        }
+        Pin::new(&mut this.stream).poll_shutdown(cx)
+    }
+}
+
 #[cfg(feature = "token")]
 struct GSASLSession {
     ctx: AtomicPtr<gsasl::Gsasl>,
@@ -472,7 +949,7 @@ impl GSASLSession {
 }
 
 #[cfg(feature = "token")]
-impl SaslSession for GSASLSession {
+impl SaslMechanism for GSASLSession {
     fn step(&mut self, token: Option<&[u8]>) -> Result<(Vec<u8>, bool)> {
         let mut clientout = ptr::null_mut::<c_char>();
         let mut clientoutlen: u64 = 0;
@@ -528,14 +1005,6 @@ impl SaslSession for GSASLSession {
     fn decode(&mut self, _buf: &[u8]) -> Result<Vec<u8>> {
         todo!()
     }
-
-    fn get_user_info(&self) -> Result<UserInfo> {
-        // The token has all the info
-        Ok(UserInfo {
-            real_user: None,
-            effective_user: None,
-        })
-    }
 }
 
 #[cfg(feature = "token")]
@@ -547,3 +1016,133 @@ impl Drop for GSASLSession {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{
+        select_auth, AuthProvider, Authentication, DefaultAuth, SaslAuthMethod, SaslDatanodeReader,
+        SaslDatanodeWriter, SaslMechanism, MAX_DATANODE_WRAP_SIZE,
+    };
+    use crate::proto::common::rpc_sasl_proto::SaslAuth;
+    use crate::security::user::Token;
+    use crate::{HdfsError, Result};
+
+    /// Wraps messages by appending their length, so framing mistakes are detected
+    struct LengthSession;
+
+    impl SaslMechanism for LengthSession {
+        fn step(&mut self, _token: Option<&[u8]>) -> Result<(Vec<u8>, bool)> {
+            Ok((Vec::new(), true))
+        }
+
+        fn has_security_layer(&self) -> bool {
+            true
+        }
+
+        fn encode(&mut self, buf: &[u8]) -> Result<Vec<u8>> {
+            let mut encoded = buf.to_vec();
+            encoded.extend_from_slice(&(buf.len() as u32).to_be_bytes());
+            Ok(encoded)
+        }
+
+        fn decode(&mut self, buf: &[u8]) -> Result<Vec<u8>> {
+            let (message, len) = buf.split_at(buf.len() - 4);
+            assert_eq!(
+                u32::from_be_bytes(len.try_into().unwrap()) as usize,
+                message.len()
+            );
+            Ok(message.to_vec())
+        }
+    }
+
+    fn auth(method: &str, mechanism: &str) -> SaslAuth {
+        SaslAuth {
+            method: method.to_string(),
+            mechanism: mechanism.to_string(),
+            protocol: Some("hdfs".to_string()),
+            server_id: Some("localhost".to_string()),
+            challenge: None,
+        }
+    }
+
+    /// Authenticates with a made up mechanism using the token's identifier
+    struct CustomAuth;
+
+    impl AuthProvider for CustomAuth {
+        fn start(
+            &self,
+            auth: &SaslAuthMethod,
+            token: Option<&Token>,
+        ) -> Result<Option<Authentication>> {
+            match (auth.mechanism.as_str(), token) {
+                ("CUSTOM", Some(_)) => Ok(Some(Authentication::Sasl(Box::new(LengthSession)))),
+                ("CUSTOM", None) => Err(HdfsError::SASLError("No token".to_string())),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_auth() {
+        let token = Token {
+            alias: "localhost:9000".to_string(),
+            identifier: b"id".to_vec(),
+            password: b"password".to_vec(),
+            kind: "CUSTOM_TOKEN".to_string(),
+            service: "localhost:9000".to_string(),
+        };
+        let auths = [auth("TOKEN", "CUSTOM"), auth("SIMPLE", "")];
+
+        let (selected, session) = select_auth(&auths, Some(&token), &CustomAuth).unwrap();
+        assert_eq!(selected.mechanism, "CUSTOM");
+        assert!(session.unwrap().has_security_layer());
+        assert!(select_auth(&auths, None, &CustomAuth).is_err());
+        assert!(matches!(
+            select_auth(&auths[1..], Some(&token), &CustomAuth),
+            Err(HdfsError::NoSASLMechanism)
+        ));
+
+        // The default ignores mechanisms it doesn't know and falls back to simple auth
+        let (selected, session) =
+            select_auth(&auths, Some(&token), &DefaultAuth::default()).unwrap();
+        assert_eq!(selected.method, "SIMPLE");
+        assert!(session.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_datanode_wrapping() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let session: Arc<Mutex<Box<dyn SaslMechanism>>> =
+            Arc::new(Mutex::new(Box::new(LengthSession)));
+        let (_, client_writer) = client.into_split();
+        let (server_reader, _) = server.into_split();
+        let mut writer = SaslDatanodeWriter::new(client_writer, Some(Arc::clone(&session)));
+        let mut reader = SaslDatanodeReader::new(server_reader, Some(session));
+
+        let data: Vec<u8> = (0..MAX_DATANODE_WRAP_SIZE * 3 + 100)
+            .map(|i| i as u8)
+            .collect();
+        let expected = data.clone();
+        let write = tokio::spawn(async move {
+            writer.write_all(&data[..10]).await.unwrap();
+            writer.flush().await.unwrap();
+            writer.write_all(&data[10..]).await.unwrap();
+            writer.shutdown().await.unwrap();
+        });
+
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).await.unwrap();
+        write.await.unwrap();
+        assert_eq!(received, expected);
+    }
+}
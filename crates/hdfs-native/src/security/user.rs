@@ -5,18 +5,23 @@ use std::env;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use users::get_current_username;
 
-use crate::proto::common::CredentialsProto;
+use crate::proto::common::{CredentialsProto, TokenProto};
+use crate::{HdfsError, Result};
+
+use super::sasl::{AuthProvider, DefaultAuth};
 
 const HADOOP_USER_NAME: &str = "HADOOP_USER_NAME";
-#[cfg(feature = "kerberos")]
 const HADOOP_PROXY_USER: &str = "HADOOP_PROXY_USER";
 const HADOOP_TOKEN_FILE_LOCATION: &str = "HADOOP_TOKEN_FILE_LOCATION";
 const TOKEN_STORAGE_MAGIC: &[u8] = "HDTS".as_bytes();
 
-#[derive(Debug)]
+/// A delegation token, either loaded from `$HADOOP_TOKEN_FILE_LOCATION` or issued by
+/// [crate::Client::get_delegation_token]
+#[derive(Clone, Debug)]
 pub struct Token {
     pub alias: String,
     pub identifier: Vec<u8>,
@@ -25,6 +30,29 @@ pub struct Token {
     pub service: String,
 }
 
+impl From<TokenProto> for Token {
+    fn from(value: TokenProto) -> Self {
+        Self {
+            alias: value.service.clone(),
+            identifier: value.identifier,
+            password: value.password,
+            kind: value.kind,
+            service: value.service,
+        }
+    }
+}
+
+impl From<&Token> for TokenProto {
+    fn from(value: &Token) -> Self {
+        Self {
+            identifier: value.identifier.clone(),
+            password: value.password.clone(),
+            kind: value.kind.clone(),
+            service: value.service.clone(),
+        }
+    }
+}
+
 impl Token {
     fn load_tokens() -> Vec<Self> {
         match env::var(HADOOP_TOKEN_FILE_LOCATION).map(PathBuf::from) {
@@ -163,16 +191,89 @@ pub(crate) struct UserInfo {
     pub(crate) effective_user: Option<String>,
 }
 
-#[derive(Debug)]
+/// Who a client acts as. This is resolved once when the client is built, so every client in a
+/// process can have its own identity and tokens.
+#[derive(Debug, Clone)]
 pub(crate) struct User {
-    #[allow(dead_code)]
     tokens: Vec<Token>,
+    // The user for simple authentication, or the current OS user if it isn't set
+    simple_user: Option<String>,
+    // The user to impersonate after authenticating with Kerberos
+    proxy_user: Option<String>,
+    // Replaces the default choice of how to authenticate to NameNodes
+    auth: Option<Arc<dyn AuthProvider>>,
+    // The client's own Kerberos ticket cache, instead of the default one
+    #[cfg(feature = "kerberos")]
+    kerberos_ccache: Option<String>,
 }
 
 impl User {
-    pub(crate) fn get() -> Self {
-        let tokens = Token::load_tokens();
-        User { tokens }
+    /// Acts as `simple_user` with simple authentication, or otherwise `$HADOOP_USER_NAME` or
+    /// the current OS user. `tokens` are used before the ones loaded from
+    /// `$HADOOP_TOKEN_FILE_LOCATION`, and `proxy_user` defaults to `$HADOOP_PROXY_USER`.
+    pub(crate) fn new(
+        simple_user: Option<String>,
+        proxy_user: Option<String>,
+        mut tokens: Vec<Token>,
+    ) -> Self {
+        tokens.extend(Token::load_tokens());
+        User {
+            tokens,
+            simple_user: simple_user.or_else(|| env::var(HADOOP_USER_NAME).ok()),
+            proxy_user: proxy_user.or_else(|| env::var(HADOOP_PROXY_USER).ok()),
+            auth: None,
+            #[cfg(feature = "kerberos")]
+            kerberos_ccache: None,
+        }
+    }
+
+    /// Authenticates to NameNodes with `auth` instead of [DefaultAuth](super::sasl::DefaultAuth)
+    pub(crate) fn with_auth(mut self, auth: Option<Arc<dyn AuthProvider>>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub(crate) fn auth_provider(&self) -> Option<&dyn AuthProvider> {
+        self.auth.as_deref()
+    }
+
+    /// Authenticates with the Kerberos credentials in `ccache` instead of the default ticket
+    /// cache
+    #[cfg(feature = "kerberos")]
+    pub(crate) fn with_kerberos_ccache(mut self, ccache: Option<String>) -> Self {
+        self.kerberos_ccache = ccache;
+        self
+    }
+
+    #[cfg(feature = "kerberos")]
+    pub(crate) fn kerberos_ccache(&self) -> Option<&str> {
+        self.kerberos_ccache.as_deref()
+    }
+
+    /// How to authenticate without an auth provider, using the client's ticket cache
+    pub(crate) fn default_auth(&self) -> DefaultAuth {
+        #[cfg(feature = "kerberos")]
+        if let Some(ccache) = self.kerberos_ccache.as_ref() {
+            return DefaultAuth::with_kerberos(super::sasl::KerberosAuth::with_ccache(ccache));
+        }
+        DefaultAuth::default()
+    }
+
+    /// The user for renewing its delegation tokens, which the NameNode only allows after
+    /// authenticating with Kerberos as the user itself, so it never uses a token or proxy user
+    pub(crate) fn for_token_renewal(&self) -> Self {
+        #[cfg(feature = "kerberos")]
+        let auth: Arc<dyn AuthProvider> = Arc::new(match self.kerberos_ccache.as_ref() {
+            Some(ccache) => super::sasl::KerberosAuth::with_ccache(ccache),
+            None => super::sasl::KerberosAuth::default(),
+        });
+        #[cfg(not(feature = "kerberos"))]
+        let auth: Arc<dyn AuthProvider> = Arc::new(super::sasl::SimpleAuth);
+        User {
+            proxy_user: None,
+            auth: Some(auth),
+            ..self.clone()
+        }
     }
 
     pub(crate) fn get_token(&self, kind: &str, service: &str) -> Option<&Token> {
@@ -181,28 +282,37 @@ impl User {
             .find(|t| t.kind == kind && t.service == service)
     }
 
-    #[cfg(feature = "kerberos")]
-    pub(crate) fn get_user_info_from_principal(principal: &str) -> UserInfo {
-        let username = User::get_user_from_principal(principal);
-        let proxy_user = env::var(HADOOP_PROXY_USER).ok();
+    /// The user info after authenticating as `real_user`, which acts as the proxy user if
+    /// there is one
+    pub(crate) fn get_proxied_user_info(&self, real_user: String) -> UserInfo {
         UserInfo {
-            real_user: Some(username),
-            effective_user: proxy_user,
+            real_user: Some(real_user),
+            effective_user: self.proxy_user.clone(),
         }
     }
 
-    pub(crate) fn get_simpler_user() -> UserInfo {
-        let effective_user = env::var(HADOOP_USER_NAME).ok().unwrap_or_else(|| {
-            get_current_username()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-        });
-        UserInfo {
-            real_user: None,
-            effective_user: Some(effective_user),
-        }
+    /// The user info with simple authentication, which acts as the proxy user if there is one.
+    /// The current OS user is only looked up here, so it isn't needed with Kerberos.
+    pub(crate) fn get_simpler_user(&self) -> Result<UserInfo> {
+        let simple_user = match &self.simple_user {
+            Some(user) => user.clone(),
+            None => get_current_username()
+                .and_then(|name| name.into_string().ok())
+                .ok_or(HdfsError::OperationFailed(format!(
+                    "Failed to get the name of the current user, set ${} instead",
+                    HADOOP_USER_NAME
+                )))?,
+        };
+        Ok(match &self.proxy_user {
+            Some(proxy_user) => UserInfo {
+                real_user: Some(simple_user),
+                effective_user: Some(proxy_user.clone()),
+            },
+            None => UserInfo {
+                real_user: None,
+                effective_user: Some(simple_user),
+            },
+        })
     }
 
     #[cfg(feature = "kerberos")]
@@ -281,4 +391,38 @@ mod tests {
         assert_eq!(tokens[0].service, "127.0.0.1:9000");
         tokens.iter().for_each(|t| println!("{:?}", t));
     }
+
+    #[test]
+    fn test_user_settings() {
+        let token = Token {
+            alias: "ha-hdfs:ns1".to_string(),
+            identifier: vec![1],
+            password: vec![2],
+            kind: "HDFS_DELEGATION_TOKEN".to_string(),
+            service: "ha-hdfs:ns1".to_string(),
+        };
+        let user = User::new(Some("alice".to_string()), None, vec![token]);
+        assert_eq!(
+            user.get_simpler_user().unwrap().effective_user.as_deref(),
+            Some("alice")
+        );
+        assert_eq!(
+            user.get_token("HDFS_DELEGATION_TOKEN", "ha-hdfs:ns1")
+                .unwrap()
+                .identifier,
+            vec![1]
+        );
+        assert!(user
+            .get_token("HDFS_DELEGATION_TOKEN", "ha-hdfs:ns2")
+            .is_none());
+
+        // The proxy user is the effective user, with whoever authenticated as the real user
+        let user = User::new(Some("alice".to_string()), Some("bob".to_string()), vec![]);
+        let user_info = user.get_simpler_user().unwrap();
+        assert_eq!(user_info.real_user.as_deref(), Some("alice"));
+        assert_eq!(user_info.effective_user.as_deref(), Some("bob"));
+        let user_info = user.get_proxied_user_info("service".to_string());
+        assert_eq!(user_info.real_user.as_deref(), Some("service"));
+        assert_eq!(user_info.effective_user.as_deref(), Some("bob"));
+    }
 }
@@ -1,3 +1,4 @@
+use std::sync::atomic::AtomicUsize;
 use std::sync::Mutex;
 
 pub static EC_FAULT_INJECTOR: Mutex<Option<EcFaultInjection>> = Mutex::new(None);
@@ -5,3 +6,16 @@ pub static EC_FAULT_INJECTOR: Mutex<Option<EcFaultInjection>> = Mutex::new(None)
 pub struct EcFaultInjection {
     pub fail_blocks: Vec<usize>,
 }
+
+pub static REPLICA_FAULT_INJECTOR: Mutex<Option<ReplicaFaultInjection>> = Mutex::new(None);
+
+#[derive(Default)]
+pub struct ReplicaFaultInjection {
+    /// Indices into a block's location list whose connections fail
+    pub fail_replicas: Vec<usize>,
+    /// Indices into a block's location list whose packet data gets corrupted when read
+    pub corrupt_replicas: Vec<usize>,
+}
+
+/// Most packets of a block that were waiting to be acknowledged at once while writing
+pub static MAX_PACKETS_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
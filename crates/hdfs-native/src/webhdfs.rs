@@ -0,0 +1,484 @@
+//! A client for the WebHDFS REST API, enabled by the `webhdfs` feature. This is for
+//! environments that can only reach HDFS over HTTP(S), and supports a subset of the operations
+//! of [Client](crate::Client). Data is read through the DataNodes the NameNode redirects to, so
+//! they still have to be reachable on their HTTP ports.
+//!
+//! ```no_run
+//! # use hdfs_native::webhdfs::WebHdfsClient;
+//! # async fn func() -> hdfs_native::Result<()> {
+//! let client = WebHdfsClient::new("webhdfs://localhost:9870")?;
+//! let mut reader = client.read("/data/file").await?;
+//! let data = reader.read(reader.file_length()).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::io;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use log::debug;
+use reqwest::Method;
+use url::Url;
+
+use crate::client::{absolute_path, FileStatus};
+use crate::common::config::Configuration;
+use crate::hdfs::proxy::NameServiceProxy;
+use crate::security::http;
+use crate::security::user::User;
+use crate::{Client, ClientBuilder, HdfsError, Result};
+
+const DEFAULT_HTTP_PORT: u16 = 9870;
+const DEFAULT_HTTPS_PORT: u16 = 9871;
+
+/// A client for the WebHDFS REST API of a NameNode. Paths are resolved like [Client] does,
+/// relative to the home directory of the user. Requests are authenticated with SPNEGO when
+/// Kerberos is enabled, and otherwise the user is passed with `user.name`.
+#[derive(Debug, Clone)]
+pub struct WebHdfsClient {
+    // `<http|https>://<host>:<port>/webhdfs/v1`
+    base_url: Url,
+    client: reqwest::Client,
+    user: Arc<User>,
+    kerberos: bool,
+    #[cfg(feature = "kerberos")]
+    _kerberos_renewer: Option<Arc<crate::security::kerberos::KerberosRenewer>>,
+    home_directory: String,
+    umask: u32,
+}
+
+impl WebHdfsClient {
+    /// Creates a client for the NameNode at a `webhdfs://<host>[:port]` URL, or a
+    /// `swebhdfs://<host>[:port]` URL to use HTTPS. The port defaults to 9870 for HTTP and 9871
+    /// for HTTPS. Name services aren't supported.
+    ///
+    /// This is the same as `ClientBuilder::new(url).build_webhdfs()`, see [ClientBuilder] for
+    /// other settings.
+    pub fn new(url: &str) -> Result<Self> {
+        ClientBuilder::new(url).build_webhdfs()
+    }
+
+    pub(crate) fn with_config(url: &str, config: Configuration, user: User) -> Result<Self> {
+        let base_url = parse_webhdfs_url(url)?;
+
+        #[cfg(feature = "kerberos")]
+        let kerberos_renewer = crate::security::kerberos::KerberosRenewer::start(&config)?;
+        #[cfg(feature = "kerberos")]
+        let user = user.with_kerberos_ccache(
+            kerberos_renewer
+                .as_ref()
+                .and_then(|renewer| renewer.ccache())
+                .map(String::from),
+        );
+        let user = Arc::new(user);
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.get_socket_timeout())
+            .build()
+            .map_err(io_error)?;
+        let username = Client::get_username(&config, &user)?;
+        Ok(Self {
+            base_url,
+            client,
+            user,
+            kerberos: config.get_kerberos_enabled(),
+            #[cfg(feature = "kerberos")]
+            _kerberos_renewer: kerberos_renewer,
+            home_directory: format!("/user/{}", username),
+            umask: config.get_umask(),
+        })
+    }
+
+    /// The home directory of the user, `/user/<username>`, which relative paths are resolved
+    /// against
+    pub fn home_directory(&self) -> &str {
+        &self.home_directory
+    }
+
+    /// Gets the status of a file or directory with `GETFILESTATUS`. Returns
+    /// [HdfsError::FileNotFound] if `path` doesn't exist.
+    pub async fn get_file_info(&self, path: &str) -> Result<FileStatus> {
+        let path = absolute_path(path, &self.home_directory);
+        let json = self
+            .request_json(Method::GET, &path, "GETFILESTATUS", &[])
+            .await?;
+        let status = json
+            .get("FileStatus")
+            .ok_or_else(|| invalid_response("missing FileStatus"))?;
+        file_status(status, &path)
+    }
+
+    /// Lists the contents of a directory with `LISTSTATUS`, or the status of `path` itself if
+    /// it's a file. With `recursive`, the contents of sub-directories are listed after each
+    /// sub-directory.
+    pub async fn list_status(&self, path: &str, recursive: bool) -> Result<Vec<FileStatus>> {
+        let path = absolute_path(path, &self.home_directory);
+        let mut statuses = Vec::new();
+        // Statuses still to be returned, from the last to the first
+        let mut pending: Vec<FileStatus> = self.list_dir(&path).await?;
+        pending.reverse();
+        while let Some(status) = pending.pop() {
+            if recursive && status.isdir {
+                let listing = self.list_dir(&status.path).await?;
+                pending.extend(listing.into_iter().rev());
+            }
+            statuses.push(status);
+        }
+        Ok(statuses)
+    }
+
+    async fn list_dir(&self, path: &str) -> Result<Vec<FileStatus>> {
+        let json = self
+            .request_json(Method::GET, path, "LISTSTATUS", &[])
+            .await?;
+        json.get("FileStatuses")
+            .and_then(|statuses| statuses.get("FileStatus"))
+            .and_then(|statuses| statuses.as_array())
+            .ok_or_else(|| invalid_response("missing FileStatuses"))?
+            .iter()
+            .map(|status| file_status(status, path))
+            .collect()
+    }
+
+    /// Creates a directory with `MKDIRS`. Like [Client::mkdirs], the umask from
+    /// `fs.permissions.umask-mode` is removed from `permission`. WebHDFS always creates the
+    /// parent directories, so without `create_parent` the parent is checked first, and a
+    /// missing parent fails with [HdfsError::FileNotFound].
+    pub async fn mkdirs(&self, path: &str, permission: u32, create_parent: bool) -> Result<()> {
+        let path = absolute_path(path, &self.home_directory);
+        if !create_parent {
+            let parent = match path.rsplit_once('/') {
+                Some(("", _)) | None => "/",
+                Some((parent, _)) => parent,
+            };
+            if !self.get_file_info(parent).await?.isdir {
+                return Err(HdfsError::ParentNotDirectory(parent.to_string()));
+            }
+        }
+
+        let permission = format!("{:o}", permission & !self.umask);
+        let json = self
+            .request_json(
+                Method::PUT,
+                &path,
+                "MKDIRS",
+                &[("permission", permission.as_str())],
+            )
+            .await?;
+        match json.get("boolean").and_then(|created| created.as_bool()) {
+            Some(true) => Ok(()),
+            Some(false) => Err(HdfsError::OperationFailed(format!(
+                "Failed to create {}",
+                path
+            ))),
+            None => Err(invalid_response("missing boolean")),
+        }
+    }
+
+    /// Opens a file for reading. The length of the file is fetched with `GETFILESTATUS`, and
+    /// each read is an `OPEN` request for its range.
+    pub async fn read(&self, path: &str) -> Result<WebHdfsFileReader> {
+        let status = self.get_file_info(path).await?;
+        if status.isdir {
+            return Err(HdfsError::IsADirectoryError(status.path));
+        }
+        Ok(WebHdfsFileReader {
+            client: self.clone(),
+            path: status.path,
+            length: status.length,
+            position: 0,
+        })
+    }
+
+    /// Sends a request for `op` on `path`, failing with the error the server responded with
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        op: &str,
+        params: &[(&str, &str)],
+    ) -> Result<reqwest::Response> {
+        let mut url = self.base_url.clone();
+        url.set_path(&format!("{}{}", self.base_url.path(), path));
+        url.query_pairs_mut()
+            .append_pair("op", op)
+            .extend_pairs(params);
+        let authorization = http::authenticate(&mut url, &self.user, self.kerberos)?;
+
+        debug!("WebHDFS request: {} {}", method, url);
+        let mut request = self.client.request(method, url);
+        if let Some(authorization) = authorization {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+        let response = request.send().await.map_err(io_error)?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let text = response.text().await.map_err(io_error)?;
+        Err(remote_exception(status, &text))
+    }
+
+    async fn request_json(
+        &self,
+        method: Method,
+        path: &str,
+        op: &str,
+        params: &[(&str, &str)],
+    ) -> Result<serde_json::Value> {
+        let text = self
+            .request(method, path, op, params)
+            .await?
+            .text()
+            .await
+            .map_err(io_error)?;
+        serde_json::from_str(&text).map_err(|e| invalid_response(&e.to_string()))
+    }
+}
+
+/// A reader for a file opened with [WebHdfsClient::read]
+#[derive(Debug)]
+pub struct WebHdfsFileReader {
+    client: WebHdfsClient,
+    path: String,
+    length: usize,
+    position: usize,
+}
+
+impl WebHdfsFileReader {
+    pub fn file_length(&self) -> usize {
+        self.length
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.length.saturating_sub(self.position)
+    }
+
+    /// Read up to `len` bytes into a new [Bytes] object, advancing the internal position in the file.
+    /// An empty [Bytes] object will be returned if the end of the file has been reached.
+    pub async fn read(&mut self, len: usize) -> Result<Bytes> {
+        if self.position >= self.length {
+            Ok(Bytes::new())
+        } else {
+            let offset = self.position;
+            self.position = usize::min(self.position + len, self.length);
+            self.read_range(offset, self.position - offset).await
+        }
+    }
+
+    /// Read up to `len` bytes starting at `offset` into a new [Bytes] object. The returned buffer
+    /// could be smaller than `len` if `offset + len` extends beyond the end of the file.
+    ///
+    /// Panics if the requested range is outside of the file
+    pub async fn read_range(&self, offset: usize, len: usize) -> Result<Bytes> {
+        assert!(offset <= self.length, "Offset is outside of the file");
+        let len = usize::min(len, self.length - offset);
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+        let (offset, length) = (offset.to_string(), len.to_string());
+        self.client
+            .request(
+                Method::GET,
+                &self.path,
+                "OPEN",
+                &[("offset", offset.as_str()), ("length", length.as_str())],
+            )
+            .await?
+            .bytes()
+            .await
+            .map_err(io_error)
+    }
+}
+
+/// Builds the base URL of the WebHDFS API from a `webhdfs://` or `swebhdfs://` URL
+fn parse_webhdfs_url(url: &str) -> Result<Url> {
+    let invalid = |reason: String| {
+        HdfsError::InvalidUrl(format!(
+            "`{}` {}. Expected webhdfs://<host>[:port] or swebhdfs://<host>[:port]",
+            url, reason
+        ))
+    };
+
+    let parsed = Url::parse(url).map_err(|e| invalid(format!("could not be parsed: {}", e)))?;
+    let (scheme, default_port) = match parsed.scheme() {
+        "webhdfs" => ("http", DEFAULT_HTTP_PORT),
+        "swebhdfs" => ("https", DEFAULT_HTTPS_PORT),
+        scheme => return Err(invalid(format!("has the unsupported scheme `{}`", scheme))),
+    };
+    let host = parsed
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| invalid("has no host".to_string()))?;
+    Ok(Url::parse(&format!(
+        "{}://{}:{}/webhdfs/v1",
+        scheme,
+        host,
+        parsed.port().unwrap_or(default_port)
+    ))?)
+}
+
+/// Converts a `FileStatus` JSON object of a file in `base_path`
+fn file_status(json: &serde_json::Value, base_path: &str) -> Result<FileStatus> {
+    let string = |name: &str| {
+        json.get(name)
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| invalid_response(&format!("FileStatus is missing {}", name)))
+    };
+    let number = |name: &str| {
+        json.get(name)
+            .and_then(|value| value.as_u64())
+            .ok_or_else(|| invalid_response(&format!("FileStatus is missing {}", name)))
+    };
+
+    let suffix = string("pathSuffix")?;
+    let path = if suffix.is_empty() {
+        base_path.to_string()
+    } else if base_path.ends_with('/') {
+        format!("{}{}", base_path, suffix)
+    } else {
+        format!("{}/{}", base_path, suffix)
+    };
+    let file_type = string("type")?;
+    let permission = u16::from_str_radix(string("permission")?, 8)
+        .map_err(|_| invalid_response("FileStatus has an invalid permission"))?;
+
+    Ok(FileStatus {
+        path,
+        length: number("length")? as usize,
+        isdir: file_type == "DIRECTORY",
+        permission,
+        owner: string("owner")?.into(),
+        group: string("group")?.into(),
+        modification_time: number("modificationTime")?,
+        access_time: number("accessTime")?,
+        replication: number("replication")? as u16,
+        block_size: number("blockSize")?,
+        symlink: if file_type == "SYMLINK" {
+            Some(string("symlink")?.to_string())
+        } else {
+            None
+        },
+    })
+}
+
+/// Converts the `RemoteException` a failed request is responded to with, like the exceptions
+/// of RPC calls
+fn remote_exception(status: reqwest::StatusCode, text: &str) -> HdfsError {
+    let exception = serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|json| json.get("RemoteException").cloned());
+    let field = |name: &str| {
+        exception
+            .as_ref()
+            .and_then(|e| e.get(name))
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    };
+    match (field("javaClassName"), field("message")) {
+        (Some(exception), Some(message)) => NameServiceProxy::convert_rpc_error(exception, message),
+        _ => HdfsError::OperationFailed(format!("{}: {}", status, text)),
+    }
+}
+
+fn invalid_response(reason: &str) -> HdfsError {
+    HdfsError::OperationFailed(format!("Invalid WebHDFS response: {}", reason))
+}
+
+fn io_error(e: reqwest::Error) -> HdfsError {
+    let kind = if e.is_timeout() {
+        io::ErrorKind::TimedOut
+    } else {
+        io::ErrorKind::Other
+    };
+    HdfsError::IOError(io::Error::new(kind, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{file_status, parse_webhdfs_url, remote_exception};
+    use crate::HdfsError;
+
+    #[test]
+    fn test_parse_webhdfs_url() {
+        assert_eq!(
+            parse_webhdfs_url("webhdfs://nn:50070").unwrap().as_str(),
+            "http://nn:50070/webhdfs/v1"
+        );
+        assert_eq!(
+            parse_webhdfs_url("webhdfs://nn").unwrap().as_str(),
+            "http://nn:9870/webhdfs/v1"
+        );
+        assert_eq!(
+            parse_webhdfs_url("swebhdfs://nn").unwrap().as_str(),
+            "https://nn:9871/webhdfs/v1"
+        );
+        assert!(matches!(
+            parse_webhdfs_url("hdfs://nn:9000"),
+            Err(HdfsError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            parse_webhdfs_url("webhdfs:///path"),
+            Err(HdfsError::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_status() {
+        let json = serde_json::json!({
+            "accessTime": 1320171722771u64,
+            "blockSize": 33554432,
+            "group": "supergroup",
+            "length": 24930,
+            "modificationTime": 1320171722771u64,
+            "owner": "webuser",
+            "pathSuffix": "a.patch",
+            "permission": "644",
+            "replication": 1,
+            "type": "FILE"
+        });
+        let status = file_status(&json, "/dir").unwrap();
+        assert_eq!(status.path, "/dir/a.patch");
+        assert_eq!(status.length, 24930);
+        assert!(!status.isdir);
+        assert_eq!(status.permission, 0o644);
+        assert_eq!(&*status.owner, "webuser");
+        assert_eq!(&*status.group, "supergroup");
+        assert_eq!(status.modification_time, 1320171722771);
+        assert_eq!(status.replication, 1);
+        assert_eq!(status.block_size, 33554432);
+        assert!(status.symlink.is_none());
+
+        // The status of the path itself has an empty suffix
+        let json = serde_json::json!({
+            "accessTime": 0,
+            "blockSize": 0,
+            "group": "supergroup",
+            "length": 0,
+            "modificationTime": 1320173277227u64,
+            "owner": "webuser",
+            "pathSuffix": "",
+            "permission": "777",
+            "replication": 0,
+            "type": "DIRECTORY"
+        });
+        let status = file_status(&json, "/").unwrap();
+        assert_eq!(status.path, "/");
+        assert!(status.isdir);
+
+        assert!(file_status(&serde_json::json!({"type": "FILE"}), "/").is_err());
+    }
+
+    #[test]
+    fn test_remote_exception() {
+        let text = r#"{"RemoteException":{"exception":"FileNotFoundException","javaClassName":"java.io.FileNotFoundException","message":"File does not exist: /foo"}}"#;
+        assert!(matches!(
+            remote_exception(reqwest::StatusCode::NOT_FOUND, text),
+            HdfsError::FileNotFound(msg) if msg == "File does not exist: /foo"
+        ));
+        assert!(matches!(
+            remote_exception(reqwest::StatusCode::BAD_GATEWAY, "Bad Gateway"),
+            HdfsError::OperationFailed(_)
+        ));
+    }
+}
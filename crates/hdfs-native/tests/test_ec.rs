@@ -12,7 +12,10 @@ mod test {
 
     use hdfs_native::minidfs::{DfsFeatures, MiniDfs};
     use hdfs_native::test::{EcFaultInjection, EC_FAULT_INJECTOR};
-    use hdfs_native::{client::Client, Result};
+    use hdfs_native::{
+        client::{Client, ErasureCodingPolicyState},
+        Result,
+    };
 
     fn create_file(url: &str, path: &str, size: usize) -> io::Result<()> {
         assert!(size % 4 == 0);
@@ -112,6 +115,67 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_erasure_coding_policies() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        #[cfg(feature = "kerberos")]
+        let _dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::EC, DfsFeatures::SECURITY]));
+        #[cfg(not(feature = "kerberos"))]
+        let _dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::EC]));
+        let client = Client::default();
+
+        let policies = client.get_erasure_coding_policies().await?;
+        let policy = policies
+            .iter()
+            .find(|p| p.name == "RS-3-2-1024k")
+            .expect("RS-3-2-1024k policy not found");
+        assert_eq!(policy.state, ErasureCodingPolicyState::Enabled);
+        assert_eq!((policy.data_units, policy.parity_units), (3, 2));
+        assert_eq!(policy.cell_size, 1024 * 1024);
+
+        let policy = client.get_erasure_coding_policy("/ec-6-3").await?.unwrap();
+        assert_eq!(policy.name, "RS-6-3-1024k");
+
+        client.mkdirs("/ec-policy", 0o755, true).await?;
+        assert!(client
+            .get_erasure_coding_policy("/ec-policy")
+            .await?
+            .is_none());
+
+        client
+            .set_erasure_coding_policy("/ec-policy", "RS-3-2-1024k")
+            .await?;
+        let policy = client
+            .get_erasure_coding_policy("/ec-policy")
+            .await?
+            .unwrap();
+        assert_eq!(policy.name, "RS-3-2-1024k");
+
+        // New files are erasure coded
+        let mut writer = client
+            .create("/ec-policy/testfile", WriteOptions::default())
+            .await?;
+        writer.write(Bytes::from(vec![0u8; 16])).await?;
+        writer.close().await?;
+        let policy = client
+            .get_erasure_coding_policy("/ec-policy/testfile")
+            .await?
+            .unwrap();
+        assert_eq!(policy.name, "RS-3-2-1024k");
+
+        client.unset_erasure_coding_policy("/ec-policy").await?;
+        assert!(client
+            .get_erasure_coding_policy("/ec-policy")
+            .await?
+            .is_none());
+
+        client.delete("/ec-policy", true).await?;
+
+        Ok(())
+    }
+
     #[tokio::test]
     #[serial]
     async fn test_erasure_coded_write() -> Result<()> {
@@ -4,10 +4,25 @@ mod common;
 #[cfg(feature = "integration-test")]
 mod test {
     use crate::common::{setup, TEST_FILE_INTS};
-    use bytes::{Buf, BufMut, BytesMut};
-    use hdfs_native::{client::FileStatus, minidfs::DfsFeatures, Client, Result, WriteOptions};
+    use bytes::{Buf, BufMut, Bytes, BytesMut};
+    use futures::StreamExt;
+    use hdfs_native::{
+        client::{
+            AclEntry, AclEntryScope, AclEntryType, Authentication, CacheDirectiveFilter,
+            CachePoolInfo, ChecksumType, FileStatus, SafeModeAction, XAttrSetFlag, QUOTA_DONT_SET,
+            QUOTA_RESET,
+        },
+        metrics::MetricsRecorder,
+        minidfs::{DfsFeatures, MiniDfs},
+        test::{ReplicaFaultInjection, MAX_PACKETS_IN_FLIGHT, REPLICA_FAULT_INJECTOR},
+        Client, ClientBuilder, HdfsError, Result, WriteOptions,
+    };
     use serial_test::serial;
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     #[tokio::test]
     #[serial]
@@ -15,6 +30,60 @@ mod test {
         test_with_features(&HashSet::new()).await.unwrap();
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_simple_auth() -> Result<()> {
+        let dfs = MiniDfs::with_features(&HashSet::new());
+        let client = ClientBuilder::new(&dfs.url)
+            .with_authentication(Authentication::Simple)
+            .build()?;
+        client.list_status("/", false).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_check_access() -> Result<()> {
+        let dfs = MiniDfs::with_features(&HashSet::new());
+        let client = Client::new(&dfs.url)?;
+        client.mkdirs("/accessdir", 0o755, true).await?;
+        client
+            .create(
+                "/accessdir/file",
+                WriteOptions {
+                    permission: 0o640,
+                    ..Default::default()
+                },
+            )
+            .await?
+            .close()
+            .await?;
+
+        client.check_access("/accessdir/file", 0o6).await?;
+        assert!(matches!(
+            client.check_access("/accessdir/missing", 0o4).await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            client.check_access("/accessdir/file", 0o10).await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        // Other users only get the permissions given to everyone
+        let other = ClientBuilder::new(&dfs.url).with_user("other").build()?;
+        other.check_access("/accessdir", 0o5).await?;
+        assert!(matches!(
+            other.check_access("/accessdir", 0o3).await,
+            Err(HdfsError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            other.check_access("/accessdir/file", 0o4).await,
+            Err(HdfsError::PermissionDenied(_))
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test]
     #[serial]
     #[cfg(feature = "kerberos")]
@@ -24,6 +93,71 @@ mod test {
             .unwrap();
     }
 
+    #[tokio::test]
+    #[serial]
+    #[cfg(feature = "kerberos")]
+    async fn test_security_kerberos_keytab() -> Result<()> {
+        let dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::SECURITY]));
+        // Clear the ticket cache from the test setup so the client has to log in itself
+        std::process::Command::new("kdestroy").status()?;
+
+        let client = hdfs_native::ClientBuilder::new(&dfs.url)
+            .with_kerberos_keytab("target/test/hdfs.keytab", "hdfs/localhost")
+            .build()?;
+        client.list_status("/", false).await?;
+        client.mkdirs("/keytab", 0o777, true).await?;
+        client.set_permission("/keytab", 0o777).await?;
+
+        // Each client logs in to its own ticket cache, so a second principal doesn't replace the
+        // first one, and the default ticket cache stays empty
+        let alice = hdfs_native::ClientBuilder::new(&dfs.url)
+            .with_kerberos_keytab("target/test/alice.keytab", "alice")
+            .build()?;
+        for (client, path, owner) in [
+            (&alice, "/keytab/alice", "alice"),
+            (&client, "/keytab/hdfs", "hdfs"),
+        ] {
+            client
+                .create(path, WriteOptions::default())
+                .await?
+                .close()
+                .await?;
+            assert_eq!(client.get_file_info(path).await?.owner.as_ref(), owner);
+        }
+        assert!(!std::process::Command::new("klist")
+            .arg("-s")
+            .status()?
+            .success());
+
+        // Dropping a client doesn't wait for its renewer, and removes its ticket cache
+        drop(alice);
+        client.list_status("/keytab", false).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg(feature = "kerberos")]
+    async fn test_delegation_tokens() -> Result<()> {
+        let dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::SECURITY]));
+        let client = Client::new(&dfs.url)?;
+
+        let token = client.get_delegation_token("hdfs").await?;
+        assert_eq!(token.kind, "HDFS_DELEGATION_TOKEN");
+        assert_eq!(token.service, "127.0.0.1:9000");
+
+        let expiration = client.renew_delegation_token(&token).await?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        assert!(expiration > now);
+
+        client.cancel_delegation_token(&token).await?;
+        assert!(client.renew_delegation_token(&token).await.is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     #[serial]
     #[cfg(feature = "token")]
@@ -33,6 +167,34 @@ mod test {
             .unwrap();
     }
 
+    #[tokio::test]
+    #[serial]
+    #[cfg(all(feature = "kerberos", feature = "token"))]
+    async fn test_security_token_renewal() -> Result<()> {
+        let _dfs = MiniDfs::with_features(&HashSet::from([
+            DfsFeatures::SECURITY,
+            DfsFeatures::TOKEN,
+            DfsFeatures::TOKEN_EXPIRY,
+        ]));
+
+        // The token expires after 5 seconds, and only the renewer can keep it valid
+        let client = ClientBuilder::default()
+            .with_kerberos_keytab("target/test/hdfs.keytab", "hdfs/localhost")
+            .with_config(HashMap::from([(
+                "dfs.client.delegation.token.auto-renew".to_string(),
+                "true".to_string(),
+            )]))
+            .build()?;
+        client.list_status("/", false).await?;
+
+        tokio::time::sleep(std::time::Duration::from_secs(12)).await;
+
+        client.list_status("/", false).await?;
+        // A new client without Kerberos credentials connects with the renewed token
+        Client::default().list_status("/", false).await?;
+        Ok(())
+    }
+
     #[tokio::test]
     #[ignore]
     #[serial]
@@ -59,6 +221,39 @@ mod test {
         .unwrap();
     }
 
+    #[tokio::test]
+    #[serial]
+    #[cfg(feature = "kerberos")]
+    async fn test_data_transfer_integrity() {
+        test_with_features(&HashSet::from([
+            DfsFeatures::SECURITY,
+            DfsFeatures::DATA_TRANSFER_SECURITY,
+        ]))
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg(feature = "kerberos")]
+    async fn test_data_transfer_privacy() {
+        test_with_features(&HashSet::from([
+            DfsFeatures::SECURITY,
+            DfsFeatures::DATA_TRANSFER_SECURITY,
+            DfsFeatures::PRIVACY,
+        ]))
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_data_transfer_encryption() {
+        test_with_features(&HashSet::from([DfsFeatures::DATA_TRANSFER_ENCRYPTION]))
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     #[serial]
     async fn test_basic_ha() {
@@ -67,6 +262,33 @@ mod test {
             .unwrap();
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_safe_mode_ha() -> Result<()> {
+        let dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::HA]));
+        let client = Client::new(&dfs.url)?;
+        let waiting = ClientBuilder::new(&dfs.url)
+            .with_safe_mode_wait(Duration::from_secs(30))
+            .build()?;
+
+        assert!(client.set_safe_mode(SafeModeAction::Enter).await?);
+        assert!(matches!(
+            client.mkdirs("/safemode", 0o755, true).await,
+            Err(HdfsError::SafeMode(_))
+        ));
+
+        // A client configured to wait retries until the NameNode leaves safe mode
+        let mkdirs = tokio::spawn(async move { waiting.mkdirs("/safemode", 0o755, true).await });
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(!mkdirs.is_finished());
+        assert!(!client.set_safe_mode(SafeModeAction::Leave).await?);
+        mkdirs.await.unwrap()?;
+        assert!(client.get_file_info("/safemode").await?.isdir);
+
+        client.delete("/safemode", true).await?;
+        Ok(())
+    }
+
     #[tokio::test]
     #[serial]
     #[cfg(feature = "kerberos")]
@@ -101,218 +323,2070 @@ mod test {
             .unwrap();
     }
 
-    pub async fn test_with_features(features: &HashSet<DfsFeatures>) -> Result<()> {
+    #[tokio::test]
+    #[serial]
+    async fn test_rbf_mount_table() -> Result<()> {
         let _ = env_logger::builder().is_test(true).try_init();
 
-        let _dfs = setup(features);
-        let client = Client::default();
-
-        test_file_info(&client).await?;
-        test_listing(&client).await?;
-        test_read(&client).await?;
-        test_rename(&client).await?;
-        test_dirs(&client).await?;
-        test_create(&client).await?;
-        test_append(&client).await?;
-        // We use writing to create files, so do this after
-        test_recursive_listing(&client).await?;
+        // The router mounts / on ns0 and /ns1 on hdfs://ns1/target-ns1
+        let dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::RBF]));
+        let router = Client::new(&dfs.url)?;
+        let ns1 = Client::new("hdfs://ns1")?;
 
-        Ok(())
-    }
+        for path in ["/rbf/file", "/ns1/rbf/file"] {
+            let mut writer = router.create(path, WriteOptions::default()).await?;
+            writer.write(Bytes::from(path)).await?;
+            writer.close().await?;
+        }
 
-    fn assert_bufs_equal(buf1: &impl Buf, buf2: &impl Buf, message: Option<String>) {
-        assert_eq!(buf1.chunk().len(), buf2.chunk().len());
+        // The file written through /ns1 only lives on the second subcluster
+        assert_eq!(
+            ns1.get_file_info("/target-ns1/rbf/file").await?.length,
+            "/ns1/rbf/file".len()
+        );
+        assert!(matches!(
+            ns1.get_file_info("/rbf/file").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
 
-        let message = message.unwrap_or_default();
+        for path in ["/rbf/file", "/ns1/rbf/file"] {
+            let status = router.get_file_info(path).await?;
+            assert_eq!(status.path, path);
 
-        buf1.chunk()
-            .iter()
-            .zip(buf2.chunk())
-            .enumerate()
-            .for_each(move |(i, (b1, b2))| {
-                assert_eq!(b1, b2, "data is different as position {} {}", i, message)
-            });
-    }
+            let listing = router
+                .list_status(path.trim_end_matches("/file"), false)
+                .await?;
+            assert_eq!(listing.len(), 1);
+            assert_eq!(listing[0].path, path);
 
-    async fn test_file_info(client: &Client) -> Result<()> {
-        let status = client.get_file_info("/testfile").await?;
-        // Path is empty, I guess because we already know what file we just got the info for?
-        assert_eq!(status.path, "/testfile");
-        assert_eq!(status.length, TEST_FILE_INTS * 4);
-        Ok(())
-    }
+            let reader = router.read(path).await?;
+            assert_eq!(reader.read_range(0, path.len()).await?, Bytes::from(path));
+        }
 
-    async fn test_listing(client: &Client) -> Result<()> {
-        let statuses: Vec<FileStatus> = client
+        assert!(router
             .list_status("/", false)
             .await?
-            .into_iter()
-            // Only include files, since federation things could result in folders being created
-            .filter(|s| !s.isdir)
-            .collect();
-        assert_eq!(statuses.len(), 1);
-        let status = &statuses[0];
-        assert_eq!(status.path, "/testfile");
-        assert_eq!(status.length, TEST_FILE_INTS * 4);
+            .iter()
+            .any(|status| status.path == "/ns1" && status.isdir));
+
+        router.delete("/rbf", true).await?;
+        router.delete("/ns1/rbf", true).await?;
+
         Ok(())
     }
 
-    async fn test_read(client: &Client) -> Result<()> {
-        // Read the whole file
-        let reader = client.read("/testfile").await?;
-        let mut buf = reader.read_range(0, TEST_FILE_INTS * 4).await?;
-        for i in 0..TEST_FILE_INTS as i32 {
-            assert_eq!(buf.get_i32(), i);
+    #[tokio::test]
+    #[serial]
+    async fn test_multiple_clients() -> Result<()> {
+        let dfs = MiniDfs::with_features(&HashSet::new());
+        let second_dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::SECOND_CLUSTER]));
+        assert_ne!(dfs.url, second_dfs.url);
+
+        let admin = Client::new(&dfs.url)?;
+        let second_admin = Client::new(&second_dfs.url)?;
+        for client in [&admin, &second_admin] {
+            client.mkdirs("/multi", 0o777, true).await?;
+            client.set_permission("/multi", 0o777).await?;
         }
 
-        // Read a single integer from the file
-        let mut buf = reader.read_range(TEST_FILE_INTS / 2 * 4, 4).await?;
-        assert_eq!(buf.get_i32(), TEST_FILE_INTS as i32 / 2);
+        let alice = ClientBuilder::new(&dfs.url)
+            .with_user("alice")
+            .with_config(HashMap::from([(
+                "dfs.replication".to_string(),
+                "2".to_string(),
+            )]))
+            .build()?;
 
-        // Read the whole file in 1 MiB chunks
-        let mut offset = 0;
-        let mut val = 0;
-        while offset < TEST_FILE_INTS * 4 {
-            let mut buf = reader.read_range(offset, 1024 * 1024).await?;
-            while !buf.is_empty() {
-                assert_eq!(buf.get_i32(), val);
-                val += 1;
-            }
-            offset += 1024 * 1024;
-        }
+        let conf_dir = tempfile::tempdir()?;
+        std::fs::write(
+            conf_dir.path().join("core-site.xml"),
+            format!(
+                "<configuration><property><name>fs.defaultFS</name><value>{}</value></property></configuration>",
+                second_dfs.url
+            ),
+        )?;
+        let bob = ClientBuilder::default()
+            .with_config_dir(conf_dir.path())
+            .with_user("bob")
+            .build()?;
+
+        let write_options = WriteOptions::default();
+        let (alice_file, bob_file) = futures::join!(
+            async {
+                alice
+                    .create("/multi/alice", &write_options)
+                    .await?
+                    .close()
+                    .await
+            },
+            async {
+                bob.create("/multi/bob", &write_options)
+                    .await?
+                    .close()
+                    .await
+            },
+        );
+        alice_file?;
+        bob_file?;
+
+        let alice_status = admin.get_file_info("/multi/alice").await?;
+        assert_eq!(&*alice_status.owner, "alice");
+        assert_eq!(alice_status.replication, 2);
+        let bob_status = second_admin.get_file_info("/multi/bob").await?;
+        assert_eq!(&*bob_status.owner, "bob");
+        // The cluster default, since only alice's client sets dfs.replication
+        assert_eq!(bob_status.replication, 1);
 
+        // Each client only ever talked to its own cluster
+        assert!(matches!(
+            admin.get_file_info("/multi/bob").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            second_admin.get_file_info("/multi/alice").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            bob.get_file_info("/multi/alice").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            alice.get_file_info("/multi/bob").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+
+        admin.delete("/multi", true).await?;
+        second_admin.delete("/multi", true).await?;
         Ok(())
     }
 
-    async fn test_rename(client: &Client) -> Result<()> {
-        client.rename("/testfile", "/testfile2", false).await?;
+    #[tokio::test]
+    #[serial]
+    #[cfg(unix)]
+    async fn test_short_circuit_reads() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dfs = setup(&HashSet::from([DfsFeatures::SHORT_CIRCUIT]));
 
-        assert!(client.list_status("/testfile", false).await.is_err());
-        assert_eq!(client.list_status("/testfile2", false).await?.len(), 1);
+        #[derive(Default)]
+        struct ShortCircuitRecorder {
+            bytes: AtomicUsize,
+        }
 
-        client.rename("/testfile2", "/testfile", false).await?;
-        assert!(client.list_status("/testfile2", false).await.is_err());
-        assert_eq!(client.list_status("/testfile", false).await?.len(), 1);
+        impl MetricsRecorder for ShortCircuitRecorder {
+            fn record_short_circuit_bytes_read(&self, bytes: usize) {
+                self.bytes.fetch_add(bytes, Ordering::SeqCst);
+            }
+        }
+
+        // Returns how many of the bytes read came from short-circuit reads
+        let check_read = |builder: ClientBuilder| async move {
+            let recorder = Arc::new(ShortCircuitRecorder::default());
+            let client = builder.with_metrics(recorder.clone()).build()?;
+            let reader = client.read("/testfile").await?;
+            let mut buf = reader.read_range(0, TEST_FILE_INTS * 4).await?;
+            for i in 0..TEST_FILE_INTS as i32 {
+                assert_eq!(buf.get_i32(), i);
+            }
+            let mut buf = reader.read_range(TEST_FILE_INTS / 2 * 4 + 4, 8).await?;
+            assert_eq!(buf.get_i32(), TEST_FILE_INTS as i32 / 2 + 1);
+            assert_eq!(buf.get_i32(), TEST_FILE_INTS as i32 / 2 + 2);
+            Ok::<_, HdfsError>(recorder.bytes.load(Ordering::SeqCst))
+        };
+
+        // The config written by the MiniDFS has short-circuit reads enabled, so everything is
+        // read from the local block files
+        assert_eq!(
+            check_read(ClientBuilder::default()).await?,
+            TEST_FILE_INTS * 4 + 8
+        );
+
+        // Reads fall back to TCP when the domain socket can't be connected to
+        let builder = ClientBuilder::new(&dfs.url).with_config(HashMap::from([
+            (
+                "dfs.client.read.shortcircuit".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "dfs.domain.socket.path".to_string(),
+                "target/test/missing_socket".to_string(),
+            ),
+        ]));
+        assert_eq!(check_read(builder).await?, 0);
 
         Ok(())
     }
 
-    async fn test_dirs(client: &Client) -> Result<()> {
-        client.mkdirs("/testdir", 0o755, false).await?;
-        assert!(client
-            .list_status("/testdir", false)
-            .await
-            .is_ok_and(|s| s.is_empty()));
+    #[tokio::test]
+    #[serial]
+    async fn test_block_token_expiry() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let _dfs = setup(&HashSet::from([DfsFeatures::BLOCK_TOKEN_EXPIRY]));
+        let client = Client::default();
 
-        client.delete("/testdir", false).await?;
-        assert!(client.list_status("/testdir", false).await.is_err());
+        let reader = client.read("/testfile").await?;
+        let mut buf = reader.read_range(0, 8).await?;
+        assert_eq!(buf.get_i32(), 0);
+        assert_eq!(buf.get_i32(), 1);
 
-        client.mkdirs("/testdir1/testdir2", 0o755, true).await?;
-        assert!(client
-            .list_status("/testdir1", false)
-            .await
-            .is_ok_and(|s| s.len() == 1));
+        // The MiniDFS issues block tokens that expire after a few seconds, so the reader has to
+        // fetch new ones from the NameNode
+        tokio::time::sleep(std::time::Duration::from_secs(6)).await;
 
-        // Deleting non-empty dir without recursive fails
-        assert!(client.delete("/testdir1", false).await.is_err());
-        assert!(client.delete("/testdir1", true).await.is_ok_and(|r| r));
+        let mut buf = reader.read_range(0, TEST_FILE_INTS * 4).await?;
+        for i in 0..TEST_FILE_INTS as i32 {
+            assert_eq!(buf.get_i32(), i);
+        }
 
         Ok(())
     }
 
-    async fn test_create(client: &Client) -> Result<()> {
-        let write_options = WriteOptions::default().overwrite(true);
-
-        // Create an empty file
-        let mut writer = client.create("/newfile", &write_options).await?;
-
-        writer.close().await?;
+    #[tokio::test]
+    #[serial]
+    async fn test_read_access_time() -> Result<()> {
+        let _dfs = setup(&HashSet::from([DfsFeatures::BLOCK_TOKEN_EXPIRY]));
+        let client = Client::default();
 
-        assert_eq!(client.get_file_info("/newfile").await?.length, 0);
+        // An access time older than the NameNode's precision would be updated by any call to
+        // getBlockLocations
+        client.set_times("/testfile", None, Some(1_000_000)).await?;
 
-        // Check a small files, a file that is exactly one block, and a file slightly bigger than a block
-        for size_to_check in [16i32, 128 * 1024 * 1024, 130 * 1024 * 1024] {
-            let ints_to_write = size_to_check / 4;
+        // Opening the file, refreshing its length and refetching expired block tokens leave the
+        // access time alone
+        let mut reader = client.read("/testfile").await?;
+        reader.read_range(0, 8).await?;
+        assert_eq!(reader.read_tail(8).await?.len(), 8);
+        tokio::time::sleep(std::time::Duration::from_secs(6)).await;
+        reader.read_range(0, TEST_FILE_INTS * 4).await?;
+        assert_eq!(
+            client.get_file_info("/testfile").await?.access_time,
+            1_000_000
+        );
 
-            let mut writer = client.create("/newfile", &write_options).await?;
+        client.get_block_locations("/testfile", 0, 8).await?;
+        assert!(client.get_file_info("/testfile").await?.access_time > 1_000_000);
 
-            let mut data = BytesMut::with_capacity(size_to_check as usize);
-            for i in 0..ints_to_write {
-                data.put_i32(i);
-            }
+        Ok(())
+    }
 
-            let buf = data.freeze();
+    #[tokio::test]
+    #[serial]
+    async fn test_read_from_datanode() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
 
-            writer.write(buf.clone()).await?;
-            writer.close().await?;
+        // The EC cluster has enough DataNodes to store multiple replicas
+        let _dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::EC]));
+        let client = Client::default();
 
-            assert_eq!(
-                client.get_file_info("/newfile").await?.length,
-                size_to_check as usize
-            );
+        let mut data = BytesMut::new();
+        for i in 0..1024 * 1024 {
+            data.put_i32(i);
+        }
+        let data = data.freeze();
 
-            let mut reader = client.read("/newfile").await?;
-            let read_data = reader.read(reader.file_length()).await?;
+        let mut writer = client
+            .create("/replicated", WriteOptions::default().replication(3))
+            .await?;
+        writer.write(data.clone()).await?;
+        writer.close().await?;
 
+        // Every replica can be read on its own
+        let reader = client.read("/replicated").await?;
+        let blocks = client
+            .get_block_locations("/replicated", 0, data.len() as u64)
+            .await?;
+        assert_eq!(blocks[0].names.len(), 3);
+        for datanode in blocks[0].names.iter() {
             assert_bufs_equal(
-                &buf,
-                &read_data,
-                Some(format!("for size {}", size_to_check)),
+                &data,
+                &reader
+                    .read_range_from_datanode(0, data.len(), datanode)
+                    .await?,
+                None,
             );
         }
+        assert!(matches!(
+            reader
+                .read_range_from_datanode(0, data.len(), "unknown:9866")
+                .await,
+            Err(HdfsError::BlocksNotFound(_))
+        ));
 
-        assert!(client.delete("/newfile", false).await.is_ok_and(|r| r));
+        client.delete("/replicated", false).await?;
 
         Ok(())
     }
 
-    async fn test_append(client: &Client) -> Result<()> {
-        // Create an empty file
-        client
-            .create("/newfile", WriteOptions::default())
-            .await?
-            .close()
-            .await?;
+    #[tokio::test]
+    #[serial]
+    async fn test_replica_failures() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
 
-        assert_eq!(client.get_file_info("/newfile").await?.length, 0);
+        // The EC cluster has enough DataNodes to store multiple replicas
+        let _dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::EC]));
+        let client = Client::default();
 
-        // Keep track of what should be in the file
-        let mut file_contents = BytesMut::new();
+        let mut data = BytesMut::new();
+        for i in 0..1024 * 1024 {
+            data.put_i32(i);
+        }
+        let data = data.freeze();
 
-        // Test a few different things with each range:
+        let mut writer = client
+            .create("/replicated", WriteOptions::default().replication(3))
+            .await?;
+        writer.write(data.clone()).await?;
+        writer.close().await?;
+
+        // A corrupt replica is detected and the read falls back to another one
+        let _ = REPLICA_FAULT_INJECTOR
+            .lock()
+            .unwrap()
+            .insert(ReplicaFaultInjection {
+                corrupt_replicas: vec![0],
+                ..Default::default()
+            });
+        let reader = client.read("/replicated").await?;
+        assert_bufs_equal(&data, &reader.read_range(0, data.len()).await?, None);
+
+        // The error is returned once every replica is corrupt
+        let _ = REPLICA_FAULT_INJECTOR
+            .lock()
+            .unwrap()
+            .insert(ReplicaFaultInjection {
+                corrupt_replicas: vec![0, 1, 2],
+                ..Default::default()
+            });
+        assert!(matches!(
+            reader.read_range(0, data.len()).await,
+            Err(HdfsError::ChecksumError { offset: 0, .. })
+        ));
+
+        // Without checksums the corrupt data is returned as is
+        let unverified_client = ClientBuilder::default()
+            .with_verify_checksums(false)
+            .build()?;
+        let unverified = unverified_client
+            .read("/replicated")
+            .await?
+            .read_range(0, data.len())
+            .await?;
+        assert_eq!(unverified.len(), data.len());
+        assert_ne!(unverified, data);
+
+        // Failed DataNode connections fall back to the other replicas
+        let _ = REPLICA_FAULT_INJECTOR
+            .lock()
+            .unwrap()
+            .insert(ReplicaFaultInjection {
+                fail_replicas: vec![0, 1],
+                ..Default::default()
+            });
+        assert_bufs_equal(&data, &reader.read_range(0, data.len()).await?, None);
+
+        let _ = REPLICA_FAULT_INJECTOR
+            .lock()
+            .unwrap()
+            .insert(ReplicaFaultInjection {
+                fail_replicas: vec![0, 1, 2],
+                ..Default::default()
+            });
+        assert!(matches!(
+            reader.read_range(0, data.len()).await,
+            Err(HdfsError::BlocksNotFound(_))
+        ));
+
+        let _ = REPLICA_FAULT_INJECTOR.lock().unwrap().take();
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_read_buffer_pool() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dfs = setup(&HashSet::new());
+
+        // The pool is smaller than everything read, so buffers are reused between reads
+        let client = ClientBuilder::new(&dfs.url)
+            .with_read_buffer_pool_size(8 * 1024 * 1024)
+            .build()?;
+
+        let len = 4 * 1024 * 1024;
+        let block_size = 128 * 1024 * 1024;
+        // Includes ranges that span the two blocks of the file and end at its last byte
+        let offsets: Vec<usize> = (0..16)
+            .map(|i| i * 4 * 1024 * 1024 + 12)
+            .chain([block_size - len / 2, TEST_FILE_INTS * 4 - len])
+            .collect();
+
+        for _ in 0..2 {
+            let reads = offsets.iter().map(|&offset| {
+                let client = &client;
+                async move {
+                    let reader = client.read("/testfile").await?;
+                    let buf = reader.read_range(offset, len).await?;
+                    Ok::<_, HdfsError>((offset, buf))
+                }
+            });
+            for result in futures::future::join_all(reads).await {
+                let (offset, mut buf) = result?;
+                assert_eq!(buf.len(), len);
+                for i in 0..(len / 4) as i32 {
+                    assert_eq!(buf.get_i32(), (offset / 4) as i32 + i);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg(feature = "encryption")]
+    async fn test_encryption_zone() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let _dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::KMS]));
+        let client = Client::default();
+
+        let mut data = BytesMut::new();
+        for i in 0..1024 * 1024 {
+            data.put_i32(i);
+        }
+        let data = data.freeze();
+
+        // The MiniDFS creates /ez as an encryption zone with a key from its KMS
+        for path in ["/ez", "/ez/file"] {
+            let zone = client.get_ez_for_path(path).await?.unwrap();
+            assert_eq!(zone.path, "/ez");
+            assert_eq!(zone.key_name, "key");
+        }
+        assert_eq!(client.get_ez_for_path("/").await?, None);
+
+        let mut writer = client.create("/ez/file", WriteOptions::default()).await?;
+        writer.write(data.slice(..data.len() / 2)).await?;
+        writer.write(data.slice(data.len() / 2..)).await?;
+        writer.close().await?;
+
+        let reader = client.read("/ez/file").await?;
+        assert_bufs_equal(&data, &reader.read_range(0, data.len()).await?, None);
+        let mut buf = reader.read_range(4 * 1000 + 4, 8).await?;
+        assert_eq!(buf.get_i32(), 1001);
+        assert_eq!(buf.get_i32(), 1002);
+
+        // Appended data is encrypted with the same key
+        let mut writer = client.append("/ez/file").await?;
+        writer.write(data.slice(..4)).await?;
+        writer.close().await?;
+        let reader = client.read("/ez/file").await?;
+        assert_eq!(reader.file_length(), data.len() + 4);
+        let mut buf = reader.read_range(data.len(), 4).await?;
+        assert_eq!(buf.get_i32(), 0);
+
+        // The raw file only contains the encrypted data
+        let reader = client.read("/.reserved/raw/ez/file").await?;
+        assert_eq!(reader.file_length(), data.len() + 4);
+        assert_ne!(reader.read_range(0, data.len()).await?, data);
+
+        client.delete("/ez/file", false).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg(feature = "encryption")]
+    async fn test_encryption_zone_trash() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::KMS]));
+        let client = ClientBuilder::new(&dfs.url)
+            .with_user("alice")
+            .with_config(HashMap::from([(
+                "fs.trash.interval".to_string(),
+                "1".to_string(),
+            )]))
+            .build()?;
+
+        let mut writer = client.create("/ez/file", WriteOptions::default()).await?;
+        writer.write(Bytes::from_static(b"encrypted")).await?;
+        writer.close().await?;
+
+        // Files in an encryption zone are moved to a trash inside the zone
+        assert_eq!(client.get_trash_root("/ez").await?, "/ez/.Trash/alice");
+        assert_eq!(client.get_trash_root("/ez/file").await?, "/ez/.Trash/alice");
+        assert_eq!(client.get_trash_root("/").await?, "/user/alice/.Trash");
+
+        let trash_path = client.move_to_trash("/ez/file", false).await?;
+        assert_eq!(
+            trash_path.as_deref(),
+            Some("/ez/.Trash/alice/Current/ez/file")
+        );
+        assert!(!client.exists("/ez/file").await?);
+        let reader = client.read(&trash_path.unwrap()).await?;
+        assert_eq!(
+            reader.read_range(0, 9).await?,
+            Bytes::from_static(b"encrypted")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_writer_dropped_without_close() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dfs = MiniDfs::with_features(&HashSet::new());
+        let other = Client::new(&dfs.url)?;
+
+        let write_and_drop = |client: Client, path: &'static str| async move {
+            let mut writer = client.create(path, WriteOptions::default()).await?;
+            writer.write(Bytes::from_static(b"flushed")).await?;
+            writer.hflush().await?;
+            writer.write(Bytes::from_static(b"lost")).await?;
+            drop(writer);
+            Ok::<_, HdfsError>(client)
+        };
+
+        // By default only a warning is logged, and the file stays open with the dropped client's
+        // lease, so no one else can append to it
+        let _client = write_and_drop(Client::new(&dfs.url)?, "/dropped").await?;
+        assert!(other.append("/dropped").await.is_err());
+
+        // The lease can be recovered in the background instead, which closes the file with the
+        // data that was flushed
+        let client = ClientBuilder::new(&dfs.url)
+            .with_recover_lease_on_drop(true)
+            .build()?;
+        let _client = write_and_drop(client, "/recovered").await?;
+        let mut attempts = 0;
+        let mut writer = loop {
+            match other.append("/recovered").await {
+                Ok(writer) => break writer,
+                // Not closed until the last block has been recovered
+                Err(_) if attempts < 30 => {
+                    attempts += 1;
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        writer.close().await?;
+        let reader = other.read("/recovered").await?;
+        assert_eq!(
+            reader.read_range(0, 7).await?,
+            Bytes::from_static(b"flushed")
+        );
+        assert_eq!(reader.file_length(), 7);
+
+        while !other.recover_lease("/dropped").await? {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_write_pipeline_failures() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        // The EC cluster has enough DataNodes to replace a failed one
+        let mut dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::EC]));
+        let client = Client::default();
+
+        let mut data = BytesMut::new();
+        for i in 0..1024 * 1024 {
+            data.put_i32(i);
+        }
+        let data = data.freeze();
+
+        // The stopped DataNode is removed from the pipeline and the write continues
+        let mut writer = client
+            .create("/pipeline", WriteOptions::default().replication(3))
+            .await?;
+        writer.write(data.slice(..data.len() / 2)).await?;
+        let stopped = dfs.stop_pipeline_datanode("/pipeline");
+        writer.write(data.slice(data.len() / 2..)).await?;
+        writer.close().await?;
+
+        let reader = client.read("/pipeline").await?;
+        assert_bufs_equal(&data, &reader.read_range(0, data.len()).await?, None);
+        let locations = client
+            .get_block_locations("/pipeline", 0, data.len() as u64)
+            .await?;
+        assert!(!locations[0].names.contains(&stopped));
+
+        // After a flush, the stopped DataNode is replaced with a new one. The write fails if no
+        // replacement can be added, since best effort isn't enabled.
+        let mut writer = client
+            .create("/pipeline_flushed", WriteOptions::default().replication(3))
+            .await?;
+        writer.write(data.slice(..data.len() / 2)).await?;
+        writer.hflush().await?;
+        let stopped = dfs.stop_pipeline_datanode("/pipeline_flushed");
+        writer.write(data.slice(data.len() / 2..)).await?;
+        writer.close().await?;
+
+        let reader = client.read("/pipeline_flushed").await?;
+        assert_bufs_equal(&data, &reader.read_range(0, data.len()).await?, None);
+        let locations = client
+            .get_block_locations("/pipeline_flushed", 0, data.len() as u64)
+            .await?;
+        assert_eq!(locations[0].names.len(), 3);
+        assert!(!locations[0].names.contains(&stopped));
+
+        Ok(())
+    }
+
+    pub async fn test_with_features(features: &HashSet<DfsFeatures>) -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let dfs = setup(features);
+        let client = Client::default();
+
+        test_file_info(&client).await?;
+        test_content_summary(&client).await?;
+        test_fs_status(&client).await?;
+        test_block_locations(&client).await?;
+        test_file_checksum(&client).await?;
+        test_listing(&client).await?;
+        test_read(&client).await?;
+        test_rename(&client).await?;
+        test_dirs(&client).await?;
+        test_create(&client).await?;
+        test_append(&client).await?;
+        test_flush(&client).await?;
+        test_follow(&client).await?;
+        test_read_under_construction(&client).await?;
+        test_async_write(&client).await?;
+        test_set_attributes(&client).await?;
+        test_truncate(&client).await?;
+        test_recover_lease(&client).await?;
+        test_concat(&client).await?;
+        test_xattrs(&client).await?;
+        test_acls(&client).await?;
+        test_snapshots(&client).await?;
+        test_storage_policies(&client).await?;
+        test_caching(&client).await?;
+        test_symlinks(&client).await?;
+        test_quotas(&client).await?;
+        test_trash(&client, &dfs.url).await?;
+        // We use writing to create files, so do this after
+        test_recursive_listing(&client).await?;
+        test_glob(&client).await?;
+
+        Ok(())
+    }
+
+    fn assert_bufs_equal(buf1: &impl Buf, buf2: &impl Buf, message: Option<String>) {
+        assert_eq!(buf1.chunk().len(), buf2.chunk().len());
+
+        let message = message.unwrap_or_default();
+
+        buf1.chunk()
+            .iter()
+            .zip(buf2.chunk())
+            .enumerate()
+            .for_each(move |(i, (b1, b2))| {
+                assert_eq!(b1, b2, "data is different as position {} {}", i, message)
+            });
+    }
+
+    async fn test_file_info(client: &Client) -> Result<()> {
+        assert!(client.exists("/testfile").await?);
+        assert!(!client.exists("/nonexistent").await?);
+        assert!(matches!(
+            client.get_file_info("/nonexistent").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+
+        let status = client.get_file_info("/testfile").await?;
+        // Path is empty, I guess because we already know what file we just got the info for?
+        assert_eq!(status.path, "/testfile");
+        assert_eq!(status.length, TEST_FILE_INTS * 4);
+
+        let statuses = client
+            .get_file_infos(&["/testfile", "/nonexistent", "/"])
+            .await;
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(
+            statuses[0].as_ref().unwrap().as_ref().unwrap().path,
+            "/testfile"
+        );
+        assert!(statuses[1].as_ref().unwrap().is_none());
+        assert!(statuses[2].as_ref().unwrap().as_ref().unwrap().isdir);
+        Ok(())
+    }
+
+    async fn test_content_summary(client: &Client) -> Result<()> {
+        let summary = client.get_content_summary("/testfile").await?;
+        assert_eq!(summary.length, TEST_FILE_INTS as u64 * 4);
+        assert_eq!(summary.file_count, 1);
+        assert_eq!(summary.directory_count, 0);
+
+        assert!(matches!(
+            client.get_content_summary("/nonexistent").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        Ok(())
+    }
+
+    async fn test_fs_status(client: &Client) -> Result<()> {
+        assert!(!client.is_in_safe_mode().await?);
+
+        // Writes fail while the NameNode is in safe mode
+        assert!(client.set_safe_mode(SafeModeAction::Enter).await?);
+        assert!(client.set_safe_mode(SafeModeAction::Get).await?);
+        assert!(matches!(
+            client.mkdirs("/safemode", 0o755, true).await,
+            Err(HdfsError::SafeMode(_))
+        ));
+        assert!(!client.set_safe_mode(SafeModeAction::Leave).await?);
+        assert!(!client.is_in_safe_mode().await?);
+
+        let status = client.get_fs_status().await?;
+        assert!(status.capacity > 0);
+        assert!(status.used > 0);
+        assert!(status.used + status.remaining <= status.capacity);
+        assert_eq!(status.missing_blocks, 0);
+        assert_eq!(status.corrupt_blocks, 0);
+        Ok(())
+    }
+
+    async fn test_block_locations(client: &Client) -> Result<()> {
+        const BLOCK_SIZE: u64 = 128 * 1024 * 1024;
+        let file_length = TEST_FILE_INTS as u64 * 4;
+
+        let blocks = client
+            .get_block_locations("/testfile", 0, file_length)
+            .await?;
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].offset, 0);
+        assert_eq!(blocks[0].length, BLOCK_SIZE);
+        assert_eq!(blocks[1].offset, BLOCK_SIZE);
+        assert_eq!(blocks[1].length, file_length - BLOCK_SIZE);
+        for block in blocks.iter() {
+            assert!(!block.hosts.is_empty());
+            assert_eq!(block.hosts.len(), block.names.len());
+            assert!(!block.corrupt);
+        }
+
+        // Only blocks overlapping the range are returned
+        let blocks = client
+            .get_block_locations("/testfile", 0, BLOCK_SIZE)
+            .await?;
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].offset, 0);
+
+        let blocks = client
+            .get_block_locations("/testfile", BLOCK_SIZE - 1, 2)
+            .await?;
+        assert_eq!(blocks.len(), 2);
+
+        let blocks = client
+            .get_block_locations("/testfile", BLOCK_SIZE, 1)
+            .await?;
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].offset, BLOCK_SIZE);
+
+        assert!(matches!(
+            client.get_block_locations("/nonexistent", 0, 1).await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+
+        // Listing with locations returns the same blocks without another call per file
+        let statuses = client.list_status_with_locations("/").await?;
+        let located = statuses
+            .iter()
+            .find(|located| located.status.path == "/testfile")
+            .unwrap();
+        assert_eq!(located.status.length as u64, file_length);
+        assert_eq!(located.blocks.len(), 2);
+        assert_eq!(located.blocks[1].offset, BLOCK_SIZE);
+        assert_eq!(located.blocks[1].length, file_length - BLOCK_SIZE);
+        assert!(statuses
+            .iter()
+            .filter(|located| located.status.isdir)
+            .all(|located| located.blocks.is_empty()));
+
+        Ok(())
+    }
+
+    async fn test_file_checksum(client: &Client) -> Result<()> {
+        let checksum = client.get_file_checksum("/testfile").await?;
+        assert_eq!(checksum.algorithm, "MD5-of-0MD5-of-512CRC32C");
+        assert_eq!(checksum.bytes.len(), 28);
+
+        client
+            .create("/emptyfile", WriteOptions::default())
+            .await?
+            .close()
+            .await?;
+        let checksum = client.get_file_checksum("/emptyfile").await?;
+        assert_eq!(checksum.algorithm, "MD5-of-0MD5-of-0CRC32");
+        client.delete("/emptyfile", false).await?;
+
+        // Files written with the same checksum settings have the same checksum
+        let data = Bytes::from(vec![7u8; 10000]);
+        let options = WriteOptions::default()
+            .overwrite(true)
+            .checksum_type(ChecksumType::Crc32)
+            .bytes_per_checksum(1024);
+        let mut checksums = Vec::new();
+        for path in ["/crc32file1", "/crc32file2"] {
+            let mut writer = client.create(path, &options).await?;
+            writer.write(data.clone()).await?;
+            writer.close().await?;
+            assert_eq!(client.read(path).await?.read_range(0, 10000).await?, data);
+            checksums.push(client.get_file_checksum(path).await?);
+            client.delete(path, false).await?;
+        }
+        assert_eq!(checksums[0].algorithm, "MD5-of-0MD5-of-1024CRC32");
+        assert_eq!(checksums[0], checksums[1]);
+
+        // Data without checksums can still be read back
+        let mut writer = client
+            .create(
+                "/nullchecksumfile",
+                WriteOptions::default().checksum_type(ChecksumType::Null),
+            )
+            .await?;
+        writer.write(data.clone()).await?;
+        writer.close().await?;
+        assert_eq!(
+            client
+                .read("/nullchecksumfile")
+                .await?
+                .read_range(0, 10000)
+                .await?,
+            data
+        );
+        client.delete("/nullchecksumfile", false).await?;
+
+        Ok(())
+    }
+
+    async fn test_listing(client: &Client) -> Result<()> {
+        let statuses: Vec<FileStatus> = client
+            .list_status("/", false)
+            .await?
+            .into_iter()
+            // Only include files, since federation things could result in folders being created
+            .filter(|s| !s.isdir)
+            .collect();
+        assert_eq!(statuses.len(), 1);
+        let status = &statuses[0];
+        assert_eq!(status.path, "/testfile");
+        assert_eq!(status.length, TEST_FILE_INTS * 4);
+        assert!(!status.is_directory());
+        assert!(!status.is_symlink());
+        assert!(status.replication > 0);
+        assert!(status.block_size > 0);
+        assert!(!status.owner.is_empty());
+        assert!(!status.group.is_empty());
+        assert!(status.modification_time > 0);
+        Ok(())
+    }
+
+    async fn test_read(client: &Client) -> Result<()> {
+        // Read the whole file
+        let reader = client.read("/testfile").await?;
+        let mut buf = reader.read_range(0, TEST_FILE_INTS * 4).await?;
+        for i in 0..TEST_FILE_INTS as i32 {
+            assert_eq!(buf.get_i32(), i);
+        }
+
+        // Read a single integer from the file
+        let mut buf = reader.read_range(TEST_FILE_INTS / 2 * 4, 4).await?;
+        assert_eq!(buf.get_i32(), TEST_FILE_INTS as i32 / 2);
+
+        // Read into a reused buffer, with a short read at the end of the file
+        let mut scratch = [0u8; 8];
+        assert_eq!(reader.read_into(0, &mut scratch).await?, 8);
+        assert_eq!((&scratch[4..]).get_i32(), 1);
+        assert_eq!(
+            reader
+                .read_into(TEST_FILE_INTS * 4 - 4, &mut scratch)
+                .await?,
+            4
+        );
+        assert_eq!((&scratch[..4]).get_i32(), TEST_FILE_INTS as i32 - 1);
+        assert_eq!(reader.read_into(TEST_FILE_INTS * 4, &mut scratch).await?, 0);
+
+        // Read the end of the file, clamped to the file length
+        let mut reader = client.read("/testfile").await?;
+        let mut buf = reader.read_tail(8).await?;
+        assert_eq!(buf.get_i32(), TEST_FILE_INTS as i32 - 2);
+        assert_eq!(buf.get_i32(), TEST_FILE_INTS as i32 - 1);
+        assert_eq!(
+            reader.read_tail(TEST_FILE_INTS * 8).await?.len(),
+            TEST_FILE_INTS * 4
+        );
+
+        // Stream the whole file through AsyncRead
+        let mut async_reader = client.read("/testfile").await?.into_async_read();
+        let mut contents = Vec::new();
+        async_reader.read_to_end(&mut contents).await?;
+        assert_eq!(contents.len(), TEST_FILE_INTS * 4);
+        assert_eq!(async_reader.position(), TEST_FILE_INTS * 4);
+        let mut buf = &contents[..];
+        for i in 0..TEST_FILE_INTS as i32 {
+            assert_eq!(buf.get_i32(), i);
+        }
+
+        // Read the whole file with read-ahead disabled and with several blocks of read-ahead
+        for read_ahead_blocks in [0, 4] {
+            let mut reader = client.read("/testfile").await?;
+            reader.set_read_ahead_blocks(read_ahead_blocks);
+            let mut buf = reader.read_range(0, TEST_FILE_INTS * 4).await?;
+            for i in 0..TEST_FILE_INTS as i32 {
+                assert_eq!(buf.get_i32(), i);
+            }
+        }
+
+        // Read the whole file with several blocks fetched at the same time
+        let mut parallel_reader = client.read("/testfile").await?;
+        parallel_reader.set_read_parallelism(4);
+        let mut buf = parallel_reader.read_range(0, TEST_FILE_INTS * 4).await?;
+        for i in 0..TEST_FILE_INTS as i32 {
+            assert_eq!(buf.get_i32(), i);
+        }
+        let mut contents = vec![0u8; TEST_FILE_INTS * 4];
+        parallel_reader.read_range_buf(&mut contents, 0).await?;
+        let mut buf = &contents[..];
+        for i in 0..TEST_FILE_INTS as i32 {
+            assert_eq!(buf.get_i32(), i);
+        }
+
+        // Connect to the DataNodes by the hostnames they registered with
+        let hostname_client = ClientBuilder::default()
+            .with_use_datanode_hostname(true)
+            .build()?;
+        let mut buf = hostname_client
+            .read("/testfile")
+            .await?
+            .read_range(0, 8)
+            .await?;
+        assert_eq!(buf.get_i32(), 0);
+        assert_eq!(buf.get_i32(), 1);
+
+        // Report the progress of a read of the whole file
+        let mut progress_reader = client.read("/testfile").await?;
+        let progress = Arc::new(AtomicUsize::new(0));
+        let callback_progress = Arc::clone(&progress);
+        progress_reader.set_progress_callback(move |bytes| {
+            callback_progress.fetch_max(bytes, Ordering::Relaxed);
+        });
+        progress_reader.read_range(0, 1024 * 1024).await?;
+        assert_eq!(progress.load(Ordering::Relaxed), 1024 * 1024);
+        progress_reader.read_range(0, TEST_FILE_INTS * 4).await?;
+        assert_eq!(
+            progress.load(Ordering::Relaxed),
+            1024 * 1024 + TEST_FILE_INTS * 4
+        );
+
+        // Read many small ranges, which reuse connections to the DataNodes
+        for i in (0..TEST_FILE_INTS).step_by(TEST_FILE_INTS / 100) {
+            let mut buf = reader.read_range(i * 4, 8).await?;
+            assert_eq!(buf.get_i32(), i as i32);
+            assert_eq!(buf.get_i32(), i as i32 + 1);
+        }
+
+        // Read the whole file in 1 MiB chunks
+        let mut offset = 0;
+        let mut val = 0;
+        while offset < TEST_FILE_INTS * 4 {
+            let mut buf = reader.read_range(offset, 1024 * 1024).await?;
+            while !buf.is_empty() {
+                assert_eq!(buf.get_i32(), val);
+                val += 1;
+            }
+            offset += 1024 * 1024;
+        }
+
+        Ok(())
+    }
+
+    async fn test_rename(client: &Client) -> Result<()> {
+        client.rename("/testfile", "/testfile2", false).await?;
+
+        assert!(client.list_status("/testfile", false).await.is_err());
+        assert_eq!(client.list_status("/testfile2", false).await?.len(), 1);
+
+        client.rename("/testfile2", "/testfile", false).await?;
+        assert!(client.list_status("/testfile2", false).await.is_err());
+        assert_eq!(client.list_status("/testfile", false).await?.len(), 1);
+
+        // Each reason for a failed rename has its own error
+        assert!(matches!(
+            client.rename("/missing", "/testfile2", false).await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            client.rename("/testfile", "/missing/testfile", false).await,
+            Err(HdfsError::ParentNotFound(_))
+        ));
+        client.mkdirs("/renamedir", 0o755, false).await?;
+        client
+            .create("/renamedir/file", WriteOptions::default())
+            .await?
+            .close()
+            .await?;
+        assert!(matches!(
+            client.rename("/testfile", "/renamedir/file", false).await,
+            Err(HdfsError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            client
+                .rename("/renamedir", "/renamedir/nested", false)
+                .await,
+            Err(HdfsError::InvalidPath(_))
+        ));
+        client.delete("/renamedir", true).await?;
+
+        // Missing parents of the destination are created, but not if the source is missing
+        client
+            .rename_with_mkdirs("/testfile", "/renamedir/2024/01/testfile", false)
+            .await?;
+        assert_eq!(
+            client.get_file_info("/renamedir/2024/01").await?.permission,
+            0o755
+        );
+        client
+            .rename_with_mkdirs("/renamedir/2024/01/testfile", "/testfile", false)
+            .await?;
+        assert!(matches!(
+            client
+                .rename_with_mkdirs("/missing", "/renamedir/2024/02/missing", false)
+                .await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            client.get_file_info("/renamedir/2024/02").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        client.delete("/renamedir", true).await?;
+
+        Ok(())
+    }
+
+    async fn test_dirs(client: &Client) -> Result<()> {
+        client.mkdirs("/testdir", 0o755, false).await?;
+        assert!(client
+            .list_status("/testdir", false)
+            .await
+            .is_ok_and(|s| s.is_empty()));
+
+        client.delete("/testdir", false).await?;
+        assert!(matches!(
+            client.list_status("/testdir", false).await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+
+        // Missing parent without create_parent fails
+        assert!(matches!(
+            client.mkdirs("/testdir1/testdir2", 0o755, false).await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+
+        client.mkdirs("/testdir1/testdir2", 0o777, true).await?;
+        // The default umask of 022 is applied
+        assert_eq!(client.get_file_info("/testdir1").await?.permission, 0o755);
+        assert!(client
+            .list_status("/testdir1", false)
+            .await
+            .is_ok_and(|s| s.len() == 1));
+
+        // Deleting non-empty dir without recursive fails
+        assert!(matches!(
+            client.delete("/testdir1", false).await,
+            Err(HdfsError::DirectoryNotEmpty(_))
+        ));
+        assert!(client.delete("/testdir1", true).await.is_ok_and(|r| r));
+
+        // Deleting a path that doesn't exist returns false
+        assert!(client.delete("/testdir1", true).await.is_ok_and(|r| !r));
+
+        // Relative paths are resolved against the home directory
+        client.mkdirs("testdir", 0o755, true).await?;
+        let home_testdir = format!("{}/testdir", client.home_directory());
+        assert_eq!(
+            client.get_file_info(&home_testdir).await?.path,
+            home_testdir
+        );
+        assert_eq!(client.get_file_info("testdir").await?.path, home_testdir);
+        assert_eq!(client.get_file_info("~/testdir").await?.path, home_testdir);
+        assert!(client
+            .delete("./nested/../testdir", false)
+            .await
+            .is_ok_and(|r| r));
+        assert!(client.get_file_info(&home_testdir).await.is_err());
+
+        Ok(())
+    }
+
+    async fn test_create(client: &Client) -> Result<()> {
+        let write_options = WriteOptions::default().overwrite(true);
+
+        // Create an empty file
+        let mut writer = client.create("/newfile", &write_options).await?;
+
+        writer.close().await?;
+
+        assert_eq!(client.get_file_info("/newfile").await?.length, 0);
+
+        // Settings not given in the write options come from the server defaults
+        let defaults = client.get_server_defaults().await?;
+        let status = client.get_file_info("/newfile").await?;
+        assert_eq!(status.block_size, defaults.block_size);
+        assert_eq!(status.replication as u32, defaults.replication);
+        assert_eq!(defaults.checksum_type, ChecksumType::Crc32c);
+
+        // Replication and block size are passed through to the NameNode, and the small packets
+        // are sent without waiting for more than two acknowledgements at a time
+        MAX_PACKETS_IN_FLIGHT.store(0, Ordering::SeqCst);
+        let mut writer = client
+            .create(
+                "/newfile",
+                write_options
+                    .clone()
+                    .replication(2)
+                    .block_size(256 * 1024 * 1024)
+                    .packet_size(16 * 1024)
+                    .max_packets_in_flight(2),
+            )
+            .await?;
+        writer.write(Bytes::from(vec![0u8; 100 * 1024])).await?;
+        writer.close().await?;
+        assert!((1..=2).contains(&MAX_PACKETS_IN_FLIGHT.load(Ordering::SeqCst)));
+        let status = client.get_file_info("/newfile").await?;
+        assert_eq!(status.replication, 2);
+        assert_eq!(status.block_size, 256 * 1024 * 1024);
+        assert_eq!(status.length, 100 * 1024);
+
+        // Appends are held to the same window
+        MAX_PACKETS_IN_FLIGHT.store(0, Ordering::SeqCst);
+        let mut writer = client
+            .append_with_options(
+                "/newfile",
+                WriteOptions::default()
+                    .packet_size(16 * 1024)
+                    .max_packets_in_flight(1),
+            )
+            .await?;
+        writer.write(Bytes::from(vec![0u8; 100 * 1024])).await?;
+        writer.close().await?;
+        assert_eq!(MAX_PACKETS_IN_FLIGHT.load(Ordering::SeqCst), 1);
+        assert_eq!(client.get_file_info("/newfile").await?.length, 200 * 1024);
+
+        // Creating an existing file without overwrite fails
+        assert!(matches!(
+            client.create("/newfile", WriteOptions::default()).await,
+            Err(HdfsError::AlreadyExists(_))
+        ));
+
+        // Check a small files, a file that is exactly one block, and a file slightly bigger than a block
+        for size_to_check in [16i32, 128 * 1024 * 1024, 130 * 1024 * 1024] {
+            let ints_to_write = size_to_check / 4;
+
+            let mut writer = client.create("/newfile", &write_options).await?;
+
+            let mut data = BytesMut::with_capacity(size_to_check as usize);
+            for i in 0..ints_to_write {
+                data.put_i32(i);
+            }
+
+            let buf = data.freeze();
+
+            writer.write(buf.clone()).await?;
+            writer.close().await?;
+
+            assert_eq!(
+                client.get_file_info("/newfile").await?.length,
+                size_to_check as usize
+            );
+
+            let mut reader = client.read("/newfile").await?;
+            let read_data = reader.read(reader.file_length()).await?;
+
+            assert_bufs_equal(
+                &buf,
+                &read_data,
+                Some(format!("for size {}", size_to_check)),
+            );
+        }
+
+        assert!(client.delete("/newfile", false).await.is_ok_and(|r| r));
+
+        Ok(())
+    }
+
+    async fn test_append(client: &Client) -> Result<()> {
+        // Create an empty file
+        client
+            .create("/newfile", WriteOptions::default())
+            .await?
+            .close()
+            .await?;
+
+        assert_eq!(client.get_file_info("/newfile").await?.length, 0);
+
+        // Keep track of what should be in the file
+        let mut file_contents = BytesMut::new();
+
+        // A reader opened before the appends, to check read_tail sees the new data
+        let mut tail_reader = client.read("/newfile").await?;
+
+        // Test a few different things with each range:
+
+        for range in [
+            // Append a few bytes to an empty file
+            0u32..4,
+            // Append a few bytes to a partial chunk
+            4..8,
+            // Append multiple chunks to a file
+            8..2048,
+            // Append to the file filling up the block
+            2048..(128 * 1024 * 1024),
+            // Append some bytes to a new block
+            0..511,
+            // Append to a chunk with only one byte missing
+            512..1024,
+        ] {
+            let mut data = BytesMut::new();
+            for i in range {
+                file_contents.put_u8((i % 256) as u8);
+                data.put_u8((i % 256) as u8);
+            }
+
+            let buf = data.freeze();
+
+            let mut writer = client.append("/newfile").await?;
+            assert_eq!(writer.pos() as usize, file_contents.len() - buf.len());
+            assert_eq!(
+                writer.block_size(),
+                client.get_file_info("/newfile").await?.block_size
+            );
+            writer.write(buf).await?;
+            assert_eq!(writer.pos() as usize, file_contents.len());
+            writer.close().await?;
+
+            let mut reader = client.read("/newfile").await?;
+            let read_data = reader.read(reader.file_length()).await?;
+
+            assert_bufs_equal(&file_contents, &read_data, None);
+
+            let tail = tail_reader.read_tail(16).await?;
+            assert_eq!(tail_reader.file_length(), file_contents.len());
+            assert_eq!(
+                tail,
+                file_contents[file_contents.len().saturating_sub(16)..]
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn test_set_attributes(client: &Client) -> Result<()> {
+        client
+            .create("/testfile3", WriteOptions::default())
+            .await?
+            .close()
+            .await?;
+
+        client.set_permission("/testfile3", 0o600).await?;
+        assert_eq!(client.get_file_info("/testfile3").await?.permission, 0o600);
+
+        client
+            .set_owner("/testfile3", Some("testuser"), Some("testgroup"))
+            .await?;
+        let status = client.get_file_info("/testfile3").await?;
+        assert_eq!(&*status.owner, "testuser");
+        assert_eq!(&*status.group, "testgroup");
+
+        // Leaving owner or group out keeps the existing value
+        client
+            .set_owner("/testfile3", None, Some("testgroup2"))
+            .await?;
+        let status = client.get_file_info("/testfile3").await?;
+        assert_eq!(&*status.owner, "testuser");
+        assert_eq!(&*status.group, "testgroup2");
+
+        client
+            .set_owner("/testfile3", Some("testuser2"), None)
+            .await?;
+        let status = client.get_file_info("/testfile3").await?;
+        assert_eq!(&*status.owner, "testuser2");
+        assert_eq!(&*status.group, "testgroup2");
+
+        assert!(client.set_replication("/testfile3", 2).await?);
+        assert!(matches!(
+            client.set_replication("/testfile3", 0).await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        client
+            .set_times("/testfile3", Some(1_000_000), Some(2_000_000))
+            .await?;
+        let status = client.get_file_info("/testfile3").await?;
+        assert_eq!(status.modification_time, 1_000_000);
+        assert_eq!(status.access_time, 2_000_000);
+
+        // Unset times are left unchanged
+        client
+            .set_times("/testfile3", Some(3_000_000), None)
+            .await?;
+        let status = client.get_file_info("/testfile3").await?;
+        assert_eq!(status.modification_time, 3_000_000);
+        assert_eq!(status.access_time, 2_000_000);
+
+        client.set_times("/testfile3", None, Some(-1)).await?;
+        let status = client.get_file_info("/testfile3").await?;
+        assert_eq!(status.modification_time, 3_000_000);
+        assert_eq!(status.access_time, 2_000_000);
+
+        let listing = client.list_status("/", false).await?;
+        let status = listing.iter().find(|s| s.path == "/testfile3").unwrap();
+        assert_eq!(status.modification_time, 3_000_000);
+
+        client.mkdirs("/testdir3", 0o755, false).await?;
+        assert!(!client.set_replication("/testdir3", 2).await?);
+
+        // Directories support changing the modification time
+        client.set_times("/testdir3", Some(4_000_000), None).await?;
+        let listing = client.list_status("/", false).await?;
+        let status = listing.iter().find(|s| s.path == "/testdir3").unwrap();
+        assert_eq!(status.modification_time, 4_000_000);
+
+        client.delete("/testdir3", false).await?;
+
+        client.delete("/testfile3", false).await?;
+
+        Ok(())
+    }
+
+    async fn test_truncate(client: &Client) -> Result<()> {
+        let mut data = BytesMut::new();
+        for i in 0..2048u32 {
+            data.put_u8((i % 256) as u8);
+        }
+        let data = data.freeze();
+
+        let mut writer = client
+            .create("/truncatefile", WriteOptions::default())
+            .await?;
+        writer.write(data.clone()).await?;
+        writer.close().await?;
+
+        assert!(matches!(
+            client.truncate("/truncatefile", 4096).await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        // Truncating to a block boundary completes immediately
+        assert!(client.truncate("/truncatefile", 0).await?);
+        assert_eq!(client.get_file_info("/truncatefile").await?.length, 0);
+
+        // Truncating in the middle of a block requires block recovery
+        let mut writer = client.append("/truncatefile").await?;
+        writer.write(data.clone()).await?;
+        writer.close().await?;
+        assert!(!client.truncate("/truncatefile", 1024).await?);
+        assert_eq!(client.get_file_info("/truncatefile").await?.length, 1024);
+
+        client.delete("/truncatefile", false).await?;
+
+        Ok(())
+    }
 
-        for range in [
-            // Append a few bytes to an empty file
-            0u32..4,
-            // Append a few bytes to a partial chunk
-            4..8,
-            // Append multiple chunks to a file
-            8..2048,
-            // Append to the file filling up the block
-            2048..(128 * 1024 * 1024),
-            // Append some bytes to a new block
-            0..511,
-            // Append to a chunk with only one byte missing
-            512..1024,
-        ] {
+    async fn test_recover_lease(client: &Client) -> Result<()> {
+        // A file closed normally has no lease to recover
+        client
+            .create("/recoverfile", WriteOptions::default())
+            .await?
+            .close()
+            .await?;
+        assert!(client.recover_lease("/recoverfile").await?);
+
+        // Another client recovers a file left open with data in its last block
+        let writer_client = Client::default();
+        let mut writer = writer_client
+            .create("/recoverfile", WriteOptions::default().overwrite(true))
+            .await?;
+        writer.write(Bytes::from(vec![1u8; 4096])).await?;
+        writer.hflush().await?;
+        // Leaves the file open like a crashed writer
+        drop(writer);
+
+        let mut recovered = false;
+        for _ in 0..30 {
+            if client.recover_lease("/recoverfile").await? {
+                recovered = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        assert!(recovered);
+        assert_eq!(client.get_file_info("/recoverfile").await?.length, 4096);
+        client.append("/recoverfile").await?.close().await?;
+
+        assert!(matches!(
+            client.recover_lease("/missingfile").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+
+        client.delete("/recoverfile", false).await?;
+        Ok(())
+    }
+
+    async fn test_concat(client: &Client) -> Result<()> {
+        const BLOCK_SIZE: usize = 1024 * 1024;
+
+        async fn create_file(
+            client: &Client,
+            path: &str,
+            data: &[u8],
+            block_size: usize,
+        ) -> Result<()> {
+            let write_options = WriteOptions {
+                block_size: Some(block_size as u64),
+                ..Default::default()
+            };
+            let mut writer = client.create(path, write_options).await?;
+            writer.write(Bytes::copy_from_slice(data)).await?;
+            writer.close().await
+        }
+
+        let mut data = BytesMut::new();
+        for i in 0..(2 * BLOCK_SIZE + 100) {
+            data.put_u8((i % 256) as u8);
+        }
+        let data = data.freeze();
+
+        create_file(client, "/concat/target", &data[..BLOCK_SIZE], BLOCK_SIZE).await?;
+        create_file(
+            client,
+            "/concat/source1",
+            &data[BLOCK_SIZE..2 * BLOCK_SIZE],
+            BLOCK_SIZE,
+        )
+        .await?;
+        create_file(
+            client,
+            "/concat/source2",
+            &data[2 * BLOCK_SIZE..],
+            BLOCK_SIZE,
+        )
+        .await?;
+        create_file(client, "/concat/other", &data[..100], 2 * BLOCK_SIZE).await?;
+
+        // Only the last source can have a partial block
+        assert!(matches!(
+            client
+                .concat("/concat/target", &["/concat/source2", "/concat/source1"])
+                .await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        // All files must have the same block size
+        assert!(matches!(
+            client.concat("/concat/target", &["/concat/other"]).await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        assert!(matches!(
+            client.concat("/concat/target", &[]).await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        client
+            .concat("/concat/target", &["/concat/source1", "/concat/source2"])
+            .await?;
+
+        // The sources are removed
+        assert!(client.get_file_info("/concat/source1").await.is_err());
+        assert!(client.get_file_info("/concat/source2").await.is_err());
+
+        let mut reader = client.read("/concat/target").await?;
+        assert_eq!(reader.file_length(), data.len());
+        assert_bufs_equal(&data, &reader.read(data.len()).await?, None);
+
+        client.delete("/concat", true).await?;
+
+        Ok(())
+    }
+
+    async fn test_xattrs(client: &Client) -> Result<()> {
+        client
+            .create("/xattrfile", WriteOptions::default())
+            .await?
+            .close()
+            .await?;
+
+        assert!(client.list_xattrs("/xattrfile").await?.is_empty());
+
+        client
+            .set_xattr(
+                "/xattrfile",
+                "user.attr1",
+                b"value1".to_vec(),
+                XAttrSetFlag::Create,
+            )
+            .await?;
+        client
+            .set_xattr(
+                "/xattrfile",
+                "user.attr2",
+                b"value2".to_vec(),
+                XAttrSetFlag::CreateOrReplace,
+            )
+            .await?;
+
+        // Create fails if the attribute exists and replace fails if it doesn't
+        assert!(client
+            .set_xattr("/xattrfile", "user.attr1", vec![], XAttrSetFlag::Create)
+            .await
+            .is_err());
+        assert!(client
+            .set_xattr("/xattrfile", "user.attr3", vec![], XAttrSetFlag::Replace)
+            .await
+            .is_err());
+        client
+            .set_xattr(
+                "/xattrfile",
+                "user.attr1",
+                b"new".to_vec(),
+                XAttrSetFlag::Replace,
+            )
+            .await?;
+
+        assert!(matches!(
+            client
+                .set_xattr("/xattrfile", "attr", vec![], XAttrSetFlag::Create)
+                .await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        assert_eq!(client.get_xattr("/xattrfile", "user.attr1").await?, b"new");
+        assert_eq!(
+            client.get_xattrs("/xattrfile").await?,
+            HashMap::from([
+                ("user.attr1".to_string(), b"new".to_vec()),
+                ("user.attr2".to_string(), b"value2".to_vec()),
+            ])
+        );
+
+        let mut names = client.list_xattrs("/xattrfile").await?;
+        names.sort();
+        assert_eq!(names, vec!["user.attr1", "user.attr2"]);
+
+        client.remove_xattr("/xattrfile", "user.attr1").await?;
+        assert!(client.get_xattr("/xattrfile", "user.attr1").await.is_err());
+        assert_eq!(client.list_xattrs("/xattrfile").await?, vec!["user.attr2"]);
+
+        client.delete("/xattrfile", false).await?;
+
+        Ok(())
+    }
+
+    async fn test_flush(client: &Client) -> Result<()> {
+        let mut writer = client
+            .create("/flushfile", WriteOptions::default().overwrite(true))
+            .await?;
+
+        // Flushed data is visible to new readers before the file is closed, including data that
+        // ends in the middle of a checksum chunk
+        assert_eq!(writer.pos(), 0);
+        writer.write(Bytes::from(vec![1u8; 1000])).await?;
+        assert_eq!(writer.pos(), 1000);
+        writer.hflush().await?;
+        assert_eq!(client.get_file_info("/flushfile").await?.length, 1000);
+        let mut reader = client.read("/flushfile").await?;
+        assert_eq!(reader.read(1000).await?, Bytes::from(vec![1u8; 1000]));
+
+        // Writing continues where the flush left off
+        writer.write(Bytes::from(vec![2u8; 100 * 1024])).await?;
+        writer.hsync().await?;
+        assert_eq!(
+            client.get_file_info("/flushfile").await?.length,
+            1000 + 100 * 1024
+        );
+        // A sync without any new data still succeeds
+        writer.hsync().await?;
+        writer.write(Bytes::from(vec![3u8; 10])).await?;
+        writer.close().await?;
+
+        let mut reader = client.read("/flushfile").await?;
+        let data = reader.read(reader.file_length()).await?;
+        assert_eq!(data.len(), 1000 + 100 * 1024 + 10);
+        assert!(data[..1000].iter().all(|b| *b == 1));
+        assert!(data[1000..1000 + 100 * 1024].iter().all(|b| *b == 2));
+        assert!(data[1000 + 100 * 1024..].iter().all(|b| *b == 3));
+
+        client.delete("/flushfile", false).await?;
+        Ok(())
+    }
+
+    async fn test_follow(client: &Client) -> Result<()> {
+        let mut writer = client
+            .create("/followfile", WriteOptions::default().overwrite(true))
+            .await?;
+        writer.write(Bytes::from(vec![1u8; 1000])).await?;
+        writer.hflush().await?;
+
+        // Follow the file in the background while it's still being written
+        let mut reader = client.read("/followfile").await?;
+        let follower = tokio::spawn(async move {
+            let mut stream = reader.follow(std::time::Duration::from_millis(50)).boxed();
             let mut data = BytesMut::new();
-            for i in range {
-                file_contents.put_u8((i % 256) as u8);
-                data.put_u8((i % 256) as u8);
+            while let Some(bytes) = stream.next().await {
+                data.put(bytes?);
             }
+            drop(stream);
+            Ok::<_, HdfsError>((data.freeze(), reader.remaining()))
+        });
 
-            let buf = data.freeze();
+        writer.write(Bytes::from(vec![2u8; 100 * 1024])).await?;
+        writer.hflush().await?;
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        writer.write(Bytes::from(vec![3u8; 10])).await?;
+        writer.close().await?;
 
-            let mut writer = client.append("/newfile").await?;
-            writer.write(buf).await?;
-            writer.close().await?;
+        // The stream ends once the file is closed and everything has been read
+        let (data, remaining) = follower.await.unwrap()?;
+        assert_eq!(data.len(), 1000 + 100 * 1024 + 10);
+        assert_eq!(remaining, 0);
+        assert!(data[..1000].iter().all(|b| *b == 1));
+        assert!(data[1000..1000 + 100 * 1024].iter().all(|b| *b == 2));
+        assert!(data[1000 + 100 * 1024..].iter().all(|b| *b == 3));
 
-            let mut reader = client.read("/newfile").await?;
-            let read_data = reader.read(reader.file_length()).await?;
+        client.delete("/followfile", false).await?;
+        Ok(())
+    }
 
-            assert_bufs_equal(&file_contents, &read_data, None);
+    async fn test_read_under_construction(client: &Client) -> Result<()> {
+        // Without a flush, the NameNode doesn't know how much of the block has been written,
+        // but the DataNodes make the data visible as soon as it's acknowledged
+        let mut writer = client
+            .create("/ucfile", WriteOptions::default().overwrite(true))
+            .await?;
+        writer.write(Bytes::from(vec![1u8; 1024 * 1024])).await?;
+
+        let mut reader = client.read("/ucfile").await?;
+        for _ in 0..50 {
+            if reader.file_length() > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            reader = client.read("/ucfile").await?;
+        }
+        assert!(reader.file_length() > 0);
+        assert_eq!(client.get_file_info("/ucfile").await?.length, 0);
+
+        let data = reader.read(reader.file_length()).await?;
+        assert!(data.iter().all(|b| *b == 1));
+
+        // Tailing the file follows the block as it grows
+        writer.write(Bytes::from(vec![2u8; 10])).await?;
+        writer.hflush().await?;
+        let tail = reader.read_tail(10).await?;
+        assert!(tail.iter().all(|b| *b == 2));
+        assert_eq!(reader.file_length(), 1024 * 1024 + 10);
+
+        writer.close().await?;
+        client.delete("/ucfile", false).await?;
+        Ok(())
+    }
+
+    async fn test_async_write(client: &Client) -> Result<()> {
+        // Copy the test file, which spans multiple blocks, from an AsyncRead into an AsyncWrite
+        let mut async_reader = client.read("/testfile").await?.into_async_read();
+        let mut async_writer = client
+            .create("/asyncfile", WriteOptions::default().overwrite(true))
+            .await?
+            .into_async_write();
+        let copied = tokio::io::copy(&mut async_reader, &mut async_writer).await?;
+        assert_eq!(copied as usize, TEST_FILE_INTS * 4);
+        async_writer.shutdown().await?;
+        // Shutting down again is a no-op, but writing after shutting down fails
+        async_writer.shutdown().await?;
+        assert!(async_writer.write_all(b"data").await.is_err());
+
+        let mut buf = client
+            .read("/asyncfile")
+            .await?
+            .read_range(0, TEST_FILE_INTS * 4)
+            .await?;
+        for i in 0..TEST_FILE_INTS as i32 {
+            assert_eq!(buf.get_i32(), i);
+        }
+
+        client.delete("/asyncfile", false).await?;
+        Ok(())
+    }
+
+    async fn test_acls(client: &Client) -> Result<()> {
+        client
+            .create(
+                "/aclfile",
+                WriteOptions {
+                    permission: 0o640,
+                    ..Default::default()
+                },
+            )
+            .await?
+            .close()
+            .await?;
+
+        let status = client.get_acl_status("/aclfile").await?;
+        assert!(status.entries.is_empty());
+        assert_eq!(status.permission & 0o777, 0o640);
+
+        let user_entry = AclEntry::new(AclEntryScope::Access, AclEntryType::User, Some("foo"), 0o7);
+        client
+            .modify_acl_entries("/aclfile", vec![user_entry.clone()])
+            .await?;
+
+        // The mask is recalculated to cover the new entry and replaces the group bits
+        let status = client.get_acl_status("/aclfile").await?;
+        assert!(status.entries.contains(&user_entry));
+        assert!(status.entries.contains(&AclEntry::new(
+            AclEntryScope::Access,
+            AclEntryType::Group,
+            None,
+            0o4
+        )));
+        assert_eq!(status.permission & 0o777, 0o670);
+
+        client
+            .remove_acl_entries(
+                "/aclfile",
+                vec![AclEntry::new(
+                    AclEntryScope::Access,
+                    AclEntryType::User,
+                    Some("foo"),
+                    0,
+                )],
+            )
+            .await?;
+        assert!(!client
+            .get_acl_status("/aclfile")
+            .await?
+            .entries
+            .contains(&user_entry));
+
+        client
+            .set_acl(
+                "/aclfile",
+                vec![
+                    AclEntry::new(AclEntryScope::Access, AclEntryType::User, None, 0o6),
+                    AclEntry::new(AclEntryScope::Access, AclEntryType::User, Some("bar"), 0o4),
+                    AclEntry::new(AclEntryScope::Access, AclEntryType::Group, None, 0o4),
+                    AclEntry::new(AclEntryScope::Access, AclEntryType::Mask, None, 0o4),
+                    AclEntry::new(AclEntryScope::Access, AclEntryType::Other, None, 0),
+                ],
+            )
+            .await?;
+        let status = client.get_acl_status("/aclfile").await?;
+        assert_eq!(status.entries.len(), 2);
+        assert_eq!(status.permission & 0o777, 0o640);
+
+        client.remove_acl("/aclfile").await?;
+        assert!(client.get_acl_status("/aclfile").await?.entries.is_empty());
+
+        client.delete("/aclfile", false).await?;
+
+        Ok(())
+    }
+
+    async fn test_snapshots(client: &Client) -> Result<()> {
+        client.mkdirs("/snapshotdir", 0o755, true).await?;
+        let mut writer = client
+            .create("/snapshotdir/file", WriteOptions::default())
+            .await?;
+        writer.write(Bytes::from_static(b"data")).await?;
+        writer.close().await?;
+
+        assert!(matches!(
+            client.create_snapshot("/snapshotdir", Some("snap1")).await,
+            Err(HdfsError::SnapshotError(_))
+        ));
+
+        client.allow_snapshot("/snapshotdir").await?;
+
+        let snapshot_path = client
+            .create_snapshot("/snapshotdir", Some("snap1"))
+            .await?;
+        assert_eq!(snapshot_path, "/snapshotdir/.snapshot/snap1");
+
+        // The snapshot is unaffected by changes to the directory
+        client.delete("/snapshotdir/file", false).await?;
+        let mut reader = client.read(&format!("{}/file", snapshot_path)).await?;
+        assert_eq!(reader.read(4).await?, Bytes::from_static(b"data"));
+
+        let generated_path = client.create_snapshot("/snapshotdir", None).await?;
+        assert!(generated_path.starts_with("/snapshotdir/.snapshot/"));
+        assert!(client.get_file_info(&generated_path).await?.isdir);
+
+        client
+            .rename_snapshot("/snapshotdir", "snap1", "snap2")
+            .await?;
+        assert!(client
+            .get_file_info("/snapshotdir/.snapshot/snap1")
+            .await
+            .is_err());
+        assert_eq!(
+            client
+                .get_file_info("/snapshotdir/.snapshot/snap2/file")
+                .await?
+                .length,
+            4
+        );
+
+        // Snapshots must be deleted before disallowing them
+        assert!(client.disallow_snapshot("/snapshotdir").await.is_err());
+        client.delete_snapshot("/snapshotdir", "snap2").await?;
+        client
+            .delete_snapshot("/snapshotdir", generated_path.rsplit_once('/').unwrap().1)
+            .await?;
+        client.disallow_snapshot("/snapshotdir").await?;
+
+        client.delete("/snapshotdir", true).await?;
+
+        Ok(())
+    }
+
+    async fn test_storage_policies(client: &Client) -> Result<()> {
+        let policies = client.list_storage_policies().await?;
+        assert!(policies.iter().any(|p| p.name == "HOT"));
+        assert!(policies.iter().any(|p| p.name == "COLD"));
+
+        client.mkdirs("/policydir", 0o755, true).await?;
+        assert_eq!(client.get_storage_policy("/policydir").await?.name, "HOT");
+
+        client.set_storage_policy("/policydir", "COLD").await?;
+        let policy = client.get_storage_policy("/policydir").await?;
+        assert_eq!(policy.name, "COLD");
+        assert_eq!(policy.storage_types, vec!["ARCHIVE".to_string()]);
+
+        // The error for an unknown policy lists the valid ones
+        match client.set_storage_policy("/policydir", "BOGUS").await {
+            Err(HdfsError::InvalidArgument(msg)) => assert!(msg.contains("COLD"), "{}", msg),
+            result => panic!("Unexpected result {:?}", result),
         }
 
+        client.unset_storage_policy("/policydir").await?;
+        assert_eq!(client.get_storage_policy("/policydir").await?.name, "HOT");
+
+        client.delete("/policydir", true).await?;
+
+        Ok(())
+    }
+
+    async fn test_caching(client: &Client) -> Result<()> {
+        let mut pool = CachePoolInfo::new("testpool");
+        pool.limit = Some(1024 * 1024);
+        client.add_cache_pool(pool).await?;
+        let pools = client.list_cache_pools().await?;
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].info.name, "testpool");
+        assert_eq!(pools[0].info.limit, Some(1024 * 1024));
+
+        client
+            .create("/cachefile", WriteOptions::default())
+            .await?
+            .close()
+            .await?;
+        let id = client
+            .add_cache_directive("/cachefile", "testpool", 1)
+            .await?;
+        let other_id = client.add_cache_directive("/", "testpool", 2).await?;
+
+        let directives = client
+            .list_cache_directives(CacheDirectiveFilter::default())
+            .await?;
+        assert_eq!(directives.len(), 2);
+        assert_eq!(directives[0].id, id);
+        assert_eq!(directives[0].path, "/cachefile");
+        assert_eq!(directives[0].pool, "testpool");
+        assert_eq!(directives[0].replication, 1);
+        assert_eq!(directives[1].id, other_id);
+
+        let filter = CacheDirectiveFilter {
+            path: Some("/cachefile".to_string()),
+            ..Default::default()
+        };
+        let directives = client.list_cache_directives(filter.clone()).await?;
+        assert_eq!(directives.len(), 1);
+        assert_eq!(directives[0].id, id);
+
+        client.remove_cache_directive(id).await?;
+        assert!(client.list_cache_directives(filter).await?.is_empty());
+
+        // Removing a pool removes its directives
+        client.remove_cache_pool("testpool").await?;
+        assert!(client.list_cache_pools().await?.is_empty());
+        assert!(client
+            .list_cache_directives(CacheDirectiveFilter::default())
+            .await?
+            .is_empty());
+
+        client.delete("/cachefile", false).await?;
+
+        Ok(())
+    }
+
+    async fn test_symlinks(client: &Client) -> Result<()> {
+        client.mkdirs("/linkdir", 0o755, true).await?;
+        client
+            .create_symlink("/linkdir/link", "/testfile", false)
+            .await?;
+        assert_eq!(client.get_link_target("/linkdir/link").await?, "/testfile");
+
+        let status = client.get_file_link_info("/linkdir/link").await?;
+        assert_eq!(status.symlink.as_deref(), Some("/testfile"));
+        assert_eq!(status.path, "/linkdir/link");
+
+        // Symlinks are followed
+        let status = client.get_file_info("/linkdir/link").await?;
+        assert_eq!(status.symlink, None);
+        assert_eq!(status.path, "/testfile");
+        assert_eq!(status.length, TEST_FILE_INTS * 4);
+
+        // Relative targets are resolved against the symlink's directory
+        client
+            .create_symlink("/linkdir/nested/relative", "../link", true)
+            .await?;
+        let status = client.get_file_info("/linkdir/nested/relative").await?;
+        assert_eq!(status.path, "/testfile");
+
+        // Symlinks in parent directories are followed too
+        client.mkdirs("/linkdir/target/dir", 0o755, true).await?;
+        client
+            .create_symlink("/linkdir/parent", "target", false)
+            .await?;
+        client
+            .create_symlink("/linkdir/target/dir/file", "/testfile", false)
+            .await?;
+        let status = client.get_file_info("/linkdir/parent/dir").await?;
+        assert!(status.isdir);
+        assert_eq!(status.path, "/linkdir/target/dir");
+        let status = client.get_file_info("/linkdir/parent/dir/file").await?;
+        assert_eq!(status.path, "/testfile");
+        assert_eq!(status.length, TEST_FILE_INTS * 4);
+
+        // Targets outside of HDFS are returned as is
+        client
+            .create_symlink("/linkdir/local", "file:///tmp/file", false)
+            .await?;
+        let status = client.get_file_info("/linkdir/local").await?;
+        assert_eq!(status.path, "/linkdir/local");
+        assert_eq!(status.symlink.as_deref(), Some("file:///tmp/file"));
+
+        client.delete("/linkdir", true).await?;
+
+        Ok(())
+    }
+
+    async fn test_quotas(client: &Client) -> Result<()> {
+        client.mkdirs("/quotadir", 0o755, true).await?;
+        let summary = client.get_content_summary("/quotadir").await?;
+        assert_eq!(summary.quota, -1);
+        assert_eq!(summary.space_quota, -1);
+
+        client
+            .set_quota("/quotadir", 10, 1024 * 1024 * 1024)
+            .await?;
+        client
+            .set_quota_by_storage_type("/quotadir", "SSD", 1024 * 1024)
+            .await?;
+        let summary = client.get_content_summary("/quotadir").await?;
+        assert_eq!(summary.quota, 10);
+        assert_eq!(summary.space_quota, 1024 * 1024 * 1024);
+        assert!(summary
+            .type_quotas
+            .iter()
+            .any(|q| q.storage_type == "SSD" && q.quota == 1024 * 1024));
+
+        // Only the space quota is cleared
+        client
+            .set_quota("/quotadir", QUOTA_DONT_SET, QUOTA_RESET)
+            .await?;
+        let summary = client.get_content_summary("/quotadir").await?;
+        assert_eq!(summary.quota, 10);
+        assert_eq!(summary.space_quota, -1);
+
+        assert!(matches!(
+            client
+                .set_quota_by_storage_type("/quotadir", "BOGUS", 1024)
+                .await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        client.delete("/quotadir", true).await?;
+
+        Ok(())
+    }
+
+    async fn test_trash(client: &Client, url: &str) -> Result<()> {
+        let create = |path: &'static str| async move {
+            client
+                .create(path, WriteOptions::default())
+                .await?
+                .close()
+                .await
+        };
+
+        // The trash is disabled by default
+        create("/trashfile").await?;
+        assert!(matches!(
+            client.move_to_trash("/trashfile", false).await,
+            Err(HdfsError::OperationFailed(_))
+        ));
+        assert_eq!(client.move_to_trash("/trashfile", true).await?, None);
+        assert!(!client.exists("/trashfile").await?);
+
+        let trash_client = ClientBuilder::new(url)
+            .with_config(HashMap::from([(
+                "fs.trash.interval".to_string(),
+                "1".to_string(),
+            )]))
+            .build()?;
+        let trash_current = format!("{}/.Trash/Current", trash_client.home_directory());
+
+        // Outside of an encryption zone, the trash is in the home directory
+        client.mkdirs("/trashdir", 0o755, true).await?;
+        assert_eq!(client.get_ez_for_path("/trashdir").await?, None);
+        assert_eq!(
+            trash_client.get_trash_root("/trashdir").await?,
+            format!("{}/.Trash", trash_client.home_directory())
+        );
+        create("/trashdir/file").await?;
+        let trash_path = trash_client.move_to_trash("/trashdir/file", false).await?;
+        assert_eq!(trash_path, Some(format!("{}/trashdir/file", trash_current)));
+        assert!(!client.exists("/trashdir/file").await?);
+        assert!(client.exists(&trash_path.unwrap()).await?);
+
+        // A second file at the same path gets a timestamp suffix
+        create("/trashdir/file").await?;
+        let trash_path = trash_client
+            .move_to_trash("/trashdir/file", false)
+            .await?
+            .unwrap();
+        assert!(trash_path.starts_with(&format!("{}/trashdir/file", trash_current)));
+        assert_ne!(trash_path, format!("{}/trashdir/file", trash_current));
+        assert!(client.exists(&trash_path).await?);
+
+        assert!(matches!(
+            trash_client.move_to_trash("/trashdir/missing", false).await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            trash_client.move_to_trash(&trash_path, false).await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        client
+            .delete(&format!("{}/.Trash", trash_client.home_directory()), true)
+            .await?;
+        client.delete("/trashdir", true).await?;
+
         Ok(())
     }
 
@@ -338,8 +2412,90 @@ mod test {
         let statuses = client.list_status("/dir", true).await?;
         assert_eq!(statuses.len(), 4);
 
+        for concurrency in [1, 4] {
+            let walked: Vec<String> = client
+                .list_status_recursive("/dir", concurrency)
+                .map(|s| s.map(|s| s.path))
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<_>>()?;
+            assert_eq!(
+                walked,
+                statuses.iter().map(|s| s.path.clone()).collect::<Vec<_>>()
+            );
+        }
+
+        // Removing a directory before it is traversed doesn't fail the listing
+        let mut iter = client.list_status_iter("/dir", true);
+        assert_eq!(iter.next().await.unwrap()?.path, "/dir/file1");
+        assert_eq!(iter.next().await.unwrap()?.path, "/dir/nested");
+        client.delete("/dir/nested", true).await?;
+        assert!(iter.next().await.is_none());
+
         client.delete("/dir", true).await?;
 
         Ok(())
     }
+
+    async fn test_glob(client: &Client) -> Result<()> {
+        let write_options = WriteOptions::default();
+        for path in [
+            "/glob/year=2023/month=01/part-0.parquet",
+            "/glob/year=2023/month=01/_SUCCESS",
+            "/glob/year=2023/month=02/part-0.parquet",
+            "/glob/year=2023/month=02/part-1.parquet",
+            "/glob/year=2024/month=01/part-0.parquet",
+        ] {
+            client.create(path, &write_options).await?.close().await?;
+        }
+
+        let glob = |pattern: &'static str| async move {
+            client
+                .glob(pattern)
+                .await
+                .map(|statuses| statuses.into_iter().map(|s| s.path).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            glob("/glob/year=2023/month=*/part-*.parquet").await?,
+            vec![
+                "/glob/year=2023/month=01/part-0.parquet",
+                "/glob/year=2023/month=02/part-0.parquet",
+                "/glob/year=2023/month=02/part-1.parquet",
+            ]
+        );
+        assert_eq!(
+            glob("/glob/year=202?/month=0[1]/*").await?,
+            vec![
+                "/glob/year=2023/month=01/_SUCCESS",
+                "/glob/year=2023/month=01/part-0.parquet",
+                "/glob/year=2024/month=01/part-0.parquet",
+            ]
+        );
+        assert_eq!(
+            glob("/glob/{year=2024/month=01,year=2023/month=02}/part-0.parquet").await?,
+            vec![
+                "/glob/year=2023/month=02/part-0.parquet",
+                "/glob/year=2024/month=01/part-0.parquet",
+            ]
+        );
+        assert_eq!(
+            glob("/glob/year={2023,2025}").await?,
+            vec!["/glob/year=2023"]
+        );
+        assert_eq!(glob("/glob/year=2023").await?, vec!["/glob/year=2023"]);
+        assert!(glob("/glob/year=2025/*").await?.is_empty());
+        assert!(glob("/glob/year=2023/month=01/_SUCCESS/*")
+            .await?
+            .is_empty());
+        assert!(matches!(
+            client.glob("/glob/[abc").await,
+            Err(HdfsError::InvalidArgument(_))
+        ));
+
+        client.delete("/glob", true).await?;
+
+        Ok(())
+    }
 }
@@ -5,7 +5,7 @@ mod common;
 mod test {
     use hdfs_native::{
         minidfs::{DfsFeatures, MiniDfs},
-        Client, Result, WriteOptions,
+        Client, HdfsError, Result, WriteOptions,
     };
     use serial_test::serial;
     use std::collections::HashSet;
@@ -42,6 +42,33 @@ mod test {
         test_rename(&viewfs, &hdfs1, &hdfs2).await.unwrap();
     }
 
+    #[tokio::test]
+    #[serial]
+    #[cfg(feature = "encryption")]
+    async fn test_viewfs_encryption_zone() -> Result<()> {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        // The zone is hdfs://ns0/nested/ez, which is visible through /mount1
+        let _dfs = MiniDfs::with_features(&HashSet::from([DfsFeatures::VIEWFS, DfsFeatures::KMS]));
+        let viewfs = Client::default();
+        let hdfs1 = Client::new("hdfs://ns0")?;
+
+        assert_eq!(
+            hdfs1.get_ez_for_path("/nested/ez").await?.unwrap().path,
+            "/nested/ez"
+        );
+        touch(&viewfs, "/mount1/ez/file").await;
+        for path in ["/mount1/ez", "/mount1/ez/file"] {
+            let zone = viewfs.get_ez_for_path(path).await?.unwrap();
+            assert_eq!(zone.path, "/mount1/ez");
+            assert_eq!(zone.key_name, "key");
+        }
+        assert_eq!(viewfs.get_ez_for_path("/mount1").await?, None);
+
+        viewfs.delete("/mount1/ez/file", false).await?;
+        Ok(())
+    }
+
     async fn test_file_info(viewfs: &Client, hdfs1: &Client, hdfs2: &Client) -> Result<()> {
         assert_eq!(
             viewfs.get_file_info("/mount1/file1").await?.path,
@@ -101,10 +128,10 @@ mod test {
             .await
             .unwrap();
 
-        assert!(viewfs
-            .rename("/mount1/file1", "/root/file1", false)
-            .await
-            .is_err());
+        assert!(matches!(
+            viewfs.rename("/mount1/file1", "/root/file1", false).await,
+            Err(HdfsError::CrossMountpoint(_))
+        ));
 
         Ok(())
     }
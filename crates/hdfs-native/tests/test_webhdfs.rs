@@ -0,0 +1,97 @@
+#[cfg(all(feature = "integration-test", feature = "webhdfs"))]
+mod test {
+    use bytes::Bytes;
+    use hdfs_native::{
+        minidfs::MiniDfs, webhdfs::WebHdfsClient, Client, HdfsError, Result, WriteOptions,
+    };
+    use serial_test::serial;
+    use std::collections::HashSet;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_webhdfs() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let _dfs = MiniDfs::with_features(&HashSet::new());
+        let client = Client::default();
+        let webhdfs = WebHdfsClient::new("webhdfs://127.0.0.1:9870").unwrap();
+
+        test_mkdirs(&client, &webhdfs).await.unwrap();
+        test_read(&client, &webhdfs).await.unwrap();
+        test_list_status(&client, &webhdfs).await.unwrap();
+    }
+
+    async fn test_mkdirs(client: &Client, webhdfs: &WebHdfsClient) -> Result<()> {
+        webhdfs.mkdirs("/webhdfs/nested", 0o755, true).await?;
+        let status = client.get_file_info("/webhdfs/nested").await?;
+        assert!(status.isdir);
+        assert_eq!(status.permission, 0o755);
+
+        assert!(matches!(
+            webhdfs.mkdirs("/missing/dir", 0o755, false).await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            client.get_file_info("/missing").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        Ok(())
+    }
+
+    async fn test_read(client: &Client, webhdfs: &WebHdfsClient) -> Result<()> {
+        let data = Bytes::from(
+            (0..100_000u32)
+                .flat_map(|i| i.to_be_bytes())
+                .collect::<Vec<_>>(),
+        );
+        let mut writer = client
+            .create("/webhdfs/file", WriteOptions::default())
+            .await?;
+        writer.write(data.clone()).await?;
+        writer.close().await?;
+
+        let status = webhdfs.get_file_info("/webhdfs/file").await?;
+        let expected = client.get_file_info("/webhdfs/file").await?;
+        assert_eq!(status.path, expected.path);
+        assert_eq!(status.length, data.len());
+        assert_eq!(status.owner, expected.owner);
+        assert_eq!(status.modification_time, expected.modification_time);
+        assert_eq!(status.block_size, expected.block_size);
+
+        let mut reader = webhdfs.read("/webhdfs/file").await?;
+        assert_eq!(reader.file_length(), data.len());
+        assert_eq!(reader.read(1000).await?, data.slice(..1000));
+        assert_eq!(reader.remaining(), data.len() - 1000);
+        assert_eq!(reader.read_range(5000, 10).await?, data.slice(5000..5010));
+        assert_eq!(reader.read(data.len()).await?, data.slice(1000..));
+        assert!(reader.read(1).await?.is_empty());
+
+        assert!(matches!(
+            webhdfs.read("/webhdfs/missing").await,
+            Err(HdfsError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            webhdfs.read("/webhdfs").await,
+            Err(HdfsError::IsADirectoryError(_))
+        ));
+        Ok(())
+    }
+
+    async fn test_list_status(client: &Client, webhdfs: &WebHdfsClient) -> Result<()> {
+        let paths = |statuses: Vec<hdfs_native::client::FileStatus>| {
+            statuses
+                .into_iter()
+                .map(|status| status.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            paths(webhdfs.list_status("/webhdfs", false).await?),
+            paths(client.list_status("/webhdfs", false).await?)
+        );
+        assert_eq!(
+            paths(webhdfs.list_status("/webhdfs", true).await?),
+            vec!["/webhdfs/file", "/webhdfs/nested"]
+        );
+        Ok(())
+    }
+}
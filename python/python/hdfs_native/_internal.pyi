@@ -18,6 +18,10 @@ class WriteOptions:
     permission: int
     overwrite: bool
     create_parent: bool
+    packet_size: Optional[int]
+    max_packets_in_flight: Optional[int]
+    checksum_type: Optional[str]
+    bytes_per_checksum: Optional[int]
 
 class RawFileReader:
     def file_length(self) -> int:
@@ -13,9 +13,15 @@ impl From<PythonHdfsError> for PyErr {
     fn from(value: PythonHdfsError) -> Self {
         match value.0 {
             HdfsError::IOError(err) => PyIOError::new_err(err),
-            HdfsError::FileNotFound(path) => PyFileNotFoundError::new_err(path),
+            HdfsError::FileNotFound(path) | HdfsError::ParentNotFound(path) => {
+                PyFileNotFoundError::new_err(path)
+            }
             HdfsError::IsADirectoryError(path) => PyIsADirectoryError::new_err(path),
+            HdfsError::PermissionDenied(msg) | HdfsError::ProxyUserNotAllowed(msg) => {
+                PyPermissionError::new_err(msg)
+            }
             HdfsError::UnsupportedFeature(feat) => PyNotImplementedError::new_err(feat),
+            HdfsError::InvalidUrl(message) => PyValueError::new_err(message),
             _ => PyRuntimeError::new_err(format!("{:?}", value.0)),
         }
     }
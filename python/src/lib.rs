@@ -2,11 +2,11 @@ use std::borrow::Cow;
 use std::sync::Arc;
 
 use ::hdfs_native::file::{FileReader, FileWriter};
-use ::hdfs_native::WriteOptions;
 use ::hdfs_native::{
     client::{FileStatus, ListStatusIterator},
     Client,
 };
+use ::hdfs_native::{HdfsError, WriteOptions};
 use bytes::Bytes;
 use log::LevelFilter;
 use pyo3::{exceptions::PyRuntimeError, prelude::*};
@@ -37,8 +37,8 @@ impl From<FileStatus> for PyFileStatus {
             length: value.length,
             isdir: value.isdir,
             permission: value.permission,
-            owner: value.owner,
-            group: value.group,
+            owner: value.owner.to_string(),
+            group: value.group.to_string(),
             modification_time: value.modification_time,
             access_time: value.access_time,
         }
@@ -108,17 +108,30 @@ struct PyWriteOptions {
     permission: u32,
     overwrite: bool,
     create_parent: bool,
+    packet_size: Option<u32>,
+    max_packets_in_flight: Option<usize>,
+    checksum_type: Option<String>,
+    bytes_per_checksum: Option<u32>,
 }
 
-impl From<PyWriteOptions> for WriteOptions {
-    fn from(value: PyWriteOptions) -> Self {
-        Self {
+impl TryFrom<PyWriteOptions> for WriteOptions {
+    type Error = HdfsError;
+
+    fn try_from(value: PyWriteOptions) -> Result<Self, Self::Error> {
+        Ok(Self {
             block_size: value.block_size,
             replication: value.replication,
             permission: value.permission,
             overwrite: value.overwrite,
             create_parent: value.create_parent,
-        }
+            packet_size: value.packet_size,
+            max_packets_in_flight: value.max_packets_in_flight,
+            checksum_type: value
+                .checksum_type
+                .map(|checksum_type| checksum_type.parse())
+                .transpose()?,
+            bytes_per_checksum: value.bytes_per_checksum,
+        })
     }
 }
 
@@ -130,6 +143,12 @@ impl From<WriteOptions> for PyWriteOptions {
             permission: value.permission,
             overwrite: value.overwrite,
             create_parent: value.create_parent,
+            packet_size: value.packet_size,
+            max_packets_in_flight: value.max_packets_in_flight,
+            checksum_type: value
+                .checksum_type
+                .map(|checksum_type| checksum_type.to_string()),
+            bytes_per_checksum: value.bytes_per_checksum,
         }
     }
 }
@@ -210,9 +229,10 @@ impl RawClient {
     }
 
     pub fn create(&self, src: &str, write_options: PyWriteOptions) -> PyHdfsResult<RawFileWriter> {
-        let file_writer = self
-            .rt
-            .block_on(self.inner.create(src, WriteOptions::from(write_options)))?;
+        let file_writer = self.rt.block_on(
+            self.inner
+                .create(src, WriteOptions::try_from(write_options)?),
+        )?;
 
         Ok(RawFileWriter {
             inner: file_writer,